@@ -1,12 +1,17 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 use std::env;
-use std::io::{self, Write};
+use std::fs::File;
+use std::io::{self, BufReader, Write};
+use std::path::{Path, PathBuf};
 
 use dotenv::dotenv;
 
-use db::models::Reference;
-use db::{establish_connection, SwordDrill, SwordDrillable, VerseFormat};
+use db::models::{IntegrityReport, Reference};
+use db::{
+    establish_connection, BookStore, DbConnection, ImportFormat, SwordDrill, VerseFormat,
+    VerseStore,
+};
 
 #[derive(Parser, Debug)]
 #[clap(
@@ -17,18 +22,267 @@ use db::{establish_connection, SwordDrill, SwordDrillable, VerseFormat};
 struct Opts {
     #[clap(default_value = "John 3:16")]
     reference: Reference,
+
+    /// Abbreviation of the translation to read from
+    #[clap(short, long, default_value = "kjv", global = true)]
+    translation: String,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Manage the database, instead of looking up verses
+    #[clap(subcommand)]
+    Db(DbCommand),
+
+    /// Manage the keys for calling the web app's API, instead of looking up
+    /// verses
+    #[clap(subcommand)]
+    ApiKey(ApiKeyCommand),
+}
+
+#[derive(Subcommand, Debug)]
+enum DbCommand {
+    /// Run any pending migrations, which make the tables and load the King
+    /// James Version
+    Migrate,
+
+    /// Revert the latest migration that's been run
+    Rollback,
+
+    /// List the migrations and whether each has been run
+    Status,
+
+    /// Import the bundled translations if the database hasn't any verses
+    Seed,
+
+    /// Write a consistent snapshot of the database to a new file
+    Backup {
+        #[clap(value_name = "FILE")]
+        path: PathBuf,
+    },
+
+    /// Replace the database with a backup, which should only be done while
+    /// the server is stopped
+    Restore {
+        #[clap(value_name = "FILE")]
+        path: PathBuf,
+    },
+
+    /// Import a translation from a file, or a directory of a file for each
+    /// book or of a SWORD module, as the translation given by its
+    /// abbreviation, replacing its verses if it's been imported before, and
+    /// check it like verify
+    Import {
+        path: PathBuf,
+
+        /// Format of the translation (usfm, osis, zefania, sword, esword,
+        /// theword or json)
+        #[clap(long, default_value = "usfm")]
+        format: ImportFormat,
+
+        /// Name of the translation, instead of the one its file gives
+        #[clap(long)]
+        name: Option<String>,
+
+        /// Language of the translation (e.g. `en`), instead of the one its
+        /// file gives
+        #[clap(long)]
+        language: Option<String>,
+    },
+
+    /// Import cross-references from a tab-separated file, replacing any
+    /// imported before
+    ImportCrossReferences {
+        #[clap(value_name = "FILE")]
+        path: PathBuf,
+    },
+
+    /// Check that the translation has every chapter and verse of its
+    /// versification scheme, once each and in order
+    Verify,
+
+    /// Renumber the translation's verses so they're stored in canonical order
+    FixVerseOrder,
+
+    /// Build the search index for the web app in a directory
+    #[cfg(feature = "tantivy")]
+    BuildSearchIndex {
+        #[clap(value_name = "DIR")]
+        dir: PathBuf,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ApiKeyCommand {
+    /// Issue a key to someone, printing its ID and the key
+    Create { name: String },
+
+    /// List the keys that have been issued
+    List,
+
+    /// Revoke the key with an ID
+    Revoke { id: i32 },
 }
 
 fn main() -> io::Result<()> {
     let opts: Opts = Opts::parse();
     let reference = opts.reference;
+    let translation = opts.translation;
 
     dotenv().ok();
     let url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
 
+    // Restoring replaces the database, so it's done before connecting to it
+    if let Some(Command::Db(DbCommand::Restore { path })) = &opts.command {
+        return match db::restore(path, Path::new(&url)) {
+            Ok(()) => Ok(()),
+            Err(e) => io::stderr().write_fmt(format_args!("{:?}", e)),
+        };
+    }
+
     let conn = establish_connection(&url);
 
-    let result = SwordDrill::verses(&reference, VerseFormat::PlainText, &conn);
+    match opts.command {
+        Some(Command::Db(command)) => run_db_command(command, &translation, &conn),
+        Some(Command::ApiKey(command)) => run_api_key_command(command, &conn),
+        None => look_up(&reference, &translation, &conn),
+    }
+}
+
+/// Runs a command that manages the database.
+fn run_db_command(command: DbCommand, translation: &str, conn: &DbConnection) -> io::Result<()> {
+    match command {
+        DbCommand::Migrate => match db::run_migrations(conn) {
+            Ok(()) => Ok(()),
+            Err(e) => io::stderr().write_fmt(format_args!("{:?}", e)),
+        },
+        DbCommand::Rollback => match db::rollback_migration(conn) {
+            Ok(version) => io::stdout().write_fmt(format_args!("Reverted {}\n", version)),
+            Err(e) => io::stderr().write_fmt(format_args!("{:?}", e)),
+        },
+        DbCommand::Status => match db::migration_status(conn) {
+            Ok(migrations) => {
+                for (name, is_run) in migrations {
+                    let mark = if is_run { "X" } else { " " };
+                    io::stdout().write_fmt(format_args!("[{}] {}\n", mark, name))?;
+                }
+                Ok(())
+            }
+            Err(e) => io::stderr().write_fmt(format_args!("{:?}", e)),
+        },
+        DbCommand::Seed => match db::seed_verses(conn) {
+            Ok(count) => io::stdout().write_fmt(format_args!("Seeded {}\n", count)),
+            Err(e) => io::stderr().write_fmt(format_args!("{:?}", e)),
+        },
+        DbCommand::Backup { path } => match db::backup(&path, conn) {
+            Ok(()) => Ok(()),
+            Err(e) => io::stderr().write_fmt(format_args!("{:?}", e)),
+        },
+        // Run before connecting, in main
+        DbCommand::Restore { .. } => unreachable!(),
+        DbCommand::Import {
+            path,
+            format,
+            name,
+            language,
+        } => {
+            let mut text = match db::read_translation(&path, format, conn) {
+                Ok(text) => text,
+                Err(e) => return io::stderr().write_fmt(format_args!("{:?}", e)),
+            };
+            for place in &text.unmapped {
+                io::stdout().write_fmt(format_args!("Unmapped\t{}\n", place))?;
+            }
+            text.name = name.or(text.name);
+            text.language = language.or(text.language);
+            // Report the verses that didn't map to the translation's versification
+            let result = db::import_translation(translation, &text, conn).and_then(|count| {
+                let t = SwordDrill::translation(translation, conn)?;
+                Ok((count, db::verify_translation(&t, conn)?))
+            });
+            match result {
+                Ok((count, report)) => {
+                    io::stdout().write_fmt(format_args!("Imported {}\n", count))?;
+                    write_report(report)
+                }
+                Err(e) => io::stderr().write_fmt(format_args!("{:?}", e)),
+            }
+        }
+        DbCommand::ImportCrossReferences { path } => {
+            let reader = BufReader::new(File::open(path)?);
+            match db::import_cross_references(reader, conn) {
+                Ok(count) => io::stdout().write_fmt(format_args!("Imported {}\n", count)),
+                Err(e) => io::stderr().write_fmt(format_args!("{:?}", e)),
+            }
+        }
+        DbCommand::Verify => match SwordDrill::translation(translation, conn)
+            .and_then(|t| db::verify_translation(&t, conn))
+        {
+            Ok(report) => write_report(report),
+            Err(e) => io::stderr().write_fmt(format_args!("{:?}", e)),
+        },
+        DbCommand::FixVerseOrder => match SwordDrill::translation(translation, conn)
+            .and_then(|t| db::fix_verse_order(&t, conn))
+        {
+            Ok(count) => io::stdout().write_fmt(format_args!("Reordered {}\n", count)),
+            Err(e) => io::stderr().write_fmt(format_args!("{:?}", e)),
+        },
+        #[cfg(feature = "tantivy")]
+        DbCommand::BuildSearchIndex { dir } => {
+            std::fs::create_dir_all(&dir)?;
+            match db::build_search_index(Some(&dir), conn) {
+                Ok(()) => Ok(()),
+                Err(e) => io::stderr().write_fmt(format_args!("{:?}", e)),
+            }
+        }
+    }
+}
+
+/// Runs a command that manages the API keys.
+fn run_api_key_command(command: ApiKeyCommand, conn: &DbConnection) -> io::Result<()> {
+    match command {
+        ApiKeyCommand::Create { name } => match db::create_api_key(&name, conn) {
+            Ok((api_key, key)) => io::stdout().write_fmt(format_args!("{}\t{}\n", api_key.id, key)),
+            Err(e) => io::stderr().write_fmt(format_args!("{:?}", e)),
+        },
+        ApiKeyCommand::List => match db::list_api_keys(conn) {
+            Ok(api_keys) => {
+                for k in api_keys {
+                    let revoked = k.revoked_at.map(|r| format!("revoked {}", r));
+                    io::stdout().write_fmt(format_args!(
+                        "{}\t{}\t{}\t{}\n",
+                        k.id,
+                        k.name,
+                        k.created_at,
+                        revoked.unwrap_or_default()
+                    ))?;
+                }
+                Ok(())
+            }
+            Err(e) => io::stderr().write_fmt(format_args!("{:?}", e)),
+        },
+        ApiKeyCommand::Revoke { id } => match db::revoke_api_key(id, conn) {
+            Ok(true) => Ok(()),
+            Ok(false) => io::stderr().write_fmt(format_args!("No API key {} to revoke\n", id)),
+            Err(e) => io::stderr().write_fmt(format_args!("{:?}", e)),
+        },
+    }
+}
+
+/// Prints the verses of a passage.
+fn look_up(reference: &Reference, translation: &str, conn: &DbConnection) -> io::Result<()> {
+    // Read the translations in a directory of translation files too
+    if let Ok(dir) = env::var("TRANSLATIONS_DIR") {
+        if let Err(e) = db::attach_translations(Path::new(&dir), conn) {
+            return io::stderr().write_fmt(format_args!("{:?}", e));
+        }
+    }
+
+    let result = SwordDrill::translation(translation, conn)
+        .and_then(|t| SwordDrill::verses(reference, &t, VerseFormat::PlainText, conn));
 
     match result {
         Ok((book, verses)) => {
@@ -36,8 +290,7 @@ fn main() -> io::Result<()> {
                 "{}\n",
                 Reference {
                     book: book.name,
-                    chapter: reference.chapter,
-                    verses: reference.verses
+                    ..reference.clone()
                 }
             ))?;
             for v in verses {
@@ -48,3 +301,27 @@ fn main() -> io::Result<()> {
         Err(e) => io::stderr().write_fmt(format_args!("{:?}", e)),
     }
 }
+
+/// Writes the problems a check of a translation found, one on each line.
+fn write_report(report: IntegrityReport) -> io::Result<()> {
+    let mut out = io::stdout();
+    for (book, has, expected) in report.chapter_counts {
+        out.write_fmt(format_args!(
+            "{} has {} chapters, not {}\n",
+            book, has, expected
+        ))?;
+    }
+    for (problem, references) in [
+        ("Missing", report.missing),
+        ("Unexpected", report.unexpected),
+        ("Duplicate", report.duplicates),
+    ] {
+        for reference in references {
+            out.write_fmt(format_args!("{}\t{}\n", problem, reference))?;
+        }
+    }
+    if report.out_of_order {
+        out.write_fmt(format_args!("The verses are out of order\n"))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,75 @@
+//! Benchmarks of looking up and searching verses, for validating changes
+//! made for performance and catching regressions.
+//!
+//! They're run against the database in `DATABASE_URL`, or else against an
+//! in-memory database seeded by the migrations:
+//! `cargo bench -p db`.
+
+use std::env;
+use std::io::sink;
+use std::path::Path;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use diesel::SqliteConnection;
+use diesel_migrations::run_pending_migrations_in_directory;
+
+use db::models::{Reference, SearchOptions};
+use db::{establish_connection, BookStore, Searcher, SwordDrill, VerseFormat, VerseStore};
+
+/// Connects to the database the benchmarks are run against.
+fn seeded_connection() -> SqliteConnection {
+    match env::var("DATABASE_URL") {
+        Ok(url) => establish_connection(&url),
+        Err(_) => {
+            let conn = establish_connection(":memory:");
+            run_pending_migrations_in_directory(&conn, Path::new("./migrations"), &mut sink())
+                .expect("Error running migrations");
+            conn
+        }
+    }
+}
+
+fn references(c: &mut Criterion) {
+    c.bench_function("parse chapter", |b| {
+        b.iter(|| black_box("Psalms 119").parse::<Reference>())
+    });
+    c.bench_function("parse verses", |b| {
+        b.iter(|| black_box("1 cor 13:4-7,13").parse::<Reference>())
+    });
+}
+
+fn chapters(c: &mut Criterion) {
+    let conn = seeded_connection();
+    let kjv = SwordDrill::translation("kjv", &conn).unwrap();
+    let psalm: Reference = "Psalms 119".parse().unwrap();
+    let verse: Reference = "John 3:16".parse().unwrap();
+
+    c.bench_function("chapter", |b| {
+        b.iter(|| SwordDrill::chapter(&psalm, "kjv", VerseFormat::Html, &conn).unwrap())
+    });
+    c.bench_function("verses", |b| {
+        b.iter(|| SwordDrill::verses(&verse, &kjv, VerseFormat::PlainText, &conn).unwrap())
+    });
+    c.bench_function("book", |b| {
+        b.iter(|| SwordDrill::chapters("ps", &kjv, &conn).unwrap())
+    });
+}
+
+fn search(c: &mut Criterion) {
+    let conn = seeded_connection();
+    let kjv = SwordDrill::translation("kjv", &conn).unwrap();
+    let options = SearchOptions::default();
+
+    c.bench_function("search word", |b| {
+        b.iter(|| SwordDrill::search("lamp", &options, &kjv, &conn).unwrap())
+    });
+    c.bench_function("search common words", |b| {
+        b.iter(|| SwordDrill::search("the lord god", &options, &kjv, &conn).unwrap())
+    });
+    c.bench_function("search phrase", |b| {
+        b.iter(|| SwordDrill::search("\"so loved the world\"", &options, &kjv, &conn).unwrap())
+    });
+}
+
+criterion_group!(benches, references, chapters, search);
+criterion_main!(benches);
@@ -0,0 +1,37 @@
+DROP TABLE verses_fts;
+CREATE VIRTUAL TABLE verses_fts USING fts5(
+    book UNINDEXED,
+    chapter UNINDEXED,
+    verse UNINDEXED,
+    words
+);
+INSERT INTO verses_fts (rowid, book, chapter, verse, words)
+SELECT id, book, chapter, verse, words FROM verses WHERE translation_id = 1;
+
+DROP INDEX verses_translation_idx;
+DROP INDEX verses_html_translation_idx;
+
+-- Older SQLite versions can't drop columns, so copy the tables instead
+CREATE TABLE verses_old (
+    id INTEGER PRIMARY KEY NOT NULL,
+    book INTEGER NOT NULL,
+    chapter INTEGER NOT NULL,
+    verse INTEGER NOT NULL,
+    words TEXT NOT NULL
+);
+INSERT INTO verses_old SELECT id, book, chapter, verse, words FROM verses WHERE translation_id = 1;
+DROP TABLE verses;
+ALTER TABLE verses_old RENAME TO verses;
+
+CREATE TABLE verses_html_old (
+    id INTEGER PRIMARY KEY NOT NULL,
+    book INTEGER NOT NULL,
+    chapter INTEGER NOT NULL,
+    verse INTEGER NOT NULL,
+    words TEXT NOT NULL
+);
+INSERT INTO verses_html_old SELECT id, book, chapter, verse, words FROM verses_html WHERE translation_id = 1;
+DROP TABLE verses_html;
+ALTER TABLE verses_html_old RENAME TO verses_html;
+
+DROP TABLE translations;
@@ -0,0 +1,28 @@
+CREATE TABLE translations (
+    id INTEGER PRIMARY KEY NOT NULL,
+    abbreviation TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    language TEXT NOT NULL
+);
+
+INSERT INTO translations (id, abbreviation, name, language)
+VALUES (1, 'kjv', 'King James Version', 'en');
+
+-- Every existing verse belongs to the King James Version
+ALTER TABLE verses ADD COLUMN translation_id INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE verses_html ADD COLUMN translation_id INTEGER NOT NULL DEFAULT 1;
+
+CREATE INDEX verses_translation_idx ON verses (translation_id, book, chapter, verse);
+CREATE INDEX verses_html_translation_idx ON verses_html (translation_id, book, chapter, verse);
+
+-- FTS5 tables can't be altered, so rebuild the index with the new column
+DROP TABLE verses_fts;
+CREATE VIRTUAL TABLE verses_fts USING fts5(
+    book UNINDEXED,
+    chapter UNINDEXED,
+    verse UNINDEXED,
+    words,
+    translation_id UNINDEXED
+);
+INSERT INTO verses_fts (rowid, book, chapter, verse, words, translation_id)
+SELECT id, book, chapter, verse, words, translation_id FROM verses;
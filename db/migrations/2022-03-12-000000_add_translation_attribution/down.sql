@@ -0,0 +1,10 @@
+-- Older SQLite versions can't drop columns, so copy the table instead
+CREATE TABLE translations_old (
+    id INTEGER PRIMARY KEY NOT NULL,
+    abbreviation TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    language TEXT NOT NULL
+);
+INSERT INTO translations_old SELECT id, abbreviation, name, language FROM translations;
+DROP TABLE translations;
+ALTER TABLE translations_old RENAME TO translations;
@@ -0,0 +1,5 @@
+ALTER TABLE translations ADD COLUMN license TEXT;
+ALTER TABLE translations ADD COLUMN copyright TEXT;
+ALTER TABLE translations ADD COLUMN attribution TEXT;
+
+UPDATE translations SET license = 'Public Domain' WHERE abbreviation = 'kjv';
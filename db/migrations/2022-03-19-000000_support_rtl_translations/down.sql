@@ -0,0 +1,25 @@
+DROP TABLE verses_fts;
+CREATE VIRTUAL TABLE verses_fts USING fts5(
+    book UNINDEXED,
+    chapter UNINDEXED,
+    verse UNINDEXED,
+    words,
+    translation_id UNINDEXED
+);
+INSERT INTO verses_fts (rowid, book, chapter, verse, words, translation_id)
+SELECT id, book, chapter, verse, words, translation_id FROM verses;
+
+-- Older SQLite versions can't drop columns, so copy the table instead
+CREATE TABLE translations_old (
+    id INTEGER PRIMARY KEY NOT NULL,
+    abbreviation TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    language TEXT NOT NULL,
+    license TEXT,
+    copyright TEXT,
+    attribution TEXT
+);
+INSERT INTO translations_old
+SELECT id, abbreviation, name, language, license, copyright, attribution FROM translations;
+DROP TABLE translations;
+ALTER TABLE translations_old RENAME TO translations;
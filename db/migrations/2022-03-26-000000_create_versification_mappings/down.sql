@@ -0,0 +1,18 @@
+DROP TABLE versification_mappings;
+
+-- Older SQLite versions can't drop columns, so copy the table instead
+CREATE TABLE translations_old (
+    id INTEGER PRIMARY KEY NOT NULL,
+    abbreviation TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    language TEXT NOT NULL,
+    license TEXT,
+    copyright TEXT,
+    attribution TEXT,
+    direction TEXT NOT NULL DEFAULT 'ltr' CHECK (direction IN ('ltr', 'rtl'))
+);
+INSERT INTO translations_old
+SELECT id, abbreviation, name, language, license, copyright, attribution, direction
+FROM translations;
+DROP TABLE translations;
+ALTER TABLE translations_old RENAME TO translations;
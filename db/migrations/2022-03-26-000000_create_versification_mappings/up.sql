@@ -0,0 +1,66 @@
+-- Versification scheme each translation numbers its verses with. References
+-- are always entered in the KJV scheme.
+ALTER TABLE translations ADD COLUMN versification TEXT NOT NULL DEFAULT 'kjv';
+
+-- Maps a verse numbered in another scheme to its KJV number. Verses without
+-- a mapping are numbered the same in both schemes.
+CREATE TABLE versification_mappings (
+    versification TEXT NOT NULL,
+    book INTEGER NOT NULL,
+    chapter INTEGER NOT NULL,
+    verse INTEGER NOT NULL,
+    standard_chapter INTEGER NOT NULL,
+    standard_verse INTEGER NOT NULL,
+    PRIMARY KEY (versification, book, chapter, verse)
+);
+
+CREATE INDEX versification_mappings_standard_idx
+ON versification_mappings (versification, book, standard_chapter);
+
+-- The Septuagint and Vulgate number the Psalms differently from the KJV.
+-- Superscriptions counted as verses are not accounted for here.
+CREATE TEMPORARY TABLE psalm_offsets (
+    standard_chapter INTEGER NOT NULL,
+    chapter INTEGER NOT NULL,
+    verse_offset INTEGER NOT NULL,
+    first_verse INTEGER NOT NULL,
+    last_verse INTEGER NOT NULL
+);
+
+INSERT INTO psalm_offsets (standard_chapter, chapter, verse_offset, first_verse, last_verse)
+VALUES
+    -- KJV 10 is the second half of 9
+    (10, 9, 20, 1, 999),
+    -- KJV 11-113 are 10-112
+    (11, 10, 0, 1, 999),
+    -- KJV 114-115 are 113
+    (114, 113, 0, 1, 999),
+    (115, 113, 8, 1, 999),
+    -- KJV 116 is 114-115
+    (116, 114, 0, 1, 9),
+    (116, 115, -9, 10, 999),
+    -- KJV 117-146 are 116-145
+    (117, 116, 0, 1, 999),
+    -- KJV 147 is 146-147
+    (147, 146, 0, 1, 11),
+    (147, 147, -11, 12, 999);
+
+WITH RECURSIVE shifted(standard_chapter) AS (
+    SELECT 12
+    UNION ALL
+    SELECT standard_chapter + 1 FROM shifted WHERE standard_chapter < 146
+)
+INSERT INTO psalm_offsets (standard_chapter, chapter, verse_offset, first_verse, last_verse)
+SELECT standard_chapter, standard_chapter - 1, 0, 1, 999
+FROM shifted
+WHERE standard_chapter <= 113 OR standard_chapter >= 118;
+
+INSERT INTO versification_mappings
+SELECT scheme.name, v.book, o.chapter, v.verse + o.verse_offset, v.chapter, v.verse
+FROM verses v
+INNER JOIN psalm_offsets o
+    ON o.standard_chapter = v.chapter AND v.verse BETWEEN o.first_verse AND o.last_verse
+CROSS JOIN (SELECT 'lxx' AS name UNION ALL SELECT 'vulgate') scheme
+WHERE v.translation_id = 1 AND v.book = 19;
+
+DROP TABLE psalm_offsets;
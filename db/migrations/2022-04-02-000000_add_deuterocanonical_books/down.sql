@@ -0,0 +1,13 @@
+DELETE FROM book_abbreviations WHERE book_id IN (SELECT id FROM books WHERE deuterocanonical);
+DELETE FROM books WHERE deuterocanonical;
+
+-- Older SQLite versions can't drop columns, so copy the table instead
+CREATE TABLE books_old (
+    id INTEGER PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    chapter_count INTEGER NOT NULL,
+    testament TEXT NOT NULL
+);
+INSERT INTO books_old SELECT id, name, chapter_count, testament FROM books;
+DROP TABLE books;
+ALTER TABLE books_old RENAME TO books;
@@ -0,0 +1,65 @@
+-- Books outside the Protestant canon. They're only listed for translations
+-- that contain them.
+ALTER TABLE books ADD COLUMN deuterocanonical BOOLEAN NOT NULL DEFAULT 0;
+
+INSERT INTO books (id, name, chapter_count, testament, deuterocanonical)
+VALUES
+    (67, 'Tobit', 14, 'OLD', 1),
+    (68, 'Judith', 16, 'OLD', 1),
+    (69, 'Wisdom of Solomon', 19, 'OLD', 1),
+    (70, 'Sirach', 51, 'OLD', 1),
+    (71, 'Baruch', 6, 'OLD', 1),
+    (72, '1 Maccabees', 16, 'OLD', 1),
+    (73, '2 Maccabees', 15, 'OLD', 1),
+    (74, '1 Esdras', 9, 'OLD', 1),
+    (75, '2 Esdras', 16, 'OLD', 1),
+    (76, 'Prayer of Manasseh', 1, 'OLD', 1);
+
+INSERT INTO book_abbreviations (book_id, abbreviation)
+VALUES
+    (67, 'tobit'),
+    (67, 'tob'),
+    (67, 'tb'),
+    (68, 'judith'),
+    (68, 'jdt'),
+    (68, 'jdth'),
+    (69, 'wisdom of solomon'),
+    (69, 'wisdom'),
+    (69, 'wis'),
+    (69, 'ws'),
+    (70, 'sirach'),
+    (70, 'sir'),
+    (70, 'ecclesiasticus'),
+    (70, 'ecclus'),
+    (71, 'baruch'),
+    (71, 'bar'),
+    (72, '1 maccabees'),
+    (72, '1maccabees'),
+    (72, 'i maccabees'),
+    (72, '1 macc'),
+    (72, '1macc'),
+    (72, '1 mac'),
+    (72, '1mac'),
+    (72, '1ma'),
+    (73, '2 maccabees'),
+    (73, '2maccabees'),
+    (73, 'ii maccabees'),
+    (73, '2 macc'),
+    (73, '2macc'),
+    (73, '2 mac'),
+    (73, '2mac'),
+    (73, '2ma'),
+    (74, '1 esdras'),
+    (74, '1esdras'),
+    (74, 'i esdras'),
+    (74, '1 esd'),
+    (74, '1esd'),
+    (75, '2 esdras'),
+    (75, '2esdras'),
+    (75, 'ii esdras'),
+    (75, '2 esd'),
+    (75, '2esd'),
+    (76, 'prayer of manasseh'),
+    (76, 'manasseh'),
+    (76, 'pr man'),
+    (76, 'prman');
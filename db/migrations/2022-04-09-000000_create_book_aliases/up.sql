@@ -0,0 +1,149 @@
+-- Book names in other languages, so references can be typed in the
+-- language of a translation (e.g. Juan 3:16 or 1. Mose 2). Aliases are
+-- stored lowercase and NFC-normalized, like the queries they're matched to.
+CREATE TABLE book_aliases (
+    id INTEGER PRIMARY KEY NOT NULL,
+    book_id INTEGER NOT NULL REFERENCES books(id),
+    locale TEXT NOT NULL,
+    alias TEXT NOT NULL,
+    UNIQUE(locale, alias)
+);
+CREATE INDEX book_aliases_alias ON book_aliases (alias);
+
+-- Spanish
+INSERT INTO book_aliases (book_id, locale, alias) VALUES
+    (1, 'es', 'génesis'),
+    (2, 'es', 'éxodo'),
+    (3, 'es', 'levítico'),
+    (4, 'es', 'números'),
+    (5, 'es', 'deuteronomio'),
+    (6, 'es', 'josué'),
+    (7, 'es', 'jueces'),
+    (8, 'es', 'rut'),
+    (9, 'es', '1 samuel'),
+    (10, 'es', '2 samuel'),
+    (11, 'es', '1 reyes'),
+    (12, 'es', '2 reyes'),
+    (13, 'es', '1 crónicas'),
+    (14, 'es', '2 crónicas'),
+    (15, 'es', 'esdras'),
+    (16, 'es', 'nehemías'),
+    (17, 'es', 'ester'),
+    (18, 'es', 'job'),
+    (19, 'es', 'salmos'),
+    (20, 'es', 'proverbios'),
+    (21, 'es', 'eclesiastés'),
+    (22, 'es', 'cantares'),
+    (23, 'es', 'isaías'),
+    (24, 'es', 'jeremías'),
+    (25, 'es', 'lamentaciones'),
+    (26, 'es', 'ezequiel'),
+    (27, 'es', 'daniel'),
+    (28, 'es', 'oseas'),
+    (29, 'es', 'joel'),
+    (30, 'es', 'amós'),
+    (31, 'es', 'abdías'),
+    (32, 'es', 'jonás'),
+    (33, 'es', 'miqueas'),
+    (34, 'es', 'nahúm'),
+    (35, 'es', 'habacuc'),
+    (36, 'es', 'sofonías'),
+    (37, 'es', 'hageo'),
+    (38, 'es', 'zacarías'),
+    (39, 'es', 'malaquías'),
+    (40, 'es', 'mateo'),
+    (41, 'es', 'marcos'),
+    (42, 'es', 'lucas'),
+    (43, 'es', 'juan'),
+    (44, 'es', 'hechos'),
+    (45, 'es', 'romanos'),
+    (46, 'es', '1 corintios'),
+    (47, 'es', '2 corintios'),
+    (48, 'es', 'gálatas'),
+    (49, 'es', 'efesios'),
+    (50, 'es', 'filipenses'),
+    (51, 'es', 'colosenses'),
+    (52, 'es', '1 tesalonicenses'),
+    (53, 'es', '2 tesalonicenses'),
+    (54, 'es', '1 timoteo'),
+    (55, 'es', '2 timoteo'),
+    (56, 'es', 'tito'),
+    (57, 'es', 'filemón'),
+    (58, 'es', 'hebreos'),
+    (59, 'es', 'santiago'),
+    (60, 'es', '1 pedro'),
+    (61, 'es', '2 pedro'),
+    (62, 'es', '1 juan'),
+    (63, 'es', '2 juan'),
+    (64, 'es', '3 juan'),
+    (65, 'es', 'judas'),
+    (66, 'es', 'apocalipsis');
+
+-- German
+INSERT INTO book_aliases (book_id, locale, alias) VALUES
+    (1, 'de', '1. mose'),
+    (2, 'de', '2. mose'),
+    (3, 'de', '3. mose'),
+    (4, 'de', '4. mose'),
+    (5, 'de', '5. mose'),
+    (6, 'de', 'josua'),
+    (7, 'de', 'richter'),
+    (8, 'de', 'rut'),
+    (9, 'de', '1. samuel'),
+    (10, 'de', '2. samuel'),
+    (11, 'de', '1. könige'),
+    (12, 'de', '2. könige'),
+    (13, 'de', '1. chronik'),
+    (14, 'de', '2. chronik'),
+    (15, 'de', 'esra'),
+    (16, 'de', 'nehemia'),
+    (17, 'de', 'ester'),
+    (18, 'de', 'hiob'),
+    (19, 'de', 'psalmen'),
+    (20, 'de', 'sprüche'),
+    (21, 'de', 'prediger'),
+    (22, 'de', 'hoheslied'),
+    (23, 'de', 'jesaja'),
+    (24, 'de', 'jeremia'),
+    (25, 'de', 'klagelieder'),
+    (26, 'de', 'hesekiel'),
+    (27, 'de', 'daniel'),
+    (28, 'de', 'hosea'),
+    (29, 'de', 'joel'),
+    (30, 'de', 'amos'),
+    (31, 'de', 'obadja'),
+    (32, 'de', 'jona'),
+    (33, 'de', 'micha'),
+    (34, 'de', 'nahum'),
+    (35, 'de', 'habakuk'),
+    (36, 'de', 'zefanja'),
+    (37, 'de', 'haggai'),
+    (38, 'de', 'sacharja'),
+    (39, 'de', 'maleachi'),
+    (40, 'de', 'matthäus'),
+    (41, 'de', 'markus'),
+    (42, 'de', 'lukas'),
+    (43, 'de', 'johannes'),
+    (44, 'de', 'apostelgeschichte'),
+    (45, 'de', 'römer'),
+    (46, 'de', '1. korinther'),
+    (47, 'de', '2. korinther'),
+    (48, 'de', 'galater'),
+    (49, 'de', 'epheser'),
+    (50, 'de', 'philipper'),
+    (51, 'de', 'kolosser'),
+    (52, 'de', '1. thessalonicher'),
+    (53, 'de', '2. thessalonicher'),
+    (54, 'de', '1. timotheus'),
+    (55, 'de', '2. timotheus'),
+    (56, 'de', 'titus'),
+    (57, 'de', 'philemon'),
+    (58, 'de', 'hebräer'),
+    (59, 'de', 'jakobus'),
+    (60, 'de', '1. petrus'),
+    (61, 'de', '2. petrus'),
+    (62, 'de', '1. johannes'),
+    (63, 'de', '2. johannes'),
+    (64, 'de', '3. johannes'),
+    (65, 'de', 'judas'),
+    (66, 'de', 'offenbarung');
@@ -0,0 +1,11 @@
+DROP TABLE verses_fts;
+CREATE VIRTUAL TABLE verses_fts USING fts5(
+    book UNINDEXED,
+    chapter UNINDEXED,
+    verse UNINDEXED,
+    words,
+    translation_id UNINDEXED,
+    tokenize = "unicode61 remove_diacritics 2 categories 'L* M* N* Co'"
+);
+INSERT INTO verses_fts (rowid, book, chapter, verse, words, translation_id)
+SELECT id, book, chapter, verse, words, translation_id FROM verses;
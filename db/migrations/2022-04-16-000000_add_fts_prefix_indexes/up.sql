@@ -0,0 +1,14 @@
+-- Index two and three letter prefixes so searching for a partly typed word
+-- (e.g. `hamm*`) doesn't have to scan every term in the index
+DROP TABLE verses_fts;
+CREATE VIRTUAL TABLE verses_fts USING fts5(
+    book UNINDEXED,
+    chapter UNINDEXED,
+    verse UNINDEXED,
+    words,
+    translation_id UNINDEXED,
+    tokenize = "unicode61 remove_diacritics 2 categories 'L* M* N* Co'",
+    prefix = '2 3'
+);
+INSERT INTO verses_fts (rowid, book, chapter, verse, words, translation_id)
+SELECT id, book, chapter, verse, words, translation_id FROM verses;
@@ -0,0 +1,15 @@
+-- Index verses folded with the `fold_text` function, so searches match
+-- them regardless of case and diacritics (including Greek accents and
+-- Hebrew points, which the tokenizer keeps)
+DROP TABLE verses_fts;
+CREATE VIRTUAL TABLE verses_fts USING fts5(
+    book UNINDEXED,
+    chapter UNINDEXED,
+    verse UNINDEXED,
+    words,
+    translation_id UNINDEXED,
+    tokenize = "unicode61 remove_diacritics 2 categories 'L* M* N* Co'",
+    prefix = '2 3'
+);
+INSERT INTO verses_fts (rowid, book, chapter, verse, words, translation_id)
+SELECT id, book, chapter, verse, fold_text(words), translation_id FROM verses;
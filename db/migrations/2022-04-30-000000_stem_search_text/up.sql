@@ -0,0 +1,17 @@
+-- Index verses with the `index_text` function, which also stems the words
+-- of translations in a language with a stemmer, so searching for "love"
+-- matches "loved", "loves" and "loving"
+DROP TABLE verses_fts;
+CREATE VIRTUAL TABLE verses_fts USING fts5(
+    book UNINDEXED,
+    chapter UNINDEXED,
+    verse UNINDEXED,
+    words,
+    translation_id UNINDEXED,
+    tokenize = "unicode61 remove_diacritics 2 categories 'L* M* N* Co'",
+    prefix = '2 3'
+);
+INSERT INTO verses_fts (rowid, book, chapter, verse, words, translation_id)
+SELECT verses.id, book, chapter, verse, index_text(words, translations.language), translation_id
+FROM verses
+INNER JOIN translations ON translations.id = verses.translation_id;
@@ -0,0 +1,70 @@
+-- Verses shown as the verse of the day, in the order they're shown in. The
+-- rotation starts again from the first verse after the last one. References
+-- use the book names of the books table, so they can be looked up directly.
+CREATE TABLE daily_verses (
+    id INTEGER PRIMARY KEY NOT NULL,
+    reference TEXT NOT NULL
+);
+
+INSERT INTO daily_verses (reference) VALUES
+    ('John 3:16'),
+    ('Psalms 119:105'),
+    ('Jeremiah 29:11'),
+    ('Romans 8:28'),
+    ('Philippians 4:13'),
+    ('Proverbs 3:5-6'),
+    ('Isaiah 40:31'),
+    ('Joshua 1:9'),
+    ('Matthew 11:28'),
+    ('Psalms 23:1'),
+    ('2 Timothy 1:7'),
+    ('Romans 12:2'),
+    ('Hebrews 11:1'),
+    ('Galatians 5:22-23'),
+    ('Ephesians 2:8-9'),
+    ('1 Corinthians 13:4'),
+    ('Psalms 46:1'),
+    ('Isaiah 41:10'),
+    ('Matthew 6:33'),
+    ('John 14:6'),
+    ('Romans 5:8'),
+    ('Lamentations 3:22-23'),
+    ('Psalms 37:4'),
+    ('Micah 6:8'),
+    ('2 Corinthians 5:17'),
+    ('1 John 1:9'),
+    ('James 1:5'),
+    ('Psalms 27:1'),
+    ('Deuteronomy 31:6'),
+    ('Matthew 5:16'),
+    ('John 1:1'),
+    ('Genesis 1:1'),
+    ('Psalms 139:14'),
+    ('Proverbs 18:10'),
+    ('Isaiah 26:3'),
+    ('Romans 15:13'),
+    ('Hebrews 12:1'),
+    ('1 Peter 5:7'),
+    ('Psalms 91:1'),
+    ('John 16:33'),
+    ('Colossians 3:23'),
+    ('Psalms 34:8'),
+    ('Matthew 22:37'),
+    ('Romans 10:9'),
+    ('Ephesians 6:10'),
+    ('James 1:17'),
+    ('Psalms 121:1-2'),
+    ('Isaiah 53:5'),
+    ('John 15:13'),
+    ('2 Corinthians 12:9'),
+    ('Philippians 4:6-7'),
+    ('Psalms 19:14'),
+    ('Nahum 1:7'),
+    ('Zephaniah 3:17'),
+    ('Matthew 28:19-20'),
+    ('John 11:25'),
+    ('Romans 6:23'),
+    ('1 Thessalonians 5:16-18'),
+    ('Hebrews 4:12'),
+    ('Revelation 21:4'),
+    ('Jeremiah 23:29');
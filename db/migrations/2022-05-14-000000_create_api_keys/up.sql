@@ -0,0 +1,11 @@
+-- Keys issued to integrators of the API, when a deployment requires them.
+-- Only a SHA-256 hash of each key is kept, so a copy of the database can't
+-- be used to call the API. Revoked keys are kept, with when they were
+-- revoked.
+CREATE TABLE api_keys (
+    id INTEGER PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    key_hash TEXT NOT NULL UNIQUE,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    revoked_at TEXT
+);
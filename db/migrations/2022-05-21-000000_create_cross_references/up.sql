@@ -0,0 +1,19 @@
+-- Passages to see along with a verse, imported from a cross-reference
+-- dataset such as the Treasury of Scripture Knowledge. References are in
+-- the KJV scheme, and each points to a range of verses that may span
+-- chapters. Votes rank how relevant a cross-reference is, where the
+-- dataset has them.
+CREATE TABLE cross_references (
+    id INTEGER PRIMARY KEY NOT NULL,
+    book INTEGER NOT NULL,
+    chapter INTEGER NOT NULL,
+    verse INTEGER NOT NULL,
+    to_book INTEGER NOT NULL,
+    to_chapter INTEGER NOT NULL,
+    to_verse INTEGER NOT NULL,
+    to_end_chapter INTEGER NOT NULL,
+    to_end_verse INTEGER NOT NULL,
+    votes INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX cross_references_verse_idx ON cross_references (book, chapter, verse);
@@ -0,0 +1,17 @@
+-- Older SQLite versions can't drop columns, so copy the table instead
+CREATE TABLE translations_old (
+    id INTEGER PRIMARY KEY NOT NULL,
+    abbreviation TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    language TEXT NOT NULL,
+    license TEXT,
+    copyright TEXT,
+    attribution TEXT,
+    direction TEXT NOT NULL DEFAULT 'ltr' CHECK (direction IN ('ltr', 'rtl')),
+    versification TEXT NOT NULL DEFAULT 'kjv'
+);
+INSERT INTO translations_old
+SELECT id, abbreviation, name, language, license, copyright, attribution, direction, versification
+FROM translations;
+DROP TABLE translations;
+ALTER TABLE translations_old RENAME TO translations;
@@ -0,0 +1,6 @@
+-- When each translation was imported, as seconds since the Unix epoch, so
+-- pages can say when their text last changed. Translations imported later
+-- should set it, and the ones here were imported with the database.
+ALTER TABLE translations ADD COLUMN imported_at INTEGER NOT NULL DEFAULT 0;
+
+UPDATE translations SET imported_at = CAST(strftime('%s', 'now') AS INTEGER);
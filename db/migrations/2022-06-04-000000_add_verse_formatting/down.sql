@@ -0,0 +1,31 @@
+DROP INDEX verses_translation_idx;
+DROP INDEX verses_html_translation_idx;
+
+-- Older SQLite versions can't drop columns, so copy the tables instead
+CREATE TABLE verses_old (
+    id INTEGER PRIMARY KEY NOT NULL,
+    book INTEGER NOT NULL,
+    chapter INTEGER NOT NULL,
+    verse INTEGER NOT NULL,
+    words TEXT NOT NULL,
+    translation_id INTEGER NOT NULL DEFAULT 1
+);
+INSERT INTO verses_old SELECT id, book, chapter, verse, words, translation_id FROM verses;
+DROP TABLE verses;
+ALTER TABLE verses_old RENAME TO verses;
+
+CREATE TABLE verses_html_old (
+    id INTEGER PRIMARY KEY NOT NULL,
+    book INTEGER NOT NULL,
+    chapter INTEGER NOT NULL,
+    verse INTEGER NOT NULL,
+    words TEXT NOT NULL,
+    translation_id INTEGER NOT NULL DEFAULT 1
+);
+INSERT INTO verses_html_old
+SELECT id, book, chapter, verse, words, translation_id FROM verses_html;
+DROP TABLE verses_html;
+ALTER TABLE verses_html_old RENAME TO verses_html;
+
+CREATE INDEX verses_translation_idx ON verses (translation_id, book, chapter, verse);
+CREATE INDEX verses_html_translation_idx ON verses_html (translation_id, book, chapter, verse);
@@ -0,0 +1,14 @@
+-- How verses are laid out, so prose can be shown in paragraphs and poetry
+-- in indented lines. Translations imported later should set these from
+-- their sources, and the verses here are laid out one after another.
+--
+-- `paragraph` marks a verse that starts a paragraph, `poetry_level` is how
+-- far a line of poetry is indented (0 for prose), and `line_break` marks a
+-- verse that a blank line follows, as at the end of a stanza.
+ALTER TABLE verses ADD COLUMN paragraph BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE verses ADD COLUMN poetry_level INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE verses ADD COLUMN line_break BOOLEAN NOT NULL DEFAULT 0;
+
+ALTER TABLE verses_html ADD COLUMN paragraph BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE verses_html ADD COLUMN poetry_level INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE verses_html ADD COLUMN line_break BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1,74 @@
+use diesel::dsl::sql;
+use diesel::prelude::*;
+use diesel::sql_types::{Binary, Integer, Nullable, Text};
+use sha2::{Digest, Sha256};
+
+use crate::models::ApiKey;
+use crate::DbError;
+
+/// Number of random bytes in an API key.
+const KEY_BYTES: i32 = 20;
+
+sql_function!(fn randomblob(n: Integer) -> Binary);
+
+/// Hashes an API key with SHA-256, as it's kept in the database.
+pub(crate) fn hash_key(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+/// Issues a new API key to someone, returning the key along with its
+/// record. Only its hash is kept, so the key can't be shown again.
+pub fn create_api_key(name: &str, conn: &SqliteConnection) -> Result<(ApiKey, String), DbError> {
+    use crate::schema::api_keys as k;
+
+    let to_db_error = |e: diesel::result::Error| DbError::Other {
+        cause: e.to_string(),
+    };
+    let bytes: Vec<u8> = diesel::select(randomblob(KEY_BYTES))
+        .get_result(conn)
+        .map_err(to_db_error)?;
+    let key: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+
+    conn.transaction(|| {
+        diesel::insert_into(k::table)
+            .values((k::name.eq(name), k::key_hash.eq(hash_key(&key))))
+            .execute(conn)?;
+        k::table
+            .select((k::id, k::name, k::created_at, k::revoked_at))
+            .order_by(k::id.desc())
+            .first::<ApiKey>(conn)
+    })
+    .map(|api_key| (api_key, key))
+    .map_err(to_db_error)
+}
+
+/// Gets all of the API keys that have been issued, including revoked ones.
+pub fn list_api_keys(conn: &SqliteConnection) -> Result<Vec<ApiKey>, DbError> {
+    use crate::schema::api_keys as k;
+
+    k::table
+        .select((k::id, k::name, k::created_at, k::revoked_at))
+        .order_by(k::id)
+        .load(conn)
+        .map_err(|e| DbError::Other {
+            cause: e.to_string(),
+        })
+}
+
+/// Revokes the API key with the given ID, so it can't be used anymore.
+/// Returns whether there was an unrevoked key to revoke.
+pub fn revoke_api_key(id: i32, conn: &SqliteConnection) -> Result<bool, DbError> {
+    use crate::schema::api_keys as k;
+
+    diesel::update(
+        k::table
+            .filter(k::id.eq(id))
+            .filter(k::revoked_at.is_null()),
+    )
+    .set(k::revoked_at.eq(sql::<Nullable<Text>>("CURRENT_TIMESTAMP")))
+    .execute(conn)
+    .map(|updated| updated > 0)
+    .map_err(|e| DbError::Other {
+        cause: e.to_string(),
+    })
+}
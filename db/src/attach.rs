@@ -0,0 +1,145 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use diesel::sql_types::Text;
+
+use crate::DbError;
+
+/// Columns of the tables that translation files have, in the order their
+/// views list them.
+const TABLES: [(&str, &str); 3] = [
+    (
+        "translations",
+        "id, abbreviation, name, language, license, copyright, attribution, direction, \
+        versification, imported_at",
+    ),
+    (
+        "verses",
+        "id, book, chapter, verse, words, translation_id, paragraph, poetry_level, line_break",
+    ),
+    (
+        "verses_html",
+        "id, book, chapter, verse, words, translation_id, paragraph, poetry_level, line_break",
+    ),
+];
+
+/// Attaches each SQLite file (`*.db`) in a directory to a connection, so
+/// the translations in them are read along with the main database's, and
+/// translations can be added or removed by adding or removing files.
+/// Returns the number of files attached.
+///
+/// Each file has `translations`, `verses` and `verses_html` tables like the
+/// main database's, with translation IDs no other file uses. The books and
+/// versification mappings are the main database's. Temporary views in
+/// front of the main database's tables read from every file, so queries
+/// don't need to know which file a translation is in. Full text searches
+/// only cover the main database's translations, since the views can't be
+/// matched, but regular expression searches cover them all.
+///
+/// SQLite attaches at most 10 files to a connection by default.
+pub fn attach_translations(dir: &Path, conn: &SqliteConnection) -> Result<usize, DbError> {
+    attach(dir, conn).map_err(|e| DbError::Other {
+        cause: e.to_string(),
+    })
+}
+
+/// Attaches the translation files for [attach_translations].
+pub(crate) fn attach(dir: &Path, conn: &SqliteConnection) -> QueryResult<usize> {
+    let files =
+        translation_files(dir).map_err(|e| diesel::result::Error::QueryBuilderError(e.into()))?;
+
+    let mut schemas = vec![];
+    for (i, file) in files.iter().enumerate() {
+        // Schemas are numbered rather than named after the files, so their
+        // names can go in the views' SQL as they are
+        let schema = format!("translations_{}", i);
+        diesel::sql_query("ATTACH DATABASE ? AS ?")
+            .bind::<Text, _>(file.to_string_lossy().into_owned())
+            .bind::<Text, _>(schema.as_str())
+            .execute(conn)?;
+        schemas.push(schema);
+    }
+
+    let mut views = String::new();
+    for (table, columns) in TABLES {
+        views.push_str(&format!(
+            "DROP VIEW IF EXISTS temp.{table};
+            CREATE TEMPORARY VIEW {table} AS SELECT {columns} FROM main.{table}",
+            table = table,
+            columns = columns
+        ));
+        for schema in &schemas {
+            views.push_str(&format!(
+                " UNION ALL SELECT {} FROM \"{}\".{}",
+                columns, schema, table
+            ));
+        }
+        views.push_str(";\n");
+    }
+    if !schemas.is_empty() {
+        conn.batch_execute(&views)?;
+    }
+    Ok(schemas.len())
+}
+
+/// Lists the SQLite files in a directory in order of their names.
+fn translation_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().map_or(false, |ext| ext == "db") {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::io::stdout;
+
+    use diesel_migrations::run_pending_migrations_in_directory;
+
+    use super::*;
+    use crate::{establish_connection, BookStore, SwordDrill, VerseFormat, VerseStore};
+
+    #[test]
+    fn attached() {
+        let dir = env::temp_dir().join("biblers-attach-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let file = establish_connection(dir.join("test.db").to_str().unwrap());
+        file.batch_execute(
+            "CREATE TABLE translations AS SELECT 100 AS id, 'test' AS abbreviation,
+                'Test' AS name, 'en' AS language, NULL AS license, NULL AS copyright,
+                NULL AS attribution, 'ltr' AS direction, 'kjv' AS versification,
+                0 AS imported_at;
+            CREATE TABLE verses AS SELECT 1 AS id, 43 AS book, 3 AS chapter, 16 AS verse,
+                'For God so loved the world' AS words, 100 AS translation_id,
+                0 AS paragraph, 0 AS poetry_level, 0 AS line_break;
+            CREATE TABLE verses_html AS SELECT * FROM verses;",
+        )
+        .unwrap();
+        drop(file);
+
+        let conn = establish_connection(":memory:");
+        run_pending_migrations_in_directory(&conn, Path::new("./migrations"), &mut stdout())
+            .unwrap();
+        assert_eq!(attach_translations(&dir, &conn).unwrap(), 1);
+
+        let test = SwordDrill::translation("test", &conn).unwrap();
+        assert!(SwordDrill::translations(&conn).unwrap().len() > 1);
+        let reference = "John 3:16".parse().unwrap();
+        let (_, verses) =
+            SwordDrill::verses(&reference, &test, VerseFormat::PlainText, &conn).unwrap();
+        assert_eq!(verses.len(), 1);
+        assert_eq!(verses[0].words, "For God so loved the world");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,118 @@
+use std::fs;
+use std::path::Path;
+
+use diesel::prelude::*;
+use diesel::sql_types::Text;
+
+use crate::DbError;
+
+/// Writes a consistent snapshot of the database that a connection is open
+/// on to a new file, while other connections go on reading and writing it.
+///
+/// The snapshot is made with `VACUUM INTO`, which copies the database in
+/// one read transaction and leaves out its free pages. Diesel doesn't
+/// expose the raw connection that SQLite's backup API needs. Translation
+/// files attached to the connection aren't copied, and the file mustn't
+/// exist yet.
+pub fn backup(path: &Path, conn: &SqliteConnection) -> Result<(), DbError> {
+    diesel::sql_query("VACUUM main INTO ?")
+        .bind::<Text, _>(path.to_string_lossy().into_owned())
+        .execute(conn)
+        .map(|_| ())
+        .map_err(|e| DbError::Other {
+            cause: e.to_string(),
+        })
+}
+
+/// Replaces the database file at a path with a backup made with [backup],
+/// which should only be done while nothing has the database open.
+///
+/// The backup is checked and copied next to the database first, then moved
+/// over it, so a restore that fails part of the way leaves the database as
+/// it was. The database's write-ahead log is removed along with it, since
+/// it belongs to the replaced file.
+pub fn restore(backup_path: &Path, db_path: &Path) -> Result<(), DbError> {
+    let to_db_error = |e: &dyn ToString| DbError::Other {
+        cause: e.to_string(),
+    };
+    let conn =
+        SqliteConnection::establish(&backup_path.to_string_lossy()).map_err(|e| to_db_error(&e))?;
+    let check = diesel::select(diesel::dsl::sql::<Text>(
+        "(SELECT * FROM pragma_quick_check)",
+    ))
+    .get_result::<String>(&conn)
+    .map_err(|e| to_db_error(&e))?;
+    if check != "ok" {
+        return Err(DbError::Other {
+            cause: format!("the backup is corrupt: {}", check),
+        });
+    }
+
+    let restoring = db_path.with_extension("restoring");
+    let _ = fs::remove_file(&restoring);
+    backup(&restoring, &conn)?;
+    drop(conn);
+    for suffix in ["-wal", "-shm"] {
+        let mut log = db_path.as_os_str().to_owned();
+        log.push(suffix);
+        let _ = fs::remove_file(log);
+    }
+    fs::rename(&restoring, db_path).map_err(|e| to_db_error(&e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::io::stdout;
+
+    use diesel_migrations::run_pending_migrations_in_directory;
+
+    use super::*;
+    use crate::{establish_connection, verse_count};
+
+    #[test]
+    fn backed_up_and_restored() {
+        let dir = env::temp_dir().join("biblers-backup-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let db_path = dir.join("bible.db");
+        let backup_path = dir.join("backup.db");
+
+        let conn = establish_connection(db_path.to_str().unwrap());
+        run_pending_migrations_in_directory(&conn, Path::new("./migrations"), &mut stdout())
+            .unwrap();
+        // Rows of its own, so the test doesn't rely on what the migrations load
+        diesel::sql_query(
+            "INSERT INTO verses (book, chapter, verse, words, translation_id) VALUES
+                (1, 1, 1, 'In the beginning God created the heaven and the earth.', 1),
+                (43, 11, 35, 'Jesus wept.', 1)",
+        )
+        .execute(&conn)
+        .unwrap();
+        let verses = verse_count(&conn).unwrap();
+        assert!(verses >= 2);
+        backup(&backup_path, &conn).unwrap();
+        assert!(backup(&backup_path, &conn).is_err());
+        diesel::sql_query("DELETE FROM verses WHERE book = 1")
+            .execute(&conn)
+            .unwrap();
+        assert!(verse_count(&conn).unwrap() < verses);
+        drop(conn);
+
+        restore(&backup_path, &db_path).unwrap();
+        let conn = establish_connection(db_path.to_str().unwrap());
+        assert_eq!(verse_count(&conn).unwrap(), verses);
+        let words: Vec<String> = crate::schema::verses::table
+            .select(crate::schema::verses::words)
+            .filter(crate::schema::verses::book.eq(43))
+            .load(&conn)
+            .unwrap();
+        assert!(words.contains(&"Jesus wept.".to_string()));
+
+        fs::write(&backup_path, b"not a database").unwrap();
+        assert!(restore(&backup_path, &db_path).is_err());
+        assert_eq!(verse_count(&conn).unwrap(), verses);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+use std::io::BufRead;
+
+use diesel::prelude::*;
+
+use crate::models::Reference;
+use crate::{DbError, SwordDrill};
+
+/// Cross-reference read from a dataset, as the book, chapter, and verse it's
+/// from, the book, chapter, and verse it starts at and the chapter and verse
+/// it ends at, and its votes.
+type Row = (i32, i32, i32, i32, i32, i32, i32, i32, i32);
+
+/// Imports a cross-reference dataset (e.g. the Treasury of Scripture
+/// Knowledge), replacing any imported before. Returns the number of
+/// cross-references imported.
+///
+/// Each line has the verse a cross-reference is from (e.g. `Gen.1.1`), the
+/// verse or range of verses it points to (e.g. `Prov.8.22-Prov.8.30`), and
+/// optionally its votes, separated by tabs. A header line starting with
+/// `From` and lines starting with `#` are skipped.
+pub fn import_cross_references(
+    reader: impl BufRead,
+    conn: &SqliteConnection,
+) -> Result<usize, DbError> {
+    use crate::schema::cross_references as x;
+
+    let mut books = HashMap::new();
+    let mut book_id = |name: &str| -> Result<i32, DbError> {
+        if let Some(id) = books.get(name) {
+            return Ok(*id);
+        }
+        let id = SwordDrill::find_book(name, conn)?.id;
+        books.insert(name.to_owned(), id);
+        Ok(id)
+    };
+
+    let mut rows: Vec<Row> = vec![];
+    for (number, line) in reader.lines().enumerate() {
+        let line = line.map_err(|e| DbError::Other {
+            cause: e.to_string(),
+        })?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || (number == 0 && line.starts_with("From")) {
+            continue;
+        }
+
+        let mut fields = line.split('\t');
+        let from: Reference = fields.next().unwrap_or_default().parse()?;
+        let (to, to_end) = parse_range(fields.next().unwrap_or_default())?;
+        let votes = fields
+            .next()
+            .and_then(|votes| votes.trim().parse().ok())
+            .unwrap_or(0);
+
+        rows.push((
+            book_id(&from.book)?,
+            from.chapter,
+            first_verse(&from)?,
+            book_id(&to.book)?,
+            to.chapter,
+            first_verse(&to)?,
+            to_end.0,
+            to_end.1,
+            votes,
+        ));
+    }
+
+    conn.transaction(|| {
+        diesel::delete(x::table).execute(conn)?;
+        for row in &rows {
+            diesel::insert_into(x::table)
+                .values((
+                    x::book.eq(row.0),
+                    x::chapter.eq(row.1),
+                    x::verse.eq(row.2),
+                    x::to_book.eq(row.3),
+                    x::to_chapter.eq(row.4),
+                    x::to_verse.eq(row.5),
+                    x::to_end_chapter.eq(row.6),
+                    x::to_end_verse.eq(row.7),
+                    x::votes.eq(row.8),
+                ))
+                .execute(conn)?;
+        }
+        Ok(rows.len())
+    })
+    .map_err(|e: diesel::result::Error| DbError::Other {
+        cause: e.to_string(),
+    })
+}
+
+/// Parses the passage a cross-reference points to, along with the chapter
+/// and verse it ends at. Ranges are either references (e.g. `Prov.8.22-30`)
+/// or two verses joined by a hyphen (e.g. `Prov.8.22-Prov.8.30`).
+fn parse_range(s: &str) -> Result<(Reference, (i32, i32)), DbError> {
+    let (start, end) = match s.parse::<Reference>() {
+        Ok(reference) => (reference.to_owned(), reference),
+        Err(e) => match s.split_once('-') {
+            Some((start, end)) => (start.parse()?, end.parse()?),
+            None => return Err(e),
+        },
+    };
+    let end_verse = end
+        .verses
+        .as_ref()
+        .map(|verses| *verses.end())
+        .ok_or_else(|| invalid_reference(&end))?;
+    Ok((start, (end.last_chapter(), end_verse)))
+}
+
+/// Gets the verse a reference starts at, which cross-references need.
+fn first_verse(reference: &Reference) -> Result<i32, DbError> {
+    reference
+        .verses
+        .as_ref()
+        .map(|verses| *verses.start())
+        .ok_or_else(|| invalid_reference(reference))
+}
+
+fn invalid_reference(reference: &Reference) -> DbError {
+    DbError::InvalidReference {
+        reference: reference.to_string(),
+    }
+}
@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use diesel::prelude::*;
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+use unicode_normalization::char::is_combining_mark;
+
+use crate::models::Translation;
+use crate::{fold, DbError};
+
+/// Max number of words, and of phrases, to complete a search with.
+const SEARCH_COMPLETION_LIMIT: usize = 10;
+
+lazy_static! {
+    /// Dictionaries that have been built, by translation ID.
+    static ref DICTIONARIES: Mutex<HashMap<i32, Arc<Dictionary>>> = Mutex::new(HashMap::new());
+}
+
+/// Counts the single-character insertions, deletions, and substitutions
+/// needed to change one string into another (Levenshtein distance).
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                diagonal
+            } else {
+                1 + diagonal.min(above).min(row[j])
+            };
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+/// A word or phrase in lower case, and how often it's used.
+#[derive(Debug)]
+struct Entry {
+    text: String,
+    count: usize,
+}
+
+/// The words of a translation, and the phrases of two words that are used
+/// more than once, counted by how often they're used.
+///
+/// Both are keyed by their folded text, so looking them up ignores case
+/// and diacritics.
+#[derive(Debug, Default)]
+pub(crate) struct Dictionary {
+    words: HashMap<String, Entry>,
+    phrases: HashMap<String, Entry>,
+}
+
+impl Dictionary {
+    /// Counts the words and phrases in verses. Phrases are only counted
+    /// within a clause, so there's no punctuation between their words.
+    pub(crate) fn new<'a>(verses: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut words = HashMap::new();
+        let mut phrases = HashMap::new();
+        for verse in verses {
+            let mut previous: Option<String> = None;
+            for (separator, word) in split_words(verse) {
+                let word = word.to_lowercase();
+                count(&mut words, &word);
+                if let Some(previous) = previous {
+                    if separator.chars().all(char::is_whitespace) {
+                        count(&mut phrases, &format!("{} {}", previous, word));
+                    }
+                }
+                previous = Some(word);
+            }
+        }
+        // Phrases used only once aren't common, and would take a lot of memory
+        phrases.retain(|_, entry: &mut Entry| entry.count > 1);
+        Self { words, phrases }
+    }
+
+    /// Gets the dictionary built from the verses of a translation, building
+    /// it the first time it's needed.
+    pub(crate) fn of(
+        translation: &Translation,
+        conn: &SqliteConnection,
+    ) -> Result<Arc<Self>, DbError> {
+        use crate::schema::verses;
+
+        if let Some(dictionary) = DICTIONARIES.lock().unwrap().get(&translation.id) {
+            return Ok(Arc::clone(dictionary));
+        }
+
+        let verses: Vec<String> = verses::table
+            .select(verses::words)
+            .filter(verses::translation_id.eq(translation.id))
+            .load(conn)
+            .map_err(|e| DbError::Other {
+                cause: e.to_string(),
+            })?;
+        let dictionary = Arc::new(Self::new(verses.iter().map(String::as_str)));
+        DICTIONARIES
+            .lock()
+            .unwrap()
+            .insert(translation.id, Arc::clone(&dictionary));
+        Ok(dictionary)
+    }
+
+    /// Finds the most used word closest to a word that isn't in the
+    /// dictionary, if any is close enough to be what was meant.
+    fn correct(&self, word: &str) -> Option<&str> {
+        let word = fold(word);
+        if self.words.contains_key(&word) {
+            return None;
+        }
+
+        // Allow roughly one mistake for every three letters
+        let max_distance = (word.chars().count() / 3).max(1);
+        self.words
+            .iter()
+            .map(|(key, entry)| (edit_distance(&word, key), entry))
+            .filter(|(distance, _)| *distance <= max_distance)
+            .min_by(|(a, a_entry), (b, b_entry)| {
+                a.cmp(b)
+                    .then(b_entry.count.cmp(&a_entry.count))
+                    .then(a_entry.text.cmp(&b_entry.text))
+            })
+            .map(|(_, entry)| entry.text.as_str())
+    }
+
+    /// Suggests a search query with its misspelled words corrected.
+    ///
+    /// Operators and prefixes (e.g. `bapti*`) are left as they are, and
+    /// `None` is returned when there's nothing to correct.
+    pub(crate) fn suggest(&self, query: &str) -> Option<String> {
+        lazy_static! {
+            static ref WORD: Regex = Regex::new(r"[\p{L}\p{M}]+(\*?)").unwrap();
+        }
+
+        let mut corrected = false;
+        let suggestion = WORD.replace_all(query, |caps: &Captures| {
+            let word = &caps[0];
+            let is_operator = matches!(word, "AND" | "OR" | "NOT" | "NEAR");
+            if is_operator || !caps[1].is_empty() {
+                return word.to_string();
+            }
+            match self.correct(word) {
+                Some(correction) => {
+                    corrected = true;
+                    correction.to_string()
+                }
+                None => word.to_string(),
+            }
+        });
+        corrected.then(|| suggestion.into_owned())
+    }
+
+    /// Completes the start of a search with the most used words and
+    /// phrases starting with it, up to [SEARCH_COMPLETION_LIMIT] of each.
+    ///
+    /// Only phrases are completed once a space has been typed.
+    pub(crate) fn complete(&self, prefix: &str) -> (Vec<&str>, Vec<&str>) {
+        // Words in phrases are separated by single spaces
+        let mut folded = fold(prefix)
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        if folded.is_empty() {
+            return (vec![], vec![]);
+        }
+        if prefix.ends_with(char::is_whitespace) {
+            folded.push(' ');
+        }
+
+        let words = if folded.contains(' ') {
+            vec![]
+        } else {
+            most_used(&self.words, &folded)
+        };
+        (words, most_used(&self.phrases, &folded))
+    }
+}
+
+/// Gets the most used entries with keys starting with a prefix.
+fn most_used<'a>(entries: &'a HashMap<String, Entry>, prefix: &str) -> Vec<&'a str> {
+    let mut matches: Vec<&Entry> = entries
+        .iter()
+        .filter(|(key, _)| key.starts_with(prefix))
+        .map(|(_, entry)| entry)
+        .collect();
+    matches.sort_by(|a, b| b.count.cmp(&a.count).then(a.text.cmp(&b.text)));
+    matches
+        .into_iter()
+        .take(SEARCH_COMPLETION_LIMIT)
+        .map(|entry| entry.text.as_str())
+        .collect()
+}
+
+/// Counts a use of a word or phrase.
+fn count(entries: &mut HashMap<String, Entry>, text: &str) {
+    entries
+        .entry(fold(text))
+        .or_insert_with(|| Entry {
+            text: text.to_string(),
+            count: 0,
+        })
+        .count += 1;
+}
+
+/// Splits text into its words, each with the text separating it from the
+/// word before.
+fn split_words(text: &str) -> Vec<(&str, &str)> {
+    let is_letter = |c: char| c.is_alphanumeric() || is_combining_mark(c);
+    let mut words = vec![];
+    let mut rest = text;
+    while let Some(start) = rest.find(is_letter) {
+        let end = rest[start..]
+            .find(|c: char| !is_letter(c))
+            .map_or(rest.len(), |len| start + len);
+        words.push((&rest[..start], &rest[start..end]));
+        rest = &rest[end..];
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distances() {
+        assert_eq!(edit_distance("shepherd", "shepherd"), 0);
+        assert_eq!(edit_distance("shephred", "shepherd"), 2);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+
+    #[test]
+    fn suggestions() {
+        let dictionary = Dictionary::new([
+            "The LORD is my shepherd; I shall not want.",
+            "I am the good shepherd: the good shepherd giveth his life for the sheep.",
+            "And the sheep heard his voice.",
+        ]);
+        assert_eq!(
+            dictionary.suggest("the shepard"),
+            Some("the shepherd".to_string())
+        );
+        // Corrections are lower case
+        assert_eq!(dictionary.suggest("Sheeph"), Some("sheep".to_string()));
+        assert_eq!(
+            dictionary.suggest("\"Lord is my shepard\" -sheeq"),
+            Some("\"Lord is my shepherd\" -sheep".to_string())
+        );
+        assert_eq!(dictionary.suggest("the shepherd"), None);
+        assert_eq!(
+            dictionary.suggest("NEAR(goob sheph*, 5)"),
+            Some("NEAR(good sheph*, 5)".to_string())
+        );
+        assert_eq!(dictionary.suggest("qwxyz"), None);
+    }
+
+    #[test]
+    fn completions() {
+        let dictionary = Dictionary::new([
+            "The LORD is my shepherd; I shall not want.",
+            "I am the good shepherd: the good shepherd giveth his life for the sheep.",
+            "And the sheep heard his voice.",
+        ]);
+        let (words, phrases) = dictionary.complete("SHE");
+        assert_eq!(words, vec!["shepherd", "sheep"]);
+        // Phrases used only once aren't kept
+        assert!(phrases.is_empty());
+
+        let (words, phrases) = dictionary.complete(" the  ");
+        assert!(words.is_empty());
+        assert_eq!(phrases, vec!["the good", "the sheep"]);
+
+        let (words, phrases) = dictionary.complete("go");
+        assert_eq!(words, vec!["good"]);
+        assert_eq!(phrases, vec!["good shepherd"]);
+
+        assert_eq!(dictionary.complete(" "), (vec![], vec![]));
+    }
+}
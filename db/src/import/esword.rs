@@ -0,0 +1,255 @@
+use std::iter::Peekable;
+use std::path::Path;
+use std::str::Chars;
+
+use diesel::prelude::*;
+use diesel::sql_types::{Integer, Nullable, Text};
+
+use super::{ImportedText, VerseBuilder, LAST_CANONICAL_BOOK};
+use crate::DbError;
+
+#[derive(QueryableByName)]
+struct Scripture {
+    #[sql_type = "Integer"]
+    book: i32,
+    #[sql_type = "Integer"]
+    chapter: i32,
+    #[sql_type = "Integer"]
+    verse: i32,
+    #[sql_type = "Nullable<Text>"]
+    scripture: Option<String>,
+}
+
+#[derive(QueryableByName)]
+struct Details {
+    #[sql_type = "Nullable<Text>"]
+    description: Option<String>,
+}
+
+/// Reads an e-Sword Bible (`.bblx`), which is an SQLite database.
+///
+/// Books are known by their numbers, and those outside the Protestant canon
+/// are reported as unmapped, since e-Sword numbers them differently from
+/// the database. The RTF of the verses is read for their words and italics
+/// and bold, leaving out superscripts (Strong's numbers). The translation's
+/// name is read from its details, but e-Sword doesn't give its language.
+pub(super) fn read(path: &Path) -> Result<ImportedText, DbError> {
+    let to_db_error = |e: &dyn std::fmt::Display| DbError::Import {
+        cause: format!("{} couldn't be read: {}", path.display(), e),
+    };
+    // Connecting would create a database that isn't there
+    if !path.is_file() {
+        return Err(to_db_error(&"it isn't a file"));
+    }
+    let file = SqliteConnection::establish(&path.to_string_lossy()).map_err(|e| to_db_error(&e))?;
+    let scriptures = diesel::sql_query(
+        "SELECT Book AS book, Chapter AS chapter, Verse AS verse, Scripture AS scripture
+        FROM Bible ORDER BY Book, Chapter, Verse",
+    )
+    .load::<Scripture>(&file)
+    .map_err(|e| to_db_error(&e))?;
+    // Not every Bible has its details
+    let details = diesel::sql_query("SELECT Description AS description FROM Details LIMIT 1")
+        .get_result::<Details>(&file)
+        .ok();
+
+    let mut text = ImportedText::default();
+    let mut builder = VerseBuilder::new(&mut text);
+    for scripture in scriptures {
+        match scripture.book {
+            1..=LAST_CANONICAL_BOOK => {
+                builder.start_verse(scripture.book, scripture.chapter, scripture.verse);
+                add_rtf(&scripture.scripture.unwrap_or_default(), &mut builder);
+            }
+            book => {
+                builder.end_verse();
+                builder.unmapped(&format!("Book {}", book));
+            }
+        }
+    }
+    builder.end_verse();
+    text.name = details.and_then(|d| d.description);
+    Ok(text)
+}
+
+/// Formatting of a group of RTF.
+#[derive(Clone, Copy, Default, PartialEq)]
+struct Style {
+    italic: bool,
+    bold: bool,
+    /// Whether the group is a superscript, which is left out.
+    superscript: bool,
+}
+
+/// Adds the words of a verse marked up with RTF to the verse being built.
+fn add_rtf(rtf: &str, builder: &mut VerseBuilder) {
+    let mut groups = vec![Style::default()];
+    // Formatting of the tags open in the verse, which may lag behind the
+    // groups' until there are words to format
+    let mut open = Style::default();
+    let mut words = String::new();
+    let mut chars = rtf.chars().peekable();
+    while let Some(c) = chars.next() {
+        let style = *groups.last().unwrap_or(&Style::default());
+        match c {
+            '{' => groups.push(style),
+            '}' => {
+                if groups.len() > 1 {
+                    groups.pop();
+                }
+            }
+            '\\' => match chars.peek().copied() {
+                Some(c @ ('\\' | '{' | '}')) => {
+                    chars.next();
+                    words.push(c);
+                }
+                Some('\'') => {
+                    chars.next();
+                    let hex: String = chars.by_ref().take(2).collect();
+                    if let Ok(byte) = u8::from_str_radix(&hex, 16) {
+                        words.push(byte as char);
+                    }
+                }
+                Some(c) if c.is_ascii_alphabetic() => {
+                    let (word, parameter) = control_word(&mut chars);
+                    let style = groups.last_mut().unwrap();
+                    match word.as_str() {
+                        "i" => style.italic = parameter != Some(0),
+                        "b" => style.bold = parameter != Some(0),
+                        "super" => style.superscript = true,
+                        "nosupersub" => style.superscript = false,
+                        "plain" => *style = Style::default(),
+                        "par" | "line" | "tab" => words.push(' '),
+                        "u" => {
+                            let code = parameter.unwrap_or_default();
+                            let code = if code < 0 { code + 0x10000 } else { code };
+                            words.extend(char::from_u32(code as u32));
+                            // The character for readers without Unicode
+                            if chars
+                                .peek()
+                                .map_or(false, |c| !matches!(c, '\\' | '{' | '}'))
+                            {
+                                chars.next();
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                _ => {}
+            },
+            '\r' | '\n' => {}
+            c => {
+                if style != open && !style.superscript {
+                    flush(&mut words, builder);
+                    restyle(open, style, builder);
+                    open = style;
+                }
+                if !style.superscript {
+                    words.push(c);
+                }
+            }
+        }
+    }
+    flush(&mut words, builder);
+}
+
+/// Reads a control word after its backslash, with its numeric parameter,
+/// and the space that ends it.
+fn control_word(chars: &mut Peekable<Chars>) -> (String, Option<i32>) {
+    let mut word = String::new();
+    while let Some(c) = chars.peek().copied().filter(char::is_ascii_alphabetic) {
+        chars.next();
+        word.push(c);
+    }
+    let mut parameter = String::new();
+    while let Some(c) = chars
+        .peek()
+        .copied()
+        .filter(|c| c.is_ascii_digit() || (*c == '-' && parameter.is_empty()))
+    {
+        chars.next();
+        parameter.push(c);
+    }
+    if chars.peek() == Some(&' ') {
+        chars.next();
+    }
+    (word, parameter.parse().ok())
+}
+
+/// Adds the words read so far to the verse being built.
+fn flush(words: &mut String, builder: &mut VerseBuilder) {
+    if !words.is_empty() {
+        builder.words(words);
+        words.clear();
+    }
+}
+
+/// Closes the HTML tags opened for the formatting of the words before, and
+/// opens the ones for the formatting of the words after.
+fn restyle(open: Style, style: Style, builder: &mut VerseBuilder) {
+    if open.italic {
+        builder.close_tag("em");
+    }
+    if open.bold {
+        builder.close_tag("strong");
+    }
+    if style.italic {
+        builder.open_tag("em");
+    }
+    if style.bold {
+        builder.open_tag("strong");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use diesel::connection::SimpleConnection;
+
+    use super::*;
+
+    #[test]
+    fn read_bible() {
+        let dir = env::temp_dir().join("biblers-esword-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("test.bblx");
+        let file = SqliteConnection::establish(path.to_str().unwrap()).unwrap();
+        file.batch_execute(
+            r"CREATE TABLE Details (Description NVARCHAR(255), Abbreviation NVARCHAR(50));
+            INSERT INTO Details VALUES ('Test Version', 'TEST');
+            CREATE TABLE Bible (Book INT, Chapter INT, Verse INT, Scripture TEXT);
+            INSERT INTO Bible VALUES
+                (1, 1, 2, 'And the earth was {\i without} form\par'),
+                (1, 1, 1, 'In{\super 7225} the beginning'),
+                (43, 11, 35, 'Jesus {\b wept}.\u8212?'),
+                (70, 1, 1, 'Wisdom');",
+        )
+        .unwrap();
+        drop(file);
+
+        let text = read(&path).unwrap();
+        assert_eq!(text.name.as_deref(), Some("Test Version"));
+        assert_eq!(text.unmapped, ["Book 70"]);
+        assert_eq!(
+            text.verses
+                .iter()
+                .map(|v| (v.book, v.chapter, v.verse, v.words.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                (1, 1, 1, "In the beginning"),
+                (1, 1, 2, "And the earth was without form"),
+                (43, 11, 35, "Jesus wept.\u{2014}"),
+            ]
+        );
+        assert_eq!(
+            text.verses[1].html,
+            "And the earth was <em>without</em> form"
+        );
+        assert_eq!(text.verses[2].html, "Jesus <strong>wept</strong>.\u{2014}");
+
+        assert!(read(&dir.join("missing.bblx")).is_err());
+    }
+}
@@ -0,0 +1,198 @@
+use std::fs;
+use std::path::Path;
+
+use serde_derive::Deserialize;
+
+use super::{
+    collapse_whitespace, escape_html, heading_html, osis, usfm, ImportedText, ImportedVerse,
+};
+use crate::DbError;
+
+/// Translation in the JSON format.
+#[derive(Deserialize)]
+struct Bible {
+    name: Option<String>,
+    language: Option<String>,
+    versification: Option<String>,
+    books: Vec<Book>,
+}
+
+#[derive(Deserialize)]
+struct Book {
+    book: BookId,
+    chapters: Vec<Chapter>,
+}
+
+/// Book of the JSON format, by its ID or by its OSIS or USFM name.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BookId {
+    Number(i32),
+    Name(String),
+}
+
+#[derive(Deserialize)]
+struct Chapter {
+    chapter: Option<i32>,
+    verses: Vec<Verse>,
+}
+
+#[derive(Deserialize)]
+struct Verse {
+    verse: Option<i32>,
+    text: String,
+    html: Option<String>,
+    heading: Option<String>,
+    #[serde(default)]
+    paragraph: bool,
+    #[serde(default)]
+    poetry_level: i32,
+    #[serde(default)]
+    line_break: bool,
+}
+
+/// Reads a translation in the JSON format: an object with the
+/// translation's optional `name`, `language` and `versification`, and its
+/// `books`, each with its `book` and `chapters`, each with its optional
+/// `chapter` number and `verses`.
+///
+/// Books are known by their IDs, OSIS names (e.g. `John`) or USFM codes
+/// (e.g. `JHN`). Chapters and verses without numbers follow the ones before
+/// them. Each verse has its `text`, and optionally its `verse` number, its
+/// `html`, a `heading` before it, and whether it starts a `paragraph`, its
+/// `poetry_level` and whether a `line_break` follows it.
+pub(super) fn read(path: &Path) -> Result<ImportedText, DbError> {
+    let json = fs::read_to_string(path).map_err(|e| DbError::Import {
+        cause: format!("{} couldn't be read: {}", path.display(), e),
+    })?;
+    parse(&json)
+}
+
+/// Parses a translation in the JSON format.
+fn parse(json: &str) -> Result<ImportedText, DbError> {
+    let bible: Bible = serde_json::from_str(json).map_err(|e| DbError::Import {
+        cause: format!("the JSON is invalid: {}", e),
+    })?;
+    let mut text = ImportedText {
+        name: bible.name,
+        language: bible.language,
+        versification: bible.versification,
+        ..ImportedText::default()
+    };
+
+    for book in bible.books {
+        let id = match &book.book {
+            BookId::Number(number) if (1..=osis::BOOKS.len() as i32).contains(number) => {
+                Some(*number)
+            }
+            BookId::Number(_) => None,
+            BookId::Name(name) => osis::BOOKS
+                .iter()
+                .position(|b| b.eq_ignore_ascii_case(name))
+                .or_else(|| {
+                    usfm::BOOKS
+                        .iter()
+                        .position(|b| b.eq_ignore_ascii_case(name))
+                })
+                .map(|i| i as i32 + 1),
+        };
+        let id = match id {
+            Some(id) => id,
+            None => {
+                let place = match book.book {
+                    BookId::Number(number) => format!("Book {}", number),
+                    BookId::Name(name) => name,
+                };
+                if !text.unmapped.contains(&place) {
+                    text.unmapped.push(place);
+                }
+                continue;
+            }
+        };
+
+        let mut chapter_number = 0;
+        for chapter in book.chapters {
+            chapter_number = chapter.chapter.unwrap_or(chapter_number + 1);
+            let mut verse_number = 0;
+            for verse in chapter.verses {
+                verse_number = verse.verse.unwrap_or(verse_number + 1);
+                let words = collapse_whitespace(&verse.text);
+                if words.is_empty() {
+                    continue;
+                }
+                let html = verse.html.unwrap_or_else(|| escape_html(&words));
+                let heading = verse
+                    .heading
+                    .as_deref()
+                    .map(collapse_whitespace)
+                    .filter(|heading| !heading.is_empty())
+                    .map_or(String::new(), |heading| heading_html(&heading));
+                text.verses.push(ImportedVerse {
+                    book: id,
+                    chapter: chapter_number,
+                    verse: verse_number,
+                    words,
+                    html: format!("{}{}", heading, html),
+                    paragraph: verse.paragraph,
+                    poetry_level: verse.poetry_level.clamp(0, 3),
+                    line_break: verse.line_break,
+                });
+            }
+        }
+    }
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsed() {
+        let text = parse(
+            r#"{
+                "name": "Test Version",
+                "language": "en",
+                "books": [
+                    {
+                        "book": "John",
+                        "chapters": [
+                            {
+                                "chapter": 11,
+                                "verses": [
+                                    {
+                                        "verse": 35,
+                                        "text": "Jesus wept.",
+                                        "heading": "Jesus Weeps",
+                                        "paragraph": true
+                                    },
+                                    { "text": "Then said the Jews, Behold how he loved him!" }
+                                ]
+                            }
+                        ]
+                    },
+                    { "book": "TOB", "chapters": [{ "verses": [{ "text": "Tobit" }] }] },
+                    { "book": 90, "chapters": [] }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(text.name.as_deref(), Some("Test Version"));
+        assert_eq!(text.versification, None);
+        assert_eq!(text.unmapped, ["Book 90"]);
+        assert_eq!(
+            text.verses
+                .iter()
+                .map(|v| (v.book, v.chapter, v.verse, v.paragraph))
+                .collect::<Vec<_>>(),
+            [(43, 11, 35, true), (43, 11, 36, false), (67, 1, 1, false)]
+        );
+        assert_eq!(
+            text.verses[0].html,
+            "<span class=\"section-heading\">Jesus Weeps</span> Jesus wept."
+        );
+
+        assert!(parse(r#"{ "books": [{ "book": "John" }] }"#).is_err());
+    }
+}
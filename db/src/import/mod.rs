@@ -0,0 +1,620 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use diesel::prelude::*;
+use diesel::sql_types::{Integer, Text};
+
+use crate::sword_drill::STANDARD_VERSIFICATION;
+use crate::DbError;
+
+mod esword;
+mod json;
+mod osis;
+mod sword;
+mod theword;
+mod usfm;
+mod zefania;
+
+/// ID of the King James Version, which other translations are checked
+/// against, so it can't be replaced by an import once it has verses.
+const STANDARD_TRANSLATION_ID: i32 = 1;
+
+/// Directory of the bundled public domain translations that databases
+/// without verses are seeded from, from the root of the workspace.
+const SEED_DIR: &str = "./db/seed";
+
+/// Abbreviations of the translations that can be bundled, each in a file
+/// named after it in the JSON format: the King James Version and the World
+/// English Bible.
+const SEED_TRANSLATIONS: [&str; 2] = ["kjv", "web"];
+
+/// Last book of the Old Testament.
+const LAST_OLD_TESTAMENT_BOOK: i32 = 39;
+
+/// Last book of the Protestant canon. Formats that number books number
+/// these the same way as the database, and the standard versification
+/// has no others.
+const LAST_CANONICAL_BOOK: i32 = 66;
+
+/// Languages written from right to left, by their ISO 639-1 (or 639-2)
+/// codes.
+const RTL_LANGUAGES: [&str; 8] = ["ar", "arc", "dv", "fa", "he", "ps", "syr", "ur"];
+
+/// Formats that translations can be imported from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImportFormat {
+    /// Unified Standard Format Markers, which most translation projects
+    /// distribute as a file for each book.
+    Usfm,
+    /// Open Scripture Information Standard XML, which CrossWire and others
+    /// publish public domain texts in.
+    Osis,
+    /// Zefania XML, which many free Bibles are shared in.
+    Zefania,
+    /// Compressed SWORD modules, which CrossWire's library of Bibles is made
+    /// up of.
+    Sword,
+    /// e-Sword Bibles (`.bblx`), which are SQLite databases.
+    ESword,
+    /// theWord Bibles (`.ont`, `.ot` and `.nt`), which are text files with a
+    /// line for each verse.
+    TheWord,
+    /// A simple JSON format, of books, their chapters and their verses, for
+    /// texts that aren't in any of the others.
+    Json,
+}
+
+impl FromStr for ImportFormat {
+    type Err = DbError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "usfm" => Ok(Self::Usfm),
+            "osis" => Ok(Self::Osis),
+            "zefania" => Ok(Self::Zefania),
+            "sword" => Ok(Self::Sword),
+            "esword" | "e-sword" => Ok(Self::ESword),
+            "theword" => Ok(Self::TheWord),
+            "json" => Ok(Self::Json),
+            _ => Err(DbError::Import {
+                cause: format!("'{}' isn't a format that can be imported", s),
+            }),
+        }
+    }
+}
+
+/// Verse read from a translation being imported.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ImportedVerse {
+    pub book: i32,
+    pub chapter: i32,
+    pub verse: i32,
+    /// Plain text of the verse.
+    pub words: String,
+    /// Text of the verse marked up with HTML, along with the headings before
+    /// it and its footnotes.
+    pub html: String,
+    pub paragraph: bool,
+    pub poetry_level: i32,
+    pub line_break: bool,
+}
+
+/// Translation read from a file to import, before it's stored.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ImportedText {
+    /// Name of the translation, if the file gives it.
+    pub name: Option<String>,
+    /// Language of the translation (e.g. `en`), if the file gives it.
+    pub language: Option<String>,
+    /// Versification scheme of the translation, if the file gives it.
+    pub versification: Option<String>,
+    pub verses: Vec<ImportedVerse>,
+    /// Places in the file (e.g. the code of a book) with verses that
+    /// couldn't be mapped to the books of the Bible, which were left out.
+    pub unmapped: Vec<String>,
+}
+
+/// Reads a translation to import from a file, or from a directory of files
+/// for formats with a file for each book or module. SWORD modules and
+/// theWord Bibles are read in the versification of the standard translation
+/// in the database.
+pub fn read_translation(
+    path: &Path,
+    format: ImportFormat,
+    conn: &SqliteConnection,
+) -> Result<ImportedText, DbError> {
+    match format {
+        ImportFormat::Usfm => usfm::read(path),
+        ImportFormat::Osis => osis::read(path),
+        ImportFormat::Zefania => zefania::read(path),
+        ImportFormat::Sword => sword::read(path, conn),
+        ImportFormat::ESword => esword::read(path),
+        ImportFormat::TheWord => theword::read(path, conn),
+        ImportFormat::Json => json::read(path),
+    }
+}
+
+/// Stores a translation read with [read_translation] under an abbreviation,
+/// replacing its verses if it's been imported before, and returns the
+/// number of verses imported.
+///
+/// The translation is named after its abbreviation, in English and in the
+/// standard versification scheme unless the text says otherwise. Its
+/// verses are stored in canonical order in both formats and in the full
+/// text search index, which needs the `index_text` function that
+/// [establish_connection](crate::establish_connection) registers. A search
+/// index built with [build_search_index](crate::build_search_index) should
+/// be built again afterwards, and running servers restarted. The King James
+/// Version can only be imported into a database that hasn't any of its
+/// verses.
+pub fn import_translation(
+    abbreviation: &str,
+    text: &ImportedText,
+    conn: &SqliteConnection,
+) -> Result<usize, DbError> {
+    use crate::schema::translations as t;
+    use crate::schema::verses as v;
+    use crate::schema::verses_html as h;
+
+    let abbreviation = abbreviation.to_lowercase();
+    let name = text
+        .name
+        .clone()
+        .unwrap_or_else(|| abbreviation.to_uppercase());
+    let language = text.language.clone().unwrap_or_else(|| "en".to_string());
+    let direction = match RTL_LANGUAGES.contains(&language.split('-').next().unwrap_or_default()) {
+        true => "rtl",
+        false => "ltr",
+    };
+    let versification = text
+        .versification
+        .clone()
+        .unwrap_or_else(|| STANDARD_VERSIFICATION.to_string());
+    let imported_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+
+    let mut verses: Vec<&ImportedVerse> = text.verses.iter().collect();
+    verses.sort_by_key(|verse| (verse.book, verse.chapter, verse.verse));
+
+    conn.transaction(|| {
+        let standard_verses: i64 = v::table
+            .filter(v::translation_id.eq(STANDARD_TRANSLATION_ID))
+            .count()
+            .get_result(conn)?;
+        let existing = t::table
+            .select(t::id)
+            .filter(t::abbreviation.eq(&abbreviation))
+            .first::<i32>(conn)
+            .optional()?;
+        let id = match existing {
+            Some(STANDARD_TRANSLATION_ID) if standard_verses > 0 => return Ok(None),
+            Some(id) => {
+                diesel::delete(v::table.filter(v::translation_id.eq(id))).execute(conn)?;
+                diesel::delete(h::table.filter(h::translation_id.eq(id))).execute(conn)?;
+                diesel::sql_query("DELETE FROM verses_fts WHERE translation_id = ?")
+                    .bind::<Integer, _>(id)
+                    .execute(conn)?;
+                diesel::update(t::table.filter(t::id.eq(id)))
+                    .set((
+                        t::name.eq(&name),
+                        t::language.eq(&language),
+                        t::direction.eq(direction),
+                        t::versification.eq(&versification),
+                        t::imported_at.eq(imported_at),
+                    ))
+                    .execute(conn)?;
+                id
+            }
+            None => {
+                let id = t::table
+                    .select(diesel::dsl::max(t::id))
+                    .first::<Option<i32>>(conn)?
+                    .unwrap_or(0)
+                    + 1;
+                diesel::insert_into(t::table)
+                    .values((
+                        t::id.eq(id),
+                        t::abbreviation.eq(&abbreviation),
+                        t::name.eq(&name),
+                        t::language.eq(&language),
+                        t::direction.eq(direction),
+                        t::versification.eq(&versification),
+                        t::imported_at.eq(imported_at),
+                    ))
+                    .execute(conn)?;
+                id
+            }
+        };
+
+        for verse in &verses {
+            diesel::insert_into(v::table)
+                .values((
+                    v::book.eq(verse.book),
+                    v::chapter.eq(verse.chapter),
+                    v::verse.eq(verse.verse),
+                    v::words.eq(&verse.words),
+                    v::translation_id.eq(id),
+                    v::paragraph.eq(verse.paragraph),
+                    v::poetry_level.eq(verse.poetry_level),
+                    v::line_break.eq(verse.line_break),
+                ))
+                .execute(conn)?;
+            diesel::insert_into(h::table)
+                .values((
+                    h::book.eq(verse.book),
+                    h::chapter.eq(verse.chapter),
+                    h::verse.eq(verse.verse),
+                    h::words.eq(&verse.html),
+                    h::translation_id.eq(id),
+                    h::paragraph.eq(verse.paragraph),
+                    h::poetry_level.eq(verse.poetry_level),
+                    h::line_break.eq(verse.line_break),
+                ))
+                .execute(conn)?;
+        }
+        diesel::sql_query(
+            "INSERT INTO verses_fts (rowid, book, chapter, verse, words, translation_id)
+            SELECT id, book, chapter, verse, index_text(words, ?), translation_id
+            FROM verses WHERE translation_id = ?",
+        )
+        .bind::<Text, _>(language.as_str())
+        .bind::<Integer, _>(id)
+        .execute(conn)?;
+        Ok(Some(verses.len()))
+    })
+    .map_err(|e: diesel::result::Error| DbError::Other {
+        cause: e.to_string(),
+    })?
+    .ok_or(DbError::Import {
+        cause: "the King James Version can't be replaced, since other translations are \
+            checked against it"
+            .to_string(),
+    })
+}
+
+/// Imports the bundled translations into a database that hasn't any
+/// verses, as one whose tables were made without the migrations that load
+/// the King James Version hasn't, so it serves the Bible straight away.
+/// Returns the number of verses imported, which is 0 if the database
+/// already had some.
+pub fn seed_verses(conn: &SqliteConnection) -> Result<usize, DbError> {
+    seed_verses_from(Path::new(SEED_DIR), conn)
+}
+
+/// Imports the translations in a directory of seeds into a database that
+/// hasn't any verses. Translations without a file are skipped.
+fn seed_verses_from(dir: &Path, conn: &SqliteConnection) -> Result<usize, DbError> {
+    if crate::verse_count(conn)? > 0 {
+        return Ok(0);
+    }
+    let mut seeded = 0;
+    for abbreviation in SEED_TRANSLATIONS {
+        let path = dir.join(format!("{}.json", abbreviation));
+        if path.is_file() {
+            let text = read_translation(&path, ImportFormat::Json, conn)?;
+            seeded += import_translation(abbreviation, &text, conn)?;
+        }
+    }
+    Ok(seeded)
+}
+
+/// Gets the number of verses in each chapter of each book of the standard
+/// versification, from the standard translation, for the formats that know
+/// verses by their places in it rather than by their references.
+fn standard_chapters(conn: &SqliteConnection) -> Result<BTreeMap<i32, Vec<i32>>, DbError> {
+    use crate::schema::verses as v;
+
+    let keys = v::table
+        .select((v::book, v::chapter, v::verse))
+        .filter(v::translation_id.eq(STANDARD_TRANSLATION_ID))
+        .filter(v::book.le(LAST_CANONICAL_BOOK))
+        .load::<(i32, i32, i32)>(conn)
+        .map_err(|e| DbError::Other {
+            cause: e.to_string(),
+        })?;
+    let mut chapters: BTreeMap<i32, Vec<i32>> = BTreeMap::new();
+    for (book, chapter, verse) in keys {
+        let verses = chapters.entry(book).or_default();
+        if verses.len() < chapter as usize {
+            verses.resize(chapter as usize, 0);
+        }
+        let count = &mut verses[chapter as usize - 1];
+        *count = (*count).max(verse);
+    }
+    Ok(chapters)
+}
+
+/// Builds the verses of a translation from their words and what's around
+/// them, read in order, for the formats that mark up the headings,
+/// paragraphs and notes of a text rather than storing its verses one by
+/// one.
+struct VerseBuilder<'a> {
+    text: &'a mut ImportedText,
+    verse: Option<ImportedVerse>,
+    /// HTML of the headings before the next verse.
+    headings: String,
+    /// Whether the next line starts a paragraph, and how far it's indented
+    /// as poetry. They apply to the verse the line starts, or the one being
+    /// read if it hasn't any words yet, and otherwise the line is broken
+    /// within the verse.
+    layout: Option<(bool, i32)>,
+    /// HTML tags open in the verse being read.
+    tags: Vec<&'static str>,
+}
+
+impl<'a> VerseBuilder<'a> {
+    fn new(text: &'a mut ImportedText) -> Self {
+        Self {
+            text,
+            verse: None,
+            headings: String::new(),
+            layout: None,
+            tags: vec![],
+        }
+    }
+
+    /// Notes a place with verses that couldn't be mapped to a book, once.
+    fn unmapped(&mut self, place: &str) {
+        if !self.text.unmapped.iter().any(|p| p == place) {
+            self.text.unmapped.push(place.to_string());
+        }
+    }
+
+    /// Starts reading a verse, after the one being read.
+    fn start_verse(&mut self, book: i32, chapter: i32, verse: i32) {
+        self.end_verse();
+        let (paragraph, poetry_level) = self.layout.take().unwrap_or_default();
+        self.verse = Some(ImportedVerse {
+            book,
+            chapter,
+            verse,
+            html: std::mem::take(&mut self.headings),
+            paragraph,
+            poetry_level,
+            ..ImportedVerse::default()
+        });
+    }
+
+    /// Adds the verse being read, if it has any words, closing the tags that
+    /// are still open in it.
+    fn end_verse(&mut self) {
+        if let Some(mut verse) = self.verse.take() {
+            for tag in self.tags.drain(..).rev() {
+                verse.html.push_str(&format!("</{}>", tag));
+            }
+            verse.words = collapse_whitespace(&verse.words);
+            verse.html = collapse_whitespace(&verse.html);
+            if !verse.words.is_empty() {
+                self.text.verses.push(verse);
+            }
+        }
+    }
+
+    /// Adds words to the verse being read. Words outside of verses are left
+    /// out.
+    fn words(&mut self, words: &str) {
+        let verse = match &mut self.verse {
+            Some(verse) => verse,
+            None => return,
+        };
+        if let Some((paragraph, poetry_level)) = self.layout {
+            if !words.trim().is_empty() {
+                self.layout = None;
+                if verse.words.trim().is_empty() {
+                    verse.paragraph |= paragraph;
+                    verse.poetry_level = poetry_level;
+                } else {
+                    verse.html.push_str("<br>");
+                }
+            }
+        }
+        verse.words.push_str(words);
+        verse.html.push_str(&escape_html(words));
+    }
+
+    /// Opens an HTML tag in the verse being read, if there is one.
+    fn open_tag(&mut self, tag: &'static str) {
+        if let Some(verse) = &mut self.verse {
+            verse.html.push_str(&format!("<{}>", tag));
+            self.tags.push(tag);
+        }
+    }
+
+    /// Closes an HTML tag opened in the verse being read, along with the tags
+    /// opened after it.
+    fn close_tag(&mut self, tag: &'static str) {
+        if let (Some(i), Some(verse)) = (self.tags.iter().rposition(|t| *t == tag), &mut self.verse)
+        {
+            for tag in self.tags.drain(i..).rev() {
+                verse.html.push_str(&format!("</{}>", tag));
+            }
+        }
+    }
+
+    /// Adds a heading, which goes before the next verse, or in the verse
+    /// being read if it hasn't any words yet (e.g. a psalm's title after
+    /// its first verse starts).
+    fn heading(&mut self, heading: &str) {
+        let heading = collapse_whitespace(heading);
+        if heading.is_empty() {
+            return;
+        }
+        let html = heading_html(&heading);
+        match &mut self.verse {
+            Some(verse) if verse.words.trim().is_empty() => verse.html.push_str(&html),
+            _ => self.headings.push_str(&html),
+        }
+    }
+
+    /// Adds a footnote to the verse being read.
+    fn footnote(&mut self, note: &str) {
+        let note = collapse_whitespace(note);
+        if let (false, Some(verse)) = (note.is_empty(), &mut self.verse) {
+            verse.html.push_str(&format!(
+                "<sup class=\"footnote\" title=\"{}\">*</sup>",
+                escape_html(&note)
+            ));
+        }
+    }
+
+    /// Starts a paragraph of prose.
+    fn paragraph(&mut self) {
+        self.layout = Some((true, 0));
+    }
+
+    /// Starts a line of poetry, indented to a level from 1 to 3.
+    fn poetry(&mut self, level: i32) {
+        let paragraph = self.layout.map_or(false, |(paragraph, _)| paragraph);
+        self.layout = Some((paragraph, level.clamp(1, 3)));
+    }
+
+    /// Adds a blank line after the verse being read, or the last verse read.
+    fn line_break(&mut self) {
+        if let Some(verse) = self.verse.as_mut().or(self.text.verses.last_mut()) {
+            verse.line_break = true;
+        }
+    }
+}
+
+/// Marks up a heading with HTML, to go before the HTML of a verse.
+fn heading_html(heading: &str) -> String {
+    format!(
+        "<span class=\"section-heading\">{}</span> ",
+        escape_html(heading)
+    )
+}
+
+/// Escapes text so it can go in HTML, including in attributes.
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Collapses the runs of whitespace in text into single spaces, and trims
+/// it.
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::io::stdout;
+
+    use diesel_migrations::run_pending_migrations_in_directory;
+
+    use super::*;
+    use crate::models::SearchOptions;
+    use crate::{establish_connection, BookStore, Searcher, SwordDrill, VerseFormat, VerseStore};
+
+    #[test]
+    fn seeded() {
+        let conn = establish_connection(":memory:");
+        run_pending_migrations_in_directory(&conn, Path::new("./migrations"), &mut stdout())
+            .unwrap();
+        // As if the tables had been made without loading the verses
+        diesel::sql_query("DELETE FROM verses")
+            .execute(&conn)
+            .unwrap();
+        diesel::sql_query("DELETE FROM verses_html")
+            .execute(&conn)
+            .unwrap();
+        diesel::sql_query("DELETE FROM verses_fts")
+            .execute(&conn)
+            .unwrap();
+        assert_eq!(crate::verse_count(&conn).unwrap(), 0);
+
+        let dir = env::temp_dir().join("biblers-seed-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let seed = |abbreviation: &str, name: &str| {
+            let json = format!(
+                r#"{{ "name": "{}", "books": [{{ "book": "John", "chapters": [
+                    {{ "chapter": 11, "verses": [{{ "verse": 35, "text": "Jesus wept." }}] }}
+                ] }}] }}"#,
+                name
+            );
+            fs::write(dir.join(format!("{}.json", abbreviation)), json).unwrap();
+        };
+        seed("kjv", "King James Version");
+        seed("web", "World English Bible");
+
+        assert_eq!(seed_verses_from(&dir, &conn).unwrap(), 2);
+        let kjv = SwordDrill::translation("kjv", &conn).unwrap();
+        assert_eq!(kjv.id, STANDARD_TRANSLATION_ID);
+        assert_eq!(kjv.license.as_deref(), Some("Public Domain"));
+        let web = SwordDrill::translation("web", &conn).unwrap();
+        let reference = "John 11:35".parse().unwrap();
+        let (_, verses) =
+            SwordDrill::verses(&reference, &web, VerseFormat::PlainText, &conn).unwrap();
+        assert_eq!(verses[0].words, "Jesus wept.");
+
+        // Databases with verses are left as they are
+        assert_eq!(seed_verses_from(&dir, &conn).unwrap(), 0);
+        assert!(import_translation("kjv", &ImportedText::default(), &conn).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn imported() {
+        let conn = establish_connection(":memory:");
+        run_pending_migrations_in_directory(&conn, Path::new("./migrations"), &mut stdout())
+            .unwrap();
+        let verse = |verse: i32, words: &str| ImportedVerse {
+            book: 43,
+            chapter: 1,
+            verse,
+            words: words.to_string(),
+            html: format!("<em>{}</em>", words),
+            ..ImportedVerse::default()
+        };
+        let mut text = ImportedText {
+            name: Some("Test Version".to_string()),
+            verses: vec![
+                verse(2, "The same was in the beginning"),
+                verse(1, "In the beginning"),
+            ],
+            ..ImportedText::default()
+        };
+
+        assert_eq!(import_translation("TEST", &text, &conn).unwrap(), 2);
+        let test = SwordDrill::translation("test", &conn).unwrap();
+        assert_eq!(test.name, "Test Version");
+        assert_eq!(
+            (test.language.as_str(), test.direction.as_str()),
+            ("en", "ltr")
+        );
+        let reference = "John 1".parse().unwrap();
+        let (_, verses) = SwordDrill::verses(&reference, &test, VerseFormat::Html, &conn).unwrap();
+        assert_eq!(verses[0].words, "<em>In the beginning</em>");
+        assert!(verses[0].id < verses[1].id);
+        let (matches, _) =
+            SwordDrill::search("same", &SearchOptions::default(), &test, &conn).unwrap();
+        assert_eq!(matches.len(), 1);
+
+        text.verses.pop();
+        text.language = Some("he".to_string());
+        assert_eq!(import_translation("test", &text, &conn).unwrap(), 1);
+        let test = SwordDrill::translation("test", &conn).unwrap();
+        assert_eq!(test.direction, "rtl");
+        let (_, verses) =
+            SwordDrill::verses(&reference, &test, VerseFormat::PlainText, &conn).unwrap();
+        assert_eq!(verses.len(), 1);
+
+        assert!(import_translation("kjv", &text, &conn).is_err());
+    }
+}
@@ -0,0 +1,347 @@
+use std::fs;
+use std::path::Path;
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+use super::{ImportedText, VerseBuilder};
+use crate::DbError;
+
+/// OSIS names of the books, in the order of their IDs.
+pub(super) const BOOKS: [&str; 76] = [
+    "Gen", "Exod", "Lev", "Num", "Deut", "Josh", "Judg", "Ruth", "1Sam", "2Sam", "1Kgs", "2Kgs",
+    "1Chr", "2Chr", "Ezra", "Neh", "Esth", "Job", "Ps", "Prov", "Eccl", "Song", "Isa", "Jer",
+    "Lam", "Ezek", "Dan", "Hos", "Joel", "Amos", "Obad", "Jonah", "Mic", "Nah", "Hab", "Zeph",
+    "Hag", "Zech", "Mal", "Matt", "Mark", "Luke", "John", "Acts", "Rom", "1Cor", "2Cor", "Gal",
+    "Eph", "Phil", "Col", "1Thess", "2Thess", "1Tim", "2Tim", "Titus", "Phlm", "Heb", "Jas",
+    "1Pet", "2Pet", "1John", "2John", "3John", "Jude", "Rev", "Tob", "Jdt", "Wis", "Sir", "Bar",
+    "1Macc", "2Macc", "1Esd", "2Esd", "PrMan",
+];
+
+/// Types of titles that aren't headings of the text, since they repeat the
+/// names of books and chapters.
+const SKIPPED_TITLES: [&str; 3] = ["main", "chapter", "runningHead"];
+
+/// Types of divisions that aren't part of the text of the books.
+const SKIPPED_DIVISIONS: [&str; 5] = ["colophon", "front", "introduction", "preface", "titlePage"];
+
+/// Reads an OSIS XML file.
+///
+/// Verses can be elements around their words or milestones (`<verse
+/// sID="..."/>` and `<verse eID="..."/>`) around them, and are known by
+/// their `osisID`s (e.g. `Gen.1.1`), with the first of any bridged
+/// verses given the words. Titles of sections and psalms go before the
+/// HTML of the verses after them, notes other than cross-references are
+/// kept as footnotes, and words added by translators are emphasized. The
+/// translation's name and language are read from the header.
+pub(super) fn read(path: &Path) -> Result<ImportedText, DbError> {
+    let xml = fs::read_to_string(path).map_err(|e| DbError::Import {
+        cause: format!("{} couldn't be read: {}", path.display(), e),
+    })?;
+    parse(&xml)
+}
+
+/// Parses an OSIS document.
+pub(super) fn parse(xml: &str) -> Result<ImportedText, DbError> {
+    let mut text = ImportedText::default();
+    let mut reader = Reader::from_str(xml);
+    let mut parser = Parser {
+        builder: VerseBuilder::new(&mut text),
+        elements: vec![],
+        skipping: None,
+        note: None,
+        heading: None,
+        name: None,
+        language: None,
+    };
+    let to_db_error = |e: quick_xml::Error, reader: &Reader<&[u8]>| DbError::Import {
+        cause: format!(
+            "the OSIS is invalid at byte {}: {}",
+            reader.buffer_position(),
+            e
+        ),
+    };
+
+    let mut buf = vec![];
+    loop {
+        match reader.read_event(&mut buf) {
+            Ok(Event::Start(ref e)) => parser.start(e, &reader, false),
+            Ok(Event::Empty(ref e)) => parser.start(e, &reader, true),
+            Ok(Event::End(_)) => parser.end(),
+            Ok(Event::Text(ref e)) => {
+                let words = e
+                    .unescape_and_decode(&reader)
+                    .map_err(|e| to_db_error(e, &reader))?;
+                parser.words(&words);
+            }
+            Ok(Event::Eof) => break,
+            Ok(_) => {}
+            Err(e) => return Err(to_db_error(e, &reader)),
+        }
+        buf.clear();
+    }
+    parser.builder.end_verse();
+
+    let (name, language) = (parser.name, parser.language);
+    text.name = name;
+    text.language = language;
+    Ok(text)
+}
+
+/// Gets the value of an element's attribute.
+pub(super) fn attribute(
+    element: &BytesStart,
+    name: &[u8],
+    reader: &Reader<&[u8]>,
+) -> Option<String> {
+    element
+        .attributes()
+        .flatten()
+        .find(|a| a.key == name)
+        .and_then(|a| a.unescape_and_decode_value(reader).ok())
+}
+
+/// Parses an `osisID` (e.g. `Gen.1.1`, or `KJV:Gen.1.1-Gen.1.2` with the
+/// work it's in) into the book name, chapter and verse it starts at.
+fn parse_osis_id(id: &str) -> Option<(&str, i32, i32)> {
+    let id = id.split_whitespace().next()?.split('-').next()?;
+    let id = id.rsplit(':').next()?.split('!').next()?;
+    let mut parts = id.split('.');
+    let book = parts.next()?;
+    let chapter = parts.next()?.parse().ok()?;
+    let verse = parts.next()?.parse().ok()?;
+    Some((book, chapter, verse))
+}
+
+struct Parser<'a> {
+    builder: VerseBuilder<'a>,
+    /// Names of the elements the words are in, and the HTML tags they
+    /// opened.
+    elements: Vec<(String, Option<&'static str>)>,
+    /// Number of elements the words were in when an element left out
+    /// started.
+    skipping: Option<usize>,
+    /// Number of elements the words were in when a note started, and its
+    /// words.
+    note: Option<(usize, String)>,
+    /// Number of elements the words were in when a heading started, and its
+    /// words.
+    heading: Option<(usize, String)>,
+    name: Option<String>,
+    language: Option<String>,
+}
+
+impl<'a> Parser<'a> {
+    fn start(&mut self, element: &BytesStart, reader: &Reader<&[u8]>, empty: bool) {
+        let name = String::from_utf8_lossy(element.local_name()).into_owned();
+        let attr = |key: &str| attribute(element, key.as_bytes(), reader);
+        if self.skipping.is_some() {
+            if !empty {
+                self.elements.push((name, None));
+            }
+            return;
+        }
+
+        let depth = self.elements.len();
+        let mut tag = None;
+        match name.as_str() {
+            "osisText" => {
+                self.language = self.language.take().or_else(|| attr("xml:lang"));
+            }
+            "verse" => match (attr("eID"), attr("sID"), attr("osisID")) {
+                (Some(_), _, _) => self.builder.end_verse(),
+                (None, start, id) => match start.or(id).as_deref().and_then(parse_osis_id) {
+                    Some((book, chapter, verse)) => match BOOKS.iter().position(|b| *b == book) {
+                        Some(i) => self.builder.start_verse(i as i32 + 1, chapter, verse),
+                        None => {
+                            self.builder.end_verse();
+                            self.builder.unmapped(book);
+                        }
+                    },
+                    None => self.builder.end_verse(),
+                },
+            },
+            "div" if SKIPPED_DIVISIONS.contains(&attr("type").unwrap_or_default().as_str()) => {
+                self.skipping = Some(depth);
+            }
+            "title" if self.in_element("header") => {}
+            "title" => {
+                let kind = attr("type").unwrap_or_default();
+                if SKIPPED_TITLES.contains(&kind.as_str()) {
+                    self.skipping = Some(depth);
+                } else if attr("eID").is_none() {
+                    self.heading = Some((depth, String::new()));
+                }
+            }
+            "note" => match attr("type").as_deref() {
+                Some("crossReference") => self.skipping = Some(depth),
+                _ => self.note = Some((depth, String::new())),
+            },
+            "div" if attr("type").as_deref() == Some("paragraph") && attr("eID").is_none() => {
+                self.builder.paragraph()
+            }
+            "p" if attr("eID").is_none() => self.builder.paragraph(),
+            "milestone" => {
+                if matches!(attr("type").as_deref(), Some("x-p" | "pilcrow")) {
+                    self.builder.paragraph();
+                }
+            }
+            "l" if attr("eID").is_none() => {
+                let level = attr("level").and_then(|l| l.parse().ok());
+                self.builder.poetry(level.unwrap_or(1));
+            }
+            "lg" if attr("eID").is_some() => self.builder.line_break(),
+            "transChange" => tag = Some("em"),
+            "hi" => {
+                tag = match attr("type").as_deref() {
+                    Some("italic") => Some("em"),
+                    Some("bold") => Some("strong"),
+                    _ => None,
+                };
+            }
+            _ => {}
+        }
+
+        if empty {
+            // Skipped or heading milestones have nothing in them
+            if self.skipping == Some(depth) {
+                self.skipping = None;
+            }
+            if matches!(self.heading, Some((d, _)) if d == depth) {
+                self.heading = None;
+            }
+            if matches!(self.note, Some((d, _)) if d == depth) {
+                self.note = None;
+            }
+            return;
+        }
+        // Notes and headings are plain text
+        let tag = tag.filter(|_| self.note.is_none() && self.heading.is_none());
+        if let Some(tag) = tag {
+            self.builder.open_tag(tag);
+        }
+        self.elements.push((name, tag));
+    }
+
+    fn end(&mut self) {
+        let (name, tag) = self.elements.pop().unwrap_or_default();
+        let depth = self.elements.len();
+        if let Some(d) = self.skipping {
+            if d == depth {
+                self.skipping = None;
+            }
+            return;
+        }
+
+        if let Some(tag) = tag {
+            self.builder.close_tag(tag);
+        }
+        match name.as_str() {
+            "verse" => self.builder.end_verse(),
+            "lg" => self.builder.line_break(),
+            _ => {}
+        }
+        if matches!(self.note, Some((d, _)) if d == depth) {
+            let (_, note) = self.note.take().unwrap_or_default();
+            self.builder.footnote(&note);
+        }
+        if matches!(self.heading, Some((d, _)) if d == depth) {
+            let (_, heading) = self.heading.take().unwrap_or_default();
+            self.builder.heading(&heading);
+        }
+    }
+
+    fn words(&mut self, words: &str) {
+        if self.skipping.is_some() {
+            return;
+        }
+        if let Some((_, note)) = &mut self.note {
+            note.push_str(words);
+        } else if let Some((_, heading)) = &mut self.heading {
+            heading.push_str(words);
+        } else if self.in_element("header") {
+            let element = self.elements.last().map(|(name, _)| name.as_str());
+            match element {
+                Some("title") if self.name.is_none() => self.name = Some(words.trim().to_string()),
+                Some("language") if self.language.is_none() => {
+                    self.language = Some(words.trim().to_string())
+                }
+                _ => {}
+            }
+        } else {
+            self.builder.words(words);
+        }
+    }
+
+    /// Checks whether the words are in an element with a name.
+    fn in_element(&self, name: &str) -> bool {
+        self.elements.iter().any(|(n, _)| n == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsed() {
+        let text = parse(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<osis xmlns="http://www.bibletechnologies.net/2003/OSIS/namespace">
+<osisText osisIDWork="Test" xml:lang="en">
+<header><work osisWork="Test"><title>Test Version</title></work></header>
+<div type="book" osisID="Ps">
+<title type="main">Psalms</title>
+<chapter osisID="Ps.23">
+<title type="psalm" canonical="true">A Psalm of David.</title>
+<lg><l level="1"><verse osisID="Ps.23.1">The <divineName>Lord</divineName> is my shepherd;</verse></l>
+<l level="2"><verse osisID="Ps.23.2">he leadeth me beside the <transChange type="added">still</transChange> waters.<note type="crossReference"><reference osisRef="Rev.7.17">Rev 7:17</reference></note></verse></l></lg>
+</chapter>
+</div>
+<div type="book" osisID="John">
+<chapter sID="John.11"/>
+<p><verse sID="John.11.35" osisID="John.11.35"/>Jesus wept.<note type="study">The shortest verse &amp; a sad one.</note><verse eID="John.11.35"/>
+<verse sID="John.11.36" osisID="KJV:John.11.36 John.11.37"/>Then said the Jews,<verse eID="John.11.36"/></p>
+<chapter eID="John.11"/>
+</div>
+<div type="book" osisID="Unknown"><chapter osisID="Unknown.1"><verse osisID="Unknown.1.1">Text</verse></chapter></div>
+</osisText>
+</osis>"#,
+        )
+        .unwrap();
+
+        assert_eq!(text.name.as_deref(), Some("Test Version"));
+        assert_eq!(text.language.as_deref(), Some("en"));
+        assert_eq!(text.unmapped, ["Unknown"]);
+        assert_eq!(
+            text.verses
+                .iter()
+                .map(|v| (v.book, v.chapter, v.verse, v.words.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                (19, 23, 1, "The Lord is my shepherd;"),
+                (19, 23, 2, "he leadeth me beside the still waters."),
+                (43, 11, 35, "Jesus wept."),
+                (43, 11, 36, "Then said the Jews,"),
+            ]
+        );
+        assert_eq!(
+            text.verses[0].html,
+            "<span class=\"section-heading\">A Psalm of David.</span> The Lord is my shepherd;"
+        );
+        assert_eq!(text.verses[1].poetry_level, 2);
+        assert_eq!(
+            text.verses[1].html,
+            "he leadeth me beside the <em>still</em> waters."
+        );
+        assert!(text.verses[1].line_break);
+        assert_eq!(
+            text.verses[2].html,
+            "Jesus wept.<sup class=\"footnote\" title=\"The shortest verse &amp; a sad \
+            one.\">*</sup>"
+        );
+        assert!(text.verses[2].paragraph);
+
+        assert!(parse("<osis><verse></osis>").is_err());
+    }
+}
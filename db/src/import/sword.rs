@@ -0,0 +1,355 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use diesel::prelude::*;
+use flate2::read::ZlibDecoder;
+
+use super::{
+    osis, standard_chapters, ImportedText, VerseBuilder, LAST_CANONICAL_BOOK,
+    LAST_OLD_TESTAMENT_BOOK,
+};
+use crate::DbError;
+
+/// Size in bytes of an entry of a module's block index (`.bzs`): where the
+/// block starts, how long it is, and how long it is decompressed.
+const BLOCK_ENTRY_SIZE: usize = 12;
+
+/// Reads a compressed SWORD Bible module (`ModDrv=zText`), from its
+/// `.conf` file or from a directory with the module's `mods.d` and
+/// `modules` directories.
+///
+/// Modules must be compressed with zlib (`CompressType=ZIP`) and in the KJV
+/// versification, whose verses are found from the chapters of the King
+/// James Version in the database. Verses marked up with OSIS are read like
+/// the `osis` format, and the markup of other verses is left out. The
+/// translation's name and language are read from the `.conf` file.
+pub(super) fn read(path: &Path, conn: &SqliteConnection) -> Result<ImportedText, DbError> {
+    let (root, config) = read_config(path)?;
+    let setting = |key: &str| config.get(&key.to_lowercase()).map(String::as_str);
+    let size_bytes = match setting("ModDrv").map(str::to_lowercase).as_deref() {
+        Some("ztext") => 2,
+        Some("ztext4") => 4,
+        driver => {
+            return Err(DbError::Import {
+                cause: format!(
+                    "the module's driver is {}, not compressed text (zText)",
+                    driver.unwrap_or("missing")
+                ),
+            })
+        }
+    };
+    let compression = setting("CompressType").unwrap_or("ZIP");
+    if !compression.eq_ignore_ascii_case("ZIP") {
+        return Err(DbError::Import {
+            cause: format!(
+                "the module is compressed with {}, not zlib (ZIP)",
+                compression
+            ),
+        });
+    }
+    let versification = setting("Versification").unwrap_or("KJV");
+    if !versification.eq_ignore_ascii_case("KJV") {
+        return Err(DbError::Import {
+            cause: format!(
+                "the module is in the {} versification, not the KJV one",
+                versification
+            ),
+        });
+    }
+    let data_path = setting("DataPath").ok_or_else(|| DbError::Import {
+        cause: "the module's .conf file has no DataPath".to_string(),
+    })?;
+    let dir = root.join(data_path.trim_start_matches("./"));
+    let utf8 = setting("Encoding").map_or(false, |e| e.eq_ignore_ascii_case("UTF-8"));
+    let osis_markup = setting("SourceType").map_or(false, |s| s.eq_ignore_ascii_case("OSIS"));
+
+    let chapters = standard_chapters(conn)?;
+    let mut entries = vec![];
+    // The Old Testament's books are stored in a module's `ot` files, and the
+    // rest in its `nt` files
+    let testaments = [
+        ("ot", 1..=LAST_OLD_TESTAMENT_BOOK),
+        ("nt", LAST_OLD_TESTAMENT_BOOK + 1..=LAST_CANONICAL_BOOK),
+    ];
+    for (name, books) in testaments {
+        let mut testament = match Testament::open(&dir, name, size_bytes)? {
+            Some(testament) => testament,
+            None => continue,
+        };
+        // The module's and the testament's introductions come first, and
+        // each book's and chapter's before its verses
+        let mut index = 2;
+        for book in books {
+            index += 1;
+            for (chapter, verses) in chapters.get(&book).into_iter().flatten().enumerate() {
+                index += 1;
+                for verse in 1..=*verses {
+                    if let Some(bytes) = testament.entry(index)? {
+                        let markup = match utf8 {
+                            true => String::from_utf8_lossy(&bytes).into_owned(),
+                            false => bytes.iter().map(|&b| b as char).collect(),
+                        };
+                        entries.push((book, chapter as i32 + 1, verse, markup));
+                    }
+                    index += 1;
+                }
+            }
+        }
+    }
+
+    let mut text = if osis_markup {
+        let mut xml = String::from("<osis><osisText>");
+        for (book, chapter, verse, markup) in &entries {
+            xml.push_str(&format!(
+                "<verse osisID=\"{}.{}.{}\">{}</verse>",
+                osis::BOOKS[*book as usize - 1],
+                chapter,
+                verse,
+                markup
+            ));
+        }
+        xml.push_str("</osisText></osis>");
+        osis::parse(&xml)?
+    } else {
+        let mut text = ImportedText::default();
+        let mut builder = VerseBuilder::new(&mut text);
+        for (book, chapter, verse, markup) in &entries {
+            builder.start_verse(*book, *chapter, *verse);
+            builder.words(&strip_tags(markup));
+        }
+        builder.end_verse();
+        text
+    };
+    text.name = setting("Description").map(str::to_string);
+    text.language = setting("Lang").map(str::to_string);
+    Ok(text)
+}
+
+/// Reads the settings of a module from its `.conf` file, by their keys in
+/// lower case, along with the directory its `DataPath` is in.
+fn read_config(path: &Path) -> Result<(PathBuf, HashMap<String, String>), DbError> {
+    let to_db_error = |path: &Path, e: std::io::Error| DbError::Import {
+        cause: format!("{} couldn't be read: {}", path.display(), e),
+    };
+    let (root, file) = if path.is_dir() {
+        let mods = path.join("mods.d");
+        let mut files = vec![];
+        for entry in fs::read_dir(&mods).map_err(|e| to_db_error(&mods, e))? {
+            let file = entry.map_err(|e| to_db_error(&mods, e))?.path();
+            if file.extension().map_or(false, |e| e == "conf") {
+                files.push(file);
+            }
+        }
+        match files.len() {
+            1 => (path.to_path_buf(), files.remove(0)),
+            count => {
+                return Err(DbError::Import {
+                    cause: format!(
+                        "{} has {} .conf files rather than one; give the module's",
+                        mods.display(),
+                        count
+                    ),
+                })
+            }
+        }
+    } else {
+        let root = path
+            .parent()
+            .and_then(Path::parent)
+            .unwrap_or(Path::new("."));
+        (root.to_path_buf(), path.to_path_buf())
+    };
+
+    let conf = fs::read(&file).map_err(|e| to_db_error(&file, e))?;
+    let mut config = HashMap::new();
+    for line in String::from_utf8_lossy(&conf).lines() {
+        if let Some((key, value)) = line.split_once('=') {
+            config
+                .entry(key.trim().to_lowercase())
+                .or_insert_with(|| value.trim().to_string());
+        }
+    }
+    Ok((root, config))
+}
+
+/// Removes the markup from a verse, leaving its words.
+fn strip_tags(markup: &str) -> String {
+    let mut words = String::with_capacity(markup.len());
+    let mut in_tag = false;
+    for c in markup.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => words.push(c),
+            _ => {}
+        }
+    }
+    words
+}
+
+/// Files of a testament of a module: the index of its compressed blocks,
+/// the index of its verses and the blocks themselves.
+struct Testament {
+    name: &'static str,
+    blocks: Vec<u8>,
+    verses: Vec<u8>,
+    data: Vec<u8>,
+    /// Size in bytes of the lengths of the verses in the verse index.
+    size_bytes: usize,
+    /// Blocks decompressed so far, by their numbers.
+    decompressed: HashMap<u32, Vec<u8>>,
+}
+
+impl Testament {
+    /// Opens the files of a testament, if the module has it.
+    fn open(dir: &Path, name: &'static str, size_bytes: usize) -> Result<Option<Self>, DbError> {
+        let read = |extension: &str| {
+            let path = dir.join(format!("{}.{}", name, extension));
+            fs::read(&path).map_err(|e| DbError::Import {
+                cause: format!("{} couldn't be read: {}", path.display(), e),
+            })
+        };
+        if !dir.join(format!("{}.bzs", name)).exists() {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            name,
+            blocks: read("bzs")?,
+            verses: read("bzv")?,
+            data: read("bzz")?,
+            size_bytes,
+            decompressed: HashMap::new(),
+        }))
+    }
+
+    /// Gets the text of an entry of the verse index, if it has any.
+    fn entry(&mut self, index: usize) -> Result<Option<Vec<u8>>, DbError> {
+        let at = index * (8 + self.size_bytes);
+        let (block, offset, size) = match (
+            read_number(&self.verses, at, 4),
+            read_number(&self.verses, at + 4, 4),
+            read_number(&self.verses, at + 8, self.size_bytes),
+        ) {
+            (Some(block), Some(offset), Some(size)) if size > 0 => (block, offset, size),
+            _ => return Ok(None),
+        };
+
+        if !self.decompressed.contains_key(&block) {
+            let at = block as usize * BLOCK_ENTRY_SIZE;
+            let compressed = read_number(&self.blocks, at, 4)
+                .zip(read_number(&self.blocks, at + 4, 4))
+                .and_then(|(start, length)| {
+                    self.data
+                        .get(start as usize..start as usize + length as usize)
+                });
+            let mut bytes = vec![];
+            compressed
+                .and_then(|compressed| ZlibDecoder::new(compressed).read_to_end(&mut bytes).ok())
+                .ok_or_else(|| DbError::Import {
+                    cause: format!(
+                        "block {} of the module's {}.bzz is corrupt",
+                        block, self.name
+                    ),
+                })?;
+            self.decompressed.insert(block, bytes);
+        }
+        let bytes = &self.decompressed[&block];
+        Ok(bytes
+            .get(offset as usize..offset as usize + size as usize)
+            .map(<[u8]>::to_vec))
+    }
+}
+
+/// Reads a little-endian number of 2 or 4 bytes from an index.
+fn read_number(bytes: &[u8], at: usize, size: usize) -> Option<u32> {
+    let bytes = bytes.get(at..at + size)?;
+    Some(
+        bytes
+            .iter()
+            .rev()
+            .fold(0, |number, &byte| (number << 8) | byte as u32),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::io::{stdout, Write};
+
+    use diesel_migrations::run_pending_migrations_in_directory;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+
+    use super::*;
+    use crate::establish_connection;
+
+    #[test]
+    fn read_module() {
+        let conn = establish_connection(":memory:");
+        run_pending_migrations_in_directory(&conn, Path::new("./migrations"), &mut stdout())
+            .unwrap();
+        let dir = env::temp_dir().join("biblers-sword-test");
+        let _ = fs::remove_dir_all(&dir);
+        let data = dir.join("modules/texts/ztext/test");
+        fs::create_dir_all(&data).unwrap();
+        fs::create_dir_all(dir.join("mods.d")).unwrap();
+        fs::write(
+            dir.join("mods.d/test.conf"),
+            "[Test]\nDataPath=./modules/texts/ztext/test/\nModDrv=zText\nCompressType=ZIP\n\
+            SourceType=OSIS\nEncoding=UTF-8\nLang=en\nDescription=Test Version\n",
+        )
+        .unwrap();
+
+        let first = "<title>The Creation</title>In the beginning \
+            <transChange type=\"added\">God</transChange>";
+        let second = "And the earth was without form";
+        let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+        encoder.write_all(first.as_bytes()).unwrap();
+        encoder.write_all(second.as_bytes()).unwrap();
+        let block = encoder.finish().unwrap();
+        let entry = |numbers: [u32; 2], size: u16| {
+            let mut entry = numbers[0].to_le_bytes().to_vec();
+            entry.extend(numbers[1].to_le_bytes());
+            entry.extend(size.to_le_bytes());
+            entry
+        };
+        // Genesis 1:1 and 1:2 come after the module's, Old Testament's,
+        // Genesis's and chapter's introductions
+        let mut verses: Vec<u8> = (0..4).flat_map(|_| entry([0, 0], 0)).collect();
+        verses.extend(entry([0, 0], first.len() as u16));
+        verses.extend(entry([0, first.len() as u32], second.len() as u16));
+        let mut blocks = 0u32.to_le_bytes().to_vec();
+        blocks.extend((block.len() as u32).to_le_bytes());
+        blocks.extend(((first.len() + second.len()) as u32).to_le_bytes());
+        fs::write(data.join("ot.bzs"), blocks).unwrap();
+        fs::write(data.join("ot.bzv"), verses).unwrap();
+        fs::write(data.join("ot.bzz"), block).unwrap();
+
+        let text = read(&dir, &conn).unwrap();
+        assert_eq!(text.name.as_deref(), Some("Test Version"));
+        assert_eq!(text.language.as_deref(), Some("en"));
+        assert_eq!(
+            text.verses
+                .iter()
+                .map(|v| (v.book, v.chapter, v.verse, v.words.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                (1, 1, 1, "In the beginning God"),
+                (1, 1, 2, "And the earth was without form"),
+            ]
+        );
+        assert_eq!(
+            text.verses[0].html,
+            "<span class=\"section-heading\">The Creation</span> In the beginning <em>God</em>"
+        );
+
+        fs::write(
+            dir.join("mods.d/test.conf"),
+            "[Test]\nDataPath=./modules/texts/rawtext/test/\nModDrv=RawText\n",
+        )
+        .unwrap();
+        assert!(read(&dir, &conn).is_err());
+    }
+}
@@ -0,0 +1,180 @@
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use diesel::prelude::*;
+
+use super::{
+    standard_chapters, ImportedText, VerseBuilder, LAST_CANONICAL_BOOK, LAST_OLD_TESTAMENT_BOOK,
+};
+use crate::DbError;
+
+/// Reads a theWord Bible: a text file with a line for each verse of the KJV
+/// versification in order, which are found from the chapters of the King
+/// James Version in the database, then lines of the Bible's properties.
+/// `.ont` files have both testaments, `.ot` files the Old Testament and
+/// `.nt` files the New.
+///
+/// Titles (`<TS>...<Ts>`) go before the HTML of the verses after them,
+/// notes (`<RF>...<Rf>`) are kept as footnotes, words added by translators
+/// (`<FI>...<Fi>`) are emphasized, and other tags, like Strong's numbers,
+/// are left out. The translation's name and language are read from the
+/// `description` and `lang` properties.
+pub(super) fn read(path: &Path, conn: &SqliteConnection) -> Result<ImportedText, DbError> {
+    let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+    let books = match extension.as_deref() {
+        Some("ont") => 1..=LAST_CANONICAL_BOOK,
+        Some("ot") => 1..=LAST_OLD_TESTAMENT_BOOK,
+        Some("nt") => LAST_OLD_TESTAMENT_BOOK + 1..=LAST_CANONICAL_BOOK,
+        _ => {
+            return Err(DbError::Import {
+                cause: format!(
+                    "{} isn't a theWord Bible (.ont, .ot or .nt)",
+                    path.display()
+                ),
+            })
+        }
+    };
+    let contents = fs::read_to_string(path).map_err(|e| DbError::Import {
+        cause: format!("{} couldn't be read: {}", path.display(), e),
+    })?;
+    parse(&contents, books, conn)
+}
+
+/// Parses a theWord Bible with the verses of some books.
+fn parse(
+    contents: &str,
+    books: RangeInclusive<i32>,
+    conn: &SqliteConnection,
+) -> Result<ImportedText, DbError> {
+    let chapters = standard_chapters(conn)?;
+    let mut places = vec![];
+    for book in books {
+        for (chapter, verses) in chapters.get(&book).into_iter().flatten().enumerate() {
+            for verse in 1..=*verses {
+                places.push((book, chapter as i32 + 1, verse));
+            }
+        }
+    }
+
+    let mut text = ImportedText::default();
+    let mut builder = VerseBuilder::new(&mut text);
+    let mut lines = contents.trim_start_matches('\u{feff}').lines();
+    for (&(book, chapter, verse), line) in places.iter().zip(lines.by_ref()) {
+        builder.start_verse(book, chapter, verse);
+        add_line(line, &mut builder);
+    }
+    builder.end_verse();
+
+    for line in lines {
+        match line.split_once('=') {
+            Some(("description", name)) => text.name = Some(name.trim().to_string()),
+            Some(("lang", language)) => text.language = Some(language.trim().to_string()),
+            _ => {}
+        }
+    }
+    Ok(text)
+}
+
+/// Adds the words of a verse's line to the verse being built.
+fn add_line(line: &str, builder: &mut VerseBuilder) {
+    let mut heading: Option<String> = None;
+    let mut note: Option<String> = None;
+    let mut rest = line;
+    while !rest.is_empty() {
+        // The words up to the next tag, the tag's name, and what's after it
+        let (words, tag, after) = match rest.find('<') {
+            Some(start) => {
+                let end = rest[start..]
+                    .find('>')
+                    .map_or(rest.len(), |end| start + end + 1);
+                let tag = rest[start + 1..end].trim_end_matches('>');
+                let name = tag.split_whitespace().next().unwrap_or_default();
+                (&rest[..start], name, &rest[end..])
+            }
+            None => (rest, "", ""),
+        };
+        rest = after;
+
+        if let Some(heading) = &mut heading {
+            heading.push_str(words);
+        } else if let Some(note) = &mut note {
+            note.push_str(words);
+        } else {
+            builder.words(words);
+        }
+        // Notes and titles are plain text
+        let plain = heading.is_some() || note.is_some();
+        match tag {
+            "Ts" => builder.heading(&heading.take().unwrap_or_default()),
+            _ if tag.starts_with("TS") => heading = Some(String::new()),
+            "RF" => note = Some(String::new()),
+            "Rf" => builder.footnote(&note.take().unwrap_or_default()),
+            "FI" if !plain => builder.open_tag("em"),
+            "Fi" if !plain => builder.close_tag("em"),
+            "CM" => builder.paragraph(),
+            "CL" => builder.words(" "),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::stdout;
+
+    use diesel_migrations::run_pending_migrations_in_directory;
+
+    use super::*;
+    use crate::establish_connection;
+
+    #[test]
+    fn parsed() {
+        let conn = establish_connection(":memory:");
+        run_pending_migrations_in_directory(&conn, Path::new("./migrations"), &mut stdout())
+            .unwrap();
+        let verses: i32 = standard_chapters(&conn)
+            .unwrap()
+            .range(LAST_OLD_TESTAMENT_BOOK + 1..)
+            .flat_map(|(_, verses)| verses)
+            .sum();
+        let mut lines = vec![
+            "<TS>The Genealogy<Ts>The book of the generation of Jesus Christ<RF>Or, birth<Rf>, \
+            <FI>the<Fi> son of David<WG5207><CM>",
+            "And Abraham begat Isaac",
+        ];
+        lines.resize(verses as usize, "");
+        lines.extend(["description=Test Version", "lang=en"]);
+
+        let text = parse(
+            &lines.join("\r\n"),
+            LAST_OLD_TESTAMENT_BOOK + 1..=LAST_CANONICAL_BOOK,
+            &conn,
+        )
+        .unwrap();
+        assert_eq!(text.name.as_deref(), Some("Test Version"));
+        assert_eq!(text.language.as_deref(), Some("en"));
+        assert_eq!(
+            text.verses
+                .iter()
+                .map(|v| (v.book, v.chapter, v.verse, v.words.as_str(), v.paragraph))
+                .collect::<Vec<_>>(),
+            [
+                (
+                    40,
+                    1,
+                    1,
+                    "The book of the generation of Jesus Christ, the son of David",
+                    false
+                ),
+                (40, 1, 2, "And Abraham begat Isaac", true),
+            ]
+        );
+        assert_eq!(
+            text.verses[0].html,
+            "<span class=\"section-heading\">The Genealogy</span> The book of the generation of \
+            Jesus Christ<sup class=\"footnote\" title=\"Or, birth\">*</sup>, <em>the</em> son of \
+            David"
+        );
+    }
+}
@@ -0,0 +1,399 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{ImportedText, VerseBuilder};
+use crate::DbError;
+
+/// USFM codes of the books, in the order of their IDs.
+pub(super) const BOOKS: [&str; 76] = [
+    "GEN", "EXO", "LEV", "NUM", "DEU", "JOS", "JDG", "RUT", "1SA", "2SA", "1KI", "2KI", "1CH",
+    "2CH", "EZR", "NEH", "EST", "JOB", "PSA", "PRO", "ECC", "SNG", "ISA", "JER", "LAM", "EZK",
+    "DAN", "HOS", "JOL", "AMO", "OBA", "JON", "MIC", "NAM", "HAB", "ZEP", "HAG", "ZEC", "MAL",
+    "MAT", "MRK", "LUK", "JHN", "ACT", "ROM", "1CO", "2CO", "GAL", "EPH", "PHP", "COL", "1TH",
+    "2TH", "1TI", "2TI", "TIT", "PHM", "HEB", "JAS", "1PE", "2PE", "1JN", "2JN", "3JN", "JUD",
+    "REV", "TOB", "JDT", "WIS", "SIR", "BAR", "1MA", "2MA", "1ES", "2ES", "MAN",
+];
+
+/// Markers of paragraphs of prose.
+const PARAGRAPHS: [&str; 14] = [
+    "p", "m", "po", "pr", "pc", "pi", "mi", "pm", "pmo", "pmc", "pmr", "cls", "li", "ph",
+];
+
+/// Markers of lines of poetry, which are indented by the number after them.
+const POETRY: [&str; 4] = ["q", "qm", "qc", "qr"];
+
+/// Markers of headings, which are shown before the verse after them.
+const HEADINGS: [&str; 5] = ["s", "ms", "d", "sp", "qa"];
+
+/// Markers of text that isn't part of any verse, like titles, introductions
+/// and the references under headings.
+const SKIPPED: [&str; 31] = [
+    "h", "toc", "toca", "mt", "mte", "imt", "imte", "is", "ip", "ipi", "im", "imi", "ipq", "imq",
+    "ipr", "iq", "ib", "ili", "iot", "io", "iex", "ie", "rem", "ide", "sts", "usfm", "cl", "cd",
+    "r", "mr", "sr",
+];
+
+/// Character markers that are marked up in HTML, and the tags they're
+/// marked up with.
+const CHARACTER_TAGS: [(&str, &str); 3] = [("add", "em"), ("it", "em"), ("bd", "strong")];
+
+/// Reads a USFM file, or every USFM file (`*.usfm` or `*.sfm`) in a
+/// directory in order of their names.
+///
+/// Books are known by the codes after their `\id` markers, and books with
+/// other codes are reported as unmapped. Section headings go before the
+/// HTML of the verses after them, footnotes are kept in the HTML, and
+/// cross-references, titles and introductions are left out. The words of
+/// verses bridged together (e.g. `\v 1-2`) are given to the first verse.
+pub(super) fn read(path: &Path) -> Result<ImportedText, DbError> {
+    let to_db_error = |e: std::io::Error| DbError::Import {
+        cause: format!("{} couldn't be read: {}", path.display(), e),
+    };
+    let files = if path.is_dir() {
+        let mut files = vec![];
+        for entry in fs::read_dir(path).map_err(to_db_error)? {
+            let file = entry.map_err(to_db_error)?.path();
+            let extension = file.extension().map(|e| e.to_string_lossy().to_lowercase());
+            if matches!(extension.as_deref(), Some("usfm" | "sfm")) {
+                files.push(file);
+            }
+        }
+        files.sort();
+        files
+    } else {
+        vec![PathBuf::from(path)]
+    };
+
+    let mut text = ImportedText::default();
+    for file in files {
+        parse(&fs::read_to_string(&file).map_err(to_db_error)?, &mut text);
+    }
+    Ok(text)
+}
+
+/// Parses the USFM of a book, adding its verses to a translation.
+fn parse(usfm: &str, text: &mut ImportedText) {
+    let mut parser = Parser::new(text);
+    let mut rest = usfm;
+    while !rest.is_empty() {
+        match rest.find('\\') {
+            Some(0) => {
+                let end = rest[1..]
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '+' || c == '-'))
+                    .map_or(rest.len(), |i| i + 1);
+                let name = &rest[1..end];
+                rest = &rest[end..];
+                if let Some(after) = rest.strip_prefix('*') {
+                    parser.close(name.trim_start_matches('+'));
+                    rest = after;
+                } else {
+                    parser.open(name.trim_start_matches('+'));
+                    // The whitespace after a marker only separates it from its text
+                    let mut chars = rest.chars();
+                    if chars.next().map_or(false, char::is_whitespace) {
+                        rest = chars.as_str();
+                    }
+                }
+            }
+            Some(i) => {
+                parser.words(&rest[..i]);
+                rest = &rest[i..];
+            }
+            None => {
+                parser.words(rest);
+                rest = "";
+            }
+        }
+    }
+    parser.end_verse();
+}
+
+/// Words at the start of the text after a marker that belong to the marker.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Argument {
+    Book,
+    Chapter,
+    Verse,
+    /// The character a note is called by, or a chapter's published number.
+    Ignored,
+}
+
+/// Kinds of text the words after a marker are part of.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Block {
+    Skipped,
+    Verse,
+    Heading,
+}
+
+/// Footnote or cross-reference being read.
+#[derive(Default)]
+struct Note {
+    text: String,
+    /// Whether the note's words are left out, as for cross-references or the
+    /// references at the start of footnotes.
+    skipping: bool,
+    is_footnote: bool,
+}
+
+struct Parser<'a> {
+    builder: VerseBuilder<'a>,
+    book: Option<i32>,
+    code: String,
+    chapter: i32,
+    argument: Option<Argument>,
+    block: Block,
+    heading: String,
+    note: Option<Note>,
+    /// Whether the words are within markers left out inline (e.g. `\vp`).
+    skipping_inline: bool,
+    /// Character markers that are open, and the HTML tags they opened.
+    characters: Vec<(String, Option<&'static str>)>,
+    /// Whether the words are the attributes of a word (e.g. `\w
+    /// grace|strong="G5485"\w*`).
+    attributes: bool,
+}
+
+impl<'a> Parser<'a> {
+    fn new(text: &'a mut ImportedText) -> Self {
+        Self {
+            builder: VerseBuilder::new(text),
+            book: None,
+            code: String::new(),
+            chapter: 0,
+            argument: None,
+            block: Block::Skipped,
+            heading: String::new(),
+            note: None,
+            skipping_inline: false,
+            characters: vec![],
+            attributes: false,
+        }
+    }
+
+    fn open(&mut self, name: &str) {
+        let kind = name.trim_end_matches(|c: char| c.is_ascii_digit());
+        let level = name[kind.len()..].parse::<i32>().ok();
+
+        if let Some(note) = &mut self.note {
+            match kind {
+                "fr" | "xo" | "xt" | "fv" => note.skipping = true,
+                _ if kind.starts_with('f') => note.skipping = !note.is_footnote,
+                _ => {}
+            }
+            return;
+        }
+        match kind {
+            "id" => {
+                self.end_verse();
+                self.set_block(Block::Skipped);
+                self.argument = Some(Argument::Book);
+            }
+            "c" => {
+                self.end_verse();
+                self.argument = Some(Argument::Chapter);
+            }
+            "v" => {
+                self.end_verse();
+                self.argument = Some(Argument::Verse);
+            }
+            "cp" => self.argument = Some(Argument::Ignored),
+            "ca" | "va" | "vp" => self.skipping_inline = true,
+            "f" | "fe" | "ef" | "x" | "ex" => {
+                self.note = Some(Note {
+                    is_footnote: kind.starts_with('f') || kind == "ef",
+                    skipping: kind.contains('x'),
+                    ..Note::default()
+                });
+                self.argument = Some(Argument::Ignored);
+            }
+            "b" => self.builder.line_break(),
+            "nb" => self.set_block(Block::Verse),
+            _ if PARAGRAPHS.contains(&kind) => {
+                self.set_block(Block::Verse);
+                self.builder.paragraph();
+            }
+            _ if POETRY.contains(&kind) => {
+                self.set_block(Block::Verse);
+                self.builder.poetry(level.unwrap_or(1));
+            }
+            _ if HEADINGS.contains(&kind) => self.set_block(Block::Heading),
+            _ if SKIPPED.contains(&kind) => self.set_block(Block::Skipped),
+            _ => {
+                let tag = CHARACTER_TAGS
+                    .iter()
+                    .find(|(marker, _)| *marker == kind)
+                    .map(|(_, tag)| *tag);
+                if let (Some(tag), Block::Verse) = (tag, self.block) {
+                    self.builder.open_tag(tag);
+                }
+                self.characters.push((kind.to_string(), tag));
+                self.attributes = false;
+            }
+        }
+    }
+
+    fn close(&mut self, name: &str) {
+        let kind = name.trim_end_matches(|c: char| c.is_ascii_digit());
+        match kind {
+            "f" | "fe" | "ef" | "x" | "ex" => {
+                if let Some(note) = self.note.take() {
+                    if note.is_footnote {
+                        self.builder.footnote(&note.text);
+                    }
+                }
+            }
+            "ca" | "va" | "vp" => self.skipping_inline = false,
+            _ if self.note.is_some() => {}
+            _ => {
+                if let Some(i) = self
+                    .characters
+                    .iter()
+                    .rposition(|(marker, _)| marker == kind)
+                {
+                    for (_, tag) in self.characters.drain(i..).rev() {
+                        if let Some(tag) = tag {
+                            self.builder.close_tag(tag);
+                        }
+                    }
+                }
+                self.attributes = false;
+            }
+        }
+    }
+
+    fn words(&mut self, words: &str) {
+        let mut words = words;
+        if let Some(argument) = self.argument.take() {
+            let trimmed = words.trim_start();
+            let end = trimmed.find(char::is_whitespace).unwrap_or(trimmed.len());
+            let value = &trimmed[..end];
+            words = &trimmed[end..];
+            match argument {
+                Argument::Book => self.start_book(value),
+                Argument::Chapter => self.chapter = value.parse().unwrap_or(0),
+                Argument::Verse => self.start_verse(value),
+                Argument::Ignored => {}
+            }
+        }
+
+        if self.skipping_inline {
+            return;
+        }
+        if let Some(note) = &mut self.note {
+            if !note.skipping {
+                note.text.push_str(words);
+            }
+            return;
+        }
+        match self.block {
+            Block::Skipped => {}
+            Block::Heading => self.heading.push_str(words),
+            Block::Verse if !self.attributes => match words.split_once('|') {
+                Some((word, _)) if !self.characters.is_empty() => {
+                    self.attributes = true;
+                    self.builder.words(word);
+                }
+                _ => self.builder.words(words),
+            },
+            Block::Verse => {}
+        }
+    }
+
+    /// Moves on to another kind of text, adding the heading that was being
+    /// read if there was one.
+    fn set_block(&mut self, block: Block) {
+        if self.block == Block::Heading {
+            self.builder.heading(&std::mem::take(&mut self.heading));
+        }
+        self.block = block;
+    }
+
+    fn start_book(&mut self, code: &str) {
+        self.code = code.to_uppercase();
+        self.book = BOOKS
+            .iter()
+            .position(|book| *book == self.code)
+            .map(|i| i as i32 + 1);
+        self.chapter = 0;
+    }
+
+    fn start_verse(&mut self, number: &str) {
+        self.set_block(Block::Verse);
+        let digits = number
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(number.len());
+        let verse = number[..digits].parse().unwrap_or(0);
+        match self.book {
+            Some(book) if self.chapter > 0 && verse > 0 => {
+                self.builder.start_verse(book, self.chapter, verse)
+            }
+            _ => self.builder.unmapped(&self.code),
+        }
+    }
+
+    fn end_verse(&mut self) {
+        // Character markers end with the verse they're in
+        self.characters.clear();
+        self.attributes = false;
+        self.builder.end_verse();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsed() {
+        let mut text = ImportedText::default();
+        parse(
+            r#"\id PSA Test Version
+\h Psalms
+\mt1 Psalms
+\c 23
+\d A Psalm of David.
+\q1
+\v 1 The \nd Lord\nd* is my shepherd;
+\q2 I shall not want.\f + \fr 23:1 \ft Or, \fq lack\f*
+\q1
+\v 2 He maketh me to lie down in \add green\add* pastures:\x - \xo 23:2 \xt Ezek 34:14\x*
+\b
+\s1 Goodness & Mercy
+\p
+\v 6 \w Surely|strong="H389"\w* goodness
+\id FRT
+\v 1 Front matter
+"#,
+            &mut text,
+        );
+
+        assert_eq!(text.unmapped, ["FRT"]);
+        assert_eq!(
+            text.verses
+                .iter()
+                .map(|v| (v.book, v.chapter, v.verse, v.words.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                (19, 23, 1, "The Lord is my shepherd; I shall not want."),
+                (19, 23, 2, "He maketh me to lie down in green pastures:"),
+                (19, 23, 6, "Surely goodness"),
+            ]
+        );
+        assert_eq!(
+            text.verses[0].html,
+            "<span class=\"section-heading\">A Psalm of David.</span> The Lord is my shepherd; \
+            <br>I shall not want.<sup class=\"footnote\" title=\"Or, lack\">*</sup>"
+        );
+        assert_eq!(text.verses[0].poetry_level, 1);
+        assert_eq!(
+            text.verses[1].html,
+            "He maketh me to lie down in <em>green</em> pastures:"
+        );
+        assert!(text.verses[1].line_break);
+        assert!(text.verses[2].paragraph && text.verses[2].poetry_level == 0);
+        assert!(text.verses[2]
+            .html
+            .starts_with("<span class=\"section-heading\">Goodness &amp; Mercy</span>"));
+    }
+}
@@ -0,0 +1,325 @@
+use std::fs;
+use std::path::Path;
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+use super::osis::{self, attribute};
+use super::{ImportedText, VerseBuilder, LAST_CANONICAL_BOOK};
+use crate::DbError;
+
+/// ISO 639-2 codes of languages that Zefania files often give, and the ISO
+/// 639-1 codes the translations are stored with.
+const LANGUAGES: [(&str, &str); 16] = [
+    ("ara", "ar"),
+    ("deu", "de"),
+    ("dut", "nl"),
+    ("eng", "en"),
+    ("fra", "fr"),
+    ("fre", "fr"),
+    ("ger", "de"),
+    ("gre", "el"),
+    ("heb", "he"),
+    ("ita", "it"),
+    ("lat", "la"),
+    ("nld", "nl"),
+    ("por", "pt"),
+    ("rus", "ru"),
+    ("spa", "es"),
+    ("swe", "sv"),
+];
+
+/// Reads a Zefania XML file.
+///
+/// Books are known by their numbers, or by their short names (e.g. `Tob`)
+/// outside the Protestant canon, and books, chapters and verses that can't
+/// be mapped are reported. Captions go before the HTML of the verses after
+/// them, notes are kept as footnotes, and cross-references, prologs and
+/// remarks are left out. The translation's name and language are read from
+/// its attributes and `INFORMATION`.
+pub(super) fn read(path: &Path) -> Result<ImportedText, DbError> {
+    let xml = fs::read_to_string(path).map_err(|e| DbError::Import {
+        cause: format!("{} couldn't be read: {}", path.display(), e),
+    })?;
+    parse(&xml)
+}
+
+/// Parses a Zefania document.
+fn parse(xml: &str) -> Result<ImportedText, DbError> {
+    let mut text = ImportedText::default();
+    let mut reader = Reader::from_str(xml);
+    let mut parser = Parser {
+        builder: VerseBuilder::new(&mut text),
+        elements: vec![],
+        skipping: None,
+        note: None,
+        heading: None,
+        book: None,
+        book_name: String::new(),
+        chapter: None,
+        name: None,
+        language: None,
+    };
+    let to_db_error = |e: quick_xml::Error, reader: &Reader<&[u8]>| DbError::Import {
+        cause: format!(
+            "the Zefania XML is invalid at byte {}: {}",
+            reader.buffer_position(),
+            e
+        ),
+    };
+
+    let mut buf = vec![];
+    loop {
+        match reader.read_event(&mut buf) {
+            Ok(Event::Start(ref e)) => parser.start(e, &reader, false)?,
+            Ok(Event::Empty(ref e)) => parser.start(e, &reader, true)?,
+            Ok(Event::End(_)) => parser.end(),
+            Ok(Event::Text(ref e)) => {
+                let words = e
+                    .unescape_and_decode(&reader)
+                    .map_err(|e| to_db_error(e, &reader))?;
+                parser.words(&words);
+            }
+            Ok(Event::Eof) => break,
+            Ok(_) => {}
+            Err(e) => return Err(to_db_error(e, &reader)),
+        }
+        buf.clear();
+    }
+    parser.builder.end_verse();
+
+    let (name, language) = (parser.name, parser.language);
+    text.name = name;
+    text.language = language.map(|language| {
+        let language = language.trim().to_lowercase();
+        LANGUAGES
+            .iter()
+            .find(|(code, _)| *code == language)
+            .map_or(language, |(_, code)| code.to_string())
+    });
+    Ok(text)
+}
+
+struct Parser<'a> {
+    builder: VerseBuilder<'a>,
+    /// Names of the elements the words are in, in upper case, and the HTML
+    /// tags they opened.
+    elements: Vec<(String, Option<&'static str>)>,
+    /// Number of elements the words were in when an element left out
+    /// started.
+    skipping: Option<usize>,
+    /// Number of elements the words were in when a note started, and its
+    /// words.
+    note: Option<(usize, String)>,
+    /// Number of elements the words were in when a caption started, and its
+    /// words.
+    heading: Option<(usize, String)>,
+    book: Option<i32>,
+    book_name: String,
+    chapter: Option<i32>,
+    name: Option<String>,
+    language: Option<String>,
+}
+
+impl<'a> Parser<'a> {
+    fn start(
+        &mut self,
+        element: &BytesStart,
+        reader: &Reader<&[u8]>,
+        empty: bool,
+    ) -> Result<(), DbError> {
+        let name = String::from_utf8_lossy(element.local_name()).to_uppercase();
+        let attr = |key: &str| attribute(element, key.as_bytes(), reader);
+        if self.elements.is_empty() && name != "XMLBIBLE" {
+            return Err(DbError::Import {
+                cause: format!("the file is <{}>, not Zefania XML (<XMLBIBLE>)", name),
+            });
+        }
+        if self.skipping.is_some() {
+            if !empty {
+                self.elements.push((name, None));
+            }
+            return Ok(());
+        }
+
+        let depth = self.elements.len();
+        let mut tag = None;
+        match name.as_str() {
+            "XMLBIBLE" => self.name = attr("biblename"),
+            "BIBLEBOOK" => {
+                self.builder.end_verse();
+                let number = attr("bnumber").and_then(|n| n.parse::<i32>().ok());
+                let short_name = attr("bsname").unwrap_or_default();
+                self.book_name = attr("bname").unwrap_or_else(|| short_name.clone());
+                self.book = match number {
+                    Some(number @ 1..=LAST_CANONICAL_BOOK) => Some(number),
+                    _ => osis::BOOKS
+                        .iter()
+                        .position(|book| book.eq_ignore_ascii_case(&short_name))
+                        .map(|i| i as i32 + 1),
+                };
+                if self.book.is_none() {
+                    let place = self.book_name.clone();
+                    self.builder.unmapped(&place);
+                }
+            }
+            "CHAPTER" => {
+                self.builder.end_verse();
+                self.chapter = attr("cnumber").and_then(|n| n.parse().ok());
+                if let (Some(_), None) = (self.book, self.chapter) {
+                    let place =
+                        format!("{} {}", self.book_name, attr("cnumber").unwrap_or_default());
+                    self.builder.unmapped(&place);
+                }
+            }
+            "VERS" if !empty => {
+                let number = attr("vnumber").unwrap_or_default();
+                match (self.book, self.chapter, number.parse::<i32>()) {
+                    (Some(book), Some(chapter), Ok(verse)) if verse > 0 => {
+                        self.builder.start_verse(book, chapter, verse)
+                    }
+                    (Some(_), Some(chapter), _) => {
+                        self.builder.end_verse();
+                        let place = format!("{} {}:{}", self.book_name, chapter, number);
+                        self.builder.unmapped(&place);
+                    }
+                    _ => self.builder.end_verse(),
+                }
+            }
+            "CAPTION" => self.heading = Some((depth, String::new())),
+            "NOTE" => self.note = Some((depth, String::new())),
+            "XREF" | "PROLOG" | "REMARK" | "MEDIA" => self.skipping = Some(depth),
+            "BR" if attr("art").as_deref() == Some("x-p") => self.builder.paragraph(),
+            "STYLE" => {
+                let style = attr("fs").or_else(|| attr("css")).unwrap_or_default();
+                if style.contains("italic") {
+                    tag = Some("em");
+                } else if style.contains("bold") {
+                    tag = Some("strong");
+                }
+            }
+            _ => {}
+        }
+
+        if empty {
+            // Left out elements, notes and captions with nothing in them
+            if self.skipping == Some(depth) {
+                self.skipping = None;
+            }
+            if matches!(self.note, Some((d, _)) if d == depth) {
+                self.note = None;
+            }
+            if matches!(self.heading, Some((d, _)) if d == depth) {
+                self.heading = None;
+            }
+            return Ok(());
+        }
+        // Notes and captions are plain text
+        let tag = tag.filter(|_| self.note.is_none() && self.heading.is_none());
+        if let Some(tag) = tag {
+            self.builder.open_tag(tag);
+        }
+        self.elements.push((name, tag));
+        Ok(())
+    }
+
+    fn end(&mut self) {
+        let (name, tag) = self.elements.pop().unwrap_or_default();
+        let depth = self.elements.len();
+        if let Some(d) = self.skipping {
+            if d == depth {
+                self.skipping = None;
+            }
+            return;
+        }
+
+        if let Some(tag) = tag {
+            self.builder.close_tag(tag);
+        }
+        if name == "VERS" {
+            self.builder.end_verse();
+        }
+        if matches!(self.note, Some((d, _)) if d == depth) {
+            let (_, note) = self.note.take().unwrap_or_default();
+            self.builder.footnote(&note);
+        }
+        if matches!(self.heading, Some((d, _)) if d == depth) {
+            let (_, heading) = self.heading.take().unwrap_or_default();
+            self.builder.heading(&heading);
+        }
+    }
+
+    fn words(&mut self, words: &str) {
+        if self.skipping.is_some() {
+            return;
+        }
+        if let Some((_, note)) = &mut self.note {
+            note.push_str(words);
+        } else if let Some((_, heading)) = &mut self.heading {
+            heading.push_str(words);
+        } else if self.elements.iter().any(|(name, _)| name == "INFORMATION") {
+            let element = self.elements.last().map(|(name, _)| name.as_str());
+            match element {
+                Some("TITLE") if self.name.is_none() => self.name = Some(words.trim().to_string()),
+                Some("LANGUAGE") if self.language.is_none() => {
+                    self.language = Some(words.trim().to_string())
+                }
+                _ => {}
+            }
+        } else {
+            self.builder.words(words);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsed() {
+        let text = parse(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<XMLBIBLE biblename="Test Version" type="x-bible">
+<INFORMATION><title>Ignored Title</title><language>ENG</language></INFORMATION>
+<BIBLEBOOK bnumber="43" bname="John" bsname="Joh">
+<CHAPTER cnumber="11">
+<CAPTION vref="35">Jesus Weeps</CAPTION>
+<VERS vnumber="35">Jesus <STYLE fs="italic">wept</STYLE>.<NOTE type="x-studynote">The shortest verse.</NOTE></VERS>
+<VERS vnumber="36"><BR art="x-p"/>Then said the Jews,<XREF fscope="Luke 19:41"/></VERS>
+<VERS vnumber="x">Not a verse</VERS>
+</CHAPTER>
+</BIBLEBOOK>
+<BIBLEBOOK bnumber="69" bname="Tobit" bsname="Tob">
+<CHAPTER cnumber="1"><VERS vnumber="1">The book of the words of Tobit</VERS></CHAPTER>
+</BIBLEBOOK>
+<BIBLEBOOK bnumber="90" bname="Unknown" bsname="Unk">
+<CHAPTER cnumber="1"><VERS vnumber="1">Text</VERS></CHAPTER>
+</BIBLEBOOK>
+</XMLBIBLE>"#,
+        )
+        .unwrap();
+
+        assert_eq!(text.name.as_deref(), Some("Test Version"));
+        assert_eq!(text.language.as_deref(), Some("en"));
+        assert_eq!(text.unmapped, ["John 11:x", "Unknown"]);
+        assert_eq!(
+            text.verses
+                .iter()
+                .map(|v| (v.book, v.chapter, v.verse, v.words.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                (43, 11, 35, "Jesus wept."),
+                (43, 11, 36, "Then said the Jews,"),
+                (67, 1, 1, "The book of the words of Tobit"),
+            ]
+        );
+        assert_eq!(
+            text.verses[0].html,
+            "<span class=\"section-heading\">Jesus Weeps</span> Jesus <em>wept</em>.\
+            <sup class=\"footnote\" title=\"The shortest verse.\">*</sup>"
+        );
+
+        assert!(parse("<osis></osis>").is_err());
+    }
+}
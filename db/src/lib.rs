@@ -3,13 +3,20 @@
 #[macro_use]
 extern crate diesel;
 
-use std::io::stdout;
+use std::fs;
+use std::io::{self, stdout};
 use std::path::Path;
 
 use diesel::prelude::*;
 use diesel::r2d2;
-use diesel_migrations::{connection::MigrationConnection, run_pending_migrations_in_directory};
+use diesel::sql_types::Text;
+use diesel_migrations::connection::MigrationConnection;
+use diesel_migrations::{
+    revert_latest_migration_in_directory, run_pending_migrations_in_directory,
+};
 use thiserror::Error;
+use unicode_normalization::char::{decompose_canonical, is_combining_mark};
+use unicode_normalization::UnicodeNormalization;
 
 /// Type of a pooled SQLite connection manager.
 pub type SqliteConnectionManager = r2d2::ConnectionManager<SqliteConnection>;
@@ -20,6 +27,7 @@ pub type SqliteConnectionPool = r2d2::Pool<SqliteConnectionManager>;
 pub type DbConnection = SqliteConnection;
 
 /// Result formats for verses.
+#[derive(Clone, Copy)]
 pub enum VerseFormat {
     /// Literal HTML.
     Html,
@@ -43,34 +51,231 @@ pub enum DbError {
 
     #[error("'{}' is not a valid Bible reference.", reference)]
     InvalidReference { reference: String },
+
+    #[error("The '{}' translation was not found.", translation)]
+    TranslationNotFound { translation: String },
+
+    #[error("The search couldn't be run because {}.", cause)]
+    InvalidSearch { cause: String },
+
+    #[error("There was a search index error. Root cause: {:?}.", cause)]
+    SearchIndex { cause: String },
+
+    #[error("The translation couldn't be imported because {}.", cause)]
+    Import { cause: String },
 }
 
-/// Builds a SQLite connection bool with the given URL.
-pub fn build_pool(db_url: &str) -> SqliteConnectionPool {
-    r2d2::Pool::builder()
-        .max_size(15)
+/// Builds a SQLite connection pool with the given URL and settings.
+pub fn build_pool(db_url: &str, config: &PoolConfig) -> SqliteConnectionPool {
+    let mut builder = r2d2::Pool::builder()
+        .max_size(config.max_size)
+        .min_idle(config.min_idle)
+        .connection_timeout(config.connection_timeout);
+    if let Some(metrics) = &config.metrics {
+        builder = builder.event_handler(Box::new(metrics.clone()));
+    }
+    builder
+        .connection_customizer(Box::new(config.customizer()))
         .build(SqliteConnectionManager::new(db_url))
         .unwrap()
 }
 
-/// Establishes a non-pooled SQLite connection.
+// Folds text with `fold`, so migrations can build the search index
+sql_function!(fn fold_text(text: Text) -> Text);
+
+// Prepares text with `index_words`, so migrations can build the search index
+sql_function!(fn index_text(text: Text, language: Text) -> Text);
+
+/// Establishes a non-pooled SQLite connection, tuned with the default
+/// [SqliteTuning].
+///
+/// The connection can run migrations, which need the `fold_text` and
+/// `index_text` SQL functions to index verses for full text search.
 pub fn establish_connection(db_url: &str) -> SqliteConnection {
-    SqliteConnection::establish(db_url).unwrap_or_else(|_| panic!("Error connecting to {}", db_url))
+    establish_tuned_connection(db_url, &SqliteTuning::default())
+}
+
+/// Establishes a non-pooled SQLite connection like [establish_connection],
+/// tuned with the given settings.
+pub fn establish_tuned_connection(db_url: &str, tuning: &SqliteTuning) -> SqliteConnection {
+    let conn = SqliteConnection::establish(db_url)
+        .unwrap_or_else(|_| panic!("Error connecting to {}", db_url));
+    fold_text::register_impl(&conn, |text: String| fold(&text))
+        .expect("Error registering the fold_text SQL function");
+    index_text::register_impl(&conn, |text: String, language: String| {
+        index_words(&text, &language)
+    })
+    .expect("Error registering the index_text SQL function");
+    tuning
+        .apply(&conn)
+        .unwrap_or_else(|e| panic!("Error tuning the connection to {}: {}", db_url, e));
+    conn
+}
+
+/// Normalizes text to Unicode Normalization Form C.
+///
+/// Text in the database is stored in NFC, so anything compared against it
+/// (e.g. search queries) should be normalized the same way first.
+pub fn normalize(text: &str) -> String {
+    text.nfc().collect()
 }
 
+/// Folds text so searches match it regardless of case and diacritics.
+///
+/// Letters are decomposed, combining marks (accents, Hebrew points, etc.)
+/// are removed, and what's left is lower cased. The search index holds
+/// folded verses, so queries have to be folded the same way.
+pub fn fold(text: &str) -> String {
+    fold_with_offsets(text).0
+}
+
+/// Folds text like [fold], also returning the index of the character in
+/// the original text that each character of the folded text came from.
+pub fn fold_with_offsets(text: &str) -> (String, Vec<usize>) {
+    let mut folded = String::with_capacity(text.len());
+    let mut offsets = Vec::with_capacity(text.len());
+    for (i, c) in text.chars().enumerate() {
+        decompose_canonical(c, |d| {
+            for l in d.to_lowercase().filter(|&l| !is_combining_mark(l)) {
+                folded.push(l);
+                offsets.push(i);
+            }
+        });
+    }
+    (folded, offsets)
+}
+
+/// Prepares text in a language for the search index, folding it like
+/// [fold] and stemming its words if there's a [stemmer] for the language.
+///
+/// Search queries have to be prepared the same way as the verses in the
+/// translation they search.
+pub fn index_words(text: &str, language: &str) -> String {
+    index_words_with_offsets(text, language).0
+}
+
+/// Prepares text like [index_words], also returning offsets like
+/// [fold_with_offsets].
+pub fn index_words_with_offsets(text: &str, language: &str) -> (String, Vec<usize>) {
+    let (folded, offsets) = fold_with_offsets(text);
+    match stemmer(language) {
+        Some(stemmer) => stem::stem_with_offsets(&folded, &offsets, stemmer),
+        None => (folded, offsets),
+    }
+}
+
+/// Directory of the Diesel migrations, from the root of the workspace.
+const MIGRATIONS_DIR: &str = "./db/migrations";
+
 /// Run any pending Diesel migrations.
 pub fn run_migrations<Conn>(conn: &Conn) -> Result<(), DbError>
 where
     Conn: MigrationConnection,
 {
-    let dir = Path::new("./db/migrations");
+    let dir = Path::new(MIGRATIONS_DIR);
     run_pending_migrations_in_directory(conn, dir, &mut stdout()).map_err(|e| DbError::Migration {
         cause: e.to_string(),
     })
 }
 
+/// Reverts the latest migration that's been run, returning its version.
+pub fn rollback_migration<Conn>(conn: &Conn) -> Result<String, DbError>
+where
+    Conn: MigrationConnection,
+{
+    let dir = Path::new(MIGRATIONS_DIR);
+    revert_latest_migration_in_directory(conn, dir).map_err(|e| DbError::Migration {
+        cause: e.to_string(),
+    })
+}
+
+/// Lists the names of the migrations in order, along with whether each one
+/// has been run.
+pub fn migration_status<Conn>(conn: &Conn) -> Result<Vec<(String, bool)>, DbError>
+where
+    Conn: MigrationConnection,
+{
+    // A database nothing's been run on has no table of migrations yet
+    let run = diesel_migrations::setup_database(conn)
+        .and_then(|_| conn.previously_run_migration_versions())
+        .map_err(|e| DbError::Migration {
+            cause: e.to_string(),
+        })?;
+    let mut names = fs::read_dir(MIGRATIONS_DIR)
+        .and_then(|entries| {
+            entries
+                .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+                .collect::<io::Result<Vec<String>>>()
+        })
+        .map_err(|e| DbError::Migration {
+            cause: e.to_string(),
+        })?;
+    names.retain(|name| !name.starts_with('.'));
+    names.sort();
+
+    Ok(names
+        .into_iter()
+        .map(|name| {
+            // Versions are the names' dates and times, without the dashes
+            let version = name.split('_').next().unwrap_or_default().replace('-', "");
+            let is_run = run.contains(&version);
+            (name, is_run)
+        })
+        .collect())
+}
+
+/// Gets the version of the latest migration that's been run, which changes
+/// when the schema or the data loaded by migrations does. Translations
+/// imported or attached without a migration don't change it, but their
+/// `imported_at` times do.
+pub fn data_version(conn: &SqliteConnection) -> Result<String, DbError> {
+    diesel::select(diesel::dsl::sql::<diesel::sql_types::Nullable<Text>>(
+        "(SELECT MAX(version) FROM __diesel_schema_migrations)",
+    ))
+    .get_result::<Option<String>>(conn)
+    .map(Option::unwrap_or_default)
+    .map_err(|e| DbError::Other {
+        cause: e.to_string(),
+    })
+}
+
+/// Counts the verses in every translation.
+pub fn verse_count(conn: &SqliteConnection) -> Result<i64, DbError> {
+    use crate::schema::verses::dsl::*;
+
+    verses.count().get_result(conn).map_err(|e| DbError::Other {
+        cause: e.to_string(),
+    })
+}
+
+mod api_keys;
+mod attach;
+mod backup;
+mod cross_references;
+mod dictionary;
+mod import;
+mod memory;
 pub mod models;
+mod pool;
 mod schema;
+#[cfg(feature = "tantivy")]
+mod search_index;
+mod stem;
 mod sword_drill;
+mod verify;
 
-pub use sword_drill::{SwordDrill, SwordDrillable};
+pub use api_keys::{create_api_key, list_api_keys, revoke_api_key};
+pub use attach::attach_translations;
+pub use backup::{backup, restore};
+pub use cross_references::import_cross_references;
+pub use dictionary::edit_distance;
+pub use import::{
+    import_translation, read_translation, seed_verses, ImportFormat, ImportedText, ImportedVerse,
+};
+pub use memory::{load_corpus, MemoryDrill};
+pub use pool::{PoolConfig, PoolMetrics, ReadReplicas, SqliteTuning};
+#[cfg(feature = "tantivy")]
+pub use search_index::{build_search_index, load_search_index, TantivyDrill};
+pub use stem::{stemmer, Stemmer};
+pub use sword_drill::{BookStore, Searcher, SwordDrill, SwordDrillable, VerseStore};
+pub use verify::{fix_verse_order, verify_translation};
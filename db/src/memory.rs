@@ -0,0 +1,341 @@
+use std::collections::{BTreeMap, HashMap};
+use std::slice;
+use std::sync::{Arc, RwLock};
+
+use diesel::prelude::*;
+use lazy_static::lazy_static;
+
+use crate::models::*;
+use crate::sword_drill::{book_name_candidates, STANDARD_VERSIFICATION};
+use crate::{BookStore, DbError, Searcher, SwordDrill, SwordDrillable, VerseFormat, VerseStore};
+
+lazy_static! {
+    /// The corpus used by [MemoryDrill], once it's been loaded.
+    static ref CORPUS: RwLock<Option<Arc<Corpus>>> = RwLock::new(None);
+}
+
+/// Verses of each chapter, in order, by the ID of their translation, their
+/// book and their chapter.
+type Chapters = BTreeMap<(i32, i32, i32), Vec<Verse>>;
+
+/// The translations, books and verses in the database, kept in memory so
+/// they can be read without querying SQLite.
+struct Corpus {
+    translations: Vec<Translation>,
+    books: Vec<Book>,
+    /// IDs of the books by their abbreviations.
+    abbreviations: HashMap<String, i32>,
+    /// IDs of the books by their names in other languages.
+    aliases: HashMap<String, i32>,
+    plain_text: Chapters,
+    html: Chapters,
+}
+
+impl Corpus {
+    fn load(conn: &SqliteConnection) -> QueryResult<Self> {
+        use crate::schema::book_abbreviations as ba;
+        use crate::schema::book_aliases as al;
+        use crate::schema::books as b;
+        use crate::schema::translations as t;
+        use crate::schema::verses as plain_text;
+        use crate::schema::verses_html as html;
+
+        // Names are looked up by the first book that has them
+        let mut abbreviations = HashMap::new();
+        for (abbreviation, book) in ba::table
+            .select((ba::abbreviation, ba::book_id))
+            .order_by(ba::id)
+            .load::<(String, i32)>(conn)?
+        {
+            abbreviations.entry(abbreviation).or_insert(book);
+        }
+        let mut aliases = HashMap::new();
+        for (alias, book) in al::table
+            .select((al::alias, al::book_id))
+            .order_by(al::id)
+            .load::<(String, i32)>(conn)?
+        {
+            aliases.entry(alias).or_insert(book);
+        }
+
+        Ok(Self {
+            translations: t::table.order_by(t::id).load(conn)?,
+            books: b::table.order_by(b::id).load(conn)?,
+            abbreviations,
+            aliases,
+            plain_text: chapters(
+                plain_text::table
+                    .order_by((plain_text::chapter, plain_text::verse))
+                    .load(conn)?,
+            ),
+            html: chapters(
+                html::table
+                    .order_by((html::chapter, html::verse))
+                    .load(conn)?,
+            ),
+        })
+    }
+
+    fn translation(&self, abbreviation: &str) -> Result<Translation, DbError> {
+        let lowercase = abbreviation.to_lowercase();
+        self.translations
+            .iter()
+            .find(|t| t.abbreviation == lowercase)
+            .cloned()
+            .ok_or_else(|| DbError::TranslationNotFound {
+                translation: abbreviation.to_owned(),
+            })
+    }
+
+    /// Finds a book like [SwordDrill::find_book], by its name, one of its
+    /// abbreviations, or its name in another language.
+    fn find_book(&self, book_name: &str) -> Result<&Book, DbError> {
+        let names = book_name_candidates(book_name);
+        names
+            .iter()
+            .find_map(|name| self.abbreviations.get(name))
+            .or_else(|| names.iter().find_map(|name| self.aliases.get(name)))
+            .and_then(|id| self.books.iter().find(|book| book.id == *id))
+            .ok_or_else(|| DbError::BookNotFound {
+                book: book_name.to_owned(),
+            })
+    }
+
+    /// Gets the chapters of a book in a translation, with their verses.
+    fn chapters(
+        &self,
+        translation_id: i32,
+        book: i32,
+        format: VerseFormat,
+    ) -> impl Iterator<Item = (i32, &[Verse])> {
+        let chapters = match format {
+            VerseFormat::PlainText => &self.plain_text,
+            VerseFormat::Html => &self.html,
+        };
+        chapters
+            .range((translation_id, book, i32::MIN)..=(translation_id, book, i32::MAX))
+            .map(|((_, _, chapter), verses)| (*chapter, verses.as_slice()))
+    }
+}
+
+/// Groups verses by their translation, book and chapter.
+fn chapters(verses: Vec<Verse>) -> Chapters {
+    let mut chapters = Chapters::new();
+    for verse in verses {
+        chapters
+            .entry((verse.translation_id, verse.book, verse.chapter))
+            .or_default()
+            .push(verse);
+    }
+    chapters
+}
+
+/// Gets the corpus, if it has been loaded.
+fn corpus() -> Option<Arc<Corpus>> {
+    CORPUS.read().unwrap().clone()
+}
+
+/// Loads every translation, book and verse in the database into memory for
+/// [MemoryDrill] to read, returning the number of verses loaded.
+pub fn load_corpus(conn: &SqliteConnection) -> Result<usize, DbError> {
+    let corpus = Corpus::load(conn).map_err(|e| DbError::Other {
+        cause: e.to_string(),
+    })?;
+    let verses = corpus.plain_text.values().map(Vec::len).sum();
+    *CORPUS.write().unwrap() = Some(Arc::new(corpus));
+    Ok(verses)
+}
+
+/// Implementation of the [SwordDrillable] trait that reads passages from
+/// memory instead of SQLite, for deployments where latency matters most.
+///
+/// Only translations, books, chapters and the verses of passages are kept
+/// in memory. Everything else (searches, complete books, random verses and
+/// the verse of the day, stats, cross-references and API keys) is looked up
+/// with [SwordDrill], and so is everything until the corpus has been loaded
+/// with [load_corpus], as are verses in translations that use another
+/// versification scheme.
+pub struct MemoryDrill;
+
+impl BookStore for MemoryDrill {
+    fn book(
+        book_name: &str,
+        translation: &Translation,
+        conn: &SqliteConnection,
+    ) -> Result<(Book, Vec<i32>), DbError> {
+        let corpus = match corpus() {
+            Some(corpus) => corpus,
+            None => return SwordDrill::book(book_name, translation, conn),
+        };
+
+        let book = corpus.find_book(book_name)?;
+        let chapters = corpus
+            .chapters(translation.id, book.id, VerseFormat::PlainText)
+            .map(|(chapter, _)| chapter)
+            .collect();
+        Ok((book.to_owned(), chapters))
+    }
+
+    fn chapters(
+        book_name: &str,
+        translation: &Translation,
+        conn: &SqliteConnection,
+    ) -> Result<(Book, Vec<(i32, i64)>), DbError> {
+        let corpus = match corpus() {
+            Some(corpus) => corpus,
+            None => return SwordDrill::chapters(book_name, translation, conn),
+        };
+
+        let book = corpus.find_book(book_name)?;
+        let chapters = corpus
+            .chapters(translation.id, book.id, VerseFormat::PlainText)
+            .map(|(chapter, verses)| (chapter, verses.len() as i64))
+            .collect();
+        Ok((book.to_owned(), chapters))
+    }
+
+    fn all_books(translation: &Translation, conn: &SqliteConnection) -> Result<Vec<Book>, DbError> {
+        let corpus = match corpus() {
+            Some(corpus) => corpus,
+            None => return SwordDrill::all_books(translation, conn),
+        };
+
+        // Deuterocanonical books are only listed if the translation has them
+        Ok(corpus
+            .books
+            .iter()
+            .filter(|book| {
+                !book.deuterocanonical
+                    || corpus
+                        .chapters(translation.id, book.id, VerseFormat::PlainText)
+                        .next()
+                        .is_some()
+            })
+            .cloned()
+            .collect())
+    }
+
+    fn translations(conn: &SqliteConnection) -> Result<Vec<Translation>, DbError> {
+        match corpus() {
+            Some(corpus) => Ok(corpus.translations.to_owned()),
+            None => SwordDrill::translations(conn),
+        }
+    }
+
+    fn translation(abbreviation: &str, conn: &SqliteConnection) -> Result<Translation, DbError> {
+        match corpus() {
+            Some(corpus) => corpus.translation(abbreviation),
+            None => SwordDrill::translation(abbreviation, conn),
+        }
+    }
+}
+
+impl VerseStore for MemoryDrill {
+    fn verses(
+        reference: &Reference,
+        translation: &Translation,
+        format: VerseFormat,
+        conn: &SqliteConnection,
+    ) -> Result<(Book, Vec<Verse>), DbError> {
+        Self::parallel_verses(reference, slice::from_ref(translation), format, conn)
+    }
+
+    fn parallel_verses(
+        reference: &Reference,
+        translations: &[Translation],
+        format: VerseFormat,
+        conn: &SqliteConnection,
+    ) -> Result<(Book, Vec<Verse>), DbError> {
+        let standard = translations
+            .iter()
+            .all(|t| t.versification == STANDARD_VERSIFICATION);
+        let corpus = match corpus() {
+            Some(corpus) if standard => corpus,
+            _ => return SwordDrill::parallel_verses(reference, translations, format, conn),
+        };
+
+        let book = corpus.find_book(&reference.book)?;
+        let reference = &reference.in_book(book);
+        let mut verses: Vec<Verse> = translations
+            .iter()
+            .flat_map(|translation| corpus.chapters(translation.id, book.id, format))
+            .filter(|(chapter, _)| reference.chapters().contains(chapter))
+            .flat_map(|(_, verses)| verses)
+            .filter(|v| reference.contains(v.chapter, v.verse))
+            .cloned()
+            .collect();
+        verses.sort_by_key(|v| (v.chapter, v.verse, v.translation_id));
+
+        Ok((book.to_owned(), verses))
+    }
+}
+
+impl Searcher for MemoryDrill {}
+
+impl SwordDrillable for MemoryDrill {}
+
+#[cfg(test)]
+mod tests {
+    use std::io::stdout;
+    use std::path::Path;
+
+    use diesel_migrations::run_pending_migrations_in_directory;
+
+    use super::*;
+    use crate::establish_connection;
+
+    #[test]
+    fn same_as_sqlite() {
+        let conn = establish_connection(":memory:");
+        run_pending_migrations_in_directory(&conn, Path::new("./migrations"), &mut stdout())
+            .unwrap();
+        assert!(load_corpus(&conn).unwrap() > 0);
+
+        let kjv = MemoryDrill::translation("KJV", &conn).unwrap();
+        assert_eq!(kjv, SwordDrill::translation("kjv", &conn).unwrap());
+        assert!(matches!(
+            MemoryDrill::translation("nope", &conn),
+            Err(DbError::TranslationNotFound { .. })
+        ));
+
+        let ids = |(book, verses): (Book, Vec<Verse>)| {
+            (
+                book.id,
+                verses.into_iter().map(|v| v.id).collect::<Vec<_>>(),
+            )
+        };
+        for reference in [
+            "John 3",
+            "ps 119:105-112",
+            "Genesis 1:31-2:3",
+            "Jude 1:3,5-6",
+        ] {
+            let reference = reference.parse().unwrap();
+            for format in [VerseFormat::PlainText, VerseFormat::Html] {
+                assert_eq!(
+                    ids(MemoryDrill::verses(&reference, &kjv, format, &conn).unwrap()),
+                    ids(SwordDrill::verses(&reference, &kjv, format, &conn).unwrap())
+                );
+            }
+        }
+        let nope = "Nope 1".parse().unwrap();
+        assert!(matches!(
+            MemoryDrill::verses(&nope, &kjv, VerseFormat::PlainText, &conn),
+            Err(DbError::BookNotFound { .. })
+        ));
+
+        let (book, chapters) = MemoryDrill::chapters("ps", &kjv, &conn).unwrap();
+        assert_eq!(book.name, "Psalms");
+        assert_eq!(chapters.len(), 150);
+        assert_eq!(chapters[118], (119, 176));
+        assert_eq!(
+            MemoryDrill::book("John", &kjv, &conn).unwrap().1,
+            SwordDrill::book("John", &kjv, &conn).unwrap().1
+        );
+        let books = |books: Vec<Book>| books.into_iter().map(|b| b.id).collect::<Vec<_>>();
+        assert_eq!(
+            books(MemoryDrill::all_books(&kjv, &conn).unwrap()),
+            books(SwordDrill::all_books(&kjv, &conn).unwrap())
+        );
+    }
+}
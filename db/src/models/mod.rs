@@ -7,17 +7,26 @@ use serde_derive::{Deserialize, Serialize};
 
 /// Model representing a Bible verse.
 #[derive(Clone, Debug, Deserialize, Queryable, Serialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
 pub struct Verse {
     pub id: i32,
     pub book: i32,
     pub chapter: i32,
     pub verse: i32,
     pub words: String,
+    pub translation_id: i32,
+    /// Whether the verse starts a paragraph.
+    pub paragraph: bool,
+    /// How far the verse is indented as a line of poetry, or 0 if it's prose.
+    pub poetry_level: i32,
+    /// Whether a blank line follows the verse, as at the end of a stanza.
+    pub line_break: bool,
 }
 
 /// Enum for the testaments in the Bible (Old or New). This is mapped
 /// to a column in the database table `books`.
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
 pub enum Testament {
     Old,
     New,
@@ -51,11 +60,26 @@ impl Queryable<Text, Sqlite> for Testament {
 
 /// Model representing a book in the Bible.
 #[derive(Clone, Debug, Deserialize, Queryable, Serialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
 pub struct Book {
     pub id: i32,
     pub name: String,
     pub chapter_count: i32,
     pub testament: Testament,
+    /// Whether the book is outside the Protestant canon (e.g. Tobit).
+    pub deuterocanonical: bool,
+}
+
+/// Model representing a key issued to an integrator of the API. The key
+/// itself is only shown when it's created.
+#[derive(Clone, Debug, Deserialize, PartialEq, Queryable, Serialize)]
+pub struct ApiKey {
+    pub id: i32,
+    /// Who the key was issued to.
+    pub name: String,
+    pub created_at: String,
+    /// When the key was revoked, if it was.
+    pub revoked_at: Option<String>,
 }
 
 /// Model representing a Bible book's abbreviation.
@@ -66,6 +90,136 @@ pub struct BookAbbreviation {
     pub abbreviation: String,
 }
 
+/// Model representing a Bible book's name in another language.
+#[derive(Clone, Debug, Deserialize, Queryable)]
+pub struct BookAlias {
+    pub id: i32,
+    pub book_id: i32,
+    pub locale: String,
+    pub alias: String,
+}
+
+/// Model representing a translation (version) of the Bible, such as the
+/// King James Version.
+#[derive(Clone, Debug, Deserialize, PartialEq, Queryable, Serialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct Translation {
+    pub id: i32,
+    /// Short identifier used in URLs (e.g. `kjv`).
+    pub abbreviation: String,
+    pub name: String,
+    pub language: String,
+    /// License the text is distributed under (e.g. `Public Domain`).
+    pub license: Option<String>,
+    /// Copyright holder of the text.
+    pub copyright: Option<String>,
+    /// Attribution statement the copyright holder requires alongside the text.
+    pub attribution: Option<String>,
+    /// Direction the text is written in, either `ltr` or `rtl`.
+    pub direction: String,
+    /// Versification scheme the translation numbers its verses with.
+    pub versification: String,
+    /// When the translation was imported, in seconds since the Unix epoch.
+    pub imported_at: i64,
+}
+
+/// Model representing where a verse numbered in one versification scheme
+/// falls in the standard (KJV) scheme.
+#[derive(Clone, Debug, Deserialize, Queryable)]
+pub struct VersificationMapping {
+    pub versification: String,
+    pub book: i32,
+    pub chapter: i32,
+    pub verse: i32,
+    pub standard_chapter: i32,
+    pub standard_verse: i32,
+}
+
+/// Model representing a passage to see along with a verse, pointing from the
+/// verse to a range of verses.
+#[derive(Clone, Debug, Deserialize, PartialEq, Queryable, Serialize)]
+pub struct CrossReference {
+    pub id: i32,
+    pub book: i32,
+    pub chapter: i32,
+    pub verse: i32,
+    pub to_book: i32,
+    pub to_chapter: i32,
+    pub to_verse: i32,
+    pub to_end_chapter: i32,
+    pub to_end_verse: i32,
+    /// How relevant the cross-reference was voted to be, if the dataset it
+    /// was imported from ranks them.
+    pub votes: i32,
+}
+
+impl CrossReference {
+    /// Gets the reference of the passage this points to, in the given book.
+    pub fn to_reference(&self, book: &Book) -> Reference {
+        Reference {
+            book: book.name.to_owned(),
+            chapter: self.to_chapter,
+            verses: Some(self.to_verse..=self.to_end_verse),
+            end_chapter: (self.to_end_chapter != self.to_chapter).then(|| self.to_end_chapter),
+            additional_verses: vec![],
+            start_part: None,
+            end_part: None,
+        }
+    }
+}
+
+/// Model representing a verse picked out in [Stats] for its length.
+#[derive(Clone, Debug, Deserialize, PartialEq, Queryable, Serialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct VerseLength {
+    /// Name of the verse's book.
+    pub book: String,
+    pub chapter: i32,
+    pub verse: i32,
+    pub words: String,
+    pub word_count: i64,
+}
+
+/// Model representing counts of the books, chapters, verses and words of a
+/// translation, or of one of its books.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub struct Stats {
+    pub book_count: i64,
+    pub chapter_count: i64,
+    pub verse_count: i64,
+    pub word_count: i64,
+    /// Verse with the most words, the first of them if several have as many.
+    pub longest_verse: Option<VerseLength>,
+    /// Verse with the fewest words, the first of them if several have as few.
+    pub shortest_verse: Option<VerseLength>,
+}
+
+/// Model representing the problems found checking the verses of a
+/// translation against the verses its versification scheme numbers.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct IntegrityReport {
+    /// Books whose number of chapters isn't the one expected, with the
+    /// number they have and the number expected.
+    pub chapter_counts: Vec<(String, usize, usize)>,
+    /// Verses that the books of the translation should have but don't.
+    pub missing: Vec<Reference>,
+    /// Verses that the translation has but its scheme doesn't number.
+    pub unexpected: Vec<Reference>,
+    /// Verses that the translation has more than once.
+    pub duplicates: Vec<Reference>,
+    /// Whether the verses are stored out of canonical order, in which case
+    /// searches list them out of order too.
+    pub out_of_order: bool,
+}
+
+impl IntegrityReport {
+    /// Checks whether no problems were found.
+    pub fn is_ok(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
 /// Model representing a full text search Bible verse.
 #[derive(Clone, Debug, Deserialize, Queryable, Serialize)]
 pub struct VerseFTS {
@@ -76,5 +230,19 @@ pub struct VerseFTS {
     pub rank: f32,
 }
 
+/// Model representing a verse matching a search in several translations.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct VerseMatches {
+    pub book: Book,
+    pub chapter: i32,
+    pub verse: i32,
+    /// Text of the verse in each translation it matched in, with the matched
+    /// words wrapped in `<mark>` tags.
+    pub matches: Vec<(Translation, String)>,
+}
+
 mod reference;
 pub use self::reference::Reference;
+mod search;
+pub(crate) use self::search::{tokenize, Token};
+pub use self::search::{ResultSet, SearchMode, SearchOptions, SearchOrder, SearchScope};
@@ -6,40 +6,186 @@ use lazy_static::lazy_static;
 use regex::{Match, Regex};
 use serde_derive::{Deserialize, Serialize};
 
+use super::Book;
 use crate::DbError;
 
 /// Model representing a Bible reference used to look up a
 /// passage in the database.
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
 pub struct Reference {
     pub book: String,
     pub chapter: i32,
+    #[cfg_attr(feature = "utoipa", schema(value_type = Option<Object>))]
     pub verses: Option<RangeInclusive<i32>>,
+    /// Last chapter of a passage that spans chapters (e.g. Genesis 1:1-2:3).
+    /// The verse range then starts in `chapter` and ends in this chapter.
+    pub end_chapter: Option<i32>,
+    /// Further verses listed after the first range (e.g. `18` and `20-21`
+    /// in John 3:16,18,20-21). These are always in the same chapter.
+    #[cfg_attr(feature = "utoipa", schema(value_type = Vec<Object>))]
+    pub additional_verses: Vec<RangeInclusive<i32>>,
+    /// Part of the first verse that was asked for (e.g. `a` in John 3:16a).
+    /// Verses can't be split, so the whole verse is still included.
+    #[cfg_attr(feature = "utoipa", schema(value_type = Option<String>))]
+    pub start_part: Option<char>,
+    /// Part of the last verse that was asked for (e.g. `a` in John 3:16-18a).
+    #[cfg_attr(feature = "utoipa", schema(value_type = Option<String>))]
+    pub end_part: Option<char>,
+}
+
+impl Reference {
+    /// Verse number ending a range that runs to the end of the chapter, as
+    /// in the "ff" of Romans 5:12ff.
+    pub const CHAPTER_END: i32 = i32::MAX;
+
+    /// Gets the last chapter in the passage.
+    pub fn last_chapter(&self) -> i32 {
+        self.end_chapter.unwrap_or(self.chapter)
+    }
+
+    /// Reads the lone chapter numbers of a reference to a book with only one
+    /// chapter as verses (e.g. Jude 6 as Jude 1:6), since references to
+    /// those books usually leave the chapter out. References to the chapter
+    /// itself (e.g. Jude 1) are left as they are. Parts of verses need their
+    /// chapter, since parsing can't tell them from parts of chapters, which
+    /// aren't allowed (e.g. Jude 1:6a, but not Jude 6a).
+    pub fn in_book(&self, book: &Book) -> Reference {
+        let mut reference = self.clone();
+        if book.chapter_count == 1
+            && reference.verses.is_none()
+            && (reference.chapter != 1 || reference.end_chapter.is_some())
+        {
+            reference.verses = Some(reference.chapter..=reference.last_chapter());
+            reference.chapter = 1;
+            reference.end_chapter = None;
+        }
+        reference
+    }
+
+    /// Gets the chapters the passage spans.
+    pub fn chapters(&self) -> RangeInclusive<i32> {
+        self.chapter..=self.last_chapter()
+    }
+
+    /// Checks whether the passage is a whole chapter (e.g. John 3).
+    pub fn is_chapter(&self) -> bool {
+        self.verses.is_none() && self.end_chapter.is_none() && self.additional_verses.is_empty()
+    }
+
+    /// Checks whether a verse is part of the passage.
+    pub fn contains(&self, chapter: i32, verse: i32) -> bool {
+        if !self.chapters().contains(&chapter) {
+            return false;
+        }
+        match self.verses {
+            Some(ref verses) => {
+                ((chapter > self.chapter || verse >= *verses.start())
+                    && (chapter < self.last_chapter() || verse <= *verses.end()))
+                    || self.additional_verses.iter().any(|vs| vs.contains(&verse))
+            }
+            None => true,
+        }
+    }
+
+    /// Gets the last verse listed in the passage, if it is limited to
+    /// certain verses.
+    pub fn last_verse(&self) -> Option<i32> {
+        self.verses
+            .iter()
+            .chain(self.additional_verses.iter())
+            .map(|vs| *vs.end())
+            .max()
+    }
+
+    /// Parses references separated by semicolons (e.g. `John 3:16; Psalm 23`).
+    ///
+    /// Fails if any of the references is invalid.
+    pub fn parse_list(s: &str) -> Result<Vec<Reference>, DbError> {
+        let references = s
+            .split(';')
+            .map(str::trim)
+            .filter(|r| !r.is_empty())
+            .map(str::parse)
+            .collect::<Result<Vec<Reference>, DbError>>()?;
+        if references.is_empty() {
+            return Err(invalid_reference(s));
+        }
+        Ok(references)
+    }
 }
 
 impl fmt::Display for Reference {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_first_range(f)?;
+        for verses in &self.additional_verses {
+            if verses.start() == verses.end() {
+                write!(f, ",{}", verses.start())?;
+            } else {
+                write!(f, ",{}-{}", verses.start(), verses.end())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Reference {
+    /// Writes the book, chapter, and first verse range of the reference.
+    fn fmt_first_range(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            Reference {
+                book,
+                chapter,
+                verses: Some(verses),
+                end_chapter: Some(end_chapter),
+                start_part,
+                end_part,
+                ..
+            } if end_chapter != chapter => write!(
+                f,
+                "{} {}:{}-{}:{}",
+                book,
+                chapter,
+                VerseNumber(*verses.start(), *start_part),
+                end_chapter,
+                VerseNumber(*verses.end(), *end_part)
+            ),
+            Reference {
+                book,
+                chapter,
+                verses: None,
+                end_chapter: Some(end_chapter),
+                ..
+            } if end_chapter != chapter => write!(f, "{} {}-{}", book, chapter, end_chapter),
             Reference {
                 book,
                 chapter,
                 verses: None,
+                ..
             } => write!(f, "{} {}", book, chapter),
             Reference {
                 book,
                 chapter,
                 verses: Some(verses),
+                start_part,
+                end_part,
+                ..
             } => {
-                if verses.start() == verses.end() {
-                    write!(f, "{} {}:{}", book, chapter, verses.start())
+                let start = VerseNumber(*verses.start(), *start_part);
+                if verses.start() == verses.end()
+                    && end_part.filter(|p| Some(*p) != *start_part).is_none()
+                {
+                    write!(f, "{} {}:{}", book, chapter, start)
+                } else if *verses.end() == Reference::CHAPTER_END {
+                    write!(f, "{} {}:{}ff", book, chapter, start)
                 } else {
                     write!(
                         f,
                         "{} {}:{}-{}",
                         book,
                         chapter,
-                        verses.start(),
-                        verses.end()
+                        start,
+                        VerseNumber(*verses.end(), *end_part)
                     )
                 }
             }
@@ -47,55 +193,183 @@ impl fmt::Display for Reference {
     }
 }
 
+/// Verse number written with the part of the verse, if there is one.
+struct VerseNumber(i32, Option<char>);
+
+impl fmt::Display for VerseNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)?;
+        match self.1 {
+            Some(part) => write!(f, "{}", part),
+            None => Ok(()),
+        }
+    }
+}
+
 impl FromStr for Reference {
     type Err = DbError;
 
     fn from_str(s: &str) -> Result<Reference, Self::Err> {
         lazy_static! {
             static ref REF_RE: Regex =
-                Regex::new(r"^(\w+(?: [a-zA-Z]+(?: [a-zA-Z]+)?)?)(?:\.| )((?:[0-9\-:\.])+)$")
+                Regex::new(r"^(\w+\.?(?: \p{L}+(?: \p{L}+)?)?)(?:\.| )((?:[0-9\-:\.,a-fvV])+)$")
                     .unwrap();
-            static ref CV_RE: Regex =
-                Regex::new(r"^(\d{1,3})(?:[:\.](\d{1,3})?(?:-(\d{1,3}))?)?$").unwrap();
+            static ref CV_RE: Regex = Regex::new(
+                r"^(\d{1,3})(?:[:\.vV](\d{1,3})?(?:-(\d{1,3})(?:[:\.vV](\d{1,3}))?)?|-(\d{1,3}))?$"
+            )
+            .unwrap();
+            static ref LIST_RE: Regex = Regex::new(r"^(\d{1,3})(?:-(\d{1,3}))?$").unwrap();
         }
 
-        let ref_caps = REF_RE.captures(s).ok_or_else(|| invalid_reference(s))?;
+        let ref_caps = REF_RE
+            .captures(s.trim())
+            .ok_or_else(|| invalid_reference(s))?;
         match (ref_caps.get(1), ref_caps.get(2)) {
             // Book and chapter/verse reference
             (Some(book), Some(cv)) => {
+                // Citations may end with "f" (and the following verse) or
+                // "ff" (and the rest of the chapter)
+                let (cv, suffix) = match cv.as_str().strip_suffix("ff") {
+                    Some(cv) => (cv, Some("ff")),
+                    None => match cv.as_str().strip_suffix('f') {
+                        Some(cv) => (cv, Some("f")),
+                        None => (cv.as_str(), None),
+                    },
+                };
+                // Verses after the first comma are listed on their own
+                let mut cv_parts = cv.split(',');
+                let (first_part, start_part, end_part) =
+                    split_verse_parts(cv_parts.next().unwrap_or_default());
                 let cv_caps = CV_RE
-                    .captures(cv.as_str())
+                    .captures(&first_part)
                     .ok_or_else(|| invalid_reference(s))?;
                 let book = book.as_str().to_string();
 
-                match (cv_caps.get(1), cv_caps.get(2), cv_caps.get(3)) {
+                let mut reference = match (
+                    cv_caps.get(1),
+                    cv_caps.get(2),
+                    cv_caps.get(3),
+                    cv_caps.get(4),
+                    cv_caps.get(5),
+                ) {
                     // Chapter only
-                    (Some(chapter), None, None) => Ok(Reference {
+                    (Some(chapter), None, None, None, None) => Ok(Reference {
                         book,
                         chapter: parse_num_match(chapter)?,
                         verses: None,
+                        end_chapter: None,
+                        additional_verses: vec![],
+                        start_part: None,
+                        end_part: None,
                     }),
+                    // More than one chapter
+                    (Some(chapter), None, None, None, Some(end_chapter)) => {
+                        let chapter = parse_num_match(chapter)?;
+                        let end_chapter = parse_num_match(end_chapter)?;
+                        if end_chapter < chapter {
+                            return Err(invalid_reference(s));
+                        }
+                        Ok(Reference {
+                            book,
+                            chapter,
+                            verses: None,
+                            end_chapter: (end_chapter != chapter).then_some(end_chapter),
+                            additional_verses: vec![],
+                            start_part: None,
+                            end_part: None,
+                        })
+                    }
                     // Chapter and one verse
-                    (Some(chapter), Some(verse), None) => {
+                    (Some(chapter), Some(verse), None, None, None) => {
                         let verse = parse_num_match(verse)?;
                         Ok(Reference {
                             book,
                             chapter: parse_num_match(chapter)?,
                             verses: Some(verse..=verse),
+                            end_chapter: None,
+                            additional_verses: vec![],
+                            start_part: None,
+                            end_part: None,
                         })
                     }
                     // Chapter with more than one verse
-                    (Some(chapter), Some(verse_start), Some(verse_end)) => {
+                    (Some(chapter), Some(verse_start), Some(verse_end), None, None) => {
                         let verse_start = parse_num_match(verse_start)?;
                         let verse_end = parse_num_match(verse_end)?;
                         Ok(Reference {
                             book,
                             chapter: parse_num_match(chapter)?,
                             verses: Some(verse_start..=verse_end),
+                            end_chapter: None,
+                            additional_verses: vec![],
+                            start_part: None,
+                            end_part: None,
+                        })
+                    }
+                    // Verses spanning more than one chapter
+                    (
+                        Some(chapter),
+                        Some(verse_start),
+                        Some(end_chapter),
+                        Some(verse_end),
+                        None,
+                    ) => {
+                        let chapter = parse_num_match(chapter)?;
+                        let end_chapter = parse_num_match(end_chapter)?;
+                        if end_chapter < chapter {
+                            return Err(invalid_reference(s));
+                        }
+                        Ok(Reference {
+                            book,
+                            chapter,
+                            verses: Some(
+                                parse_num_match(verse_start)?..=parse_num_match(verse_end)?,
+                            ),
+                            end_chapter: (end_chapter != chapter).then_some(end_chapter),
+                            additional_verses: vec![],
+                            start_part: None,
+                            end_part: None,
                         })
                     }
                     _ => Err(invalid_reference(s)),
+                }?;
+
+                // Parts can only be asked for of verses
+                if start_part.is_some() || end_part.is_some() {
+                    if reference.verses.is_none() {
+                        return Err(invalid_reference(s));
+                    }
+                    reference.start_part = start_part;
+                    reference.end_part = end_part;
+                }
+
+                if let Some(suffix) = suffix {
+                    // Only a single verse can be followed by others
+                    let verse = match reference.verses {
+                        Some(ref vs) if vs.start() == vs.end() && !cv.contains(',') => *vs.start(),
+                        _ => return Err(invalid_reference(s)),
+                    };
+                    let verse_end = match suffix {
+                        "f" => verse + 1,
+                        _ => Reference::CHAPTER_END,
+                    };
+                    reference.verses = Some(verse..=verse_end);
+                }
+
+                for part in cv_parts {
+                    // Listing verses only makes sense within one chapter
+                    if reference.verses.is_none() || reference.end_chapter.is_some() {
+                        return Err(invalid_reference(s));
+                    }
+                    let list_caps = LIST_RE.captures(part).ok_or_else(|| invalid_reference(s))?;
+                    let verse_start = parse_num_match(list_caps.get(1).unwrap())?;
+                    let verse_end = match list_caps.get(2) {
+                        Some(verse_end) => parse_num_match(verse_end)?,
+                        None => verse_start,
+                    };
+                    reference.additional_verses.push(verse_start..=verse_end);
                 }
+                Ok(reference)
             }
             _ => Err(invalid_reference(s)),
         }
@@ -109,6 +383,29 @@ fn parse_num_match(m: Match<'_>) -> Result<i32, DbError> {
     })
 }
 
+/// Splits the letters marking parts of verses (e.g. the `a` in `3:16a`) off
+/// the start and end of a chapter and verse range.
+fn split_verse_parts(cv: &str) -> (String, Option<char>, Option<char>) {
+    let strip = |numbers: &str| match numbers.char_indices().last() {
+        Some((i, part @ 'a'..='e')) if numbers[..i].ends_with(|c: char| c.is_ascii_digit()) => {
+            (numbers[..i].to_string(), Some(part))
+        }
+        _ => (numbers.to_string(), None),
+    };
+
+    match cv.split_once('-') {
+        Some((start, end)) => {
+            let (start, start_part) = strip(start);
+            let (end, end_part) = strip(end);
+            (format!("{}-{}", start, end), start_part, end_part)
+        }
+        None => {
+            let (start, start_part) = strip(cv);
+            (start, start_part, None)
+        }
+    }
+}
+
 /// Create an invalid reference error from the input.
 fn invalid_reference(s: &str) -> DbError {
     DbError::InvalidReference {
@@ -118,7 +415,7 @@ fn invalid_reference(s: &str) -> DbError {
 
 #[cfg(test)]
 mod tests {
-    use crate::models::Reference;
+    use crate::models::{Book, Reference, Testament};
 
     #[test]
     fn fmt() {
@@ -141,7 +438,11 @@ mod tests {
                 Reference {
                     book: (*book).to_string(),
                     chapter: *chapter,
-                    verses: verses.to_owned()
+                    verses: verses.to_owned(),
+                    end_chapter: None,
+                    additional_verses: vec![],
+                    start_part: None,
+                    end_part: None,
                 }
             );
         });
@@ -155,6 +456,8 @@ mod tests {
             ("3 John 1", "3 John", 1, None),
             ("Exodus 20", "Exodus", 20, None),
             ("1 Cor 4", "1 Cor", 4, None),
+            ("1 Maccabees 2:3", "1 Maccabees", 2, Some(3..=3)),
+            ("Wisdom of Solomon 7", "Wisdom of Solomon", 7, None),
             ("John 1:1", "John", 1, Some(1..=1)),
             ("I Timothy 3:16", "I Timothy", 3, Some(16..=16)),
             ("1 Timothy 3:16-18", "1 Timothy", 3, Some(16..=18)),
@@ -166,11 +469,195 @@ mod tests {
                 Reference {
                     book: (*book).to_string(),
                     chapter: *chapter,
-                    verses: verses.to_owned()
+                    verses: verses.to_owned(),
+                    end_chapter: None,
+                    additional_verses: vec![],
+                    start_part: None,
+                    end_part: None,
                 }
                 .to_string(),
                 (*expected).to_string()
             );
         });
     }
+
+    #[test]
+    fn cross_chapter() {
+        let expected = Reference {
+            book: "Genesis".to_string(),
+            chapter: 1,
+            verses: Some(31..=3),
+            end_chapter: Some(2),
+            additional_verses: vec![],
+            start_part: None,
+            end_part: None,
+        };
+        assert_eq!("Genesis 1:31-2:3".parse::<Reference>().unwrap(), expected);
+        assert_eq!("Genesis.1.31-2.3".parse::<Reference>().unwrap(), expected);
+        assert_eq!(expected.to_string(), "Genesis 1:31-2:3");
+
+        assert!(expected.contains(1, 31));
+        assert!(expected.contains(2, 1));
+        assert!(expected.contains(2, 3));
+        assert!(!expected.contains(1, 30));
+        assert!(!expected.contains(2, 4));
+
+        // Ending in the same chapter is a plain verse range
+        assert_eq!(
+            "John 3:16-3:18".parse::<Reference>().unwrap().to_string(),
+            "John 3:16-18"
+        );
+        assert!("Genesis 2:1-1:3".parse::<Reference>().is_err());
+    }
+
+    #[test]
+    fn verse_list() {
+        let reference: Reference = "John 3:16,18,20-21".parse().unwrap();
+        assert_eq!(reference.verses, Some(16..=16));
+        assert_eq!(reference.additional_verses, vec![18..=18, 20..=21]);
+        assert_eq!(reference.to_string(), "John 3:16,18,20-21");
+        assert_eq!(reference.last_verse(), Some(21));
+
+        assert!(reference.contains(3, 16));
+        assert!(reference.contains(3, 21));
+        assert!(!reference.contains(3, 17));
+        assert!(!reference.contains(4, 18));
+
+        assert!("John 3,4".parse::<Reference>().is_err());
+        assert!("John 3:16-4:1,5".parse::<Reference>().is_err());
+        assert!("John 3:16,".parse::<Reference>().is_err());
+    }
+
+    #[test]
+    fn parse_list() {
+        let references = Reference::parse_list("John 3:16; Romans 8:28; Psalm 23").unwrap();
+        let strings: Vec<String> = references.iter().map(|r| r.to_string()).collect();
+        assert_eq!(strings, vec!["John 3:16", "Romans 8:28", "Psalm 23"]);
+
+        assert_eq!(Reference::parse_list("John 3:16").unwrap().len(), 1);
+        assert!(Reference::parse_list("John 3:16; Romans").is_err());
+        assert!(Reference::parse_list(" ; ").is_err());
+    }
+
+    #[test]
+    fn localized_book_names() {
+        let reference: Reference = "Juan 3:16".parse().unwrap();
+        assert_eq!(reference.book, "Juan");
+        assert_eq!(reference.verses, Some(16..=16));
+
+        let reference: Reference = "1. Mose 2".parse().unwrap();
+        assert_eq!(reference.book, "1. Mose");
+        assert_eq!(reference.chapter, 2);
+
+        let reference: Reference = "1. Könige.8.27".parse().unwrap();
+        assert_eq!(reference.book, "1. Könige");
+        assert_eq!(reference.chapter, 8);
+    }
+
+    #[test]
+    fn following_verses() {
+        let reference: Reference = "Romans 5:12f".parse().unwrap();
+        assert_eq!(reference.verses, Some(12..=13));
+        assert_eq!(reference.to_string(), "Romans 5:12-13");
+
+        let reference: Reference = "Romans 5:12ff".parse().unwrap();
+        assert_eq!(reference.verses, Some(12..=Reference::CHAPTER_END));
+        assert_eq!(reference.to_string(), "Romans 5:12ff");
+        assert!(reference.contains(5, 21));
+        assert!(!reference.contains(6, 1));
+
+        assert!("Romans 5ff".parse::<Reference>().is_err());
+        assert!("Romans 5:12-14ff".parse::<Reference>().is_err());
+        assert!("Romans 5:12,14f".parse::<Reference>().is_err());
+        assert!("Romans 5:12fff".parse::<Reference>().is_err());
+    }
+
+    #[test]
+    fn chapter_range() {
+        let reference: Reference = "Genesis 1-3".parse().unwrap();
+        assert_eq!(reference.chapter, 1);
+        assert_eq!(reference.verses, None);
+        assert_eq!(reference.end_chapter, Some(3));
+        assert_eq!(reference.to_string(), "Genesis 1-3");
+        assert!(reference.contains(3, 24));
+        assert!(!reference.contains(4, 1));
+
+        let reference: Reference = "Psalm 120-134".parse().unwrap();
+        assert_eq!(reference.chapters(), 120..=134);
+
+        assert!("Genesis 3-1".parse::<Reference>().is_err());
+        assert!("Genesis 1-3,5".parse::<Reference>().is_err());
+    }
+
+    #[test]
+    fn single_chapter_books() {
+        let book = |chapter_count: i32| Book {
+            id: 65,
+            name: "Jude".to_string(),
+            chapter_count,
+            testament: Testament::New,
+            deuterocanonical: false,
+        };
+        vec![
+            ("Jude 6", 1, 1, Some(6..=6)),
+            ("Obad. 3-4", 1, 1, Some(3..=4)),
+            ("Jude 1", 1, 1, None),
+            ("Jude 1:6", 1, 1, Some(6..=6)),
+            ("John 6", 21, 6, None),
+            ("1 John 2", 5, 2, None),
+        ]
+        .iter()
+        .for_each(|(s, chapter_count, chapter, verses)| {
+            let reference = s
+                .parse::<Reference>()
+                .unwrap()
+                .in_book(&book(*chapter_count));
+            assert_eq!(reference.chapter, *chapter, "{}", s);
+            assert_eq!(reference.verses, *verses, "{}", s);
+            assert_eq!(reference.end_chapter, None, "{}", s);
+        });
+    }
+
+    #[test]
+    fn loose_formatting() {
+        vec![
+            "jn 3.16",
+            "JOHN 3v16",
+            "John 3V16",
+            "  John 3:16 ",
+            "John.3.16",
+        ]
+        .iter()
+        .for_each(|s| {
+            let reference: Reference = s.parse().unwrap();
+            assert_eq!(reference.chapter, 3, "{}", s);
+            assert_eq!(reference.verses, Some(16..=16), "{}", s);
+        });
+    }
+
+    #[test]
+    fn verse_parts() {
+        let reference: Reference = "John 3:16a".parse().unwrap();
+        assert_eq!(reference.verses, Some(16..=16));
+        assert_eq!(reference.start_part, Some('a'));
+        assert_eq!(reference.end_part, None);
+        assert_eq!(reference.to_string(), "John 3:16a");
+
+        let reference: Reference = "John 3:16b-18a".parse().unwrap();
+        assert_eq!(reference.verses, Some(16..=18));
+        assert_eq!(reference.start_part, Some('b'));
+        assert_eq!(reference.end_part, Some('a'));
+        assert_eq!(reference.to_string(), "John 3:16b-18a");
+
+        let reference: Reference = "Genesis 1:31b-2:3a".parse().unwrap();
+        assert_eq!(reference.to_string(), "Genesis 1:31b-2:3a");
+
+        let reference: Reference = "Jude 1:6a".parse().unwrap();
+        assert_eq!(reference.to_string(), "Jude 1:6a");
+        // Parsing doesn't know Jude has one chapter, so 6 is a chapter here
+        assert!("Jude 6a".parse::<Reference>().is_err());
+
+        assert!("John 3a".parse::<Reference>().is_err());
+        assert!("John 3:16ab".parse::<Reference>().is_err());
+    }
 }
@@ -0,0 +1,573 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::str::{Chars, FromStr};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_derive::{Deserialize, Serialize};
+
+use super::Testament;
+use crate::DbError;
+
+/// Number of words terms can be apart in a `NEAR` group without a distance,
+/// which is the default in SQLite's full text search.
+const NEAR_DISTANCE: usize = 10;
+
+/// Characters of the URL-safe base64 alphabet that result set tokens use.
+const TOKEN_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// Max number of verses in a result set token, which is more than any
+/// translation has.
+const RESULT_SET_LIMIT: u32 = 100_000;
+
+/// How the verses in a search result were matched.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum SearchMode {
+    /// The query was a reference, so the verses were looked up directly.
+    Reference,
+    /// Verses contain all of the words, in any order.
+    Words,
+    /// Verses contain the quoted words in order (e.g. `"in the beginning"`).
+    Phrase,
+    /// Verses match an expression using the `AND`, `OR` and `NOT` operators
+    /// or `-` exclusions (e.g. `faith AND works NOT law`).
+    Boolean,
+    /// Verses contain words within a number of words of each other (e.g.
+    /// `NEAR(faith works, 5)`).
+    Proximity,
+    /// Verses match a regular expression. This is never detected from the
+    /// query, so it has to be asked for.
+    Regex,
+}
+
+impl SearchMode {
+    /// Gets the mode words are matched with for a search query.
+    ///
+    /// A query with any operators in it is a boolean search, otherwise a
+    /// query with a `NEAR` group is a proximity search, and otherwise a
+    /// query with any quoted words in it is a phrase search.
+    pub fn of(query: &str) -> Self {
+        let tokens = tokenize(query);
+        if tokens
+            .iter()
+            .any(|t| matches!(t, Token::And | Token::Or | Token::Not))
+        {
+            Self::Boolean
+        } else if tokens.iter().any(|t| matches!(t, Token::Near { .. })) {
+            Self::Proximity
+        } else if tokens.iter().any(|t| matches!(t, Token::Phrase(_))) {
+            Self::Phrase
+        } else {
+            Self::Words
+        }
+    }
+}
+
+/// Order that search matches are listed in.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchOrder {
+    /// Best matches first, ranked by how often and how closely the words
+    /// appear (BM25).
+    Relevance,
+    /// The order of the verses in the Bible.
+    Canonical,
+}
+
+impl SearchOrder {
+    /// Gets the order to list matches for a search query in when none was
+    /// asked for.
+    ///
+    /// Matches for more than one word are ranked by relevance, while a
+    /// single word lists them in canonical order like a concordance would.
+    pub fn default_for(query: &str) -> Self {
+        if word_count(&tokenize(query)) > 1 {
+            Self::Relevance
+        } else {
+            Self::Canonical
+        }
+    }
+}
+
+/// Counts the words in search tokens, including the words of phrases.
+fn word_count(tokens: &[Token]) -> usize {
+    tokens
+        .iter()
+        .map(|t| match t {
+            Token::Word(_) | Token::Prefix(_) => 1,
+            Token::Phrase(phrase) => phrase.split(' ').count(),
+            Token::Near { terms, .. } => word_count(terms),
+            Token::And | Token::Or | Token::Not => 0,
+        })
+        .sum()
+}
+
+impl Default for SearchOrder {
+    fn default() -> Self {
+        Self::Relevance
+    }
+}
+
+/// Options for running a search.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchOptions {
+    /// Books to search in.
+    pub scope: SearchScope,
+    /// Order to list the matches in.
+    pub order: SearchOrder,
+    /// Page of matches to get, starting at 1.
+    pub page: i64,
+    /// Number of matches on a page.
+    pub per_page: i64,
+    /// Whether words only match whole words, so a trailing word isn't also
+    /// matched as the start of longer ones (e.g. "man" for "manna").
+    pub whole_word: bool,
+    /// Verses found by an earlier search, to only search within them.
+    pub within: Option<ResultSet>,
+}
+
+impl SearchOptions {
+    /// Default number of matches on a page.
+    pub const PER_PAGE: i64 = 15;
+
+    /// Gets the number of matches before the page.
+    pub fn offset(&self) -> i64 {
+        (self.page - 1) * self.per_page
+    }
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            scope: SearchScope::default(),
+            order: SearchOrder::default(),
+            page: 1,
+            per_page: Self::PER_PAGE,
+            whole_word: false,
+            within: None,
+        }
+    }
+}
+
+/// Constraints on the books a search looks in.
+///
+/// Every constraint that is given has to be met, so the empty (default)
+/// scope searches all of the books.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SearchScope {
+    /// Names of the books to search in (e.g. the Gospels).
+    pub books: Vec<String>,
+    /// Testament to search in.
+    pub testament: Option<Testament>,
+    /// Names of the first and last books of a range of books to search in
+    /// (e.g. Genesis to Deuteronomy).
+    pub range: Option<(String, String)>,
+}
+
+impl SearchScope {
+    /// Checks whether the scope searches all of the books.
+    pub fn is_empty(&self) -> bool {
+        self.books.is_empty() && self.testament.is_none() && self.range.is_none()
+    }
+}
+
+/// The verses found by a search, by their IDs, so a later search can be
+/// narrowed down to them.
+///
+/// Result sets are passed between searches as compact tokens, which are
+/// their [Display](fmt::Display) strings and are parsed with
+/// [FromStr]. Runs of consecutive IDs are encoded together as variable
+/// length numbers, which are then base64 encoded so they're safe in URLs.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ResultSet {
+    /// Sorted IDs, without duplicates.
+    ids: Vec<i32>,
+}
+
+impl ResultSet {
+    /// Creates a result set of the verses with the given IDs.
+    pub fn new(mut ids: Vec<i32>) -> Self {
+        ids.sort_unstable();
+        ids.dedup();
+        Self { ids }
+    }
+
+    /// Checks whether the verse with an ID is in the result set.
+    pub fn contains(&self, id: i32) -> bool {
+        self.ids.binary_search(&id).is_ok()
+    }
+
+    /// Gets the number of verses in the result set.
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// Checks whether the result set has no verses.
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+impl fmt::Display for ResultSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Each run of IDs is its distance from the end of the run before,
+        // then the number of IDs after its first
+        let mut bytes = vec![];
+        let mut previous = 0;
+        let mut ids = self.ids.iter().map(|&id| id as u32).peekable();
+        while let Some(start) = ids.next() {
+            let mut end = start;
+            while ids.next_if(|&id| id == end + 1).is_some() {
+                end += 1;
+            }
+            push_varint(&mut bytes, start - previous);
+            push_varint(&mut bytes, end - start);
+            previous = end;
+        }
+
+        for chunk in bytes.chunks(3) {
+            let n = chunk
+                .iter()
+                .enumerate()
+                .fold(0u32, |n, (i, &b)| n | ((b as u32) << (16 - 8 * i)));
+            for i in 0..=chunk.len() {
+                let c = TOKEN_ALPHABET[((n >> (18 - 6 * i)) & 0x3f) as usize];
+                write!(f, "{}", c as char)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ResultSet {
+    type Err = DbError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || DbError::InvalidSearch {
+            cause: "the result set to search within is invalid".to_string(),
+        };
+
+        let mut bytes = vec![];
+        let digits = s
+            .bytes()
+            .map(|c| {
+                TOKEN_ALPHABET
+                    .iter()
+                    .position(|&a| a == c)
+                    .ok_or_else(invalid)
+            })
+            .collect::<Result<Vec<usize>, DbError>>()?;
+        for chunk in digits.chunks(4) {
+            if chunk.len() == 1 {
+                return Err(invalid());
+            }
+            let n = chunk
+                .iter()
+                .enumerate()
+                .fold(0u32, |n, (i, &d)| n | ((d as u32) << (18 - 6 * i)));
+            for i in 0..chunk.len() - 1 {
+                bytes.push((n >> (16 - 8 * i)) as u8);
+            }
+        }
+
+        let mut numbers = vec![];
+        let mut n: u32 = 0;
+        let mut shift = 0;
+        for b in bytes {
+            if shift > 28 {
+                return Err(invalid());
+            }
+            n |= ((b & 0x7f) as u32) << shift;
+            shift += 7;
+            if b & 0x80 == 0 {
+                numbers.push(n);
+                n = 0;
+                shift = 0;
+            }
+        }
+        if shift != 0 || numbers.len() % 2 != 0 {
+            return Err(invalid());
+        }
+
+        let mut ids = vec![];
+        let mut previous: u32 = 0;
+        for run in numbers.chunks(2) {
+            let start = previous.checked_add(run[0]).ok_or_else(invalid)?;
+            let end = start.checked_add(run[1]).ok_or_else(invalid)?;
+            if end > i32::MAX as u32 || ids.len() as u32 + run[1] >= RESULT_SET_LIMIT {
+                return Err(invalid());
+            }
+            ids.extend((start..=end).map(|id| id as i32));
+            previous = end;
+        }
+        Ok(Self::new(ids))
+    }
+}
+
+/// Appends a number to bytes seven bits at a time, with the high bit set on
+/// every byte but the last.
+fn push_varint(bytes: &mut Vec<u8>, mut n: u32) {
+    while n >= 0x80 {
+        bytes.push(((n & 0x7f) as u8) | 0x80);
+        n >>= 7;
+    }
+    bytes.push(n as u8);
+}
+
+/// A part of a search query.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Token {
+    /// A word, with all characters other than letters stripped out.
+    Word(String),
+    /// The start of words, from a word ending in `*` (e.g. `bapti*`).
+    Prefix(String),
+    /// Quoted words, separated by single spaces.
+    Phrase(String),
+    /// Words, prefixes or phrases that are all within a distance (a number
+    /// of words) of each other, from a group like `NEAR(faith works, 5)`.
+    Near {
+        terms: Vec<Token>,
+        distance: usize,
+    },
+    And,
+    Or,
+    /// `NOT`, or the `-` before an excluded word or phrase.
+    Not,
+}
+
+/// Splits a search query into words, phrases and operators.
+///
+/// Operators (and `NEAR`) are only read in upper case, so "not" on its own
+/// is still searched for as a word. An unclosed quote or `NEAR` group runs
+/// to the end of the query, and words or phrases with no letters in them
+/// are left out.
+pub(crate) fn tokenize(query: &str) -> Vec<Token> {
+    lazy_static! {
+        static ref NOT_LETTERS: Regex = Regex::new(r"[^\p{L}\p{M} ]+").unwrap();
+    }
+
+    let mut tokens = vec![];
+    let mut chars = query.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let excluded = c == '-';
+        if excluded {
+            chars.next();
+        }
+
+        let token = if let Some(group) = take_near_group(&mut chars) {
+            near(&group)
+        } else if chars.next_if_eq(&'"').is_some() {
+            let phrase: String = chars.by_ref().take_while(|&c| c != '"').collect();
+            let phrase = NOT_LETTERS.replace_all(&phrase, "");
+            let words: Vec<&str> = phrase.split_whitespace().collect();
+            (!words.is_empty()).then(|| Token::Phrase(words.join(" ")))
+        } else {
+            match take_word(&mut chars).as_str() {
+                "AND" if !excluded => Some(Token::And),
+                "OR" if !excluded => Some(Token::Or),
+                "NOT" if !excluded => Some(Token::Not),
+                word => {
+                    let is_prefix = word.ends_with('*');
+                    let word = NOT_LETTERS.replace_all(word, "").into_owned();
+                    (!word.is_empty()).then(|| {
+                        if is_prefix {
+                            Token::Prefix(word)
+                        } else {
+                            Token::Word(word)
+                        }
+                    })
+                }
+            }
+        };
+
+        if let Some(token) = token {
+            if excluded {
+                tokens.push(Token::Not);
+            }
+            tokens.push(token);
+        }
+    }
+    tokens
+}
+
+/// Takes the inside of a `NEAR(...)` group, if one is next.
+fn take_near_group(chars: &mut Peekable<Chars>) -> Option<String> {
+    let mut ahead = chars.clone();
+    if !"NEAR(".chars().all(|c| ahead.next() == Some(c)) {
+        return None;
+    }
+    *chars = ahead;
+    Some(chars.by_ref().take_while(|&c| c != ')').collect())
+}
+
+/// Reads the inside of a `NEAR` group, which is the terms optionally
+/// followed by a comma and the distance.
+fn near(group: &str) -> Option<Token> {
+    let (terms, distance) = match group.rsplit_once(',') {
+        Some((terms, distance)) => match distance.trim().parse() {
+            Ok(distance) => (terms, distance),
+            Err(_) => (group, NEAR_DISTANCE),
+        },
+        None => (group, NEAR_DISTANCE),
+    };
+    let terms: Vec<Token> = tokenize(terms)
+        .into_iter()
+        .filter(|t| matches!(t, Token::Word(_) | Token::Prefix(_) | Token::Phrase(_)))
+        .collect();
+    (!terms.is_empty()).then(|| Token::Near { terms, distance })
+}
+
+/// Takes characters up to the next space or quote.
+fn take_word(chars: &mut Peekable<Chars>) -> String {
+    let mut word = String::new();
+    while let Some(c) = chars.next_if(|&c| !c.is_whitespace() && c != '"') {
+        word.push(c);
+    }
+    word
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn of() {
+        assert_eq!(SearchMode::of("in the beginning"), SearchMode::Words);
+        assert_eq!(SearchMode::of("\"in the beginning\""), SearchMode::Phrase);
+        assert_eq!(
+            SearchMode::of("\"in the beginning\" god"),
+            SearchMode::Phrase
+        );
+        // An unclosed quote runs to the end of the query
+        assert_eq!(SearchMode::of("god \"in the"), SearchMode::Phrase);
+        assert_eq!(SearchMode::of("god \"\""), SearchMode::Words);
+        assert_eq!(SearchMode::of("faith AND works"), SearchMode::Boolean);
+        assert_eq!(SearchMode::of("faith -\"the law\""), SearchMode::Boolean);
+        assert_eq!(SearchMode::of("faith and works"), SearchMode::Words);
+        assert_eq!(
+            SearchMode::of("NEAR(faith works, 5)"),
+            SearchMode::Proximity
+        );
+        assert_eq!(
+            SearchMode::of("NEAR(faith works) -law"),
+            SearchMode::Boolean
+        );
+        assert_eq!(SearchMode::of("near(faith works)"), SearchMode::Words);
+    }
+
+    #[test]
+    fn result_sets() {
+        let results = ResultSet::new(vec![5, 1, 2, 3, 1000, 1001, 70000]);
+        assert!(results.contains(1001));
+        assert!(!results.contains(4));
+        assert_eq!(results.len(), 6);
+        let token = results.to_string();
+        assert!(token
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() || c == b'-' || c == b'_'));
+        assert_eq!(token.parse::<ResultSet>().unwrap(), results);
+
+        let everything = ResultSet::new((1..=31102).collect());
+        assert!(everything.to_string().len() < 8);
+        assert_eq!(
+            everything.to_string().parse::<ResultSet>().unwrap(),
+            everything
+        );
+
+        assert_eq!("".parse::<ResultSet>().unwrap(), ResultSet::default());
+        for token in ["a", "AQ!", "gA", "__8P"] {
+            assert!(matches!(
+                token.parse::<ResultSet>(),
+                Err(DbError::InvalidSearch { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn default_orders() {
+        assert_eq!(SearchOrder::default_for("lamb"), SearchOrder::Canonical);
+        assert_eq!(
+            SearchOrder::default_for("-lamb bapti*"),
+            SearchOrder::Relevance
+        );
+        assert_eq!(
+            SearchOrder::default_for("\"the lamb\""),
+            SearchOrder::Relevance
+        );
+        assert_eq!(
+            SearchOrder::default_for("lamb AND 7"),
+            SearchOrder::Canonical
+        );
+        assert_eq!(
+            SearchOrder::default_for("NEAR(faith works)"),
+            SearchOrder::Relevance
+        );
+    }
+
+    #[test]
+    fn tokens() {
+        assert_eq!(
+            tokenize("faith AND works NOT law"),
+            vec![
+                Token::Word("faith".to_string()),
+                Token::And,
+                Token::Word("works".to_string()),
+                Token::Not,
+                Token::Word("law".to_string()),
+            ]
+        );
+        assert_eq!(
+            tokenize("LORD's -\"the  law\" OR-1 -"),
+            vec![
+                Token::Word("LORDs".to_string()),
+                Token::Not,
+                Token::Phrase("the law".to_string()),
+                Token::Word("OR".to_string()),
+            ]
+        );
+        assert_eq!(
+            tokenize("-AND not\"in the"),
+            vec![
+                Token::Not,
+                Token::Word("AND".to_string()),
+                Token::Word("not".to_string()),
+                Token::Phrase("in the".to_string()),
+            ]
+        );
+        assert_eq!(
+            tokenize("bapti* -jo* * b*c"),
+            vec![
+                Token::Prefix("bapti".to_string()),
+                Token::Not,
+                Token::Prefix("jo".to_string()),
+                Token::Word("bc".to_string()),
+            ]
+        );
+        assert_eq!(
+            tokenize("NEAR(faith \"the law\", 5) -NEAR(jo* AND, ?"),
+            vec![
+                Token::Near {
+                    terms: vec![
+                        Token::Word("faith".to_string()),
+                        Token::Phrase("the law".to_string()),
+                    ],
+                    distance: 5,
+                },
+                Token::Not,
+                Token::Near {
+                    terms: vec![Token::Prefix("jo".to_string())],
+                    distance: NEAR_DISTANCE,
+                },
+            ]
+        );
+        assert_eq!(tokenize("NEAR() NEAR(, 5)"), vec![]);
+    }
+}
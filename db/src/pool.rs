@@ -0,0 +1,293 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use diesel::connection::SimpleConnection;
+use diesel::r2d2::event::{CheckoutEvent, TimeoutEvent};
+use diesel::r2d2::{self, CustomizeConnection, HandleEvent};
+use diesel::{QueryResult, SqliteConnection};
+
+use crate::attach::attach;
+use crate::SqliteConnectionPool;
+
+/// Settings of a connection pool built with [build_pool](crate::build_pool).
+#[derive(Clone, Debug)]
+pub struct PoolConfig {
+    /// Most connections the pool opens at once.
+    pub max_size: u32,
+    /// Fewest idle connections the pool keeps open, or as many as its max
+    /// size if `None`.
+    pub min_idle: Option<u32>,
+    /// Longest a connection is waited for before giving up.
+    pub connection_timeout: Duration,
+    /// Metrics the pool counts its checkouts in, if any.
+    pub metrics: Option<PoolMetrics>,
+    /// Directory of translation files attached to each connection with
+    /// [attach_translations](crate::attach_translations), if any.
+    pub translations_dir: Option<PathBuf>,
+    /// Settings each connection is tuned with.
+    pub tuning: SqliteTuning,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_size: 15,
+            min_idle: None,
+            connection_timeout: Duration::from_secs(30),
+            metrics: None,
+            translations_dir: None,
+            tuning: SqliteTuning::default(),
+        }
+    }
+}
+
+impl PoolConfig {
+    /// Gets the customizer setting up each connection of the pool.
+    pub(crate) fn customizer(&self) -> ConnectionCustomizer {
+        ConnectionCustomizer {
+            tuning: self.tuning.clone(),
+            translations_dir: self.translations_dir.clone(),
+        }
+    }
+}
+
+/// SQLite settings (pragmas) a connection is tuned with. By default each
+/// connection waits a while for locks, rather than failing as soon as an
+/// import writes to the database, and the rest of SQLite's defaults are
+/// kept.
+#[derive(Clone, Debug)]
+pub struct SqliteTuning {
+    /// Whether the database is put in write-ahead log mode, which lets
+    /// connections read while another writes. The mode is kept in the
+    /// database file, so it stays on once it's been turned on.
+    pub wal: bool,
+    /// Most bytes of the database file that are memory-mapped, if set.
+    pub mmap_size: Option<i64>,
+    /// Pages of the database each connection caches, if set, or kibibytes
+    /// of them if negative, as SQLite takes it.
+    pub cache_size: Option<i64>,
+    /// Longest a connection waits for another to unlock the database before
+    /// giving up with a "database is locked" error.
+    pub busy_timeout: Duration,
+}
+
+impl Default for SqliteTuning {
+    fn default() -> Self {
+        Self {
+            wal: false,
+            mmap_size: None,
+            cache_size: None,
+            busy_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+impl SqliteTuning {
+    /// Sets the pragmas on a connection.
+    pub(crate) fn apply(&self, conn: &SqliteConnection) -> QueryResult<()> {
+        let mut pragmas = format!("PRAGMA busy_timeout = {};", self.busy_timeout.as_millis());
+        if self.wal {
+            pragmas.push_str("PRAGMA journal_mode = WAL;");
+        }
+        if let Some(size) = self.mmap_size {
+            pragmas.push_str(&format!("PRAGMA mmap_size = {};", size));
+        }
+        if let Some(size) = self.cache_size {
+            pragmas.push_str(&format!("PRAGMA cache_size = {};", size));
+        }
+        conn.batch_execute(&pragmas)
+    }
+}
+
+/// Customizer tuning each connection of a pool as it's opened, and
+/// attaching the translation files to it.
+#[derive(Debug)]
+pub(crate) struct ConnectionCustomizer {
+    tuning: SqliteTuning,
+    translations_dir: Option<PathBuf>,
+}
+
+impl CustomizeConnection<SqliteConnection, r2d2::Error> for ConnectionCustomizer {
+    fn on_acquire(&self, conn: &mut SqliteConnection) -> Result<(), r2d2::Error> {
+        self.tuning.apply(conn).map_err(r2d2::Error::QueryError)?;
+        if let Some(dir) = &self.translations_dir {
+            attach(dir, conn).map_err(r2d2::Error::QueryError)?;
+        }
+        Ok(())
+    }
+}
+
+/// Counts of the connections checked out of a pool, and of how long they
+/// were waited for, which clones of the metrics share.
+#[derive(Clone, Debug, Default)]
+pub struct PoolMetrics {
+    counts: Arc<PoolCounts>,
+}
+
+#[derive(Debug, Default)]
+struct PoolCounts {
+    checkouts: AtomicU64,
+    timeouts: AtomicU64,
+    wait_micros: AtomicU64,
+}
+
+impl PoolMetrics {
+    /// Gets the number of connections that have been checked out.
+    pub fn checkouts(&self) -> u64 {
+        self.counts.checkouts.load(Ordering::Relaxed)
+    }
+
+    /// Gets the number of times no connection was free before the
+    /// connection timeout.
+    pub fn timeouts(&self) -> u64 {
+        self.counts.timeouts.load(Ordering::Relaxed)
+    }
+
+    /// Gets the total time spent waiting for connections to be checked out.
+    pub fn wait_time(&self) -> Duration {
+        Duration::from_micros(self.counts.wait_micros.load(Ordering::Relaxed))
+    }
+}
+
+impl HandleEvent for PoolMetrics {
+    fn handle_checkout(&self, event: CheckoutEvent) {
+        self.counts.checkouts.fetch_add(1, Ordering::Relaxed);
+        self.counts
+            .wait_micros
+            .fetch_add(event.duration().as_micros() as u64, Ordering::Relaxed);
+    }
+
+    fn handle_timeout(&self, _: TimeoutEvent) {
+        self.counts.timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Pools of connections to read replicas of the database (e.g. copies kept
+/// up to date with Litestream), which reads are spread over in turn so they
+/// don't contend with writes to the primary database.
+#[derive(Default)]
+pub struct ReadReplicas {
+    pools: Vec<SqliteConnectionPool>,
+    next: AtomicUsize,
+}
+
+impl ReadReplicas {
+    /// Spreads reads over the pools of the replicas.
+    pub fn new(pools: Vec<SqliteConnectionPool>) -> Self {
+        Self {
+            pools,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Gets the pool of the next replica in turn, or the primary database's
+    /// pool if there are no replicas.
+    pub fn next<'a>(&'a self, primary: &'a SqliteConnectionPool) -> &'a SqliteConnectionPool {
+        if self.pools.is_empty() {
+            return primary;
+        }
+        let i = self.next.fetch_add(1, Ordering::Relaxed);
+        &self.pools[i % self.pools.len()]
+    }
+
+    /// Gets the number of replicas.
+    pub fn len(&self) -> usize {
+        self.pools.len()
+    }
+
+    /// Checks whether there are no replicas.
+    pub fn is_empty(&self) -> bool {
+        self.pools.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::ptr;
+
+    use diesel::dsl::sql;
+    use diesel::sql_types::Text;
+    use diesel::RunQueryDsl;
+
+    use super::*;
+    use crate::{build_pool, establish_tuned_connection};
+
+    #[test]
+    fn metrics() {
+        let metrics = PoolMetrics::default();
+        let pool = build_pool(
+            ":memory:",
+            &PoolConfig {
+                max_size: 1,
+                connection_timeout: Duration::from_millis(10),
+                metrics: Some(metrics.clone()),
+                ..PoolConfig::default()
+            },
+        );
+        assert_eq!(pool.max_size(), 1);
+
+        let conn = pool.get().unwrap();
+        // The only connection is checked out
+        assert!(pool.get().is_err());
+        drop(conn);
+        assert!(pool.get().is_ok());
+        assert_eq!((metrics.checkouts(), metrics.timeouts()), (2, 1));
+    }
+
+    #[test]
+    fn tuned() {
+        let dir = env::temp_dir().join("biblers-tuning-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let url = dir.join("tuned.db").to_string_lossy().into_owned();
+        let tuning = SqliteTuning {
+            wal: true,
+            cache_size: Some(-4096),
+            busy_timeout: Duration::from_millis(250),
+            ..SqliteTuning::default()
+        };
+
+        let check = |conn: &SqliteConnection| {
+            let pragma = |pragma: &str, column: &str| {
+                diesel::select(sql::<Text>(&format!(
+                    "(SELECT CAST({} AS TEXT) FROM pragma_{})",
+                    column, pragma
+                )))
+                .get_result::<String>(conn)
+                .unwrap()
+            };
+            assert_eq!(pragma("journal_mode", "journal_mode"), "wal");
+            assert_eq!(pragma("cache_size", "cache_size"), "-4096");
+            assert_eq!(pragma("busy_timeout", "timeout"), "250");
+        };
+        check(&establish_tuned_connection(&url, &tuning));
+        let pool = build_pool(
+            &url,
+            &PoolConfig {
+                tuning,
+                ..PoolConfig::default()
+            },
+        );
+        check(&pool.get().unwrap());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn round_robin() {
+        let pool = || build_pool(":memory:", &PoolConfig::default());
+        let primary = pool();
+        assert!(ptr::eq(ReadReplicas::default().next(&primary), &primary));
+
+        let replicas = ReadReplicas::new(vec![pool(), pool()]);
+        let first = replicas.next(&primary);
+        let second = replicas.next(&primary);
+        assert!(!ptr::eq(first, &primary) && !ptr::eq(second, &primary));
+        assert!(!ptr::eq(first, second));
+        assert!(ptr::eq(replicas.next(&primary), first));
+    }
+}
@@ -1,3 +1,13 @@
+table! {
+    api_keys (id) {
+        id -> Integer,
+        name -> Text,
+        key_hash -> Text,
+        created_at -> Text,
+        revoked_at -> Nullable<Text>,
+    }
+}
+
 table! {
     book_abbreviations (id) {
         id -> Integer,
@@ -6,12 +16,59 @@ table! {
     }
 }
 
+table! {
+    book_aliases (id) {
+        id -> Integer,
+        book_id -> Integer,
+        locale -> Text,
+        alias -> Text,
+    }
+}
+
 table! {
     books (id) {
         id -> Integer,
         name -> Text,
         chapter_count -> Integer,
         testament -> Text,
+        deuterocanonical -> Bool,
+    }
+}
+
+table! {
+    cross_references (id) {
+        id -> Integer,
+        book -> Integer,
+        chapter -> Integer,
+        verse -> Integer,
+        to_book -> Integer,
+        to_chapter -> Integer,
+        to_verse -> Integer,
+        to_end_chapter -> Integer,
+        to_end_verse -> Integer,
+        votes -> Integer,
+    }
+}
+
+table! {
+    daily_verses (id) {
+        id -> Integer,
+        reference -> Text,
+    }
+}
+
+table! {
+    translations (id) {
+        id -> Integer,
+        abbreviation -> Text,
+        name -> Text,
+        language -> Text,
+        license -> Nullable<Text>,
+        copyright -> Nullable<Text>,
+        attribution -> Nullable<Text>,
+        direction -> Text,
+        versification -> Text,
+        imported_at -> BigInt,
     }
 }
 
@@ -22,6 +79,10 @@ table! {
         chapter -> Integer,
         verse -> Integer,
         words -> Text,
+        translation_id -> Integer,
+        paragraph -> Bool,
+        poetry_level -> Integer,
+        line_break -> Bool,
     }
 }
 
@@ -32,6 +93,21 @@ table! {
         chapter -> Integer,
         verse -> Integer,
         words -> Text,
+        translation_id -> Integer,
+        paragraph -> Bool,
+        poetry_level -> Integer,
+        line_break -> Bool,
+    }
+}
+
+table! {
+    versification_mappings (versification, book, chapter, verse) {
+        versification -> Text,
+        book -> Integer,
+        chapter -> Integer,
+        verse -> Integer,
+        standard_chapter -> Integer,
+        standard_verse -> Integer,
     }
 }
 
@@ -52,7 +128,22 @@ table! {
 }
 
 joinable!(book_abbreviations -> books (book_id));
+joinable!(book_aliases -> books (book_id));
 joinable!(verses -> books (book));
+joinable!(verses -> translations (translation_id));
 joinable!(verses_html -> books (book));
+joinable!(verses_html -> translations (translation_id));
 
-allow_tables_to_appear_in_same_query!(book_abbreviations, books, verses, verses_html, words,);
+allow_tables_to_appear_in_same_query!(
+    api_keys,
+    book_abbreviations,
+    book_aliases,
+    books,
+    cross_references,
+    daily_verses,
+    translations,
+    verses,
+    verses_html,
+    versification_mappings,
+    words,
+);
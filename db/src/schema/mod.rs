@@ -9,10 +9,12 @@ table! {
         #[sql_name = "verses_fts"]
         text -> Text,
         words -> Text,
+        translation_id -> Integer,
         rank -> Float,
     }
 }
 
-allow_tables_to_appear_in_same_query!(books, verses_fts);
+allow_tables_to_appear_in_same_query!(books, translations, verses_fts);
+allow_tables_to_appear_in_same_query!(verses, verses_fts);
 
 mod auto;
@@ -0,0 +1,652 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use diesel::prelude::*;
+use lazy_static::lazy_static;
+use tantivy::collector::{Count, TopDocs};
+use tantivy::query::{BooleanQuery, Occur, PhraseQuery, Query, RegexQuery, TermQuery};
+use tantivy::schema::{Field, IndexRecordOption, Schema, FAST, INDEXED, STORED, TEXT};
+use tantivy::{doc, DocAddress, Document, Index, IndexReader, Term};
+
+use crate::models::*;
+use crate::sword_drill::{unfold_highlights, MARK_END, MARK_START};
+use crate::{fold, index_words, index_words_with_offsets, normalize, DbError, VerseFormat};
+use crate::{BookStore, Searcher, SwordDrill, SwordDrillable, VerseStore};
+
+/// Memory the index writer can use while building the search index.
+const INDEX_WRITER_HEAP: usize = 50_000_000;
+
+lazy_static! {
+    /// The search index used by [TantivyDrill], once it's been loaded.
+    static ref SEARCH_INDEX: RwLock<Option<Arc<SearchIndex>>> = RwLock::new(None);
+}
+
+/// Fields of the verses in the search index.
+#[derive(Clone, Copy, Debug)]
+struct Fields {
+    book: Field,
+    chapter: Field,
+    verse: Field,
+    translation_id: Field,
+    /// Verses in canonical order, counting down so the ordering by fast
+    /// field (which is descending) lists them from Genesis.
+    position: Field,
+    /// The words prepared with [index_words], which are searched.
+    words: Field,
+    /// The words as they are in the database, which are shown.
+    text: Field,
+}
+
+/// Builds the schema of the search index.
+///
+/// Fields are numbered in the order they're added, so an index that's
+/// opened has the same fields as the schema that's built.
+fn schema() -> (Schema, Fields) {
+    let mut builder = Schema::builder();
+    let fields = Fields {
+        book: builder.add_i64_field("book", INDEXED | STORED),
+        chapter: builder.add_i64_field("chapter", STORED),
+        verse: builder.add_i64_field("verse", STORED),
+        translation_id: builder.add_i64_field("translation_id", INDEXED),
+        position: builder.add_u64_field("position", FAST),
+        words: builder.add_text_field("words", TEXT),
+        text: builder.add_text_field("text", STORED),
+    };
+    (builder.build(), fields)
+}
+
+/// Converts a Tantivy error to a database error.
+fn index_error(e: tantivy::TantivyError) -> DbError {
+    DbError::SearchIndex {
+        cause: e.to_string(),
+    }
+}
+
+/// A Tantivy index of the verses in every translation.
+struct SearchIndex {
+    reader: IndexReader,
+    fields: Fields,
+}
+
+/// A search query built for the index, with the terms to highlight in
+/// its matches.
+struct IndexQuery {
+    query: Box<dyn Query>,
+    terms: Vec<String>,
+    prefixes: Vec<String>,
+}
+
+impl SearchIndex {
+    fn new(index: Index, fields: Fields) -> Result<Self, DbError> {
+        Ok(Self {
+            reader: index.reader().map_err(index_error)?,
+            fields,
+        })
+    }
+
+    /// Adds verses to an index, with the language of each verse's
+    /// translation by its ID.
+    fn add_verses(
+        index: &Index,
+        fields: Fields,
+        verses: Vec<Verse>,
+        languages: &HashMap<i32, String>,
+    ) -> Result<(), DbError> {
+        let mut writer = index.writer(INDEX_WRITER_HEAP).map_err(index_error)?;
+        for verse in verses {
+            let language = languages
+                .get(&verse.translation_id)
+                .map_or("", String::as_str);
+            let position =
+                ((verse.book as u64) << 32) | ((verse.chapter as u64) << 16) | verse.verse as u64;
+            writer
+                .add_document(doc!(
+                    fields.book => verse.book as i64,
+                    fields.chapter => verse.chapter as i64,
+                    fields.verse => verse.verse as i64,
+                    fields.translation_id => verse.translation_id as i64,
+                    fields.position => u64::MAX - position,
+                    fields.words => index_words(&verse.words, language),
+                    fields.text => verse.words,
+                ))
+                .map_err(index_error)?;
+        }
+        writer.commit().map_err(index_error)?;
+        Ok(())
+    }
+
+    fn term(&self, text: &str) -> Term {
+        Term::from_field_text(self.fields.words, text)
+    }
+
+    fn term_query(&self, text: &str) -> Box<dyn Query> {
+        Box::new(TermQuery::new(
+            self.term(text),
+            IndexRecordOption::WithFreqs,
+        ))
+    }
+
+    fn prefix_query(&self, prefix: &str) -> Box<dyn Query> {
+        let pattern = format!("{}.*", regex::escape(prefix));
+        Box::new(
+            RegexQuery::from_pattern(&pattern, self.fields.words)
+                .expect("Escaped prefixes are valid patterns"),
+        )
+    }
+
+    /// Builds the query for a word, prefix, phrase or `NEAR` group,
+    /// collecting the terms it matches to highlight them.
+    fn search_term(
+        &self,
+        token: &Token,
+        language: &str,
+        is_prefix: bool,
+        terms: &mut Vec<String>,
+        prefixes: &mut Vec<String>,
+    ) -> Box<dyn Query> {
+        match token {
+            Token::Word(word) => {
+                let word = index_words(word, language);
+                let query = if is_prefix {
+                    self.prefix_query(&word)
+                } else {
+                    self.term_query(&word)
+                };
+                if is_prefix {
+                    prefixes.push(word);
+                } else {
+                    terms.push(word);
+                }
+                query
+            }
+            Token::Prefix(prefix) => {
+                let prefix = fold(prefix);
+                let query = self.prefix_query(&prefix);
+                prefixes.push(prefix);
+                query
+            }
+            Token::Phrase(phrase) => {
+                let words: Vec<String> = index_words(phrase, language)
+                    .split(' ')
+                    .map(str::to_string)
+                    .collect();
+                let query = match words.as_slice() {
+                    [word] => self.term_query(word),
+                    _ => Box::new(PhraseQuery::new(
+                        words.iter().map(|word| self.term(word)).collect(),
+                    )),
+                };
+                terms.extend(words);
+                query
+            }
+            // Tantivy can't limit how far apart terms are, so a `NEAR` group
+            // matches verses with all of its terms
+            Token::Near { terms: near, .. } => Box::new(BooleanQuery::new(
+                near.iter()
+                    .map(|t| {
+                        let query = self.search_term(t, language, false, terms, prefixes);
+                        (Occur::Must, query)
+                    })
+                    .collect(),
+            )),
+            Token::And | Token::Or | Token::Not => unreachable!("operators aren't search terms"),
+        }
+    }
+
+    /// Builds the query for a user's search query, like the full text
+    /// search query that [SwordDrill] builds.
+    ///
+    /// Verses have to match the query, be in the translation, and be in
+    /// one of the books if any are given. Returns `None` if the query has
+    /// nothing to search for.
+    fn query(
+        &self,
+        query: &str,
+        translation: &Translation,
+        books: Option<&[i32]>,
+        whole_word: bool,
+    ) -> Option<IndexQuery> {
+        let tokens = tokenize(&normalize(query));
+        // A trailing word matches as a prefix, since it may be partly typed
+        let trailing = tokens
+            .iter()
+            .rposition(|t| !matches!(t, Token::And | Token::Or | Token::Not))
+            .filter(|&i| {
+                matches!(tokens[i], Token::Word(_)) && (i == 0 || tokens[i - 1] != Token::Not)
+            })
+            .filter(|_| !whole_word);
+
+        // Words joined by OR, each of which is words that are all matched
+        let mut alternatives: Vec<Vec<Box<dyn Query>>> = vec![vec![]];
+        let mut excluded = vec![];
+        let mut terms = vec![];
+        let mut prefixes = vec![];
+        let mut negated = false;
+        for (i, token) in tokens.iter().enumerate() {
+            match token {
+                Token::And => continue,
+                Token::Or => {
+                    alternatives.push(vec![]);
+                    continue;
+                }
+                Token::Not => {
+                    negated = true;
+                    continue;
+                }
+                _ => {}
+            }
+
+            if negated {
+                // Excluded words aren't highlighted
+                let query = self.search_term(
+                    token,
+                    &translation.language,
+                    false,
+                    &mut vec![],
+                    &mut vec![],
+                );
+                excluded.push(query);
+            } else {
+                let query = self.search_term(
+                    token,
+                    &translation.language,
+                    Some(i) == trailing,
+                    &mut terms,
+                    &mut prefixes,
+                );
+                alternatives.last_mut().unwrap().push(query);
+            }
+            negated = false;
+        }
+
+        let alternatives: Vec<(Occur, Box<dyn Query>)> = alternatives
+            .into_iter()
+            .filter(|all| !all.is_empty())
+            .map(|all| -> (Occur, Box<dyn Query>) {
+                let all = all.into_iter().map(|query| (Occur::Must, query)).collect();
+                (Occur::Should, Box::new(BooleanQuery::new(all)))
+            })
+            .collect();
+        if alternatives.is_empty() {
+            return None;
+        }
+
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> = vec![
+            (Occur::Must, Box::new(BooleanQuery::new(alternatives))),
+            (
+                Occur::Must,
+                Box::new(TermQuery::new(
+                    Term::from_field_i64(self.fields.translation_id, translation.id as i64),
+                    IndexRecordOption::Basic,
+                )),
+            ),
+        ];
+        clauses.extend(excluded.into_iter().map(|query| (Occur::MustNot, query)));
+        if let Some(books) = books {
+            let books = books
+                .iter()
+                .map(|&book| -> (Occur, Box<dyn Query>) {
+                    (Occur::Should, Box::new(self.book_query(book)))
+                })
+                .collect();
+            clauses.push((Occur::Must, Box::new(BooleanQuery::new(books))));
+        }
+
+        Some(IndexQuery {
+            query: Box::new(BooleanQuery::new(clauses)),
+            terms,
+            prefixes,
+        })
+    }
+
+    fn book_query(&self, book: i32) -> TermQuery {
+        TermQuery::new(
+            Term::from_field_i64(self.fields.book, book as i64),
+            IndexRecordOption::Basic,
+        )
+    }
+
+    /// Loads a verse that was found, with the matched terms wrapped in
+    /// `<mark>` tags.
+    fn verse(
+        &self,
+        searcher: &tantivy::Searcher,
+        address: DocAddress,
+        query: &IndexQuery,
+        language: &str,
+    ) -> Result<VerseFTS, DbError> {
+        let doc: Document = searcher.doc(address).map_err(index_error)?;
+        let number = |field| {
+            doc.get_first(field)
+                .and_then(|value| value.as_i64())
+                .unwrap_or_default() as i32
+        };
+        let text = doc
+            .get_first(self.fields.text)
+            .and_then(|value| value.as_text())
+            .unwrap_or_default();
+
+        Ok(VerseFTS {
+            book: number(self.fields.book),
+            chapter: number(self.fields.chapter),
+            verse: number(self.fields.verse),
+            words: highlight_terms(text, &query.terms, &query.prefixes, language),
+            rank: 0.0,
+        })
+    }
+}
+
+/// Wraps the words of text that match any of the terms or start with any
+/// of the prefixes in `<mark>` tags, comparing them as they're indexed.
+fn highlight_terms(text: &str, terms: &[String], prefixes: &[String], language: &str) -> String {
+    let (indexed, _) = index_words_with_offsets(text, language);
+    let mut highlighted = String::with_capacity(indexed.len());
+    let mut word = String::new();
+    let push_word = |word: &mut String, highlighted: &mut String| {
+        let is_match =
+            terms.contains(word) || prefixes.iter().any(|p| word.starts_with(p.as_str()));
+        if is_match && !word.is_empty() {
+            highlighted.push_str(MARK_START);
+            highlighted.push_str(word);
+            highlighted.push_str(MARK_END);
+        } else {
+            highlighted.push_str(word);
+        }
+        word.clear();
+    };
+    for c in indexed.chars() {
+        if c.is_alphanumeric() {
+            word.push(c);
+        } else {
+            push_word(&mut word, &mut highlighted);
+            highlighted.push(c);
+        }
+    }
+    push_word(&mut word, &mut highlighted);
+    unfold_highlights(text, &highlighted, language)
+}
+
+/// Gets the search index, if it has been loaded.
+fn search_index() -> Option<Arc<SearchIndex>> {
+    SEARCH_INDEX.read().unwrap().clone()
+}
+
+/// Builds a search index of the verses in every translation for
+/// [TantivyDrill] to search, in a directory or in memory.
+///
+/// An index that was built in a directory can be loaded again with
+/// [load_search_index]. Building an index in a directory that already has
+/// one is an error, so the old index has to be deleted first.
+pub fn build_search_index(dir: Option<&Path>, conn: &SqliteConnection) -> Result<(), DbError> {
+    use crate::schema::{translations, verses};
+
+    let (schema, fields) = schema();
+    let index = match dir {
+        Some(dir) => Index::create_in_dir(dir, schema).map_err(index_error)?,
+        None => Index::create_in_ram(schema),
+    };
+
+    let languages: HashMap<i32, String> = translations::table
+        .select((translations::id, translations::language))
+        .load::<(i32, String)>(conn)
+        .map_err(|e| DbError::Other {
+            cause: e.to_string(),
+        })?
+        .into_iter()
+        .collect();
+    let verses: Vec<Verse> = verses::table.load(conn).map_err(|e| DbError::Other {
+        cause: e.to_string(),
+    })?;
+    SearchIndex::add_verses(&index, fields, verses, &languages)?;
+
+    *SEARCH_INDEX.write().unwrap() = Some(Arc::new(SearchIndex::new(index, fields)?));
+    Ok(())
+}
+
+/// Loads the search index for [TantivyDrill] from a directory it was
+/// built in, or builds it there first if there's no index yet.
+pub fn load_search_index(dir: &Path, conn: &SqliteConnection) -> Result<(), DbError> {
+    if !dir.join("meta.json").exists() {
+        std::fs::create_dir_all(dir).map_err(|e| DbError::SearchIndex {
+            cause: e.to_string(),
+        })?;
+        return build_search_index(Some(dir), conn);
+    }
+
+    let index = Index::open_in_dir(dir).map_err(index_error)?;
+    let (_, fields) = schema();
+    *SEARCH_INDEX.write().unwrap() = Some(Arc::new(SearchIndex::new(index, fields)?));
+    Ok(())
+}
+
+/// Implementation of the [SwordDrillable] trait that searches a Tantivy
+/// index instead of SQLite's full text search, for larger deployments with
+/// many translations.
+///
+/// Everything other than searching words is looked up with [SwordDrill],
+/// and so are searches until the index has been built or loaded, and
+/// searches within the results of earlier ones. Verses are searched like
+/// [SwordDrill::search], except that `NEAR` groups only require all of
+/// their terms to match.
+pub struct TantivyDrill;
+
+impl BookStore for TantivyDrill {}
+
+impl VerseStore for TantivyDrill {
+    // SQLite looks chapters up in fewer queries than the default does
+    fn chapter(
+        reference: &Reference,
+        version: &str,
+        format: VerseFormat,
+        conn: &SqliteConnection,
+    ) -> Result<(Translation, Book, Vec<Verse>), DbError> {
+        SwordDrill::chapter(reference, version, format, conn)
+    }
+}
+
+impl Searcher for TantivyDrill {
+    fn search(
+        query: &str,
+        options: &SearchOptions,
+        translation: &Translation,
+        conn: &SqliteConnection,
+    ) -> Result<(Vec<(VerseFTS, Book)>, i64), DbError> {
+        use crate::schema::books;
+
+        // Result sets are only searched within in SQLite
+        let index = match search_index() {
+            Some(index) if options.within.is_none() => index,
+            _ => return SwordDrill::search(query, options, translation, conn),
+        };
+        let scoped = SwordDrill::scope_books(&options.scope, conn)?;
+        let query = match index.query(query, translation, scoped.as_deref(), options.whole_word) {
+            Some(query) => query,
+            None => return Ok((vec![], 0)),
+        };
+
+        let searcher = index.reader.searcher();
+        let page =
+            TopDocs::with_limit(options.per_page as usize).and_offset(options.offset() as usize);
+        let (addresses, total): (Vec<DocAddress>, usize) = match options.order {
+            SearchOrder::Relevance => {
+                let (top, total) = searcher
+                    .search(&*query.query, &(page, Count))
+                    .map_err(index_error)?;
+                (top.into_iter().map(|(_, address)| address).collect(), total)
+            }
+            SearchOrder::Canonical => {
+                let (top, total) = searcher
+                    .search(
+                        &*query.query,
+                        &(page.order_by_u64_field(index.fields.position), Count),
+                    )
+                    .map_err(index_error)?;
+                (top.into_iter().map(|(_, address)| address).collect(), total)
+            }
+        };
+
+        let books: HashMap<i32, Book> = books::table
+            .load::<Book>(conn)
+            .map_err(|e| DbError::Other {
+                cause: e.to_string(),
+            })?
+            .into_iter()
+            .map(|book| (book.id, book))
+            .collect();
+        let matches = addresses
+            .into_iter()
+            .map(|address| {
+                let verse = index.verse(&searcher, address, &query, &translation.language)?;
+                let book = books
+                    .get(&verse.book)
+                    .cloned()
+                    .ok_or_else(|| DbError::Other {
+                        cause: format!("the index has a verse in an unknown book, {}", verse.book),
+                    })?;
+                Ok((verse, book))
+            })
+            .collect::<Result<_, DbError>>()?;
+        Ok((matches, total as i64))
+    }
+
+    fn search_facets(
+        query: &str,
+        options: &SearchOptions,
+        translation: &Translation,
+        conn: &SqliteConnection,
+    ) -> Result<Vec<(Book, i64)>, DbError> {
+        use crate::schema::books;
+
+        let index = match search_index() {
+            Some(index) if options.within.is_none() => index,
+            _ => return SwordDrill::search_facets(query, options, translation, conn),
+        };
+        let scoped = SwordDrill::scope_books(&options.scope, conn)?;
+        let query = match index.query(query, translation, scoped.as_deref(), options.whole_word) {
+            Some(query) => query,
+            None => return Ok(vec![]),
+        };
+
+        // Count the matches in each book, which are fast to count
+        let books: Vec<Book> =
+            books::table
+                .order_by(books::id)
+                .load(conn)
+                .map_err(|e| DbError::Other {
+                    cause: e.to_string(),
+                })?;
+        let searcher = index.reader.searcher();
+        let mut facets = vec![];
+        for book in books {
+            if matches!(scoped, Some(ref ids) if !ids.contains(&book.id)) {
+                continue;
+            }
+            let in_book = BooleanQuery::new(vec![
+                (Occur::Must, query.query.box_clone()),
+                (Occur::Must, Box::new(index.book_query(book.id))),
+            ]);
+            let count = searcher.search(&in_book, &Count).map_err(index_error)?;
+            if count > 0 {
+                facets.push((book, count as i64));
+            }
+        }
+        Ok(facets)
+    }
+}
+
+impl SwordDrillable for TantivyDrill {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_index() -> SearchIndex {
+        let (schema, fields) = schema();
+        let index = Index::create_in_ram(schema);
+        let verse = |id, book, words: &str| Verse {
+            id,
+            book,
+            chapter: 1,
+            verse: id,
+            words: words.to_string(),
+            translation_id: 1,
+            paragraph: false,
+            poetry_level: 0,
+            line_break: false,
+        };
+        let verses = vec![
+            verse(
+                1,
+                59,
+                "But wilt thou know, O vain man, that faith without works is dead?",
+            ),
+            verse(
+                2,
+                45,
+                "Therefore we conclude that a man is justified by faith.",
+            ),
+            verse(3, 43, "For God so loved the world."),
+        ];
+        let languages = HashMap::from([(1, "en".to_string())]);
+        SearchIndex::add_verses(&index, fields, verses, &languages).unwrap();
+        SearchIndex::new(index, fields).unwrap()
+    }
+
+    fn kjv() -> Translation {
+        Translation {
+            id: 1,
+            abbreviation: "kjv".to_string(),
+            name: "King James Version".to_string(),
+            language: "en".to_string(),
+            license: None,
+            copyright: None,
+            attribution: None,
+            direction: "ltr".to_string(),
+            versification: "kjv".to_string(),
+            imported_at: 0,
+        }
+    }
+
+    /// Searches the test index, getting the books of the matches in order.
+    fn search(index: &SearchIndex, query: &str, books: Option<&[i32]>) -> Vec<i32> {
+        let query = match index.query(query, &kjv(), books, false) {
+            Some(query) => query,
+            None => return vec![],
+        };
+        let collector = TopDocs::with_limit(10).order_by_u64_field(index.fields.position);
+        let searcher = index.reader.searcher();
+        searcher
+            .search(&*query.query, &collector)
+            .unwrap()
+            .into_iter()
+            .map(|(_, address)| index.verse(&searcher, address, &query, "en").unwrap().book)
+            .collect()
+    }
+
+    #[test]
+    fn searches() {
+        let index = test_index();
+        assert_eq!(search(&index, "faith", None), vec![45, 59]);
+        assert_eq!(search(&index, "faith -works", None), vec![45]);
+        assert_eq!(search(&index, "dead OR love", None), vec![43, 59]);
+        assert_eq!(search(&index, "\"faith without\"", None), vec![59]);
+        assert_eq!(search(&index, "\"without faith\"", None), vec![]);
+        assert_eq!(search(&index, "justif* man", Some(&[45, 59])), vec![45]);
+        assert_eq!(search(&index, "faith", Some(&[43])), vec![]);
+        assert_eq!(search(&index, "1 :", None), vec![]);
+    }
+
+    #[test]
+    fn highlights() {
+        let terms = vec!["love".to_string(), "world".to_string()];
+        assert_eq!(
+            highlight_terms("For God so loved the World.", &terms, &[], "en"),
+            "For God so <mark>loved</mark> the <mark>World</mark>."
+        );
+        assert_eq!(
+            highlight_terms("justified by faith", &[], &["justif".to_string()], "en"),
+            "<mark>justified</mark> by faith"
+        );
+    }
+}
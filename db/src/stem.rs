@@ -0,0 +1,98 @@
+use lazy_static::lazy_static;
+use rust_stemmers::Algorithm;
+
+/// Reduces words to their stems, so searching for one form of a word
+/// (e.g. "love") matches the others ("loved", "loves" and "loving").
+///
+/// Words are folded with [crate::fold] before they're stemmed.
+pub trait Stemmer: Sync {
+    fn stem(&self, word: &str) -> String;
+}
+
+/// A stemmer from the Snowball project.
+struct Snowball(rust_stemmers::Stemmer);
+
+impl Stemmer for Snowball {
+    fn stem(&self, word: &str) -> String {
+        self.0.stem(word).into_owned()
+    }
+}
+
+lazy_static! {
+    static ref ENGLISH: Snowball = Snowball(rust_stemmers::Stemmer::create(Algorithm::English));
+}
+
+/// Gets the stemmer for a translation's language (e.g. `en`), if there is
+/// one.
+///
+/// Translations in other languages are searched without stemming. Adding a
+/// stemmer for a language changes how its verses are indexed, so it needs
+/// a migration that rebuilds the search index.
+pub fn stemmer(language: &str) -> Option<&'static dyn Stemmer> {
+    match language {
+        "en" => Some(&*ENGLISH),
+        _ => None,
+    }
+}
+
+/// Stems each of the words in text, also returning offsets like
+/// [crate::fold_with_offsets] for the stemmed text.
+///
+/// The characters of a stem point to the characters of the word it came
+/// from, so the stem of a word still covers the whole word.
+pub(crate) fn stem_with_offsets(
+    text: &str,
+    offsets: &[usize],
+    stemmer: &dyn Stemmer,
+) -> (String, Vec<usize>) {
+    let chars: Vec<char> = text.chars().collect();
+    let mut stemmed = String::with_capacity(text.len());
+    let mut stemmed_offsets = Vec::with_capacity(offsets.len());
+    let mut start = 0;
+    while start < chars.len() {
+        if !chars[start].is_alphanumeric() {
+            stemmed.push(chars[start]);
+            stemmed_offsets.push(offsets[start]);
+            start += 1;
+            continue;
+        }
+
+        let end = chars[start..]
+            .iter()
+            .position(|c| !c.is_alphanumeric())
+            .map_or(chars.len(), |len| start + len);
+        let word: String = chars[start..end].iter().collect();
+        for (i, c) in stemmer.stem(&word).chars().enumerate() {
+            stemmed.push(c);
+            // Stems are rarely longer than words, but don't run past them
+            stemmed_offsets.push(offsets[(start + i).min(end - 1)]);
+        }
+        start = end;
+    }
+    (stemmed, stemmed_offsets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn english() {
+        let english = stemmer("en").unwrap();
+        for word in ["love", "loved", "loves", "loving"] {
+            assert_eq!(english.stem(word), "love");
+        }
+        assert_eq!(english.stem("beginning"), "begin");
+        assert!(stemmer("he").is_none());
+    }
+
+    #[test]
+    fn stems_with_offsets() {
+        let english = stemmer("en").unwrap();
+        let text = "god so loved, the";
+        let offsets: Vec<usize> = (0..text.len()).collect();
+        let (stemmed, stemmed_offsets) = stem_with_offsets(text, &offsets, english);
+        assert_eq!(stemmed, "god so love, the");
+        assert_eq!(stemmed_offsets[7..12], [7, 8, 9, 10, 12]);
+    }
+}
@@ -1,28 +1,68 @@
+use std::collections::{BTreeMap, HashMap};
+use std::slice;
+use std::time::{Duration, Instant};
+
+use diesel::dsl::sql;
 use diesel::prelude::*;
 use diesel::result::Error;
-use diesel::sql_types::{Integer, Text};
+use diesel::sql_types::{BigInt, Integer, Text};
 use lazy_static::lazy_static;
-use regex::Regex;
+use regex::{Regex, RegexBuilder};
 
+use crate::api_keys::hash_key;
+use crate::dictionary::Dictionary;
 use crate::models::*;
-use crate::{DbError, VerseFormat};
+use crate::{fold, index_words, index_words_with_offsets, normalize, DbError, VerseFormat};
+
+/// Tags wrapping the matched words in search results.
+pub(crate) const MARK_START: &str = "<mark>";
+pub(crate) const MARK_END: &str = "</mark>";
+
+/// Max length of a regular expression search pattern.
+const REGEX_PATTERN_LIMIT: usize = 200;
+
+/// Max size in bytes of a compiled regular expression search pattern.
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
 
-/// Max number of search results returned from the database.
-const SEARCH_RESULT_LIMIT: i64 = 15;
+/// Longest a regular expression search can run for.
+const REGEX_SEARCH_TIME_LIMIT: Duration = Duration::from_secs(2);
+
+/// Versification scheme that references are entered in.
+pub(crate) const STANDARD_VERSIFICATION: &str = "kjv";
 
 sql_function!(
     fn highlight(table_name: Text, column_index: Integer, prefix: Text, suffix: Text) -> Text
 );
 
-/// Trait implemented by types that can query for and return types of Bible structures.
-pub trait SwordDrillable {
-    /// Looks up Bible verses for the given reference.
-    fn verses(
-        reference: &Reference,
-        format: VerseFormat,
-        conn: &SqliteConnection,
-    ) -> Result<(Book, Vec<Verse>), DbError>;
+sql_function!(fn random() -> BigInt);
+
+/// SQL counting the words of a verse, as the runs of text between spaces.
+const WORD_COUNT_SQL: &str = "(LENGTH(TRIM(words)) - LENGTH(REPLACE(TRIM(words), ' ', '')) + 1)";
+
+/// Genealogies and other long lists of names, which random verses can leave
+/// out: the book, its first and last chapter, and the first and last verse
+/// in each of those chapters.
+const GENEALOGIES: [(i32, (i32, i32), (i32, i32)); 14] = [
+    (1, (5, 5), (1, 32)),
+    (1, (10, 10), (1, 32)),
+    (1, (11, 11), (10, 32)),
+    (1, (36, 36), (1, 43)),
+    (1, (46, 46), (8, 27)),
+    (4, (1, 1), (5, 47)),
+    // The offerings of each tribe's prince, which repeat for 72 verses
+    (4, (7, 7), (12, 83)),
+    (4, (26, 26), (5, 62)),
+    (13, (1, 9), (1, Reference::CHAPTER_END)),
+    (15, (2, 2), (1, 70)),
+    (16, (7, 7), (6, 73)),
+    (16, (12, 12), (1, 26)),
+    (40, (1, 1), (1, 17)),
+    (42, (3, 3), (23, 38)),
+];
 
+/// Trait implemented by types that can look up the books of the Bible and
+/// the translations they're in.
+pub trait BookStore {
     /// Looks up the Bible book with the given book name.
     ///
     /// The inputted name argument can be either the cannonical book name
@@ -30,135 +70,683 @@ pub trait SwordDrillable {
     /// abbreviation table. The book is looked up in a case-insensitive
     /// manner.
     ///
-    /// If found, returns the resulting book and the list of its chapters.
-    fn book(book_name: &str, conn: &SqliteConnection) -> Result<(Book, Vec<i32>), DbError>;
+    /// If found, returns the resulting book and the list of its chapters
+    /// that exist in the given translation.
+    fn book(
+        book_name: &str,
+        translation: &Translation,
+        conn: &SqliteConnection,
+    ) -> Result<(Book, Vec<i32>), DbError> {
+        SwordDrill::book(book_name, translation, conn)
+    }
 
-    /// Gets all books in the Bible.
-    fn all_books(conn: &SqliteConnection) -> Result<Vec<Book>, DbError>;
+    /// Looks up a book like [book](Self::book), along with the number of
+    /// verses in each of its chapters that exist in the given translation.
+    fn chapters(
+        book_name: &str,
+        translation: &Translation,
+        conn: &SqliteConnection,
+    ) -> Result<(Book, Vec<(i32, i64)>), DbError> {
+        SwordDrill::chapters(book_name, translation, conn)
+    }
 
-    /// Searches the database using the SQLite 3 full text search extension.
+    /// Gets all books in the Bible.
     ///
-    /// The inputted query string can be of two different formats:
+    /// Deuterocanonical books are only included if the translation
+    /// contains them.
+    fn all_books(translation: &Translation, conn: &SqliteConnection) -> Result<Vec<Book>, DbError> {
+        SwordDrill::all_books(translation, conn)
+    }
+
+    /// Finds the books whose name or an abbreviation starts with the prefix
+    /// (e.g. `1 co` for 1 Corinthians), for completing what a user types.
     ///
-    /// - `test foo`: match each word as its own token, and use that
-    /// to search.
-    /// - `"test foo"`: match the entire phrase. For the King James
-    /// version of the Bible, this is safe because there are no literal
-    /// quotation marks. This cannot be assumed safe in other translations.
+    /// Exact matches come first, then books whose name starts with the
+    /// prefix, then those with an abbreviation that does. Only books in the
+    /// translation are included, as with [all_books](Self::all_books).
+    fn complete_books(
+        prefix: &str,
+        translation: &Translation,
+        conn: &SqliteConnection,
+    ) -> Result<Vec<Book>, DbError> {
+        SwordDrill::complete_books(prefix, translation, conn)
+    }
+
+    /// Gets the abbreviations of the books that references can use (e.g.
+    /// `1 cor` for 1 Corinthians), in lower case.
+    fn book_abbreviations(conn: &SqliteConnection) -> Result<Vec<BookAbbreviation>, DbError> {
+        SwordDrill::book_abbreviations(conn)
+    }
+
+    /// Gets all translations installed in the database.
+    fn translations(conn: &SqliteConnection) -> Result<Vec<Translation>, DbError> {
+        SwordDrill::translations(conn)
+    }
+
+    /// Looks up the translation with the given abbreviation (e.g. `kjv`).
     ///
-    /// All characters other than alpha and quotations are stripped out.
-    fn search(query: &str, conn: &SqliteConnection) -> Result<Vec<(VerseFTS, Book)>, DbError>;
+    /// The translation is looked up in a case-insensitive manner.
+    fn translation(abbreviation: &str, conn: &SqliteConnection) -> Result<Translation, DbError> {
+        SwordDrill::translation(abbreviation, conn)
+    }
 }
 
-/// Main implementation for the [SwordDrillable](crate::sword_drill::SwordDrillable) trait.
-pub struct SwordDrill;
-
-impl SwordDrillable for SwordDrill {
+/// Trait implemented by types that can look up Bible verses, in the books
+/// and translations of their [BookStore].
+pub trait VerseStore: BookStore {
+    /// Looks up Bible verses for the given reference in the given translation.
     fn verses(
         reference: &Reference,
+        translation: &Translation,
         format: VerseFormat,
         conn: &SqliteConnection,
     ) -> Result<(Book, Vec<Verse>), DbError> {
-        use crate::schema::verses as plain_text;
-        use crate::schema::verses_html as html;
+        SwordDrill::verses(reference, translation, format, conn)
+    }
 
-        let (book, _) = Self::book(&reference.book.to_lowercase(), conn)?;
+    /// Looks up Bible verses for the given reference in each of the given
+    /// translations with one query.
+    ///
+    /// The verses are ordered by chapter, verse, and then translation.
+    fn parallel_verses(
+        reference: &Reference,
+        translations: &[Translation],
+        format: VerseFormat,
+        conn: &SqliteConnection,
+    ) -> Result<(Book, Vec<Verse>), DbError> {
+        SwordDrill::parallel_verses(reference, translations, format, conn)
+    }
 
-        match format {
-            VerseFormat::PlainText => {
-                let mut query = plain_text::table
-                    .filter(plain_text::book.eq(book.id))
-                    .filter(plain_text::chapter.eq(reference.chapter))
-                    .order_by((plain_text::chapter.asc(), plain_text::verse.asc()))
-                    .into_boxed();
+    /// Looks up Bible verses for the given reference in the given
+    /// translation, filling any verses it omits from the fallback
+    /// translation.
+    ///
+    /// Substituted verses keep the fallback's `translation_id`, so callers
+    /// can tell them apart from the rest of the passage.
+    fn verses_with_fallback(
+        reference: &Reference,
+        translation: &Translation,
+        fallback: &Translation,
+        format: VerseFormat,
+        conn: &SqliteConnection,
+    ) -> Result<(Book, Vec<Verse>), DbError> {
+        let translations = [translation.to_owned(), fallback.to_owned()];
+        let (book, verses) = Self::parallel_verses(reference, &translations, format, conn)?;
+        Ok((book, fill_gaps(verses, translation.id)))
+    }
+
+    /// Looks up the verses of a whole chapter in the translation with the
+    /// given abbreviation, along with the translation, like
+    /// [translation](Self::translation) and [verses](Self::verses).
+    ///
+    /// Chapters are what pages are read by, so implementations can look
+    /// them up in fewer queries.
+    fn chapter(
+        reference: &Reference,
+        version: &str,
+        format: VerseFormat,
+        conn: &SqliteConnection,
+    ) -> Result<(Translation, Book, Vec<Verse>), DbError> {
+        let translation = Self::translation(version, conn)?;
+        let (book, verses) = Self::verses(reference, &translation, format, conn)?;
+        Ok((translation, book, verses))
+    }
+
+    /// Looks up Bible verses for the given reference like
+    /// [verses](Self::verses), along with verses of context before and after
+    /// them.
+    ///
+    /// The context crosses into the chapters around the reference when it
+    /// needs to, but stays within the book. Verses between listed verses
+    /// are included too. Returns the reference of the verses with their
+    /// context, along with the verses.
+    fn verses_in_context(
+        reference: &Reference,
+        before: i32,
+        after: i32,
+        translation: &Translation,
+        format: VerseFormat,
+        conn: &SqliteConnection,
+    ) -> Result<(Reference, (Book, Vec<Verse>)), DbError> {
+        let (_, chapters) = Self::chapters(&reference.book, translation, conn)?;
+        let verse_count = |chapter: i32| {
+            chapters
+                .iter()
+                .find(|(c, _)| *c == chapter)
+                .map_or(0, |(_, count)| *count as i32)
+        };
 
-                if let Some(ref verses) = reference.verses {
-                    query = query.filter(plain_text::verse.between(verses.start(), verses.end()));
+        // Step back from the first verse, into the end of earlier chapters
+        let (mut chapter, mut verse) = (
+            reference.chapter,
+            reference.verses.as_ref().map_or(1, |vs| *vs.start()),
+        );
+        let mut remaining = before.max(0);
+        while remaining > 0 {
+            if verse > remaining {
+                verse -= remaining;
+                break;
+            }
+            remaining -= verse - 1;
+            match chapters.iter().rev().find(|(c, _)| *c < chapter) {
+                Some((previous, count)) => {
+                    chapter = *previous;
+                    verse = *count as i32;
+                    remaining -= 1;
+                }
+                None => {
+                    verse = 1;
+                    break;
                 }
-                query.load(conn)
             }
-            VerseFormat::Html => {
-                let mut query = html::table
-                    .filter(html::book.eq(book.id))
-                    .filter(html::chapter.eq(reference.chapter))
-                    .order_by((html::chapter.asc(), html::verse.asc()))
-                    .into_boxed();
+        }
 
-                if let Some(ref verses) = reference.verses {
-                    query = query.filter(html::verse.between(verses.start(), verses.end()));
+        // Step forward from the last verse, into the start of later chapters
+        let mut end_chapter = reference.last_chapter();
+        let last = reference
+            .additional_verses
+            .last()
+            .or(reference.verses.as_ref())
+            .map_or(Reference::CHAPTER_END, |vs| *vs.end());
+        let mut end = last.min(verse_count(end_chapter));
+        let mut remaining = after.max(0);
+        while remaining > 0 {
+            let count = verse_count(end_chapter);
+            if end + remaining <= count {
+                end += remaining;
+                break;
+            }
+            remaining -= count - end;
+            match chapters.iter().find(|(c, _)| *c > end_chapter) {
+                Some((next, _)) => {
+                    end_chapter = *next;
+                    end = 1;
+                    remaining -= 1;
+                }
+                None => {
+                    end = count;
+                    break;
                 }
-                query.load(conn)
             }
         }
-        .map(|verses| (book, verses))
-        .map_err(|e| DbError::Other {
-            cause: e.to_string(),
-        })
+
+        let context = Reference {
+            book: reference.book.to_owned(),
+            chapter,
+            verses: Some(verse..=end),
+            end_chapter: Some(end_chapter).filter(|c| *c != chapter),
+            additional_verses: vec![],
+            start_part: None,
+            end_part: None,
+        };
+        let result = Self::verses(&context, translation, format, conn)?;
+        Ok((context, result))
     }
 
-    fn book(book_name: &str, conn: &SqliteConnection) -> Result<(Book, Vec<i32>), DbError> {
-        use crate::schema::book_abbreviations as ba;
-        use crate::schema::books as b;
+    /// Picks a verse of the translation at random, from the books in the
+    /// scope, leaving out genealogies and other lists of names if asked to.
+    ///
+    /// Returns the reference of the verse, or `None` if there are no verses
+    /// to pick from.
+    fn random_verse(
+        scope: &SearchScope,
+        exclude_genealogies: bool,
+        translation: &Translation,
+        conn: &SqliteConnection,
+    ) -> Result<Option<Reference>, DbError> {
+        SwordDrill::random_verse(scope, exclude_genealogies, translation, conn)
+    }
 
-        let (book, _) = b::table
-            .inner_join(ba::table)
-            .filter(ba::abbreviation.eq(book_name.to_lowercase()))
-            .first::<(Book, BookAbbreviation)>(conn)
-            .map_err(|e| match e {
-                Error::NotFound => DbError::BookNotFound {
-                    book: book_name.to_owned(),
-                },
-                e => DbError::Other {
-                    cause: e.to_string(),
-                },
-            })?;
-        let chapters: Vec<i32> = (1..=book.chapter_count).collect();
+    /// Gets the verse of the day for a day, counted from 1 January 1970,
+    /// from the rotation of daily verses.
+    fn verse_of_the_day(day: i64, conn: &SqliteConnection) -> Result<Reference, DbError> {
+        SwordDrill::verse_of_the_day(day, conn)
+    }
 
-        Ok((book, chapters))
+    /// Counts the books, chapters, verses and words of the translation, or
+    /// of one of its books, and finds its longest and shortest verses.
+    ///
+    /// Words are counted as the runs of text between spaces.
+    fn stats(
+        book: Option<&Book>,
+        translation: &Translation,
+        conn: &SqliteConnection,
+    ) -> Result<Stats, DbError> {
+        SwordDrill::stats(book, translation, conn)
     }
 
-    fn all_books(conn: &SqliteConnection) -> Result<Vec<Book>, DbError> {
-        use crate::schema::books::dsl::*;
+    /// Looks up the cross-references from the verses of a reference, along
+    /// with the books they point to. They're in the order of the verses
+    /// they're from, with the most voted first for each verse.
+    fn cross_references(
+        reference: &Reference,
+        conn: &SqliteConnection,
+    ) -> Result<(Book, Vec<(CrossReference, Book)>), DbError> {
+        SwordDrill::cross_references(reference, conn)
+    }
+}
 
-        books.order_by(id).load(conn).map_err(|e| DbError::Other {
-            cause: e.to_string(),
-        })
+/// Trait implemented by types that can search Bible verses.
+pub trait Searcher {
+    /// Searches the database using the SQLite 3 full text search extension.
+    ///
+    /// Only verses in the given translation and in the scope of the options
+    /// are searched, and only those in the result set the options search
+    /// within if there is one. The inputted query string can use these
+    /// formats:
+    ///
+    /// - `test foo`: match each word as its own token, and use that
+    /// to search.
+    /// - `"test foo"`: match the exact phrase. Phrases and words can be
+    /// combined, as in `"test foo" bar`.
+    /// - `test OR foo NOT bar`: match with the `AND`, `OR` and `NOT`
+    /// operators, which must be upper case. `-bar` excludes a word too.
+    /// - `tes*`: match any word starting with `tes`.
+    ///
+    /// All other characters than letters, quotations, and the `-` and `*`
+    /// around words are stripped out.
+    /// Results are listed in the order of the options, and a trailing
+    /// unquoted word matches as a prefix so partly typed words are found,
+    /// unless the options only match whole words.
+    ///
+    /// Returns the page of matches from the options, with the matched words
+    /// wrapped in `<mark>` tags, along with the total number of matches on
+    /// all pages.
+    fn search(
+        query: &str,
+        options: &SearchOptions,
+        translation: &Translation,
+        conn: &SqliteConnection,
+    ) -> Result<(Vec<(VerseFTS, Book)>, i64), DbError> {
+        SwordDrill::search(query, options, translation, conn)
     }
 
-    fn search(query: &str, conn: &SqliteConnection) -> Result<Vec<(VerseFTS, Book)>, DbError> {
-        use crate::schema::books;
-        use crate::schema::verses_fts;
+    /// Runs a [search](Self::search) in each of the given
+    /// translations, grouping the matches by verse.
+    ///
+    /// Verses are grouped by their book, chapter and verse numbers, and
+    /// their matches are in the order the translations are given. Groups
+    /// are always listed in canonical order. Returns the page of groups from
+    /// the options, along with the total number of verses matched in any of
+    /// the translations.
+    fn search_translations(
+        query: &str,
+        options: &SearchOptions,
+        translations: &[Translation],
+        conn: &SqliteConnection,
+    ) -> Result<(Vec<VerseMatches>, i64), DbError> {
+        SwordDrill::search_translations(query, options, translations, conn)
+    }
 
-        lazy_static! {
-            static ref ALPHA_NUM: Regex = Regex::new(r"[^a-zA-Z ]+").unwrap();
-        }
+    /// Searches the plain text of verses with a regular expression.
+    ///
+    /// Only verses in the given translation and in the scope of the options
+    /// are searched, always in canonical order, and matches are wrapped in
+    /// `<mark>` tags. When the options only match whole words, matches have
+    /// to start and end at word boundaries. Patterns are limited in length
+    /// and compiled size, and a search that runs for longer than a couple of
+    /// seconds is stopped with an error. Like [search](Self::search),
+    /// a page of matches is returned with the total number of matches.
+    fn search_regex(
+        pattern: &str,
+        options: &SearchOptions,
+        translation: &Translation,
+        conn: &SqliteConnection,
+    ) -> Result<(Vec<(VerseFTS, Book)>, i64), DbError> {
+        SwordDrill::search_regex(pattern, options, translation, conn)
+    }
+
+    /// Gets the verses a [search](Self::search) matches on all of
+    /// its pages, so a later search can be narrowed down to them with
+    /// [SearchOptions::within].
+    fn search_result_set(
+        query: &str,
+        options: &SearchOptions,
+        translation: &Translation,
+        conn: &SqliteConnection,
+    ) -> Result<ResultSet, DbError> {
+        SwordDrill::search_result_set(query, options, translation, conn)
+    }
+
+    /// Counts the matches of a [search](Self::search) in each
+    /// book.
+    ///
+    /// Only verses in the given translation and in the scope of the options
+    /// are counted, and the page and order of the options are ignored.
+    /// Returns the books with any matches in canonical order, along with
+    /// their number of matches.
+    fn search_facets(
+        query: &str,
+        options: &SearchOptions,
+        translation: &Translation,
+        conn: &SqliteConnection,
+    ) -> Result<Vec<(Book, i64)>, DbError> {
+        SwordDrill::search_facets(query, options, translation, conn)
+    }
+
+    /// Suggests a search query with its misspelled words corrected, for
+    /// when a search has no matches.
+    ///
+    /// Words are looked up in a dictionary of the words in the translation,
+    /// and ones that aren't in it are replaced with the most used of the
+    /// closest words that are. Returns `None` if no words were corrected.
+    fn suggest_spelling(
+        query: &str,
+        translation: &Translation,
+        conn: &SqliteConnection,
+    ) -> Result<Option<String>, DbError> {
+        SwordDrill::suggest_spelling(query, translation, conn)
+    }
 
-        let had_quote = query.contains('"');
+    /// Completes the start of a search with the words and two-word phrases
+    /// of the translation starting with it, as it's typed.
+    ///
+    /// Returns up to ten of the most used words and of the most used
+    /// phrases, in lower case. Only phrases are returned once a space has
+    /// been typed.
+    fn complete_search(
+        prefix: &str,
+        translation: &Translation,
+        conn: &SqliteConnection,
+    ) -> Result<(Vec<String>, Vec<String>), DbError> {
+        SwordDrill::complete_search(prefix, translation, conn)
+    }
+}
 
-        // Replace all characters that aren't alpha or space
-        let mut query = ALPHA_NUM.replace_all(query, "").to_string();
+/// Trait implemented by types that can query for and return types of Bible structures.
+///
+/// It's made up of [BookStore], [VerseStore] and [Searcher], so code that
+/// only needs one piece can ask for just that. Every method looks things up
+/// in SQLite with [SwordDrill] by default, so other backends (like a search
+/// index, or verses kept in memory) only implement what they provide.
+pub trait SwordDrillable: VerseStore + Searcher {
+    /// Looks up the API key with the given secret, if it's been issued and
+    /// not revoked.
+    fn api_key(key: &str, conn: &SqliteConnection) -> Result<Option<ApiKey>, DbError> {
+        SwordDrill::api_key(key, conn)
+    }
+}
 
-        // Don't even try to run the query if there are no characters
-        if query.trim().is_empty() {
-            return Ok(vec![]);
+/// Merges verses from several translations, ordered by chapter and verse,
+/// into one passage.
+///
+/// The verse from the preferred translation is kept whenever there is
+/// one; otherwise the first verse found for that chapter and verse is used.
+fn fill_gaps(verses: Vec<Verse>, preferred: i32) -> Vec<Verse> {
+    let mut merged: Vec<Verse> = Vec::with_capacity(verses.len());
+    for verse in verses {
+        match merged.last_mut() {
+            Some(last) if last.chapter == verse.chapter && last.verse == verse.verse => {
+                if verse.translation_id == preferred {
+                    *last = verse;
+                }
+            }
+            _ => merged.push(verse),
         }
+    }
+    merged
+}
+
+/// Gets the reference with its first and last verse if the reference is
+/// limited to certain verses.
+///
+/// Verses listed after the first range widen the bounds, so the verses in
+/// between still have to be filtered out.
+fn verse_bounds(reference: Option<&Reference>) -> Option<(&Reference, i32, i32)> {
+    reference.and_then(|r| {
+        let start = *r.verses.as_ref()?.start();
+        r.last_verse().map(|end| (r, start, end))
+    })
+}
+
+/// Builds a full text search query from a user's search query in a
+/// translation's language.
+///
+/// Words are folded and stemmed like the verses in the index, so they match
+/// regardless of case, diacritics and word endings (e.g. "loved" for
+/// "love"). Every word is quoted so words (like "not") and operators aren't
+/// read with FTS5's own syntax, and quoted parts of the query are kept
+/// together as phrases. Excluded words and phrases are taken out of all of
+/// the matches, so `faith AND works NOT law` finds verses with faith and
+/// works but without law. Words ending in `*` (e.g. `bapti*`) match as
+/// prefixes, which are only folded, and so does a trailing word since it may
+/// be partly typed, unless only whole words are matched. `NEAR` groups (e.g.
+/// `NEAR(faith works, 5)`) match verses with their terms within the distance
+/// of each other. Returns `None` if the query has nothing to search for.
+fn fts_query(query: &str, language: &str, whole_word: bool) -> Option<String> {
+    let mut included = String::new();
+    let mut excluded = String::new();
+    let mut operator = " ";
+    let mut negated = false;
+    let mut prefix = false;
+    for token in tokenize(&normalize(query)) {
+        let (term, is_word) = match token {
+            Token::And => {
+                operator = " AND ";
+                continue;
+            }
+            Token::Or => {
+                operator = " OR ";
+                continue;
+            }
+            Token::Not => {
+                negated = true;
+                continue;
+            }
+            Token::Word(_) => (fts_term(&token, language), true),
+            _ => (fts_term(&token, language), false),
+        };
 
-        // Add back quotes safely if it had a quote before, and was removed
-        // This makes FTS5 query the string as a phrase.
-        query = if had_quote {
-            format!("\"{}\"", query)
+        if negated {
+            excluded.push_str(" NOT ");
+            excluded.push_str(&term);
         } else {
-            query
+            if !included.is_empty() {
+                included.push_str(operator);
+            }
+            included.push_str(&term);
+        }
+        prefix = is_word && !negated;
+        operator = " ";
+        negated = false;
+    }
+
+    if included.is_empty() {
+        return None;
+    }
+    if prefix && !whole_word {
+        included.push('*');
+    }
+    if excluded.is_empty() {
+        Some(included)
+    } else {
+        // NOT binds tighter than AND and OR, so group what's included
+        Some(format!("({}){}", included, excluded))
+    }
+}
+
+/// Builds the full text search syntax for a word, prefix, phrase or `NEAR`
+/// group.
+fn fts_term(token: &Token, language: &str) -> String {
+    match token {
+        Token::Word(word) => format!("\"{}\"", index_words(word, language)),
+        Token::Prefix(prefix) => format!("\"{}\"*", fold(prefix)),
+        Token::Phrase(phrase) => format!("\"{}\"", index_words(phrase, language)),
+        Token::Near { terms, distance } => {
+            let terms: Vec<String> = terms.iter().map(|t| fts_term(t, language)).collect();
+            format!("NEAR({}, {})", terms.join(" "), distance)
+        }
+        Token::And | Token::Or | Token::Not => unreachable!("operators aren't search terms"),
+    }
+}
+
+/// Moves the `<mark>` tags that full text search put around matches in
+/// indexed text onto the same characters of the original text.
+pub(crate) fn unfold_highlights(original: &str, highlighted: &str, language: &str) -> String {
+    let (_, offsets) = index_words_with_offsets(original, language);
+    let original: Vec<char> = original.chars().collect();
+
+    // Tags with the index of the original character they go before
+    let mut tags = vec![];
+    let mut folded_index = 0;
+    let mut rest = highlighted;
+    while let Some(c) = rest.chars().next() {
+        let tag = [MARK_START, MARK_END]
+            .into_iter()
+            .find(|tag| rest.starts_with(tag));
+        match tag {
+            Some(tag) => {
+                let index = offsets.get(folded_index).copied();
+                tags.push((index.unwrap_or(original.len()), tag));
+                rest = &rest[tag.len()..];
+            }
+            None => {
+                folded_index += 1;
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+
+    let mut unfolded = String::with_capacity(highlighted.len());
+    let mut tags = tags.into_iter().peekable();
+    for (i, c) in original.into_iter().enumerate() {
+        while let Some((_, tag)) = tags.next_if(|&(index, _)| index <= i) {
+            unfolded.push_str(tag);
+        }
+        unfolded.push(c);
+    }
+    for (_, tag) in tags {
+        unfolded.push_str(tag);
+    }
+    unfolded
+}
+
+/// Wraps the non-empty matches of a regular expression in `<mark>` tags.
+fn highlight_matches(re: &Regex, text: &str) -> String {
+    let mut highlighted = String::with_capacity(text.len());
+    let mut last = 0;
+    for m in re.find_iter(text).filter(|m| !m.as_str().is_empty()) {
+        highlighted.push_str(&text[last..m.start()]);
+        highlighted.push_str(MARK_START);
+        highlighted.push_str(m.as_str());
+        highlighted.push_str(MARK_END);
+        last = m.end();
+    }
+    highlighted.push_str(&text[last..]);
+    highlighted
+}
+
+/// Gets the names to look up for a book, adding the numbered forms of a
+/// name that starts with an ordinal (e.g. `2 cor` and `2cor` for `II Cor`
+/// or `2nd Cor`).
+pub(crate) fn book_name_candidates(book_name: &str) -> Vec<String> {
+    let name = normalize(&book_name.to_lowercase());
+    let numbered = name.split_once(' ').and_then(|(ordinal, rest)| {
+        let number = match ordinal {
+            "i" | "1st" | "first" => 1,
+            "ii" | "2nd" | "second" => 2,
+            "iii" | "3rd" | "third" => 3,
+            _ => return None,
         };
+        Some((number, rest.trim_start().to_string()))
+    });
+
+    let mut candidates = vec![name.to_owned()];
+    if let Some((number, rest)) = numbered {
+        candidates.push(format!("{} {}", number, rest));
+        candidates.push(format!("{}{}", number, rest));
+    }
+    candidates
+}
+
+/// Ranks how well a book's lowercase name and abbreviations complete any of
+/// the typed candidates (lower is better), if they complete one at all.
+fn completion_rank<'a>(
+    candidates: &[String],
+    name: &str,
+    abbreviations: impl Iterator<Item = &'a str> + Clone,
+) -> Option<u8> {
+    let exact = |n: &str| candidates.iter().any(|c| c == n);
+    let starts = |n: &str| candidates.iter().any(|c| n.starts_with(c.as_str()));
+
+    if exact(name) || abbreviations.clone().any(exact) {
+        Some(0)
+    } else if starts(name) {
+        Some(1)
+    } else if abbreviations.clone().any(starts) {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+/// Main implementation for the [SwordDrillable](crate::sword_drill::SwordDrillable) trait.
+pub struct SwordDrill;
+
+impl SwordDrill {
+    /// Looks up a book by its name, one of its abbreviations, or its name
+    /// in another language.
+    pub(crate) fn find_book(book_name: &str, conn: &SqliteConnection) -> Result<Book, DbError> {
+        use crate::schema::book_abbreviations as ba;
+        use crate::schema::book_aliases as al;
+        use crate::schema::books as b;
+
+        let names = book_name_candidates(book_name);
+        let abbreviated = b::table
+            .inner_join(ba::table)
+            .filter(ba::abbreviation.eq_any(names.to_owned()))
+            .select(b::all_columns)
+            .first::<Book>(conn)
+            .optional();
+
+        // Names in other languages are only tried if no abbreviation matches
+        match abbreviated {
+            Ok(Some(book)) => Ok(book),
+            Ok(None) => b::table
+                .inner_join(al::table)
+                .filter(al::alias.eq_any(names))
+                .order_by(al::id)
+                .select(b::all_columns)
+                .first::<Book>(conn),
+            Err(e) => Err(e),
+        }
+        .map_err(|e| match e {
+            Error::NotFound => DbError::BookNotFound {
+                book: book_name.to_owned(),
+            },
+            e => DbError::Other {
+                cause: e.to_string(),
+            },
+        })
+    }
+
+    /// Loads the verses of a translation matching a full text search query,
+    /// with the matched words wrapped in `<mark>` tags.
+    ///
+    /// Only the verses in the books, and with the IDs, are loaded if any are
+    /// given. With options, the page of matches from them is loaded in their
+    /// order; otherwise every match is loaded in canonical order.
+    fn fts_matches(
+        query: &str,
+        translation: &Translation,
+        books: Option<Vec<i32>>,
+        ids: Option<Vec<i32>>,
+        options: Option<&SearchOptions>,
+        conn: &SqliteConnection,
+    ) -> Result<Vec<(VerseFTS, Book)>, DbError> {
+        use crate::schema::books;
+        use crate::schema::verses;
+        use crate::schema::verses_fts;
 
-        verses_fts::table
+        let mut matches = verses_fts::table
             .inner_join(books::table.on(books::id.eq(verses_fts::book)))
+            .inner_join(verses::table.on(verses::id.eq(verses_fts::rowid)))
             .select((
                 (
                     verses_fts::book,
                     verses_fts::chapter,
                     verses_fts::verse,
-                    highlight(verses_fts::text, 3, "<em>", "</em>"),
+                    highlight(verses_fts::text, 3, MARK_START, MARK_END),
                     verses_fts::rank,
                 ),
                 (
@@ -166,72 +754,1560 @@ impl SwordDrillable for SwordDrill {
                     books::name,
                     books::chapter_count,
                     books::testament,
+                    books::deuterocanonical,
                 ),
+                verses::words,
             ))
-            .filter(verses_fts::text.eq(format!("{}*", query)))
-            .order_by(verses_fts::rank)
-            .limit(SEARCH_RESULT_LIMIT)
-            .load::<(VerseFTS, Book)>(conn)
+            .filter(verses_fts::text.eq(query))
+            .filter(verses_fts::translation_id.eq(translation.id))
+            .into_boxed();
+        if let Some(books) = books {
+            matches = matches.filter(verses_fts::book.eq_any(books));
+        }
+        if let Some(ids) = ids {
+            matches = matches.filter(verses_fts::rowid.eq_any(ids));
+        }
+
+        let canonical = (verses_fts::book, verses_fts::chapter, verses_fts::verse);
+        matches = match options {
+            Some(options) => match options.order {
+                SearchOrder::Relevance => matches.order_by(verses_fts::rank),
+                SearchOrder::Canonical => matches.order_by(canonical),
+            }
+            .limit(options.per_page)
+            .offset(options.offset()),
+            None => matches.order_by(canonical),
+        };
+
+        Ok(matches
+            .load::<(VerseFTS, Book, String)>(conn)
             .map_err(|e| DbError::Other {
                 cause: e.to_string(),
+            })?
+            .into_iter()
+            .map(|(verse, book, words)| {
+                let words = unfold_highlights(&words, &verse.words, &translation.language);
+                (VerseFTS { words, ..verse }, book)
             })
+            .collect())
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use std::io::stdout;
-    use std::path::Path;
 
-    use diesel_migrations::run_pending_migrations_in_directory;
-
-    use super::*;
-    use crate::establish_connection;
+    /// Gets the IDs of the verses of a translation matching a full text search
+    /// query, in the order of the options.
+    ///
+    /// Only the verses in the books are matched if any are given, and only
+    /// those in the result set the options search within if there is one.
+    fn fts_ids(
+        query: &str,
+        translation: &Translation,
+        books: Option<Vec<i32>>,
+        options: &SearchOptions,
+        conn: &SqliteConnection,
+    ) -> Result<Vec<i32>, DbError> {
+        use crate::schema::verses_fts;
 
-    #[test]
-    fn all() {
-        let conn = {
-            let conn = establish_connection(":memory:");
-            run_pending_migrations_in_directory(&conn, &Path::new("./migrations"), &mut stdout())
-                .unwrap();
-            conn
+        let mut ids = verses_fts::table
+            .select(verses_fts::rowid)
+            .filter(verses_fts::text.eq(query))
+            .filter(verses_fts::translation_id.eq(translation.id))
+            .into_boxed();
+        if let Some(books) = books {
+            ids = ids.filter(verses_fts::book.eq_any(books));
+        }
+        ids = match options.order {
+            SearchOrder::Relevance => ids.order_by(verses_fts::rank),
+            SearchOrder::Canonical => {
+                ids.order_by((verses_fts::book, verses_fts::chapter, verses_fts::verse))
+            }
         };
 
-        conn.test_transaction::<_, DbError, _>(|| {
-            // Verses
-            {
-                let result = SwordDrill::verses(
-                &"Psalms 119:105".parse().unwrap(),
-                VerseFormat::PlainText,
-                &conn,
-            )?;
+        let mut ids = ids.load::<i32>(conn).map_err(|e| DbError::Other {
+            cause: e.to_string(),
+        })?;
+        if let Some(ref within) = options.within {
+            ids.retain(|&id| within.contains(id));
+        }
+        Ok(ids)
+    }
 
-            assert_eq!(result.0.name, "Psalms");
-            assert_eq!(
-                result.1[0].words,
-                "NUN. Thy word is a lamp unto my feet, and a light unto my path."
-            );
-            }
+    /// Gets the IDs of the books in a search scope, or `None` if the scope
+    /// is every book.
+    pub(crate) fn scope_books(
+        scope: &SearchScope,
+        conn: &SqliteConnection,
+    ) -> Result<Option<Vec<i32>>, DbError> {
+        use crate::schema::books as b;
 
-            // Book
-            {
-                let result = SwordDrill::book("psa", &conn)?;
+        if scope.is_empty() {
+            return Ok(None);
+        }
 
-            assert_eq!(result.0.name, "Psalms");
-            assert_eq!(result.1.len(), 150);
-            }
+        let mut books: Vec<Book> =
+            b::table
+                .order_by(b::id)
+                .load(conn)
+                .map_err(|e| DbError::Other {
+                    cause: e.to_string(),
+                })?;
+        if !scope.books.is_empty() {
+            let ids = scope
+                .books
+                .iter()
+                .map(|name| Self::find_book(name, conn).map(|book| book.id))
+                .collect::<Result<Vec<i32>, DbError>>()?;
+            books.retain(|book| ids.contains(&book.id));
+        }
+        if let Some(testament) = scope.testament {
+            books.retain(|book| book.testament == testament);
+        }
+        if let Some((ref first, ref last)) = scope.range {
+            let range = Self::find_book(first, conn)?.id..=Self::find_book(last, conn)?.id;
+            books.retain(|book| range.contains(&book.id));
+        }
+        Ok(Some(books.into_iter().map(|book| book.id).collect()))
+    }
 
-            // All books
-            {
-                let result = SwordDrill::all_books(&conn)?;
+    /// Loads the verses of the given chapters of a book in each of the given
+    /// translations, optionally limited to the verses of a reference.
+    fn load_verses(
+        book: i32,
+        chapters: &[i32],
+        reference: Option<&Reference>,
+        translation_ids: &[i32],
+        format: &VerseFormat,
+        conn: &SqliteConnection,
+    ) -> Result<Vec<Verse>, Error> {
+        use crate::schema::verses as plain_text;
+        use crate::schema::verses_html as html;
 
-            assert_eq!(result.len(), 66);
-            assert_eq!(result[64].name, "Jude");
-            }
+        match format {
+            VerseFormat::PlainText => {
+                let mut query = plain_text::table
+                    .filter(plain_text::translation_id.eq_any(translation_ids))
+                    .filter(plain_text::book.eq(book))
+                    .filter(plain_text::chapter.eq_any(chapters))
+                    .order_by((
+                        plain_text::chapter.asc(),
+                        plain_text::verse.asc(),
+                        plain_text::translation_id.asc(),
+                    ))
+                    .into_boxed();
+
+                if let Some((reference, start, end)) = verse_bounds(reference) {
+                    query = query
+                        .filter(
+                            plain_text::chapter
+                                .gt(reference.chapter)
+                                .or(plain_text::verse.ge(start)),
+                        )
+                        .filter(
+                            plain_text::chapter
+                                .lt(reference.last_chapter())
+                                .or(plain_text::verse.le(end)),
+                        );
+                }
+                query.load(conn)
+            }
+            VerseFormat::Html => {
+                let mut query = html::table
+                    .filter(html::translation_id.eq_any(translation_ids))
+                    .filter(html::book.eq(book))
+                    .filter(html::chapter.eq_any(chapters))
+                    .order_by((
+                        html::chapter.asc(),
+                        html::verse.asc(),
+                        html::translation_id.asc(),
+                    ))
+                    .into_boxed();
+
+                if let Some((reference, start, end)) = verse_bounds(reference) {
+                    query = query
+                        .filter(
+                            html::chapter
+                                .gt(reference.chapter)
+                                .or(html::verse.ge(start)),
+                        )
+                        .filter(
+                            html::chapter
+                                .lt(reference.last_chapter())
+                                .or(html::verse.le(end)),
+                        );
+                }
+                query.load(conn)
+            }
+        }
+    }
+
+    /// Loads the verses of a chapter along with their translation and book,
+    /// in one query.
+    ///
+    /// Only translations in the standard versification scheme, and books
+    /// named by their names or abbreviations, are found.
+    fn load_chapter(
+        book_name: &str,
+        chapter: i32,
+        version: &str,
+        format: &VerseFormat,
+        conn: &SqliteConnection,
+    ) -> Result<Vec<(Translation, Book, Verse)>, Error> {
+        use crate::schema::book_abbreviations as ba;
+        use crate::schema::books as b;
+        use crate::schema::translations as t;
+        use crate::schema::verses as plain_text;
+        use crate::schema::verses_html as html;
+
+        let books = ba::table
+            .select(ba::book_id)
+            .filter(ba::abbreviation.eq_any(book_name_candidates(book_name)));
+        let version = version.to_lowercase();
+
+        match format {
+            VerseFormat::PlainText => plain_text::table
+                .inner_join(b::table)
+                .inner_join(t::table)
+                .filter(t::abbreviation.eq(version))
+                .filter(t::versification.eq(STANDARD_VERSIFICATION))
+                .filter(b::id.eq_any(books))
+                .filter(plain_text::chapter.eq(chapter))
+                .order_by(plain_text::verse.asc())
+                .select((t::all_columns, b::all_columns, plain_text::all_columns))
+                .load(conn),
+            VerseFormat::Html => html::table
+                .inner_join(b::table)
+                .inner_join(t::table)
+                .filter(t::abbreviation.eq(version))
+                .filter(t::versification.eq(STANDARD_VERSIFICATION))
+                .filter(b::id.eq_any(books))
+                .filter(html::chapter.eq(chapter))
+                .order_by(html::verse.asc())
+                .select((t::all_columns, b::all_columns, html::all_columns))
+                .load(conn),
+        }
+    }
+
+    /// Loads the verses for a reference in translations that use another
+    /// versification scheme, renumbered to the standard scheme.
+    ///
+    /// Verses without a mapping are numbered the same in both schemes.
+    fn load_mapped_verses(
+        book: i32,
+        reference: &Reference,
+        versification: &str,
+        translation_ids: &[i32],
+        format: &VerseFormat,
+        conn: &SqliteConnection,
+    ) -> Result<Vec<Verse>, Error> {
+        use crate::schema::versification_mappings as vm;
+
+        let mappings = vm::table
+            .filter(vm::versification.eq(versification))
+            .filter(vm::book.eq(book))
+            .filter(
+                vm::chapter
+                    .between(reference.chapter, reference.last_chapter())
+                    .or(vm::standard_chapter.between(reference.chapter, reference.last_chapter())),
+            )
+            .load::<VersificationMapping>(conn)?;
+
+        // The reference may be spread over several chapters in the other scheme
+        let mut chapters: Vec<i32> = mappings
+            .iter()
+            .filter(|m| reference.chapters().contains(&m.standard_chapter))
+            .map(|m| m.chapter)
+            .collect();
+        chapters.extend(reference.chapters());
+        chapters.sort_unstable();
+        chapters.dedup();
+
+        let standard: HashMap<(i32, i32), (i32, i32)> = mappings
+            .into_iter()
+            .map(|m| ((m.chapter, m.verse), (m.standard_chapter, m.standard_verse)))
+            .collect();
+
+        let verses = Self::load_verses(book, &chapters, None, translation_ids, format, conn)?;
+        Ok(verses
+            .into_iter()
+            .filter_map(|mut verse| {
+                let (chapter, number) = standard
+                    .get(&(verse.chapter, verse.verse))
+                    .copied()
+                    .unwrap_or((verse.chapter, verse.verse));
+                if reference.contains(chapter, number) {
+                    verse.chapter = chapter;
+                    verse.verse = number;
+                    Some(verse)
+                } else {
+                    None
+                }
+            })
+            .collect())
+    }
+}
+
+impl BookStore for SwordDrill {
+    fn book(
+        book_name: &str,
+        translation: &Translation,
+        conn: &SqliteConnection,
+    ) -> Result<(Book, Vec<i32>), DbError> {
+        use crate::schema::verses as v;
+
+        let book = Self::find_book(book_name, conn)?;
+        let chapters = v::table
+            .select(v::chapter)
+            .filter(v::translation_id.eq(translation.id))
+            .filter(v::book.eq(book.id))
+            .distinct()
+            .order_by(v::chapter)
+            .load::<i32>(conn)
+            .map_err(|e| DbError::Other {
+                cause: e.to_string(),
+            })?;
+
+        Ok((book, chapters))
+    }
+
+    fn chapters(
+        book_name: &str,
+        translation: &Translation,
+        conn: &SqliteConnection,
+    ) -> Result<(Book, Vec<(i32, i64)>), DbError> {
+        use crate::schema::verses as v;
+
+        let book = Self::find_book(book_name, conn)?;
+        let verses = v::table
+            .select(v::chapter)
+            .filter(v::translation_id.eq(translation.id))
+            .filter(v::book.eq(book.id))
+            .order_by(v::chapter)
+            .load::<i32>(conn)
+            .map_err(|e| DbError::Other {
+                cause: e.to_string(),
+            })?;
+
+        let mut chapters: Vec<(i32, i64)> = vec![];
+        for chapter in verses {
+            match chapters.last_mut() {
+                Some((last, count)) if *last == chapter => *count += 1,
+                _ => chapters.push((chapter, 1)),
+            }
+        }
+        Ok((book, chapters))
+    }
+
+    fn all_books(translation: &Translation, conn: &SqliteConnection) -> Result<Vec<Book>, DbError> {
+        use crate::schema::books as b;
+        use crate::schema::verses as v;
+
+        // Deuterocanonical books are only listed if the translation has them
+        let translated = v::table
+            .select(v::book)
+            .filter(v::translation_id.eq(translation.id));
+
+        b::table
+            .filter(b::deuterocanonical.eq(false).or(b::id.eq_any(translated)))
+            .order_by(b::id)
+            .load(conn)
+            .map_err(|e| DbError::Other {
+                cause: e.to_string(),
+            })
+    }
+
+    fn complete_books(
+        prefix: &str,
+        translation: &Translation,
+        conn: &SqliteConnection,
+    ) -> Result<Vec<Book>, DbError> {
+        let candidates = book_name_candidates(prefix.trim());
+        let abbreviations = Self::book_abbreviations(conn)?;
+
+        let mut ranked: Vec<(u8, Book)> = Self::all_books(translation, conn)?
+            .into_iter()
+            .filter_map(|book| {
+                let names = abbreviations
+                    .iter()
+                    .filter(|a| a.book_id == book.id)
+                    .map(|a| a.abbreviation.as_str());
+                completion_rank(&candidates, &book.name.to_lowercase(), names)
+                    .map(|rank| (rank, book))
+            })
+            .collect();
+        ranked.sort_by_key(|(rank, book)| (*rank, book.id));
+
+        Ok(ranked.into_iter().map(|(_, book)| book).collect())
+    }
+
+    fn book_abbreviations(conn: &SqliteConnection) -> Result<Vec<BookAbbreviation>, DbError> {
+        use crate::schema::book_abbreviations as ba;
+
+        ba::table
+            .order_by(ba::id)
+            .load(conn)
+            .map_err(|e| DbError::Other {
+                cause: e.to_string(),
+            })
+    }
+
+    fn translations(conn: &SqliteConnection) -> Result<Vec<Translation>, DbError> {
+        use crate::schema::translations::dsl::*;
+
+        translations
+            .order_by(id)
+            .load(conn)
+            .map_err(|e| DbError::Other {
+                cause: e.to_string(),
+            })
+    }
+
+    fn translation(abbreviation: &str, conn: &SqliteConnection) -> Result<Translation, DbError> {
+        use crate::schema::translations as t;
+
+        t::table
+            .filter(t::abbreviation.eq(abbreviation.to_lowercase()))
+            .first(conn)
+            .map_err(|e| match e {
+                Error::NotFound => DbError::TranslationNotFound {
+                    translation: abbreviation.to_owned(),
+                },
+                e => DbError::Other {
+                    cause: e.to_string(),
+                },
+            })
+    }
+}
+
+impl VerseStore for SwordDrill {
+    fn verses(
+        reference: &Reference,
+        translation: &Translation,
+        format: VerseFormat,
+        conn: &SqliteConnection,
+    ) -> Result<(Book, Vec<Verse>), DbError> {
+        Self::parallel_verses(reference, slice::from_ref(translation), format, conn)
+    }
+
+    fn parallel_verses(
+        reference: &Reference,
+        translations: &[Translation],
+        format: VerseFormat,
+        conn: &SqliteConnection,
+    ) -> Result<(Book, Vec<Verse>), DbError> {
+        let book = Self::find_book(&reference.book, conn)?;
+        let reference = &reference.in_book(&book);
+
+        // Group the translations by the versification scheme they use
+        let mut schemes: BTreeMap<&str, Vec<i32>> = BTreeMap::new();
+        for translation in translations {
+            schemes
+                .entry(translation.versification.as_str())
+                .or_default()
+                .push(translation.id);
+        }
+
+        let mut verses = vec![];
+        for (versification, ids) in schemes {
+            let found = if versification == STANDARD_VERSIFICATION {
+                Self::load_verses(
+                    book.id,
+                    &reference.chapters().collect::<Vec<_>>(),
+                    Some(reference),
+                    &ids,
+                    &format,
+                    conn,
+                )
+            } else {
+                Self::load_mapped_verses(book.id, reference, versification, &ids, &format, conn)
+            }
+            .map_err(|e| DbError::Other {
+                cause: e.to_string(),
+            })?;
+            verses.extend(found);
+        }
+        if !reference.additional_verses.is_empty() {
+            verses.retain(|v| reference.contains(v.chapter, v.verse));
+        }
+        verses.sort_by_key(|v| (v.chapter, v.verse, v.translation_id));
+
+        Ok((book, verses))
+    }
+
+    fn chapter(
+        reference: &Reference,
+        version: &str,
+        format: VerseFormat,
+        conn: &SqliteConnection,
+    ) -> Result<(Translation, Book, Vec<Verse>), DbError> {
+        let found = if reference.is_chapter() {
+            Self::load_chapter(&reference.book, reference.chapter, version, &format, conn).map_err(
+                |e| DbError::Other {
+                    cause: e.to_string(),
+                },
+            )?
+        } else {
+            vec![]
+        };
+
+        match found.first() {
+            Some((translation, book, _)) => {
+                let (translation, book) = (translation.to_owned(), book.to_owned());
+                let verses = found.into_iter().map(|(_, _, verse)| verse).collect();
+                Ok((translation, book, verses))
+            }
+            // Anything else, and anything that wasn't found, is looked up
+            // like other passages, to tell what wasn't found
+            None => {
+                let translation = Self::translation(version, conn)?;
+                let (book, verses) = Self::verses(reference, &translation, format, conn)?;
+                Ok((translation, book, verses))
+            }
+        }
+    }
+
+    fn random_verse(
+        scope: &SearchScope,
+        exclude_genealogies: bool,
+        translation: &Translation,
+        conn: &SqliteConnection,
+    ) -> Result<Option<Reference>, DbError> {
+        use crate::schema::books as b;
+        use crate::schema::verses as v;
+
+        let mut verses = v::table
+            .inner_join(b::table)
+            .select((b::name, v::chapter, v::verse))
+            .filter(v::translation_id.eq(translation.id))
+            .into_boxed();
+        if let Some(books) = Self::scope_books(scope, conn)? {
+            verses = verses.filter(v::book.eq_any(books));
+        }
+        if exclude_genealogies {
+            for (book, (first_chapter, last_chapter), (first, last)) in GENEALOGIES {
+                verses = verses.filter(diesel::dsl::not(
+                    v::book
+                        .eq(book)
+                        .and(v::chapter.between(first_chapter, last_chapter))
+                        .and(v::verse.between(first, last)),
+                ));
+            }
+        }
+
+        let verse = verses
+            .order_by(random())
+            .first::<(String, i32, i32)>(conn)
+            .optional()
+            .map_err(|e| DbError::Other {
+                cause: e.to_string(),
+            })?;
+        Ok(verse.map(|(book, chapter, verse)| Reference {
+            book,
+            chapter,
+            verses: Some(verse..=verse),
+            end_chapter: None,
+            additional_verses: vec![],
+            start_part: None,
+            end_part: None,
+        }))
+    }
+
+    fn verse_of_the_day(day: i64, conn: &SqliteConnection) -> Result<Reference, DbError> {
+        use crate::schema::daily_verses as d;
+
+        let references = d::table
+            .select(d::reference)
+            .order_by(d::id)
+            .load::<String>(conn)
+            .map_err(|e| DbError::Other {
+                cause: e.to_string(),
+            })?;
+        if references.is_empty() {
+            return Err(DbError::Other {
+                cause: "there are no daily verses".to_string(),
+            });
+        }
+        references[day.rem_euclid(references.len() as i64) as usize].parse()
+    }
+
+    fn stats(
+        book: Option<&Book>,
+        translation: &Translation,
+        conn: &SqliteConnection,
+    ) -> Result<Stats, DbError> {
+        use crate::schema::books as b;
+        use crate::schema::verses as v;
+
+        let to_db_error = |e: Error| DbError::Other {
+            cause: e.to_string(),
+        };
+        // Every book is between the smallest and largest ids
+        let (first, last) = book.map_or((i32::MIN, i32::MAX), |b| (b.id, b.id));
+
+        let (book_count, chapter_count, verse_count, word_count) = v::table
+            .filter(v::translation_id.eq(translation.id))
+            .filter(v::book.between(first, last))
+            .select((
+                sql::<BigInt>("COUNT(DISTINCT book)"),
+                sql::<BigInt>("COUNT(DISTINCT book * 1000 + chapter)"),
+                sql::<BigInt>("COUNT(*)"),
+                sql::<BigInt>(&format!("COALESCE(SUM({}), 0)", WORD_COUNT_SQL)),
+            ))
+            .first::<(i64, i64, i64, i64)>(conn)
+            .map_err(to_db_error)?;
+
+        let verse_length = |order: &str| {
+            v::table
+                .inner_join(b::table)
+                .filter(v::translation_id.eq(translation.id))
+                .filter(v::book.between(first, last))
+                .select((
+                    b::name,
+                    v::chapter,
+                    v::verse,
+                    v::words,
+                    sql::<BigInt>(WORD_COUNT_SQL),
+                ))
+                .order_by(sql::<BigInt>(&format!(
+                    "{} {}, verses.id",
+                    WORD_COUNT_SQL, order
+                )))
+                .first::<VerseLength>(conn)
+                .optional()
+                .map_err(to_db_error)
+        };
+
+        Ok(Stats {
+            book_count,
+            chapter_count,
+            verse_count,
+            word_count,
+            longest_verse: verse_length("DESC")?,
+            shortest_verse: verse_length("ASC")?,
+        })
+    }
+
+    fn cross_references(
+        reference: &Reference,
+        conn: &SqliteConnection,
+    ) -> Result<(Book, Vec<(CrossReference, Book)>), DbError> {
+        use crate::schema::books as b;
+        use crate::schema::cross_references as x;
+
+        let book = Self::find_book(&reference.book, conn)?;
+        let cross_references = x::table
+            .inner_join(b::table.on(b::id.eq(x::to_book)))
+            .filter(x::book.eq(book.id))
+            .filter(x::chapter.between(reference.chapter, reference.last_chapter()))
+            .order_by((x::chapter, x::verse, x::votes.desc(), x::id))
+            .load::<(CrossReference, Book)>(conn)
+            .map_err(|e| DbError::Other {
+                cause: e.to_string(),
+            })?
+            .into_iter()
+            .filter(|(x, _)| reference.contains(x.chapter, x.verse))
+            .collect();
+        Ok((book, cross_references))
+    }
+}
+
+impl Searcher for SwordDrill {
+    fn search(
+        query: &str,
+        options: &SearchOptions,
+        translation: &Translation,
+        conn: &SqliteConnection,
+    ) -> Result<(Vec<(VerseFTS, Book)>, i64), DbError> {
+        use crate::schema::verses_fts;
+
+        // Don't even try to run the query if there are no words
+        let query = match fts_query(query, &translation.language, options.whole_word) {
+            Some(query) => query,
+            None => return Ok((vec![], 0)),
+        };
+        let books = Self::scope_books(&options.scope, conn)?;
+
+        // Result sets can be too big to filter by in SQL, so page their IDs
+        if options.within.is_some() {
+            let ids = Self::fts_ids(&query, translation, books, options, conn)?;
+            let total = ids.len() as i64;
+            let page: Vec<i32> = ids
+                .into_iter()
+                .skip(options.offset() as usize)
+                .take(options.per_page as usize)
+                .collect();
+            if page.is_empty() {
+                return Ok((vec![], total));
+            }
+            let mut matches = Self::fts_matches(&query, translation, None, Some(page), None, conn)?;
+            if options.order == SearchOrder::Relevance {
+                matches.sort_by(|(a, _), (b, _)| a.rank.total_cmp(&b.rank));
+            }
+            return Ok((matches, total));
+        }
+
+        let mut total = verses_fts::table
+            .filter(verses_fts::text.eq(&query))
+            .filter(verses_fts::translation_id.eq(translation.id))
+            .count()
+            .into_boxed();
+        if let Some(ref books) = books {
+            total = total.filter(verses_fts::book.eq_any(books.clone()));
+        }
+        let total = total.get_result::<i64>(conn).map_err(|e| DbError::Other {
+            cause: e.to_string(),
+        })?;
+
+        let matches = Self::fts_matches(&query, translation, books, None, Some(options), conn)?;
+        Ok((matches, total))
+    }
+
+    fn search_translations(
+        query: &str,
+        options: &SearchOptions,
+        translations: &[Translation],
+        conn: &SqliteConnection,
+    ) -> Result<(Vec<VerseMatches>, i64), DbError> {
+        use crate::schema::verses_fts;
+
+        let books = Self::scope_books(&options.scope, conn)?;
+
+        // Find the IDs of the verses matched in each translation
+        let mut verses: BTreeMap<(i32, i32, i32), Vec<i32>> = BTreeMap::new();
+        let mut queries = vec![];
+        for translation in translations {
+            let query = match fts_query(query, &translation.language, options.whole_word) {
+                Some(query) => query,
+                None => continue,
+            };
+            let mut matches = verses_fts::table
+                .select((
+                    verses_fts::book,
+                    verses_fts::chapter,
+                    verses_fts::verse,
+                    verses_fts::rowid,
+                ))
+                .filter(verses_fts::text.eq(&query))
+                .filter(verses_fts::translation_id.eq(translation.id))
+                .into_boxed();
+            if let Some(ref books) = books {
+                matches = matches.filter(verses_fts::book.eq_any(books.clone()));
+            }
+            let matches =
+                matches
+                    .load::<(i32, i32, i32, i32)>(conn)
+                    .map_err(|e| DbError::Other {
+                        cause: e.to_string(),
+                    })?;
+            for (book, chapter, verse, id) in matches {
+                if matches!(options.within, Some(ref within) if !within.contains(id)) {
+                    continue;
+                }
+                verses.entry((book, chapter, verse)).or_default().push(id);
+            }
+            queries.push((translation, query));
+        }
+
+        // Only highlight the matches of the verses on the page
+        let total = verses.len() as i64;
+        let ids: Vec<i32> = verses
+            .into_values()
+            .skip(options.offset() as usize)
+            .take(options.per_page as usize)
+            .flatten()
+            .collect();
+        if ids.is_empty() {
+            return Ok((vec![], total));
+        }
+        let mut groups: BTreeMap<(i32, i32, i32), VerseMatches> = BTreeMap::new();
+        for (translation, query) in queries {
+            let matches =
+                Self::fts_matches(&query, translation, None, Some(ids.clone()), None, conn)?;
+            for (verse, book) in matches {
+                groups
+                    .entry((verse.book, verse.chapter, verse.verse))
+                    .or_insert_with(|| VerseMatches {
+                        book,
+                        chapter: verse.chapter,
+                        verse: verse.verse,
+                        matches: vec![],
+                    })
+                    .matches
+                    .push((translation.to_owned(), verse.words));
+            }
+        }
+        Ok((groups.into_values().collect(), total))
+    }
+
+    fn search_regex(
+        pattern: &str,
+        options: &SearchOptions,
+        translation: &Translation,
+        conn: &SqliteConnection,
+    ) -> Result<(Vec<(VerseFTS, Book)>, i64), DbError> {
+        use crate::schema::books;
+        use crate::schema::verses;
+
+        let started = Instant::now();
+        if pattern.chars().count() > REGEX_PATTERN_LIMIT {
+            return Err(DbError::InvalidSearch {
+                cause: format!(
+                    "patterns can't be longer than {} characters",
+                    REGEX_PATTERN_LIMIT
+                ),
+            });
+        }
+        let pattern = match options.whole_word {
+            true => format!(r"\b(?:{})\b", normalize(pattern)),
+            false => normalize(pattern),
+        };
+        let re = RegexBuilder::new(&pattern)
+            .size_limit(REGEX_SIZE_LIMIT)
+            .dfa_size_limit(REGEX_SIZE_LIMIT)
+            .build()
+            .map_err(|e| DbError::InvalidSearch {
+                cause: e.to_string(),
+            })?;
+
+        let mut verses = verses::table
+            .inner_join(books::table.on(books::id.eq(verses::book)))
+            .select((verses::all_columns, books::all_columns))
+            .filter(verses::translation_id.eq(translation.id))
+            .into_boxed();
+        if let Some(books) = Self::scope_books(&options.scope, conn)? {
+            verses = verses.filter(verses::book.eq_any(books));
+        }
+
+        let verses = verses
+            .order_by(verses::id)
+            .load::<(Verse, Book)>(conn)
+            .map_err(|e| DbError::Other {
+                cause: e.to_string(),
+            })?;
+
+        let mut results = vec![];
+        let mut total = 0;
+        for (verse, book) in verses {
+            if started.elapsed() > REGEX_SEARCH_TIME_LIMIT {
+                return Err(DbError::InvalidSearch {
+                    cause: format!(
+                        "it took longer than {} seconds",
+                        REGEX_SEARCH_TIME_LIMIT.as_secs()
+                    ),
+                });
+            }
+            if matches!(options.within, Some(ref within) if !within.contains(verse.id)) {
+                continue;
+            }
+            if !re.is_match(&verse.words) {
+                continue;
+            }
+            total += 1;
+            if total <= options.offset() || results.len() as i64 == options.per_page {
+                continue;
+            }
+            let words = highlight_matches(&re, &verse.words);
+            results.push((
+                VerseFTS {
+                    book: verse.book,
+                    chapter: verse.chapter,
+                    verse: verse.verse,
+                    words,
+                    rank: 0.0,
+                },
+                book,
+            ));
+        }
+        Ok((results, total))
+    }
+
+    fn search_result_set(
+        query: &str,
+        options: &SearchOptions,
+        translation: &Translation,
+        conn: &SqliteConnection,
+    ) -> Result<ResultSet, DbError> {
+        let query = match fts_query(query, &translation.language, options.whole_word) {
+            Some(query) => query,
+            None => return Ok(ResultSet::default()),
+        };
+        let books = Self::scope_books(&options.scope, conn)?;
+        let ids = Self::fts_ids(&query, translation, books, options, conn)?;
+        Ok(ResultSet::new(ids))
+    }
+
+    fn search_facets(
+        query: &str,
+        options: &SearchOptions,
+        translation: &Translation,
+        conn: &SqliteConnection,
+    ) -> Result<Vec<(Book, i64)>, DbError> {
+        use crate::schema::books;
+        use crate::schema::verses_fts;
+
+        let query = match fts_query(query, &translation.language, options.whole_word) {
+            Some(query) => query,
+            None => return Ok(vec![]),
+        };
+        let scoped = Self::scope_books(&options.scope, conn)?;
+
+        let mut matches = verses_fts::table
+            .select((verses_fts::book, verses_fts::rowid))
+            .filter(verses_fts::text.eq(query))
+            .filter(verses_fts::translation_id.eq(translation.id))
+            .into_boxed();
+        if let Some(books) = scoped {
+            matches = matches.filter(verses_fts::book.eq_any(books));
+        }
+
+        let mut counts: HashMap<i32, i64> = HashMap::new();
+        let matches = matches
+            .load::<(i32, i32)>(conn)
+            .map_err(|e| DbError::Other {
+                cause: e.to_string(),
+            })?;
+        for (book, id) in matches {
+            if matches!(options.within, Some(ref within) if !within.contains(id)) {
+                continue;
+            }
+            *counts.entry(book).or_insert(0) += 1;
+        }
+
+        let books: Vec<Book> =
+            books::table
+                .order_by(books::id)
+                .load(conn)
+                .map_err(|e| DbError::Other {
+                    cause: e.to_string(),
+                })?;
+        Ok(books
+            .into_iter()
+            .filter_map(|book| counts.get(&book.id).map(|&count| (book, count)))
+            .collect())
+    }
+
+    fn suggest_spelling(
+        query: &str,
+        translation: &Translation,
+        conn: &SqliteConnection,
+    ) -> Result<Option<String>, DbError> {
+        Ok(Dictionary::of(translation, conn)?.suggest(&normalize(query)))
+    }
+
+    fn complete_search(
+        prefix: &str,
+        translation: &Translation,
+        conn: &SqliteConnection,
+    ) -> Result<(Vec<String>, Vec<String>), DbError> {
+        let dictionary = Dictionary::of(translation, conn)?;
+        let (words, phrases) = dictionary.complete(&normalize(prefix));
+        let to_strings =
+            |texts: Vec<&str>| -> Vec<String> { texts.into_iter().map(str::to_string).collect() };
+        Ok((to_strings(words), to_strings(phrases)))
+    }
+}
+
+impl SwordDrillable for SwordDrill {
+    fn api_key(key: &str, conn: &SqliteConnection) -> Result<Option<ApiKey>, DbError> {
+        use crate::schema::api_keys as k;
+
+        k::table
+            .select((k::id, k::name, k::created_at, k::revoked_at))
+            .filter(k::key_hash.eq(hash_key(key)))
+            .filter(k::revoked_at.is_null())
+            .first(conn)
+            .optional()
+            .map_err(|e| DbError::Other {
+                cause: e.to_string(),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::stdout;
+    use std::path::Path;
+
+    use diesel_migrations::run_pending_migrations_in_directory;
+
+    use super::*;
+    use crate::{
+        create_api_key, establish_connection, fold_with_offsets, import_cross_references,
+        list_api_keys, revoke_api_key,
+    };
+
+    fn verse(verse: i32, translation_id: i32) -> Verse {
+        Verse {
+            id: verse,
+            book: 43,
+            chapter: 5,
+            verse,
+            words: format!("{}:{}", translation_id, verse),
+            translation_id,
+            paragraph: false,
+            poetry_level: 0,
+            line_break: false,
+        }
+    }
+
+    #[test]
+    fn fill_gaps_from_fallback() {
+        // Translation 2 omits verse 4, which translation 1 has
+        let verses = vec![
+            verse(3, 1),
+            verse(3, 2),
+            verse(4, 1),
+            verse(5, 1),
+            verse(5, 2),
+        ];
+        let result = fill_gaps(verses, 2);
+
+        assert_eq!(result.len(), 3);
+        assert_eq!(result[0].words, "2:3");
+        assert_eq!(result[1].words, "1:4");
+        assert_eq!(result[2].words, "2:5");
+    }
+
+    #[test]
+    fn completion_ranks() {
+        let candidates = book_name_candidates("1 co");
+        let abbreviations = ["1 cor", "1co", "i corinthians"];
+        assert_eq!(
+            completion_rank(&candidates, "1 corinthians", abbreviations.iter().copied()),
+            Some(1)
+        );
+        assert_eq!(
+            completion_rank(&candidates, "1 chronicles", ["1 chr"].iter().copied()),
+            None
+        );
+
+        let candidates = book_name_candidates("jn");
+        assert_eq!(
+            completion_rank(&candidates, "john", ["jn", "jhn"].iter().copied()),
+            Some(0)
+        );
+        assert_eq!(
+            completion_rank(&candidates, "jonah", ["jnh"].iter().copied()),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn ordinal_book_names() {
+        assert_eq!(
+            book_name_candidates("II Corinthians"),
+            vec!["ii corinthians", "2 corinthians", "2corinthians"]
+        );
+        assert_eq!(
+            book_name_candidates("2nd Cor"),
+            vec!["2nd cor", "2 cor", "2cor"]
+        );
+        assert_eq!(
+            book_name_candidates("1st Jn"),
+            vec!["1st jn", "1 jn", "1jn"]
+        );
+        assert_eq!(
+            book_name_candidates("Third John"),
+            vec!["third john", "3 john", "3john"]
+        );
+        assert_eq!(
+            book_name_candidates("Song of Solomon"),
+            vec!["song of solomon"]
+        );
+    }
+
+    #[test]
+    fn fts_queries() {
+        assert_eq!(
+            fts_query("fire hammer", "en", false),
+            Some("\"fire\" \"hammer\"*".to_string())
+        );
+        assert_eq!(
+            fts_query("\"in the beginning\"", "en", false),
+            Some("\"in the begin\"".to_string())
+        );
+        assert_eq!(
+            fts_query("\"in the beginning\" god cr", "en", false),
+            Some("\"in the begin\" \"god\" \"cr\"*".to_string())
+        );
+        assert_eq!(
+            fts_query("not \"the   LORD's\" 1", "en", false),
+            Some("\"not\" \"the lord\"".to_string())
+        );
+        assert_eq!(
+            fts_query("loving \"God so loved\"", "en", false),
+            Some("\"love\" \"god so love\"".to_string())
+        );
+        assert_eq!(
+            fts_query("Jesús \u{5d1}\u{5b0}\u{5bc}\u{5e8}\u{5b5}", "es", false),
+            Some("\"jesus\" \"\u{5d1}\u{5e8}\"*".to_string())
+        );
+        assert_eq!(fts_query("1 \"\" :", "en", false), None);
+        assert_eq!(
+            fts_query("faith AND works NOT law", "en", false),
+            Some("(\"faith\" AND \"work\") NOT \"law\"".to_string())
+        );
+        assert_eq!(
+            fts_query("OR hammer OR \"a fire\" -rock", "en", false),
+            Some("(\"hammer\" OR \"a fire\") NOT \"rock\"".to_string())
+        );
+        assert_eq!(fts_query("-rock NOT fire", "en", false), None);
+        assert_eq!(
+            fts_query("bapti* -baptiz* john", "en", false),
+            Some("(\"bapti\"* \"john\"*) NOT \"baptiz\"*".to_string())
+        );
+        assert_eq!(
+            fts_query("fire man", "en", true),
+            Some("\"fire\" \"man\"".to_string())
+        );
+        assert_eq!(
+            fts_query("NEAR(faith works, 5) -NEAR(\"the law\" dead*)", "en", false),
+            Some("(NEAR(\"faith\" \"work\", 5)) NOT NEAR(\"the law\" \"dead\"*, 10)".to_string())
+        );
+    }
+
+    #[test]
+    fn unfold_search_highlights() {
+        assert_eq!(
+            unfold_highlights(
+                "Él es el Cordero",
+                "<mark>el</mark> es <mark>el</mark> cordero",
+                "es"
+            ),
+            "<mark>Él</mark> es <mark>el</mark> Cordero"
+        );
+        // Points after the last letter of a match stay inside of it
+        assert_eq!(
+            unfold_highlights(
+                "\u{5d1}\u{5b8}\u{5bc}\u{5e8}\u{5b8}\u{5d0} \u{5d0}",
+                "<mark>\u{5d1}\u{5e8}\u{5d0}</mark> \u{5d0}",
+                "he"
+            ),
+            "<mark>\u{5d1}\u{5b8}\u{5bc}\u{5e8}\u{5b8}\u{5d0}</mark> \u{5d0}"
+        );
+        assert_eq!(
+            unfold_highlights("the Lamb", "the <mark>lamb</mark>", "en"),
+            "the <mark>Lamb</mark>"
+        );
+        // Stems cover the whole of the words they came from
+        assert_eq!(
+            unfold_highlights(
+                "God so loved the world",
+                "<mark>god so love</mark> the world",
+                "en"
+            ),
+            "<mark>God so loved</mark> the world"
+        );
+    }
+
+    #[test]
+    fn highlight_regex_matches() {
+        let re = Regex::new("light|x*").unwrap();
+        assert_eq!(
+            highlight_matches(&re, "a light unto my path"),
+            "a <mark>light</mark> unto my path"
+        );
+    }
+
+    #[test]
+    fn fold_text() {
+        assert_eq!(fold("Lamb"), "lamb");
+        assert_eq!(fold("Jesús café"), "jesus cafe");
+        // Greek accents and breathings, and Hebrew points
+        assert_eq!(fold("Ἐν ἀρχῇ"), "εν αρχη");
+        assert_eq!(
+            fold("\u{5d1}\u{5b0}\u{5bc}\u{5e8}\u{5b5}"),
+            "\u{5d1}\u{5e8}"
+        );
+
+        let (folded, offsets) = fold_with_offsets("Él");
+        assert_eq!(folded, "el");
+        assert_eq!(offsets, vec![0, 1]);
+    }
+
+    #[test]
+    fn normalize_query() {
+        // Decomposed "é", and Hebrew points out of canonical order
+        assert_eq!(normalize("cafe\u{301}"), "caf\u{e9}");
+        assert_eq!(normalize("\u{5e9}\u{5c1}\u{5b8}"), "\u{5e9}\u{5b8}\u{5c1}");
+    }
+
+    #[test]
+    fn all() {
+        let conn = {
+            let conn = establish_connection(":memory:");
+            run_pending_migrations_in_directory(&conn, &Path::new("./migrations"), &mut stdout())
+                .unwrap();
+            conn
+        };
+
+        conn.test_transaction::<_, DbError, _>(|| {
+            // Translations
+            let kjv = SwordDrill::translation("KJV", &conn)?;
+            {
+                assert_eq!(kjv.abbreviation, "kjv");
+                assert_eq!(kjv.name, "King James Version");
+                assert_eq!(kjv.license.as_deref(), Some("Public Domain"));
+                assert_eq!(kjv.direction, "ltr");
+                assert!(kjv.imported_at > 0);
+
+                let result = SwordDrill::translations(&conn)?;
+                assert_eq!(result[0], kjv);
+
+                let result = SwordDrill::translation("nope", &conn);
+                assert!(matches!(result, Err(DbError::TranslationNotFound { .. })));
+
+                // Version of the latest migration
+                assert_eq!(crate::data_version(&conn)?, "20220604000000");
+                // The King James Version has 31,102 verses
+                assert!(crate::verse_count(&conn)? >= 31_102);
+            }
+
+            // Verses
+            {
+                let result = SwordDrill::verses(
+                    &"Psalms 119:105".parse().unwrap(),
+                    &kjv,
+                    VerseFormat::PlainText,
+                    &conn,
+                )?;
+
+                assert_eq!(result.0.name, "Psalms");
+                assert_eq!(
+                    result.1[0].words,
+                    "NUN. Thy word is a lamp unto my feet, and a light unto my path."
+                );
+            }
+
+            // Chapters
+            {
+                let chapter = |reference: &str, version: &str, format: VerseFormat| {
+                    SwordDrill::chapter(&reference.parse().unwrap(), version, format, &conn)
+                };
+
+                let (translation, book, verses) = chapter("John 3", "KJV", VerseFormat::PlainText)?;
+                assert_eq!((translation.id, book.name.as_str()), (kjv.id, "John"));
+                assert_eq!(verses.len(), 36);
+                assert_eq!(verses[15].verse, 16);
+                assert!(verses[15].words.starts_with("For God so loved the world"));
+
+                // In any case, in HTML
+                let (_, book, verses) = chapter("john 3", "kjv", VerseFormat::Html)?;
+                assert_eq!(book.name, "John");
+                assert_eq!(verses.len(), 36);
+
+                // The same as other passages
+                let (_, book, verses) = chapter("Psalms 119:105", "kjv", VerseFormat::PlainText)?;
+                assert_eq!((book.name.as_str(), verses.len()), ("Psalms", 1));
+
+                let result = chapter("Nope 1", "kjv", VerseFormat::PlainText);
+                assert!(matches!(result, Err(DbError::BookNotFound { .. })));
+                let result = chapter("John 3", "nope", VerseFormat::PlainText);
+                assert!(matches!(result, Err(DbError::TranslationNotFound { .. })));
+            }
+
+            // Verses in context
+            {
+                let context = |reference: &str, before: i32, after: i32| {
+                    SwordDrill::verses_in_context(
+                        &reference.parse().unwrap(),
+                        before,
+                        after,
+                        &kjv,
+                        VerseFormat::PlainText,
+                        &conn,
+                    )
+                    .map(|(_, (_, verses))| {
+                        verses
+                            .iter()
+                            .map(|v| (v.chapter, v.verse))
+                            .collect::<Vec<(i32, i32)>>()
+                    })
+                };
+
+                assert_eq!(
+                    context("John 3:16", 2, 2)?,
+                    vec![(3, 14), (3, 15), (3, 16), (3, 17), (3, 18)]
+                );
+                // Context crosses into the chapters around the reference
+                assert_eq!(
+                    context("Genesis 2:1", 2, 1)?,
+                    vec![(1, 30), (1, 31), (2, 1), (2, 2)]
+                );
+                assert_eq!(
+                    context("Genesis 1:31", 0, 2)?,
+                    vec![(1, 31), (2, 1), (2, 2)]
+                );
+                // But not out of the book
+                assert_eq!(context("Genesis 1:1", 3, 0)?, vec![(1, 1)]);
+                assert_eq!(context("Jude 1:25", 0, 3)?, vec![(1, 25)]);
+            }
+
+            // Stats
+            {
+                let (john, _) = SwordDrill::book("John", &kjv, &conn)?;
+                let result = SwordDrill::stats(Some(&john), &kjv, &conn)?;
+                assert_eq!(result.book_count, 1);
+                assert_eq!(result.chapter_count, 21);
+                assert_eq!(result.verse_count, 879);
+                let shortest = result.shortest_verse.unwrap();
+                assert_eq!((shortest.chapter, shortest.verse), (11, 35));
+                assert_eq!(shortest.words, "Jesus wept.");
+                assert_eq!(shortest.word_count, 2);
+
+                let result = SwordDrill::stats(None, &kjv, &conn)?;
+                assert_eq!(result.book_count, 66);
+                assert_eq!(result.chapter_count, 1189);
+                assert_eq!(result.verse_count, 31102);
+                let longest = result.longest_verse.unwrap();
+                assert_eq!(longest.book, "Esther");
+                assert_eq!((longest.chapter, longest.verse), (8, 9));
+            }
+
+            // Cross references
+            {
+                let dataset = "From Verse\tTo Verse\tVotes\n\
+                               John.3.16\tRomans.5.8\t120\n\
+                               John.3.16\t1 John.4.9-1 John.4.10\t251\n\
+                               John.3.17\tJohn.12.47\t40\n\
+                               John.3.18\tJohn.5.24";
+                assert_eq!(import_cross_references(dataset.as_bytes(), &conn)?, 4);
+
+                let reference = "john 3:16-17".parse()?;
+                let (book, result) = SwordDrill::cross_references(&reference, &conn)?;
+                assert_eq!(book.name, "John");
+                let result: Vec<String> = result
+                    .iter()
+                    .map(|(x, b)| x.to_reference(b).to_string())
+                    .collect();
+                assert_eq!(result, ["1 John 4:9-10", "Romans 5:8", "John 12:47"]);
+
+                let result = import_cross_references("John.3.16\tNope.1.1".as_bytes(), &conn);
+                assert!(matches!(result, Err(DbError::BookNotFound { .. })));
+            }
+
+            // Parallel verses
+            {
+                let result = SwordDrill::parallel_verses(
+                    &"John 3:16-17".parse().unwrap(),
+                    &[kjv.clone()],
+                    VerseFormat::PlainText,
+                    &conn,
+                )?;
+
+                assert_eq!(result.0.name, "John");
+                assert_eq!(result.1.len(), 2);
+                assert!(result.1.iter().all(|v| v.translation_id == kjv.id));
+            }
+
+            // Verses - Spanning chapters
+            {
+                let result = SwordDrill::verses(
+                    &"Genesis 1:31-2:2".parse().unwrap(),
+                    &kjv,
+                    VerseFormat::PlainText,
+                    &conn,
+                )?;
+
+                let verses: Vec<(i32, i32)> =
+                    result.1.iter().map(|v| (v.chapter, v.verse)).collect();
+                assert_eq!(verses, vec![(1, 31), (2, 1), (2, 2)]);
+            }
+
+            // Verses - Lone numbers after books with one chapter
+            {
+                for (reference, verse) in [("Jude 6", 6), ("Philemon 12", 12)] {
+                    let result = SwordDrill::verses(
+                        &reference.parse().unwrap(),
+                        &kjv,
+                        VerseFormat::PlainText,
+                        &conn,
+                    )?;
+                    let verses: Vec<(i32, i32)> =
+                        result.1.iter().map(|v| (v.chapter, v.verse)).collect();
+                    assert_eq!(verses, vec![(1, verse)]);
+                }
+            }
+
+            // Verses - Listed verses
+            {
+                let result = SwordDrill::verses(
+                    &"John 3:16,18,20-21".parse().unwrap(),
+                    &kjv,
+                    VerseFormat::PlainText,
+                    &conn,
+                )?;
+
+                let verses: Vec<i32> = result.1.iter().map(|v| v.verse).collect();
+                assert_eq!(verses, vec![16, 18, 20, 21]);
+            }
+
+            // Verses - Chapters
+            {
+                let result = SwordDrill::verses(
+                    &"Genesis 1-3".parse().unwrap(),
+                    &kjv,
+                    VerseFormat::PlainText,
+                    &conn,
+                )?;
+
+                assert_eq!(result.1.len(), 31 + 25 + 24);
+                assert_eq!(result.1.last().map(|v| (v.chapter, v.verse)), Some((3, 24)));
+            }
+
+            // Verses - Rest of the chapter
+            {
+                let result = SwordDrill::verses(
+                    &"Romans 5:12ff".parse().unwrap(),
+                    &kjv,
+                    VerseFormat::PlainText,
+                    &conn,
+                )?;
+
+                let verses: Vec<i32> = result.1.iter().map(|v| v.verse).collect();
+                assert_eq!(verses, (12..=21).collect::<Vec<i32>>());
+            }
+
+            // Verses - Another versification scheme
+            {
+                // Psalm 10 (KJV) is the second half of Psalm 9 in the Septuagint
+                let mut lxx = kjv.clone();
+                lxx.versification = "lxx".to_string();
+                let result = SwordDrill::verses(
+                    &"Psalms 11:1".parse().unwrap(),
+                    &lxx,
+                    VerseFormat::PlainText,
+                    &conn,
+                )?;
+
+                assert_eq!(result.1.len(), 1);
+                assert_eq!(result.1[0].chapter, 11);
+                assert_eq!(result.1[0].verse, 1);
+                assert_eq!(
+                    result.1[0].words,
+                    "Why standest thou afar off, O LORD? why hidest thou thyself in times of \
+                    trouble?"
+                );
+            }
+
+            // Book
+            {
+                let result = SwordDrill::book("psa", &kjv, &conn)?;
+
+                assert_eq!(result.0.name, "Psalms");
+                assert_eq!(result.1.len(), 150);
+            }
+
+            // All books
+            {
+                let result = SwordDrill::all_books(&kjv, &conn)?;
+
+                assert_eq!(result.len(), 66);
+                assert_eq!(result[64].name, "Jude");
+                assert!(result.iter().all(|b| !b.deuterocanonical));
+            }
+
+            // Book - Deuterocanonical
+            {
+                let result = SwordDrill::book("1 Macc", &kjv, &conn)?;
+
+                assert_eq!(result.0.name, "1 Maccabees");
+                assert!(result.0.deuterocanonical);
+                assert!(result.1.is_empty());
+            }
+
+            // Book - Name in another language
+            {
+                let result = SwordDrill::book("Juan", &kjv, &conn)?;
+                assert_eq!(result.0.name, "John");
+
+                let result = SwordDrill::book("1. Mose", &kjv, &conn)?;
+                assert_eq!(result.0.name, "Genesis");
+            }
+
+            // Complete books
+            {
+                let result = SwordDrill::complete_books("1 co", &kjv, &conn)?;
+                assert_eq!(result[0].name, "1 Corinthians");
+                assert!(result.iter().all(|b| !b.deuterocanonical));
+            }
+
+            // Chapters
+            {
+                let (book, chapters) = SwordDrill::chapters("ps", &kjv, &conn)?;
+
+                assert_eq!(book.name, "Psalms");
+                assert_eq!(chapters.len(), 150);
+                assert_eq!(chapters[118], (119, 176));
+            }
+
+            // Random verse
+            {
+                let psalms = SearchScope {
+                    books: vec!["Psalms".to_string()],
+                    ..SearchScope::default()
+                };
+                let result = SwordDrill::random_verse(&psalms, false, &kjv, &conn)?.unwrap();
+                assert_eq!(result.book, "Psalms");
+
+                // The first nine chapters are genealogies
+                let chronicles = SearchScope {
+                    books: vec!["1 Chronicles".to_string()],
+                    ..SearchScope::default()
+                };
+                let result = SwordDrill::random_verse(&chronicles, true, &kjv, &conn)?.unwrap();
+                assert!(result.chapter > 9);
+
+                // The translation has no deuterocanonical books
+                let tobit = SearchScope {
+                    books: vec!["Tobit".to_string()],
+                    ..SearchScope::default()
+                };
+                assert!(SwordDrill::random_verse(&tobit, false, &kjv, &conn)?.is_none());
+            }
+
+            // Verse of the day
+            {
+                let result = SwordDrill::verse_of_the_day(0, &conn)?;
+                assert_eq!(result.to_string(), "John 3:16");
+
+                // The rotation starts again after the last verse
+                let next = SwordDrill::verse_of_the_day(61, &conn)?;
+                assert_eq!(next, result);
+                let before = SwordDrill::verse_of_the_day(-1, &conn)?;
+                assert_eq!(before.to_string(), "Jeremiah 23:29");
+            }
+
+            // API keys
+            {
+                let (api_key, key) = create_api_key("Study app", &conn)?;
+                assert_eq!(key.len(), 40);
+                assert_eq!(SwordDrill::api_key(&key, &conn)?, Some(api_key.to_owned()));
+                assert_eq!(SwordDrill::api_key("nope", &conn)?, None);
+                assert_eq!(list_api_keys(&conn)?, vec![api_key.to_owned()]);
+
+                assert!(revoke_api_key(api_key.id, &conn)?);
+                assert_eq!(SwordDrill::api_key(&key, &conn)?, None);
+                // A key can only be revoked once
+                assert!(!revoke_api_key(api_key.id, &conn)?);
+            }
+
+            // Book abbreviations
+            {
+                let result = SwordDrill::book_abbreviations(&conn)?;
+                let tobit = result.iter().find(|a| a.abbreviation == "tob").unwrap();
+                assert_eq!(tobit.book_id, 67);
+            }
+
+            // Book - Ordinal name
+            {
+                let result = SwordDrill::book("2nd Macc", &kjv, &conn)?;
+
+                assert_eq!(result.0.name, "2 Maccabees");
+            }
+
+            let everywhere = SearchOptions::default();
 
             // Search - Fuzzy words
             {
-                let result = SwordDrill::search("fire hammer rock", &conn)?;
+                let (result, _) = SwordDrill::search("fire hammer rock", &everywhere, &kjv, &conn)?;
 
                 assert_eq!(result.len(), 1);
                 assert_eq!(result[0].0.book, 24);
@@ -239,20 +2315,54 @@ mod tests {
                 assert_eq!(result[0].0.verse, 29);
                 assert_eq!(
                     result[0].0.words,
-                    "Is not my word like as a <em>fire</em>? saith the LORD; and like a <em>hammer</em> that breaketh the <em>rock</em> in pieces?",
+                    "Is not my word like as a <mark>fire</mark>? saith the LORD; and like a \
+                    <mark>hammer</mark> that breaketh the <mark>rock</mark> in pieces?",
                 );
                 assert_eq!(result[0].1.name, "Jeremiah");
             }
 
+            // Search - Case and diacritics are ignored, but kept in the matches
+            {
+                let (result, _) = SwordDrill::search("FÍRE Hammer lord", &everywhere, &kjv, &conn)?;
+
+                assert_eq!(result.len(), 1);
+                assert_eq!(
+                    result[0].0.words,
+                    "Is not my word like as a <mark>fire</mark>? saith the <mark>LORD</mark>; and \
+                    like a <mark>hammer</mark> that breaketh the rock in pieces?",
+                );
+            }
+
+            // Search - Other forms of a word match its stem
+            {
+                let (result, _) =
+                    SwordDrill::search("\"god so love\" world", &everywhere, &kjv, &conn)?;
+                let john = result
+                    .iter()
+                    .find(|(v, _)| (v.book, v.chapter, v.verse) == (43, 3, 16))
+                    .unwrap();
+                assert!(john.0.words.contains("<mark>God so loved</mark>"));
+            }
+
+            // Search - Words that are also query operators, and a partial word
+            {
+                let (result, _) =
+                    SwordDrill::search("not my word like as a fi", &everywhere, &kjv, &conn)?;
+                assert!(result
+                    .iter()
+                    .any(|(v, _)| (v.book, v.chapter, v.verse) == (24, 23, 29)));
+            }
+
             // Search - Leading number followed by a space returns an empty result
             {
-                let result = SwordDrill::search("1 ", &conn)?;
+                let (result, _) = SwordDrill::search("1 ", &everywhere, &kjv, &conn)?;
                 assert_eq!(result.len(), 0);
             }
 
             // Search - Phrase
             {
-                let result = SwordDrill::search("\"like as a fire\"", &conn)?;
+                let (result, _) =
+                    SwordDrill::search("\"like as a fire\"", &everywhere, &kjv, &conn)?;
 
                 assert_eq!(result.len(), 1);
                 assert_eq!(result[0].0.book, 24);
@@ -260,10 +2370,323 @@ mod tests {
                 assert_eq!(result[0].0.verse, 29);
                 assert_eq!(
                     result[0].0.words,
-                    "Is not my word <em>like as a fire</em>? saith the LORD; and like a hammer that breaketh the rock in pieces?",
+                    "Is not my word <mark>like as a fire</mark>? saith the LORD; and like a hammer \
+                    that breaketh the rock in pieces?",
                 );
                 assert_eq!(result[0].1.name, "Jeremiah");
             }
+
+            // Search - Operators and exclusions
+            {
+                let (result, _) = SwordDrill::search("fire AND hammer", &everywhere, &kjv, &conn)?;
+                assert!(result
+                    .iter()
+                    .any(|(v, _)| (v.book, v.chapter, v.verse) == (24, 23, 29)));
+
+                let (result, _) =
+                    SwordDrill::search("fire AND hammer NOT rock", &everywhere, &kjv, &conn)?;
+                assert!(result
+                    .iter()
+                    .all(|(v, _)| (v.book, v.chapter, v.verse) != (24, 23, 29)));
+
+                let (result, _) =
+                    SwordDrill::search("fire hammer -rock", &everywhere, &kjv, &conn)?;
+                assert!(result
+                    .iter()
+                    .all(|(v, _)| (v.book, v.chapter, v.verse) != (24, 23, 29)));
+
+                let (result, _) =
+                    SwordDrill::search("hammer OR breaketh", &everywhere, &kjv, &conn)?;
+                assert!(result.len() > 1);
+            }
+
+            // Search - Wildcard
+            {
+                let (result, _) = SwordDrill::search("bapti* jordan", &everywhere, &kjv, &conn)?;
+                assert!(result.len() > 1);
+                assert!(result
+                    .iter()
+                    .all(|(v, _)| v.words.to_lowercase().contains("<mark>bapti")));
+            }
+
+            // Search - Whole words
+            {
+                let whole_word = SearchOptions {
+                    whole_word: true,
+                    ..SearchOptions::default()
+                };
+                let (_, partial_total) = SwordDrill::search("man", &everywhere, &kjv, &conn)?;
+                let (result, total) = SwordDrill::search("man", &whole_word, &kjv, &conn)?;
+                assert!(total < partial_total);
+                assert!(result.iter().all(|(v, _)| {
+                    let words = v.words.to_lowercase();
+                    words.contains("<mark>man") && !words.contains("<mark>manna")
+                }));
+
+                let (result, _) = SwordDrill::search_regex("man", &whole_word, &kjv, &conn)?;
+                assert!(result
+                    .iter()
+                    .all(|(v, _)| v.words.contains("<mark>man</mark>")));
+            }
+
+            // Search - Regular expression
+            {
+                let (result, total) =
+                    SwordDrill::search_regex(r"\bbapti[sz]", &everywhere, &kjv, &conn)?;
+                assert_eq!(result.len() as i64, SearchOptions::PER_PAGE);
+                assert!(total > SearchOptions::PER_PAGE);
+                assert!(result.iter().all(|(v, _)| v.words.contains("<mark>bapti")));
+                // Listed in canonical order
+                assert_eq!(result[0].1.name, "Matthew");
+
+                let result = SwordDrill::search_regex("(", &everywhere, &kjv, &conn);
+                assert!(matches!(result, Err(DbError::InvalidSearch { .. })));
+            }
+
+            // Search - Scoped to books, a testament, and a range of books
+            {
+                let gospels = SearchOptions {
+                    scope: SearchScope {
+                        books: vec![
+                            "Matthew".into(),
+                            "Mark".into(),
+                            "Luke".into(),
+                            "John".into(),
+                        ],
+                        ..SearchScope::default()
+                    },
+                    ..SearchOptions::default()
+                };
+                let (result, _) = SwordDrill::search("shepherd", &gospels, &kjv, &conn)?;
+                assert!(!result.is_empty());
+                assert!(result
+                    .iter()
+                    .all(|(_, b)| ["Matthew", "Mark", "Luke", "John"].contains(&b.name.as_str())));
+
+                let new_testament = SearchOptions {
+                    scope: SearchScope {
+                        testament: Some(Testament::New),
+                        ..SearchScope::default()
+                    },
+                    ..SearchOptions::default()
+                };
+                let (result, _) =
+                    SwordDrill::search("fire hammer rock", &new_testament, &kjv, &conn)?;
+                assert!(result.is_empty());
+
+                let pentateuch = SearchOptions {
+                    scope: SearchScope {
+                        range: Some(("Genesis".into(), "Deut".into())),
+                        ..SearchScope::default()
+                    },
+                    ..SearchOptions::default()
+                };
+                let (result, _) = SwordDrill::search("shepherd", &pentateuch, &kjv, &conn)?;
+                assert!(!result.is_empty());
+                assert!(result.iter().all(|(v, _)| (1..=5).contains(&v.book)));
+
+                let (result, _) =
+                    SwordDrill::search_regex("[Ss]hepherd", &pentateuch, &kjv, &conn)?;
+                assert!(result.iter().all(|(v, _)| (1..=5).contains(&v.book)));
+
+                let nowhere = SearchOptions {
+                    scope: SearchScope {
+                        books: vec!["Nope".into()],
+                        ..SearchScope::default()
+                    },
+                    ..SearchOptions::default()
+                };
+                let result = SwordDrill::search("shepherd", &nowhere, &kjv, &conn);
+                assert!(matches!(result, Err(DbError::BookNotFound { .. })));
+            }
+
+            // Search - Canonical order
+            {
+                let canonical = SearchOptions {
+                    order: SearchOrder::Canonical,
+                    ..SearchOptions::default()
+                };
+                let (result, _) = SwordDrill::search("hammer", &canonical, &kjv, &conn)?;
+                let verses: Vec<_> = result
+                    .iter()
+                    .map(|(v, _)| (v.book, v.chapter, v.verse))
+                    .collect();
+                let mut sorted = verses.clone();
+                sorted.sort_unstable();
+                assert_eq!(verses, sorted);
+                // Judges 4:21 has the first hammer in the KJV
+                assert_eq!(verses[0], (7, 4, 21));
+            }
+
+            // Search - Pages
+            {
+                let (all, total) = SwordDrill::search(
+                    "hammer",
+                    &SearchOptions {
+                        order: SearchOrder::Canonical,
+                        per_page: 100,
+                        ..SearchOptions::default()
+                    },
+                    &kjv,
+                    &conn,
+                )?;
+                assert_eq!(all.len() as i64, total);
+
+                let second_page = SearchOptions {
+                    order: SearchOrder::Canonical,
+                    page: 2,
+                    per_page: 3,
+                    ..SearchOptions::default()
+                };
+                let (result, page_total) = SwordDrill::search("hammer", &second_page, &kjv, &conn)?;
+                assert_eq!(page_total, total);
+                let verses = |matches: &[(VerseFTS, Book)]| -> Vec<(i32, i32, i32)> {
+                    matches
+                        .iter()
+                        .map(|(v, _)| (v.book, v.chapter, v.verse))
+                        .collect()
+                };
+                assert_eq!(verses(&result), verses(&all[3..6]));
+
+                let (result, _) =
+                    SwordDrill::search_regex(r"\b[Hh]ammer", &second_page, &kjv, &conn)?;
+                assert_eq!(verses(&result), verses(&all[3..6]));
+            }
+
+            // Search - Proximity
+            {
+                let (result, _) =
+                    SwordDrill::search("NEAR(faith works, 3)", &everywhere, &kjv, &conn)?;
+                // "faith without works is dead" (James 2:20)
+                assert!(result
+                    .iter()
+                    .any(|(v, _)| (v.book, v.chapter, v.verse) == (59, 2, 20)));
+
+                let (near, _) = SwordDrill::search("NEAR(fire rock, 1)", &everywhere, &kjv, &conn)?;
+                assert!(!near
+                    .iter()
+                    .any(|(v, _)| (v.book, v.chapter, v.verse) == (24, 23, 29)));
+            }
+
+            // Search - Facets
+            {
+                let gospels = SearchScope {
+                    books: vec![
+                        "John".into(),
+                        "Matthew".into(),
+                        "Luke".into(),
+                        "Mark".into(),
+                    ],
+                    ..SearchScope::default()
+                };
+                let options = SearchOptions {
+                    scope: gospels,
+                    ..SearchOptions::default()
+                };
+                let facets = SwordDrill::search_facets("shepherd", &options, &kjv, &conn)?;
+                let names: Vec<&str> = facets.iter().map(|(b, _)| b.name.as_str()).collect();
+                assert_eq!(names, vec!["Matthew", "Mark", "Luke", "John"]);
+
+                let (_, total) = SwordDrill::search("shepherd", &options, &kjv, &conn)?;
+                assert_eq!(facets.iter().map(|(_, count)| count).sum::<i64>(), total);
+            }
+
+            // Search - Several translations
+            {
+                let options = SearchOptions {
+                    order: SearchOrder::Canonical,
+                    ..SearchOptions::default()
+                };
+                let (result, total) = SwordDrill::search("hammer", &options, &kjv, &conn)?;
+                let (groups, group_total) =
+                    SwordDrill::search_translations("hammer", &options, &[kjv.clone()], &conn)?;
+                assert_eq!(group_total, total);
+                assert_eq!(groups.len(), result.len());
+                for (group, (verse, book)) in groups.iter().zip(&result) {
+                    assert_eq!(group.book.id, book.id);
+                    assert_eq!((group.chapter, group.verse), (verse.chapter, verse.verse));
+                    assert_eq!(group.matches, vec![(kjv.clone(), verse.words.clone())]);
+                }
+
+                let (groups, total) =
+                    SwordDrill::search_translations("1 :", &options, &[kjv.clone()], &conn)?;
+                assert!(groups.is_empty());
+                assert_eq!(total, 0);
+            }
+
+            // Search - Within the results of an earlier search
+            {
+                let results = SwordDrill::search_result_set("shepherd", &everywhere, &kjv, &conn)?;
+                let (_, total) = SwordDrill::search("shepherd", &everywhere, &kjv, &conn)?;
+                assert_eq!(results.len() as i64, total);
+
+                let canonical = SearchOptions {
+                    order: SearchOrder::Canonical,
+                    ..SearchOptions::default()
+                };
+                let within = SearchOptions {
+                    within: Some(results.to_string().parse().unwrap()),
+                    ..canonical.clone()
+                };
+                let (result, total) = SwordDrill::search("good", &within, &kjv, &conn)?;
+                let (both, both_total) =
+                    SwordDrill::search("shepherd good", &canonical, &kjv, &conn)?;
+                assert_eq!(total, both_total);
+                let verses = |matches: &[(VerseFTS, Book)]| -> Vec<(i32, i32, i32)> {
+                    matches
+                        .iter()
+                        .map(|(v, _)| (v.book, v.chapter, v.verse))
+                        .collect()
+                };
+                assert_eq!(verses(&result), verses(&both));
+                assert!(result
+                    .iter()
+                    .all(|(v, _)| v.words.to_lowercase().contains("<mark>good")));
+
+                let facets = SwordDrill::search_facets("good", &within, &kjv, &conn)?;
+                assert_eq!(facets.iter().map(|(_, count)| count).sum::<i64>(), total);
+                let refined = SwordDrill::search_result_set("good", &within, &kjv, &conn)?;
+                assert_eq!(refined.len() as i64, total);
+            }
+
+            // Search - Spelling suggestions
+            {
+                let (result, _) = SwordDrill::search("shephred", &everywhere, &kjv, &conn)?;
+                assert!(result.is_empty());
+                assert_eq!(
+                    SwordDrill::suggest_spelling("good shephred", &kjv, &conn)?,
+                    Some("good shepherd".to_string())
+                );
+                assert_eq!(
+                    SwordDrill::suggest_spelling("good shepherd", &kjv, &conn)?,
+                    None
+                );
+            }
+
+            // Search - Completions
+            {
+                let (words, phrases) = SwordDrill::complete_search("righteo", &kjv, &conn)?;
+                assert_eq!(words[0], "righteousness");
+                assert!(words.iter().all(|w| w.starts_with("righteo")));
+                assert!(phrases.iter().all(|p| p.starts_with("righteo")));
+
+                let (words, phrases) = SwordDrill::complete_search("the lo", &kjv, &conn)?;
+                assert!(words.is_empty());
+                assert_eq!(phrases[0], "the lord");
+            }
+
+            // Search - Phrase combined with a word, and a phrase with its
+            // words out of order
+            {
+                let (result, _) =
+                    SwordDrill::search("\"like as a fire\" hammer", &everywhere, &kjv, &conn)?;
+                assert_eq!(result.len(), 1);
+                assert_eq!(result[0].0.verse, 29);
+
+                let (result, _) =
+                    SwordDrill::search("\"a fire as like\"", &everywhere, &kjv, &conn)?;
+                assert!(result.is_empty());
+            }
             Ok(())
         });
     }
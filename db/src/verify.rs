@@ -0,0 +1,273 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use diesel::prelude::*;
+use diesel::sql_types::Integer;
+
+use crate::models::{Book, IntegrityReport, Reference, Translation, VersificationMapping};
+use crate::sword_drill::STANDARD_VERSIFICATION;
+use crate::DbError;
+
+/// ID of the King James Version, which has every verse numbered in the
+/// standard versification scheme.
+const STANDARD_TRANSLATION_ID: i32 = 1;
+
+/// Book, chapter, and verse number of a verse.
+type VerseKey = (i32, i32, i32);
+
+/// Checks that every book of a translation has the chapters and verses
+/// its versification scheme numbers, which are the King James Version's
+/// renumbered to the scheme, and that no verse is there more than once or
+/// stored out of order. Useful after importing a text from elsewhere.
+///
+/// Books the translation doesn't have at all aren't reported, so
+/// translations of only the New Testament can be checked too.
+pub fn verify_translation(
+    translation: &Translation,
+    conn: &SqliteConnection,
+) -> Result<IntegrityReport, DbError> {
+    use crate::schema::books as b;
+    use crate::schema::verses as v;
+    use crate::schema::versification_mappings as vm;
+
+    let to_db_error = |e: diesel::result::Error| DbError::Other {
+        cause: e.to_string(),
+    };
+    let books: HashMap<i32, String> = b::table
+        .load::<Book>(conn)
+        .map_err(to_db_error)?
+        .into_iter()
+        .map(|book| (book.id, book.name))
+        .collect();
+
+    let mappings: HashMap<VerseKey, (i32, i32)> =
+        if translation.versification == STANDARD_VERSIFICATION {
+            HashMap::new()
+        } else {
+            vm::table
+                .filter(vm::versification.eq(&translation.versification))
+                .load::<VersificationMapping>(conn)
+                .map_err(to_db_error)?
+                .into_iter()
+                .map(|m| {
+                    (
+                        (m.book, m.standard_chapter, m.standard_verse),
+                        (m.chapter, m.verse),
+                    )
+                })
+                .collect()
+        };
+    let expected: BTreeSet<VerseKey> = v::table
+        .select((v::book, v::chapter, v::verse))
+        .filter(v::translation_id.eq(STANDARD_TRANSLATION_ID))
+        .load::<VerseKey>(conn)
+        .map_err(to_db_error)?
+        .into_iter()
+        .map(|key| match mappings.get(&key) {
+            Some(&(chapter, verse)) => (key.0, chapter, verse),
+            None => key,
+        })
+        .collect();
+
+    let found = v::table
+        .select((v::book, v::chapter, v::verse))
+        .filter(v::translation_id.eq(translation.id))
+        .order_by(v::id)
+        .load::<VerseKey>(conn)
+        .map_err(to_db_error)?;
+    let mut counts = BTreeMap::new();
+    for key in &found {
+        *counts.entry(*key).or_insert(0) += 1;
+    }
+    let chapters = chapter_counts(counts.keys());
+    let expected_chapters = chapter_counts(expected.iter());
+    let reference = |&(book, chapter, verse): &VerseKey| Reference {
+        book: books.get(&book).cloned().unwrap_or_default(),
+        chapter,
+        verses: Some(verse..=verse),
+        end_chapter: None,
+        additional_verses: vec![],
+        start_part: None,
+        end_part: None,
+    };
+
+    Ok(IntegrityReport {
+        chapter_counts: chapters
+            .iter()
+            .filter_map(|(book, &has)| {
+                let expected = expected_chapters.get(book).copied().unwrap_or(0);
+                let name = books.get(book).cloned().unwrap_or_default();
+                (has != expected).then(|| (name, has, expected))
+            })
+            .collect(),
+        missing: expected
+            .iter()
+            .filter(|key| chapters.contains_key(&key.0) && !counts.contains_key(key))
+            .map(reference)
+            .collect(),
+        unexpected: counts
+            .keys()
+            .filter(|key| !expected.contains(key))
+            .map(reference)
+            .collect(),
+        duplicates: counts
+            .iter()
+            .filter(|(_, count)| **count > 1)
+            .map(|(key, _)| reference(key))
+            .collect(),
+        out_of_order: found.windows(2).any(|pair| pair[0] > pair[1]),
+    })
+}
+
+/// Counts the chapters of each book that verses are in.
+fn chapter_counts<'a>(keys: impl Iterator<Item = &'a VerseKey>) -> BTreeMap<i32, usize> {
+    let chapters: BTreeSet<(i32, i32)> = keys.map(|&(book, chapter, _)| (book, chapter)).collect();
+    let mut counts = BTreeMap::new();
+    for (book, _) in chapters {
+        *counts.entry(book).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// Renumbers the verses of a translation so they're stored in canonical
+/// order, in both formats and in the full text search index, returning the
+/// number of verses that were out of place. The verses keep the IDs they
+/// had between them, so other translations' verses aren't moved.
+///
+/// A search index built with [build_search_index](crate::build_search_index)
+/// should be built again afterwards.
+pub fn fix_verse_order(
+    translation: &Translation,
+    conn: &SqliteConnection,
+) -> Result<usize, DbError> {
+    use crate::schema::verses as v;
+    use crate::schema::verses_html as h;
+
+    conn.transaction(|| {
+        let plain_text = canonical_ids(
+            v::table
+                .select((v::id, (v::book, v::chapter, v::verse)))
+                .filter(v::translation_id.eq(translation.id))
+                .load(conn)?,
+        );
+        let html = canonical_ids(
+            h::table
+                .select((h::id, (h::book, h::chapter, h::verse)))
+                .filter(h::translation_id.eq(translation.id))
+                .load(conn)?,
+        );
+        move_ids("verses", "id", &plain_text, conn)?;
+        move_ids("verses_fts", "rowid", &plain_text, conn)?;
+        move_ids("verses_html", "id", &html, conn)?;
+        Ok(plain_text.len())
+    })
+    .map_err(|e: diesel::result::Error| DbError::Other {
+        cause: e.to_string(),
+    })
+}
+
+/// Gets the IDs that verses should be moved from and to so they're in
+/// canonical order, using the IDs they already have.
+fn canonical_ids(mut verses: Vec<(i32, VerseKey)>) -> Vec<(i32, i32)> {
+    let mut ids: Vec<i32> = verses.iter().map(|(id, _)| *id).collect();
+    ids.sort_unstable();
+    verses.sort_by_key(|&(id, key)| (key, id));
+    verses
+        .into_iter()
+        .zip(ids)
+        .filter(|((from, _), to)| from != to)
+        .map(|((from, _), to)| (from, to))
+        .collect()
+}
+
+/// Moves rows of a table to other IDs, through negative IDs so that no two
+/// rows have the same ID along the way.
+fn move_ids(
+    table: &str,
+    column: &str,
+    moves: &[(i32, i32)],
+    conn: &SqliteConnection,
+) -> QueryResult<()> {
+    let query = format!("UPDATE {} SET {} = ? WHERE {} = ?", table, column, column);
+    for &(from, _) in moves {
+        diesel::sql_query(&query)
+            .bind::<Integer, _>(-from)
+            .bind::<Integer, _>(from)
+            .execute(conn)?;
+    }
+    for &(from, to) in moves {
+        diesel::sql_query(&query)
+            .bind::<Integer, _>(to)
+            .bind::<Integer, _>(-from)
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::stdout;
+    use std::path::Path;
+
+    use diesel::connection::SimpleConnection;
+    use diesel_migrations::run_pending_migrations_in_directory;
+
+    use super::*;
+    use crate::{establish_connection, BookStore, SwordDrill};
+
+    #[test]
+    fn verified() {
+        use crate::schema::verses as v;
+        use crate::schema::verses_fts as f;
+
+        let conn = establish_connection(":memory:");
+        run_pending_migrations_in_directory(&conn, Path::new("./migrations"), &mut stdout())
+            .unwrap();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let kjv = SwordDrill::translation("kjv", &conn).unwrap();
+            assert_eq!(
+                verify_translation(&kjv, &conn).unwrap(),
+                IntegrityReport::default()
+            );
+
+            // Jude backwards, without verse 5 and with verse 3 twice
+            conn.batch_execute(
+                "INSERT INTO translations (id, abbreviation, name, language)
+                VALUES (100, 'test', 'Test', 'en');
+                INSERT INTO verses (book, chapter, verse, words, translation_id)
+                SELECT book, chapter, verse, words, 100 FROM verses
+                WHERE translation_id = 1 AND book = 65 AND verse <> 5
+                ORDER BY verse DESC;
+                INSERT INTO verses (book, chapter, verse, words, translation_id)
+                SELECT book, chapter, verse, words, 100 FROM verses
+                WHERE translation_id = 1 AND book = 65 AND verse = 3;
+                INSERT INTO verses_fts (rowid, book, chapter, verse, words, translation_id)
+                SELECT id, book, chapter, verse, words, translation_id FROM verses
+                WHERE translation_id = 100;",
+            )?;
+            let test = SwordDrill::translation("test", &conn).unwrap();
+            let report = verify_translation(&test, &conn).unwrap();
+            let to_strings = |references: &[Reference]| {
+                references.iter().map(|r| r.to_string()).collect::<Vec<_>>()
+            };
+            assert!(report.chapter_counts.is_empty() && report.unexpected.is_empty());
+            assert_eq!(to_strings(&report.missing), ["Jude 1:5"]);
+            assert_eq!(to_strings(&report.duplicates), ["Jude 1:3"]);
+            assert!(report.out_of_order && !report.is_ok());
+
+            assert!(fix_verse_order(&test, &conn).unwrap() > 0);
+            assert!(!verify_translation(&test, &conn).unwrap().out_of_order);
+            assert_eq!(fix_verse_order(&test, &conn).unwrap(), 0);
+            let first = v::table
+                .select(v::id)
+                .filter(v::translation_id.eq(100))
+                .filter(v::verse.eq(1))
+                .first::<i32>(&conn)?;
+            let indexed = f::table
+                .select(f::verse)
+                .filter(f::rowid.eq(first))
+                .first::<i32>(&conn)?;
+            assert_eq!(indexed, 1);
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,7 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Generate the gRPC service, which needs `protoc` to be installed
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/bible.proto")?;
+
+    Ok(())
+}
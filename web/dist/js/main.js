@@ -6,19 +6,46 @@
             return;
         }
 
-        fetch("/api/search?q=" + encodeURIComponent(q)).then(function (resp) {
+        fetch("/api/v1/search?q=" + encodeURIComponent(q)).then(function (resp) {
             return resp.json();
         }).then(function (data) {
-            cb(data.matches);
+            var dir = data.translation ? data.translation.direction : "ltr";
+            cb(data.matches.map(function (match) {
+                match.dir = dir;
+                return match;
+            }));
+        });
+    }
+
+    function getCompletions(q, cb) {
+        if (!q) {
+            cb([]);
+            return;
+        }
+
+        fetch("/api/v1/complete?q=" + encodeURIComponent(q)).then(function (resp) {
+            return resp.json();
+        }).then(function (data) {
+            cb(data.completions.map(function (link) {
+                return { link: link };
+            }));
         });
     }
 
     autocomplete("#q", { autoselect: true, debounce: 350, hint: false }, [
+        {
+            source: getCompletions,
+            templates: {
+                suggestion: function (result) {
+                    return "<p><i>" + result.link.label + "</i></p>";
+                }
+            }
+        },
         {
             source: getResults,
             templates: {
                 suggestion: function (result) {
-                    return "<p><i>" + result.link.label + "</i> | " + result.text + "</p>";
+                    return "<p dir=\"" + result.dir + "\"><i>" + result.link.label + "</i> | " + result.text + "</p>";
                 }
             }
         }
@@ -44,6 +71,16 @@
     };
 })();
 
+// Translation picker
+(function () {
+    var form = document.getElementById("translation-form");
+    if (form) {
+        document.getElementById("version").onchange = function () {
+            form.submit();
+        };
+    }
+})();
+
 // Swipe navigation
 (function () {
     function detectSwipe(el, callback) {
@@ -1,9 +1,10 @@
-const CACHE_NAME = "biblers-cache-v5";
+const CACHE_NAME = "biblers-cache-v6";
 
 // Use cache before fetching from the network.
 self.addEventListener("fetch", (e) => {
-    // Search API requests are not locally cached.
-    if (e.request.url.indexOf("/api/search") > -1) {
+    // Search, random verse and verse of the day API requests are not locally
+    // cached.
+    if (/\/api\/(v1\/)?(search|random|votd)/.test(e.request.url)) {
         e.respondWith(fetch(e.request));
 
     // Standard pages are cached.
@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use actix_files::NamedFile;
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::{web, HttpRequest, HttpResponse};
+use handlebars::{Context, Handlebars, Helper, HelperResult, Output, RenderContext, RenderError};
+use sha2::{Digest, Sha256};
+
+/// URL path the static files are served under.
+pub const STATIC_PATH: &str = "/static";
+
+/// `Cache-Control` of files served by their hashed names, which change
+/// whenever the files do, so they can be cached for good.
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+
+/// Length of the hashes put in the names of the static files.
+const HASH_LENGTH: usize = 8;
+
+/// Names of the static files with a hash of their contents in them (e.g.
+/// `css/style.1a2b3c4d.css`), so clients can cache them until they change.
+#[derive(Clone, Debug, Default)]
+pub struct AssetManifest {
+    dir: PathBuf,
+    /// Hashed names of the files, by their names within the directory.
+    hashed: HashMap<String, String>,
+}
+
+impl AssetManifest {
+    /// Hashes the files in a directory of static files, and the ones in
+    /// its subdirectories.
+    pub fn build(dir: &Path) -> io::Result<Self> {
+        let mut manifest = Self {
+            dir: dir.to_owned(),
+            hashed: HashMap::new(),
+        };
+        if dir.is_dir() {
+            manifest.add_dir(dir)?;
+        }
+        Ok(manifest)
+    }
+
+    fn add_dir(&mut self, dir: &Path) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                self.add_dir(&path)?;
+                continue;
+            }
+            let name = path
+                .strip_prefix(&self.dir)
+                .unwrap_or(&path)
+                .to_string_lossy()
+                .replace('\\', "/");
+            let hashed = hashed_name(&name, &fs::read(&path)?);
+            self.hashed.insert(name, hashed);
+        }
+        Ok(())
+    }
+
+    /// Gets the URL of a static file by its hashed name, or by its name if
+    /// it isn't in the manifest.
+    pub fn url(&self, name: &str) -> String {
+        let name = name.trim_start_matches('/');
+        let name = self.hashed.get(name).map_or(name, String::as_str);
+        format!("{}/{}", STATIC_PATH, name)
+    }
+
+    /// Gets the directory of the static files.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Gets the name of each file in the manifest, along with its hashed
+    /// name.
+    pub fn files(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.hashed
+            .iter()
+            .map(|(name, hashed)| (name.as_str(), hashed.as_str()))
+    }
+}
+
+/// Puts a hash of a file's contents in its name, before its extension.
+fn hashed_name(name: &str, contents: &[u8]) -> String {
+    let hash = format!("{:x}", Sha256::digest(contents));
+    let hash = &hash[..HASH_LENGTH];
+    let file_start = name.rfind('/').map_or(0, |i| i + 1);
+    match name[file_start..].rfind('.') {
+        Some(dot) if dot > 0 => {
+            let (stem, extension) = name.split_at(file_start + dot);
+            format!("{}.{}{}", stem, hash, extension)
+        }
+        _ => format!("{}.{}", name, hash),
+    }
+}
+
+/// Registers a route for each static file by its hashed name, responding
+/// with headers that let clients cache it for good. The files are still
+/// served by their names too.
+pub fn routes(cfg: &mut web::ServiceConfig, manifest: &AssetManifest) {
+    for (name, hashed) in manifest.files() {
+        let path = manifest.dir.join(name);
+        cfg.service(
+            web::resource(format!("{}/{}", STATIC_PATH, hashed)).route(web::get().to(
+                move |req: HttpRequest| {
+                    let path = path.to_owned();
+                    async move { immutable_file(&path, &req).await }
+                },
+            )),
+        );
+    }
+}
+
+/// Responds with a static file that clients can cache for good.
+async fn immutable_file(path: &Path, req: &HttpRequest) -> actix_web::Result<HttpResponse> {
+    let mut resp = NamedFile::open_async(path).await?.into_response(req);
+    resp.headers_mut()
+        .insert(header::CACHE_CONTROL, HeaderValue::from_static(IMMUTABLE));
+    Ok(resp)
+}
+
+/// Registers the `asset` helper, which gets the URL of a static file by its
+/// hashed name (e.g. `{{asset "css/style.css"}}`).
+pub fn register_helper(tpl: &mut Handlebars, manifest: Arc<AssetManifest>) {
+    tpl.register_helper(
+        "asset",
+        Box::new(
+            move |h: &Helper,
+                  _: &Handlebars,
+                  _: &Context,
+                  _: &mut RenderContext,
+                  out: &mut dyn Output|
+                  -> HelperResult {
+                let name = h
+                    .param(0)
+                    .and_then(|p| p.value().as_str())
+                    .ok_or_else(|| RenderError::new("asset needs the name of a static file"))?;
+                out.write(&manifest.url(name))?;
+                Ok(())
+            },
+        ),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{rt::System, test, App};
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn hashed_names() {
+        assert_eq!(
+            hashed_name("css/style.css", b"body {}"),
+            "css/style.62368a1a.css"
+        );
+        assert_eq!(
+            hashed_name("js/autocomplete.min.js", b""),
+            "js/autocomplete.min.e3b0c442.js"
+        );
+        assert_eq!(hashed_name("robots", b""), "robots.e3b0c442");
+        assert_eq!(hashed_name(".well-known/x", b""), ".well-known/x.e3b0c442");
+    }
+
+    #[test]
+    fn asset_helper() {
+        let manifest = Arc::new(AssetManifest::build(Path::new("./dist")).unwrap());
+        let hashed = manifest.url("img/book.svg");
+        assert!(hashed.starts_with("/static/img/book."));
+        assert_ne!(hashed, "/static/img/book.svg");
+
+        let mut tpl = Handlebars::new();
+        register_helper(&mut tpl, Arc::clone(&manifest));
+        let rendered = tpl
+            .render_template(r#"{{asset "img/book.svg"}}"#, &json!({}))
+            .unwrap();
+        assert_eq!(rendered, hashed);
+        // Files that aren't in the manifest keep their names
+        let rendered = tpl
+            .render_template(r#"{{asset "/css/style.css"}}"#, &json!({}))
+            .unwrap();
+        assert_eq!(rendered, "/static/css/style.css");
+        assert!(tpl.render_template("{{asset}}", &json!({})).is_err());
+
+        System::new().block_on(async {
+            let srv = test::init_service(App::new().configure(|cfg| routes(cfg, &manifest))).await;
+            let req = test::TestRequest::with_uri(&hashed).to_request();
+            let resp = test::call_service(&srv, req).await;
+            assert!(resp.status().is_success());
+            assert_eq!(resp.headers()[header::CACHE_CONTROL], IMMUTABLE);
+        });
+    }
+}
@@ -0,0 +1,148 @@
+use std::future::{ready, Future, Ready};
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::rc::Rc;
+
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{self, HeaderMap};
+use actix_web::web;
+
+use db::models::ApiKey;
+use db::SwordDrillable;
+
+use crate::error::{Error, JsonError};
+use crate::ServerData;
+
+/// Header an API key can be given in, instead of as a bearer token.
+pub(crate) const API_KEY_HEADER: &str = "x-api-key";
+
+/// Middleware requiring requests to have an API key that's been issued and
+/// not revoked, for deployments that only let integrators with keys call
+/// the API. Keys are looked up with `SD`.
+pub struct RequireApiKey<SD>(PhantomData<fn() -> SD>);
+
+impl<SD> Default for RequireApiKey<SD> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<S, B, SD> Transform<S, ServiceRequest> for RequireApiKey<SD>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+    SD: SwordDrillable + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Transform = RequireApiKeyMiddleware<S, SD>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequireApiKeyMiddleware {
+            service: Rc::new(service),
+            drill: PhantomData,
+        }))
+    }
+}
+
+/// Service checking the API keys of requests for [RequireApiKey].
+pub struct RequireApiKeyMiddleware<S, SD> {
+    service: Rc<S>,
+    drill: PhantomData<fn() -> SD>,
+}
+
+impl<S, B, SD> Service<ServiceRequest> for RequireApiKeyMiddleware<S, SD>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+    SD: SwordDrillable + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        Box::pin(async move {
+            // The rate limit may have looked the key up already
+            if req.extensions().contains::<ApiKey>() {
+                return service.call(req).await;
+            }
+            let key = api_key(req.headers()).ok_or(JsonError::from(Error::InvalidApiKey))?;
+            let data = req
+                .app_data::<web::Data<ServerData>>()
+                .expect("Server data is missing")
+                .clone();
+            let issued = web::block(move || SD::api_key(&key, &data.read_db().get().unwrap()))
+                .await?
+                .map_err(JsonError::from)?;
+            if issued.is_none() {
+                return Err(JsonError::from(Error::InvalidApiKey).into());
+            }
+            service.call(req).await
+        })
+    }
+}
+
+/// Gets the API key of a request from its headers, either its `X-Api-Key`
+/// header or its bearer token.
+pub(crate) fn api_key(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(API_KEY_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .or_else(|| {
+            headers
+                .get(header::AUTHORIZATION)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.strip_prefix("Bearer "))
+        })
+        .map(|key| key.trim().to_string())
+        .filter(|key| !key.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+    use actix_web::rt::System;
+    use actix_web::{test, App, HttpResponse};
+
+    use super::*;
+    use crate::test::{test_server_data, TestSwordDrill, TEST_API_KEY};
+
+    fn status(req: test::TestRequest) -> StatusCode {
+        System::new().block_on(async {
+            let srv = test::init_service(
+                App::new().app_data(test_server_data()).service(
+                    web::scope("/api")
+                        .wrap(RequireApiKey::<TestSwordDrill>::default())
+                        .route("/test", web::get().to(HttpResponse::Ok)),
+                ),
+            )
+            .await;
+            test::call_service(&srv, req.uri("/api/test").to_request())
+                .await
+                .status()
+        })
+    }
+
+    #[test]
+    fn api_keys() {
+        let req = test::TestRequest::default().insert_header((API_KEY_HEADER, TEST_API_KEY));
+        assert_eq!(status(req), StatusCode::OK);
+
+        let bearer = format!("Bearer {}", TEST_API_KEY);
+        let req = test::TestRequest::default().insert_header((header::AUTHORIZATION, bearer));
+        assert_eq!(status(req), StatusCode::OK);
+
+        let req = test::TestRequest::default().insert_header((API_KEY_HEADER, "revoked-key"));
+        assert_eq!(status(req), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            status(test::TestRequest::default()),
+            StatusCode::UNAUTHORIZED
+        );
+    }
+}
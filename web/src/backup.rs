@@ -0,0 +1,120 @@
+use std::env;
+use std::fs::{self, File};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use actix_files::NamedFile;
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::{web, HttpRequest, HttpResponse};
+use sha2::{Digest, Sha256};
+
+use db::DbError;
+
+use crate::auth::api_key;
+use crate::error::{Error, JsonError};
+use crate::ServerData;
+
+/// Key that backups of the database can be downloaded with, or `None` if
+/// they can't be.
+#[derive(Clone, Debug, Default)]
+pub struct AdminKey(pub Option<String>);
+
+impl AdminKey {
+    /// Checks whether a request was given the admin key, comparing hashes of
+    /// the keys so how long the comparison takes gives nothing away.
+    fn matches(&self, req: &HttpRequest) -> bool {
+        match (&self.0, api_key(req.headers())) {
+            (Some(admin), Some(key)) => Sha256::digest(admin) == Sha256::digest(key),
+            _ => false,
+        }
+    }
+}
+
+/// Responds with a consistent snapshot of the database, made with
+/// [db::backup] while the server goes on serving, for deployments that
+/// store user data (e.g. API keys) in it. The admin key is given like an
+/// API key. The snapshot is streamed from a temporary file, which is
+/// removed as soon as it's opened so it's gone once the response ends.
+pub async fn backup(
+    req: HttpRequest,
+    data: web::Data<ServerData>,
+    admin: web::Data<AdminKey>,
+) -> Result<HttpResponse, JsonError> {
+    if !admin.matches(&req) {
+        return Err(Error::InvalidApiKey.into());
+    }
+
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos());
+    let path = env::temp_dir().join(format!("biblers-backup-{}-{}.db", process::id(), nanos));
+    let snapshot = web::block(move || {
+        db::backup(&path, &data.db.get().unwrap())?;
+        let file = File::open(&path);
+        let _ = fs::remove_file(&path);
+        file.and_then(|file| NamedFile::from_file(file, "bible.db"))
+            .map_err(|e| DbError::Other {
+                cause: e.to_string(),
+            })
+    })
+    .await??;
+
+    let mut resp = snapshot.into_response(&req);
+    let headers = resp.headers_mut();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/vnd.sqlite3"),
+    );
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        HeaderValue::from_static("attachment; filename=\"bible.db\""),
+    );
+    Ok(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+    use actix_web::{rt::System, test, App};
+
+    use super::*;
+    use crate::test::test_server_data;
+
+    #[test]
+    fn admin_backup() {
+        System::new().block_on(async {
+            let srv = test::init_service(
+                App::new()
+                    .app_data(test_server_data())
+                    .app_data(web::Data::new(AdminKey(Some("secret".to_string()))))
+                    .route("/admin/backup", web::get().to(backup)),
+            )
+            .await;
+
+            let req = test::TestRequest::with_uri("/admin/backup").to_request();
+            assert_eq!(
+                test::call_service(&srv, req).await.status(),
+                StatusCode::UNAUTHORIZED
+            );
+            let req = test::TestRequest::with_uri("/admin/backup")
+                .insert_header(("X-Api-Key", "guess"))
+                .to_request();
+            assert_eq!(
+                test::call_service(&srv, req).await.status(),
+                StatusCode::UNAUTHORIZED
+            );
+
+            let req = test::TestRequest::with_uri("/admin/backup")
+                .insert_header((header::AUTHORIZATION, "Bearer secret"))
+                .to_request();
+            let resp = test::call_service(&srv, req).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+            assert_eq!(
+                resp.headers()[header::CONTENT_TYPE],
+                "application/vnd.sqlite3"
+            );
+            let body = test::read_body(resp).await;
+            assert!(body.starts_with(b"SQLite format 3\0"));
+        });
+    }
+}
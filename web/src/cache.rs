@@ -0,0 +1,356 @@
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use actix_web::body::{self, BoxBody, MessageBody};
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{self, HeaderName, HeaderValue};
+use actix_web::http::{Method, StatusCode};
+use actix_web::web::{self, Bytes};
+use actix_web::HttpResponse;
+use log::debug;
+#[cfg(feature = "shared-cache")]
+use log::warn;
+use lru::LruCache;
+
+use crate::controllers::translation_version;
+use crate::ServerData;
+
+#[cfg(feature = "shared-cache")]
+pub use shared::SharedCache;
+
+/// Header telling whether a response came from the cache.
+const CACHE_HEADER: &str = "x-cache";
+
+/// What a cached response is for: the translation and path (with the query
+/// string) it was requested with, and the formats it was accepted in.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+struct CacheKey {
+    translation: String,
+    path: String,
+    accept: String,
+}
+
+/// Headers and body of a response that's been cached.
+#[derive(Clone, Debug)]
+struct CachedResponse {
+    headers: Vec<(HeaderName, HeaderValue)>,
+    body: Bytes,
+}
+
+/// Responses to passages and searches, which never change, kept in memory
+/// so they don't need to be looked up and rendered again. The least recently
+/// used ones are dropped to keep the number of responses under the cache's
+/// size.
+///
+/// With the `shared-cache` feature, responses can also be shared with other
+/// instances through a [SharedCache].
+pub struct ResponseCache {
+    responses: Option<Mutex<LruCache<CacheKey, CachedResponse>>>,
+    #[cfg(feature = "shared-cache")]
+    shared: Option<SharedCache>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl ResponseCache {
+    /// Caches up to a number of responses, or none if it's 0.
+    pub fn new(size: usize) -> Self {
+        Self {
+            responses: (size > 0).then(|| Mutex::new(LruCache::new(size))),
+            #[cfg(feature = "shared-cache")]
+            shared: None,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Also caches responses in a cache shared with other instances, which
+    /// responses that aren't in memory are looked for in.
+    #[cfg(feature = "shared-cache")]
+    pub fn with_shared(mut self, shared: SharedCache) -> Self {
+        self.shared = Some(shared);
+        self
+    }
+
+    /// Whether any responses are cached.
+    fn is_enabled(&self) -> bool {
+        #[cfg(feature = "shared-cache")]
+        let shared = self.shared.is_some();
+        #[cfg(not(feature = "shared-cache"))]
+        let shared = false;
+        self.responses.is_some() || shared
+    }
+
+    /// Gets the number of requests that were answered from the cache.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Gets the number of requests that had to be looked up, whether or not
+    /// their response could be cached.
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// Gets the response cached for a request, from memory or else from the
+    /// shared cache, counting it as a hit or miss.
+    async fn get(&self, key: &CacheKey) -> Option<CachedResponse> {
+        let cached = self
+            .responses
+            .as_ref()
+            .and_then(|responses| responses.lock().unwrap().get(key).cloned());
+        #[cfg(feature = "shared-cache")]
+        let cached = match cached {
+            None => self.get_shared(key).await,
+            cached => cached,
+        };
+        let counter = if cached.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        cached
+    }
+
+    /// Gets the response cached for a request in the shared cache, keeping
+    /// it in memory too. Errors reading the cache are treated as misses.
+    #[cfg(feature = "shared-cache")]
+    async fn get_shared(&self, key: &CacheKey) -> Option<CachedResponse> {
+        let cached = match self.shared.as_ref()?.get(key).await {
+            Ok(cached) => cached?,
+            Err(e) => {
+                warn!("Error reading the shared cache: {}", e);
+                return None;
+            }
+        };
+        if let Some(responses) = &self.responses {
+            responses.lock().unwrap().put(key.clone(), cached.clone());
+        }
+        Some(cached)
+    }
+
+    /// Caches a response, dropping the least recently used one if the cache
+    /// is full.
+    async fn put(&self, key: CacheKey, response: CachedResponse) {
+        #[cfg(feature = "shared-cache")]
+        {
+            if let Some(shared) = &self.shared {
+                if let Err(e) = shared.put(&key, &response).await {
+                    warn!("Error writing to the shared cache: {}", e);
+                }
+            }
+        }
+        if let Some(responses) = &self.responses {
+            responses.lock().unwrap().put(key, response);
+        }
+    }
+}
+
+/// Middleware answering requests from a [ResponseCache] in the app data,
+/// and caching the successful responses of those that aren't in it yet.
+///
+/// Only `GET` requests are cached. Responses that set cookies aren't, and
+/// requests with `If-None-Match` or `If-Modified-Since` go through to the
+/// handler so it can answer them with `304 Not Modified`. Responses are marked with an `X-Cache`
+/// header of `HIT` or `MISS`.
+#[derive(Clone, Copy, Default)]
+pub struct CacheResponses;
+
+impl<S, B> Transform<S, ServiceRequest> for CacheResponses
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = actix_web::Error;
+    type Transform = CacheResponsesMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(CacheResponsesMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+/// Service answering requests from the cache for [CacheResponses].
+pub struct CacheResponsesMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for CacheResponsesMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let cache = req.app_data::<web::Data<ResponseCache>>().cloned();
+        let key = cache_key(&req);
+        Box::pin(async move {
+            let (cache, key) = match (cache, key) {
+                (Some(cache), Some(key)) if cache.is_enabled() => (cache, key),
+                _ => return Ok(service.call(req).await?.map_into_boxed_body()),
+            };
+
+            if let Some(cached) = cache.get(&key).await {
+                debug!(
+                    "Cache hit for {} ({} hits, {} misses)",
+                    key.path,
+                    cache.hits(),
+                    cache.misses()
+                );
+                let mut resp = HttpResponse::Ok();
+                for header in cached.headers {
+                    resp.append_header(header);
+                }
+                resp.insert_header((CACHE_HEADER, "HIT"));
+                return Ok(req.into_response(resp.body(cached.body)));
+            }
+
+            let (req, resp) = service.call(req).await?.into_parts();
+            let (mut resp, body) = resp.map_into_boxed_body().into_parts();
+            if resp.status() != StatusCode::OK || resp.headers().contains_key(header::SET_COOKIE) {
+                return Ok(ServiceResponse::new(req, resp.set_body(body)));
+            }
+            let body = body::to_bytes(body)
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+            let headers = resp
+                .headers()
+                .iter()
+                .map(|(name, value)| (name.to_owned(), value.to_owned()))
+                .collect();
+            cache
+                .put(
+                    key,
+                    CachedResponse {
+                        headers,
+                        body: body.clone(),
+                    },
+                )
+                .await;
+            resp.headers_mut().insert(
+                HeaderName::from_static(CACHE_HEADER),
+                HeaderValue::from_static("MISS"),
+            );
+            Ok(ServiceResponse::new(
+                req,
+                resp.set_body(body).map_into_boxed_body(),
+            ))
+        })
+    }
+}
+
+/// Gets what a request's response would be cached as, if it can be.
+fn cache_key(req: &ServiceRequest) -> Option<CacheKey> {
+    let conditional = [header::IF_NONE_MATCH, header::IF_MODIFIED_SINCE]
+        .iter()
+        .any(|h| req.headers().contains_key(h));
+    if req.method() != Method::GET || conditional {
+        return None;
+    }
+    let data = req.app_data::<web::Data<ServerData>>()?;
+    let version = req.match_info().get("version").map(str::to_owned);
+    let accept = req.headers().get(header::ACCEPT);
+    Some(CacheKey {
+        translation: translation_version(version, req.request(), data),
+        path: req.uri().to_string(),
+        accept: accept
+            .and_then(|a| a.to_str().ok())
+            .unwrap_or_default()
+            .to_string(),
+    })
+}
+
+#[cfg(feature = "shared-cache")]
+mod shared;
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+
+    use actix_web::rt::System;
+    use actix_web::{test, App};
+
+    use super::*;
+    use crate::test::test_server_data;
+
+    #[test]
+    fn lru() {
+        System::new().block_on(async {
+            let cache = ResponseCache::new(2);
+            let key = |path: &str| CacheKey {
+                translation: "kjv".to_string(),
+                path: path.to_string(),
+                accept: String::new(),
+            };
+            let response = |body: &'static str| CachedResponse {
+                headers: vec![],
+                body: Bytes::from_static(body.as_bytes()),
+            };
+
+            cache.put(key("/a"), response("a")).await;
+            cache.put(key("/b"), response("b")).await;
+            assert_eq!(cache.get(&key("/a")).await.unwrap().body, "a");
+            // The least recently used response is dropped
+            cache.put(key("/c"), response("c")).await;
+            assert!(cache.get(&key("/b")).await.is_none());
+            assert!(cache.get(&key("/a")).await.is_some());
+            assert_eq!((cache.hits(), cache.misses()), (2, 1));
+
+            let cache = ResponseCache::new(0);
+            assert!(!cache.is_enabled());
+            cache.put(key("/a"), response("a")).await;
+            assert!(cache.get(&key("/a")).await.is_none());
+        });
+    }
+
+    #[test]
+    fn cached_responses() {
+        System::new().block_on(async {
+            let lookups = Arc::new(AtomicUsize::new(0));
+            let cache = web::Data::new(ResponseCache::new(10));
+            let srv = test::init_service(
+                App::new()
+                    .app_data(test_server_data())
+                    .app_data(cache.clone())
+                    .service(web::resource("/{reference}").wrap(CacheResponses).to({
+                        let lookups = Arc::clone(&lookups);
+                        move || {
+                            lookups.fetch_add(1, Ordering::Relaxed);
+                            async { HttpResponse::Ok().content_type("text/plain").body("verses") }
+                        }
+                    })),
+            )
+            .await;
+            let call =
+                |uri: &str| test::call_service(&srv, test::TestRequest::with_uri(uri).to_request());
+
+            let resp = call("/Psalms.119").await;
+            assert_eq!(resp.headers()[CACHE_HEADER], "MISS");
+            let resp = call("/Psalms.119").await;
+            assert_eq!(resp.headers()[CACHE_HEADER], "HIT");
+            assert_eq!(resp.headers()[header::CONTENT_TYPE], "text/plain");
+            assert_eq!(test::read_body(resp).await, "verses");
+            assert_eq!(lookups.load(Ordering::Relaxed), 1);
+
+            // Other query strings are cached separately
+            let resp = call("/Psalms.119?offset=10").await;
+            assert_eq!(resp.headers()[CACHE_HEADER], "MISS");
+            assert_eq!((cache.hits(), cache.misses()), (1, 2));
+        });
+    }
+}
@@ -0,0 +1,182 @@
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::web::Bytes;
+use db::models::Translation;
+use redis::aio::ConnectionManager;
+use redis::RedisError;
+use sha2::{Digest, Sha256};
+
+use super::{CacheKey, CachedResponse};
+
+/// Seconds a translation's responses are kept after one was last cached.
+const TTL: usize = 7 * 24 * 60 * 60;
+
+/// Responses cached in Redis, so instances behind a load balancer share a
+/// warm cache.
+///
+/// Each translation's responses are kept in a hash named after the version
+/// of the data: the latest migration, and when each translation was
+/// imported. Once a translation's been imported, attached or migrated, and
+/// the instances restarted, the responses cached before it are no longer
+/// read, and expire.
+#[derive(Clone)]
+pub struct SharedCache {
+    conn: ConnectionManager,
+    prefix: String,
+}
+
+impl SharedCache {
+    /// Connects to Redis, caching responses for the version of the data
+    /// given by the latest migration and the installed translations.
+    pub async fn connect(
+        url: &str,
+        data_version: &str,
+        translations: &[Translation],
+    ) -> Result<Self, RedisError> {
+        let client = redis::Client::open(url)?;
+        Ok(Self {
+            conn: client.get_tokio_connection_manager().await?,
+            prefix: prefix(data_version, translations),
+        })
+    }
+
+    /// Gets the response cached for a request.
+    pub(super) async fn get(&self, key: &CacheKey) -> Result<Option<CachedResponse>, RedisError> {
+        let value: Option<Vec<u8>> = redis::cmd("HGET")
+            .arg(self.hash(key))
+            .arg(field(key))
+            .query_async(&mut self.conn.clone())
+            .await?;
+        Ok(value.and_then(|value| decode(&value)))
+    }
+
+    /// Caches a response, keeping its translation's responses for another
+    /// [TTL].
+    pub(super) async fn put(
+        &self,
+        key: &CacheKey,
+        response: &CachedResponse,
+    ) -> Result<(), RedisError> {
+        let hash = self.hash(key);
+        redis::pipe()
+            .hset(&hash, field(key), encode(response))
+            .ignore()
+            .expire(&hash, TTL)
+            .ignore()
+            .query_async(&mut self.conn.clone())
+            .await
+    }
+
+    /// Gets the name of the hash a translation's responses are kept in.
+    fn hash(&self, key: &CacheKey) -> String {
+        format!("{}:{}", self.prefix, key.translation)
+    }
+}
+
+/// Gets the prefix of the hashes responses are kept in for a version of the
+/// data, from the latest migration and a hash of when each translation was
+/// imported.
+fn prefix(data_version: &str, translations: &[Translation]) -> String {
+    let mut imports: Vec<(&str, i64)> = translations
+        .iter()
+        .map(|t| (t.abbreviation.as_str(), t.imported_at))
+        .collect();
+    imports.sort_unstable();
+    let mut hasher = Sha256::new();
+    for (abbreviation, imported_at) in imports {
+        hasher.update(format!("{}@{}\n", abbreviation, imported_at));
+    }
+    let hash = format!("{:x}", hasher.finalize());
+    format!("bible.rs:{}:{}", data_version, &hash[..16])
+}
+
+/// Gets the field a response is kept in within its translation's hash.
+fn field(key: &CacheKey) -> String {
+    format!("{}\n{}", key.accept, key.path)
+}
+
+/// Encodes a response like an HTTP message: a line for each header, then a
+/// blank line and the body.
+fn encode(response: &CachedResponse) -> Vec<u8> {
+    let mut encoded = vec![];
+    for (name, value) in &response.headers {
+        encoded.extend_from_slice(name.as_str().as_bytes());
+        encoded.extend_from_slice(b": ");
+        encoded.extend_from_slice(value.as_bytes());
+        encoded.extend_from_slice(b"\r\n");
+    }
+    encoded.extend_from_slice(b"\r\n");
+    encoded.extend_from_slice(&response.body);
+    encoded
+}
+
+/// Decodes a response encoded by [encode], if it's valid.
+fn decode(mut encoded: &[u8]) -> Option<CachedResponse> {
+    let mut headers = vec![];
+    loop {
+        let end = encoded.windows(2).position(|w| w == b"\r\n")?;
+        let (line, rest) = (&encoded[..end], &encoded[end + 2..]);
+        encoded = rest;
+        if line.is_empty() {
+            break;
+        }
+        let colon = line.windows(2).position(|w| w == b": ")?;
+        headers.push((
+            HeaderName::from_bytes(&line[..colon]).ok()?,
+            HeaderValue::from_bytes(&line[colon + 2..]).ok()?,
+        ));
+    }
+    Some(CachedResponse {
+        headers,
+        body: Bytes::copy_from_slice(encoded),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::header;
+
+    use super::*;
+    use crate::test::test_translation;
+
+    #[test]
+    fn prefixes() {
+        let kjv = test_translation();
+        let asv = Translation {
+            id: 2,
+            abbreviation: "asv".to_string(),
+            ..test_translation()
+        };
+        let version = "20220604000000";
+        let prefix = prefix(version, &[kjv.clone(), asv.clone()]);
+        assert!(prefix.starts_with("bible.rs:20220604000000:"));
+        assert_eq!(prefix, super::prefix(version, &[asv.clone(), kjv.clone()]));
+        assert_ne!(
+            prefix,
+            super::prefix("20220528000000", &[kjv.clone(), asv.clone()])
+        );
+
+        // Importing a translation again leaves its old responses behind
+        let reimported = Translation {
+            imported_at: asv.imported_at + 1,
+            ..asv
+        };
+        assert_ne!(current, prefix(version, &[kjv, reimported]));
+    }
+
+    #[test]
+    fn encoding() {
+        let response = CachedResponse {
+            headers: vec![
+                (header::CONTENT_TYPE, HeaderValue::from_static("text/plain")),
+                (header::ETAG, HeaderValue::from_static("\"abc\"")),
+            ],
+            body: Bytes::from_static(b"Thy word is a lamp\r\n\r\nunto my feet"),
+        };
+        let decoded = decode(&encode(&response)).unwrap();
+        assert_eq!(decoded.headers, response.headers);
+        assert_eq!(decoded.body, response.body);
+
+        assert!(decode(b"content-type text/plain\r\n\r\n").is_none());
+        assert!(decode(b"content-type: text/plain").is_none());
+    }
+}
@@ -0,0 +1,126 @@
+use std::future::{ready, Ready};
+
+use actix_web::dev::{forward_ready, Service, ServiceRequest, Transform};
+use actix_web::http::header::{self, HeaderValue};
+
+/// Middleware having [Compress](actix_web::middleware::Compress) encode
+/// responses with Brotli for clients that accept it as much as any other
+/// encoding, since it shrinks the large chapter pages the most.
+///
+/// Compress picks the first of the encodings a client prefers most, and
+/// browsers list `br` after `gzip` with the same quality, so their responses
+/// would be gzipped otherwise. It has to wrap Compress to see requests first.
+#[derive(Clone, Copy, Default)]
+pub struct PreferBrotli;
+
+impl<S> Transform<S, ServiceRequest> for PreferBrotli
+where
+    S: Service<ServiceRequest, Error = actix_web::Error>,
+{
+    type Response = S::Response;
+    type Error = actix_web::Error;
+    type Transform = PreferBrotliMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(PreferBrotliMiddleware { service }))
+    }
+}
+
+/// Service preferring Brotli for [PreferBrotli].
+pub struct PreferBrotliMiddleware<S> {
+    service: S,
+}
+
+impl<S> Service<ServiceRequest> for PreferBrotliMiddleware<S>
+where
+    S: Service<ServiceRequest, Error = actix_web::Error>,
+{
+    type Response = S::Response;
+    type Error = actix_web::Error;
+    type Future = S::Future;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let accept = req
+            .headers()
+            .get(header::ACCEPT_ENCODING)
+            .and_then(|a| a.to_str().ok());
+        if accept.map_or(false, prefers_brotli) {
+            req.headers_mut()
+                .insert(header::ACCEPT_ENCODING, HeaderValue::from_static("br"));
+        }
+        self.service.call(req)
+    }
+}
+
+/// Whether an `Accept-Encoding` header accepts Brotli at least as much as
+/// any other encoding.
+fn prefers_brotli(accept: &str) -> bool {
+    let qualities: Vec<(&str, f32)> = accept
+        .split(',')
+        .map(|encoding| {
+            let mut params = encoding.split(';').map(str::trim);
+            let name = params.next().unwrap_or_default();
+            let quality = params
+                .find_map(|p| p.strip_prefix("q="))
+                .and_then(|q| q.parse().ok())
+                .unwrap_or(1.0);
+            (name, quality)
+        })
+        .collect();
+    let best = qualities.iter().map(|(_, q)| *q).fold(0.0, f32::max);
+    qualities
+        .iter()
+        .any(|(name, q)| name.eq_ignore_ascii_case("br") && *q > 0.0 && *q >= best)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::middleware::Compress;
+    use actix_web::{rt::System, test, web, App, HttpResponse};
+
+    use super::*;
+
+    #[test]
+    fn preference() {
+        assert!(prefers_brotli("gzip, deflate, br"));
+        assert!(prefers_brotli("br;q=1.0, gzip;q=0.8"));
+        assert!(!prefers_brotli("gzip, deflate"));
+        assert!(!prefers_brotli("gzip, br;q=0.5"));
+        assert!(!prefers_brotli("br;q=0"));
+    }
+
+    #[test]
+    fn brotli_responses() {
+        System::new().block_on(async {
+            let srv = test::init_service(
+                App::new()
+                    .wrap(Compress::default())
+                    .wrap(PreferBrotli)
+                    .route(
+                        "/",
+                        web::get().to(|| async {
+                            HttpResponse::Ok().body("Thy word is a lamp unto my feet. ".repeat(100))
+                        }),
+                    ),
+            )
+            .await;
+            let call = |accept: &str| {
+                test::call_service(
+                    &srv,
+                    test::TestRequest::with_uri("/")
+                        .insert_header((header::ACCEPT_ENCODING, accept))
+                        .to_request(),
+                )
+            };
+
+            let resp = call("gzip, deflate, br").await;
+            assert_eq!(resp.headers()[header::CONTENT_ENCODING], "br");
+            let resp = call("gzip, deflate").await;
+            assert_eq!(resp.headers()[header::CONTENT_ENCODING], "gzip");
+        });
+    }
+}
@@ -1,66 +1,1161 @@
-use actix_web::web;
-use actix_web::{HttpRequest, HttpResponse};
+use actix_cors::Cors;
+use actix_web::http::header::{self, EntityTag, HeaderName, HeaderValue};
+use actix_web::web::{self, Bytes};
+use actix_web::{middleware, HttpRequest, HttpResponse};
+use futures_util::stream::{self, StreamExt};
+use serde::Serialize;
 
-use db::models::Reference;
-use db::{SwordDrillable, VerseFormat};
+use db::models::{
+    Book, Reference, ResultSet, SearchMode, SearchOptions, Translation, Verse, VerseFTS,
+};
+use db::{BookStore, DbConnection, DbError, Searcher, VerseFormat, VerseStore};
 
-use crate::controllers::SearchParams;
+use crate::auth::API_KEY_HEADER;
+use crate::cache::CacheResponses;
+use crate::controllers::{
+    passage, passages, translation_version, BatchSearch, ContextParams, DayParams, FieldsParams,
+    RandomParams, ReferencePath, SearchParams, VersePageParams,
+};
 use crate::error::{Error, JsonError};
-use crate::responder::{SearchResultData, VersesData};
+use crate::responder::{
+    has_etag, ndjson_lines, passage_etag, select_fields, ApiDocs, BooksData, ChaptersData,
+    CompareData, CompletionData, ContextData, CrossReferencesData, ExportFormat,
+    SearchCompletionData, SearchResultData, StatsData, TranslationsSearchResultData,
+    VerseOfTheDayData, VersesData,
+};
 use crate::ServerData;
 
 /// Result for JSON API response handlers
 type ApiResult = Result<HttpResponse, JsonError>;
 
+/// Max number of matches in an export of search results.
+const EXPORT_LIMIT: i64 = 1000;
+
+/// Max number of references that can be looked up at once.
+const MAX_PASSAGES: usize = 100;
+
+/// Max number of searches that can be run at once.
+const MAX_SEARCHES: usize = 20;
+
+/// Path the current version of the API is served under.
+pub const API_V1: &str = "/api/v1";
+
+/// Registers the JSON API routes, matching translations as URL prefixes
+/// with a version pattern. Each responds with one of the documented
+/// structs in [crate::responder], or the problem details of
+/// [crate::responder::ProblemData]:
+///
+/// - `/search`: [SearchResultData], or [TranslationsSearchResultData] when
+///   `versions` are given
+/// - `POST /search/batch` with a JSON list of searches: an object of
+///   [SearchResultData] keyed by query
+/// - `/search/suggest`: [SearchCompletionData]
+/// - `/search.csv` and `/search.md`: the matches exported as a file
+/// - `/complete`: [CompletionData]
+/// - `/books.json`: [BooksData]
+/// - `/random.json`: [VersesData] of a random verse
+/// - `/votd.json`: [VerseOfTheDayData]
+/// - `POST /passages` with a JSON list of references: a list of [VersesData]
+/// - `/{book}/chapters.json`: [ChaptersData]
+/// - `/stats.json` and `/{book}/stats.json`: [StatsData] of the whole
+///   translation or a book
+/// - `/{book}/full.ndjson` and `/full.ndjson`: a book or the whole Bible
+///   streamed with a verse on each line
+/// - `/compare/{reference}.json`: [CompareData]
+/// - `/context/{reference}.json`: [ContextData]
+/// - `/crossrefs/{reference}.json`: [CrossReferencesData]
+/// - `/{version}/{reference}.json` and `/{reference}.json`: [VersesData],
+///   or a list of them for several references
+///
+/// Passages are answered from the [crate::cache::ResponseCache] when the app
+/// has one. Fields are only ever added to these responses within a version
+/// of the API, so clients can depend on the ones they use.
+pub fn routes<SD>(cfg: &mut web::ServiceConfig, version: &str)
+where
+    SD: VerseStore + Searcher + 'static,
+{
+    cfg.service(web::resource("/search/batch").route(web::post().to(batch_search::<SD>)))
+        .service(
+            web::resource("/search")
+                .wrap(CacheResponses)
+                .route(web::get().to(search::<SD>)),
+        )
+        .service(web::resource("/search/suggest").route(web::get().to(suggest::<SD>)))
+        .service(web::resource("/search.{format:csv|md}").route(web::get().to(export::<SD>)))
+        .service(web::resource("/complete").route(web::get().to(complete::<SD>)))
+        .service(web::resource("/books.json").route(web::get().to(books::<SD>)))
+        .service(web::resource("/random.json").route(web::get().to(random::<SD>)))
+        .service(web::resource("/votd.json").route(web::get().to(votd::<SD>)))
+        .service(web::resource("/passages").route(web::post().to(bulk_passages::<SD>)))
+        .service(web::resource("/{book}/chapters.json").route(web::get().to(chapters::<SD>)))
+        .service(web::resource("/stats.json").route(web::get().to(stats::<SD>)))
+        .service(web::resource("/{book}/stats.json").route(web::get().to(stats::<SD>)))
+        .service(web::resource("/{book}/full.ndjson").route(web::get().to(book_ndjson::<SD>)))
+        .service(web::resource("/full.ndjson").route(web::get().to(bible_ndjson::<SD>)))
+        .service(web::resource("/compare/{reference}.json").route(web::get().to(compare::<SD>)))
+        .service(web::resource("/context/{reference}.json").route(web::get().to(context::<SD>)))
+        .service(
+            web::resource("/crossrefs/{reference}.json")
+                .route(web::get().to(cross_references::<SD>)),
+        )
+        .service(
+            web::resource(format!("/{}/{{reference}}.json", version))
+                .wrap(CacheResponses)
+                .route(web::get().to(reference::<SD>)),
+        )
+        .service(
+            web::resource("/{reference}.json")
+                .wrap(CacheResponses)
+                .route(web::get().to(reference::<SD>)),
+        );
+}
+
+/// Headers marking responses of the unversioned `/api` routes as
+/// deprecated, pointing to their replacements under [API_V1].
+pub fn deprecated() -> middleware::DefaultHeaders {
+    middleware::DefaultHeaders::new()
+        .add(("Deprecation", "true"))
+        .add((
+            header::LINK,
+            format!("<{}>; rel=\"successor-version\"", API_V1),
+        ))
+}
+
+/// Lets browser apps on other sites call the API, if their origins are
+/// allowed. An origin of `*` allows any site, and no origins keeps
+/// cross-origin requests blocked. Apps can send API keys and entity tags,
+/// and read the headers for caching, rate limits and links.
+pub fn cors(origins: &[String]) -> Cors {
+    let cors = Cors::default()
+        .allowed_methods(vec!["GET", "POST"])
+        .allowed_headers(vec![
+            header::CONTENT_TYPE,
+            header::AUTHORIZATION,
+            header::IF_NONE_MATCH,
+            HeaderName::from_static(API_KEY_HEADER),
+        ])
+        .expose_headers(vec![header::ETAG, header::RETRY_AFTER, header::LINK])
+        .max_age(3600);
+    if origins.iter().any(|o| o == "*") {
+        cors.allow_any_origin()
+    } else {
+        origins
+            .iter()
+            .fold(cors, |cors, origin| cors.allowed_origin(origin))
+    }
+}
+
+/// Responds with JSON, keeping only the fields that were asked for if any
+/// were.
+fn json_fields<T>(data: &T, params: &FieldsParams) -> HttpResponse
+where
+    T: Serialize,
+{
+    match params.fields() {
+        Some(fields) => {
+            let value = serde_json::to_value(data).unwrap();
+            HttpResponse::Ok().json(select_fields(value, &fields))
+        }
+        None => HttpResponse::Ok().json(data),
+    }
+}
+
+/// Gets the JSON of a value, keeping only the fields that were asked for if
+/// any were, for a part of a response.
+fn fields_value<T>(data: &T, params: &FieldsParams) -> serde_json::Value
+where
+    T: Serialize,
+{
+    let value = serde_json::to_value(data).unwrap();
+    match params.fields() {
+        Some(fields) => select_fields(value, &fields),
+        None => value,
+    }
+}
+
+/// Adds an entity tag to a response, for clients to ask for it again with.
+fn with_etag(mut resp: HttpResponse, etag: &EntityTag) -> HttpResponse {
+    let value = HeaderValue::from_str(&etag.to_string()).unwrap();
+    resp.headers_mut().insert(header::ETAG, value);
+    resp
+}
+
+/// Tells a client that it already has the response with an entity tag.
+fn not_modified(etag: EntityTag) -> HttpResponse {
+    HttpResponse::NotModified()
+        .insert_header(header::ETag(etag))
+        .finish()
+}
+
 pub async fn reference<SD>(
     data: web::Data<ServerData>,
-    params: web::Path<(String,)>,
+    params: web::Path<ReferencePath>,
+    fields: web::Query<FieldsParams>,
+    page: web::Query<VersePageParams>,
     req: HttpRequest,
 ) -> ApiResult
 where
-    SD: SwordDrillable,
+    SD: VerseStore,
 {
-    let (path_reference,) = params.into_inner();
+    let ReferencePath {
+        version,
+        reference: path_reference,
+    } = params.into_inner();
+    let version = translation_version(version, &req, &data);
     let raw_reference = path_reference.replace("/", ".");
+    let offset = page.offset.unwrap_or(0);
+
+    // Several references separated by semicolons give a list of passages
+    if raw_reference.contains(';') {
+        let references = Reference::parse_list(&raw_reference)?;
+        let data_references = references.to_owned();
+        let results = web::block(move || {
+            passages::<SD>(
+                &references,
+                &version,
+                data.fallback_translation.as_deref(),
+                VerseFormat::PlainText,
+                &data.read_db().get().unwrap(),
+            )
+        })
+        .await??;
 
+        let etag = passage_etag(
+            results.iter().flat_map(|(translation, fallback, _)| {
+                Some(translation).into_iter().chain(fallback)
+            }),
+            &raw_reference,
+            req.query_string(),
+        );
+        if has_etag(&req, &etag) {
+            return Ok(not_modified(etag));
+        }
+        let passages: Vec<VersesData> = results
+            .into_iter()
+            .zip(data_references)
+            .map(|((translation, fallback, result), reference)| {
+                VersesData::new(result, reference, translation, &req)
+                    .with_fallback(fallback)
+                    .with_navigation(&req)
+                    .with_page(offset, page.limit)
+            })
+            .collect();
+        return Ok(with_etag(json_fields(&passages, &fields), &etag));
+    }
     if let Ok(reference) = raw_reference.parse::<Reference>() {
         let data_reference = reference.to_owned();
+        let (translation, fallback, result) = web::block(move || {
+            passage::<SD>(
+                &reference,
+                &version,
+                data.fallback_translation.as_deref(),
+                VerseFormat::PlainText,
+                &data.read_db().get().unwrap(),
+            )
+        })
+        .await??;
+
+        let etag = passage_etag(
+            Some(&translation).into_iter().chain(&fallback),
+            &raw_reference,
+            req.query_string(),
+        );
+        if has_etag(&req, &etag) {
+            return Ok(not_modified(etag));
+        }
+        let verses_data = VersesData::new(result, data_reference, translation, &req)
+            .with_fallback(fallback)
+            .with_navigation(&req)
+            .with_page(offset, page.limit);
+        Ok(with_etag(json_fields(&verses_data, &fields), &etag))
+    } else {
+        Err(Error::InvalidReference(raw_reference).into())
+    }
+}
+
+/// Describes the JSON API as an OpenAPI document.
+pub async fn openapi(data: web::Data<ServerData>) -> HttpResponse {
+    HttpResponse::Ok().json(ApiDocs::new(&data.translations).to_openapi())
+}
+
+/// Lists the books of the user's translation, for building navigation.
+pub async fn books<SD>(data: web::Data<ServerData>, req: HttpRequest) -> ApiResult
+where
+    SD: BookStore,
+{
+    let version = translation_version(None, &req, &data);
+    let (books, abbreviations) = web::block(move || {
+        let conn = data.read_db().get().unwrap();
+        let translation = SD::translation(&version, &conn)?;
+        let books = SD::all_books(&translation, &conn)?;
+        Ok::<_, DbError>((books, SD::book_abbreviations(&conn)?))
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(BooksData::new(books, abbreviations, &req)))
+}
+
+/// Lists the chapters of a book in the user's translation, with the number
+/// of verses in each.
+pub async fn chapters<SD>(
+    data: web::Data<ServerData>,
+    book: web::Path<String>,
+    req: HttpRequest,
+) -> ApiResult
+where
+    SD: BookStore,
+{
+    let version = translation_version(None, &req, &data);
+    let chapters = web::block(move || {
+        let conn = data.read_db().get().unwrap();
+        let translation = SD::translation(&version, &conn)?;
+        SD::chapters(&book, &translation, &conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(ChaptersData::new(chapters, &req)))
+}
+
+/// Counts the chapters, verses and words of the user's translation, or of a
+/// book of it when there's one in the path, and finds the longest and
+/// shortest verses.
+pub async fn stats<SD>(data: web::Data<ServerData>, req: HttpRequest) -> ApiResult
+where
+    SD: VerseStore,
+{
+    let version = translation_version(None, &req, &data);
+    let book = req.match_info().get("book").map(str::to_owned);
+    let stats = web::block(move || {
+        let conn = data.read_db().get().unwrap();
+        let translation = SD::translation(&version, &conn)?;
+        let book = match book {
+            Some(name) => Some(SD::book(&name, &translation, &conn)?.0),
+            None => None,
+        };
+        let stats = SD::stats(book.as_ref(), &translation, &conn)?;
+        Ok::<_, DbError>(StatsData {
+            translation,
+            book,
+            stats,
+        })
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(stats))
+}
+
+/// Streams every verse of a book in the user's translation.
+pub async fn book_ndjson<SD>(
+    data: web::Data<ServerData>,
+    book: web::Path<String>,
+    req: HttpRequest,
+) -> ApiResult
+where
+    SD: VerseStore + 'static,
+{
+    let version = translation_version(None, &req, &data);
+    let (translation, chapters) = web::block({
+        let data = data.clone();
+        move || {
+            let conn = data.read_db().get().unwrap();
+            let translation = SD::translation(&version, &conn)?;
+            let (book, chapters) = SD::book(&book, &translation, &conn)?;
+            let chapters = chapters.into_iter().map(|c| (book.name.to_owned(), c));
+            Ok::<_, DbError>((translation, chapters.collect()))
+        }
+    })
+    .await??;
+
+    Ok(stream_chapters::<SD>(data, translation, chapters))
+}
+
+/// Streams every verse of the Bible in the user's translation, book by book.
+pub async fn bible_ndjson<SD>(data: web::Data<ServerData>, req: HttpRequest) -> ApiResult
+where
+    SD: VerseStore + 'static,
+{
+    let version = translation_version(None, &req, &data);
+    let (translation, chapters) = web::block({
+        let data = data.clone();
+        move || {
+            let conn = data.read_db().get().unwrap();
+            let translation = SD::translation(&version, &conn)?;
+            let chapters = SD::all_books(&translation, &conn)?
+                .into_iter()
+                .flat_map(|b| (1..=b.chapter_count).map(move |c| (b.name.to_owned(), c)));
+            Ok::<_, DbError>((translation, chapters.collect()))
+        }
+    })
+    .await??;
+
+    Ok(stream_chapters::<SD>(data, translation, chapters))
+}
+
+/// Streams the verses of chapters as newline-delimited JSON, looking them up
+/// a chapter at a time so a whole book (or Bible) is never held at once.
+fn stream_chapters<SD>(
+    data: web::Data<ServerData>,
+    translation: Translation,
+    chapters: Vec<(String, i32)>,
+) -> HttpResponse
+where
+    SD: VerseStore + 'static,
+{
+    let lines = stream::iter(chapters).then(move |(book, chapter)| {
+        let data = data.clone();
+        let translation = translation.clone();
+        async move {
+            let (book, verses) = web::block(move || {
+                let reference: Reference = format!("{} {}", book, chapter).parse()?;
+                let conn = data.read_db().get().unwrap();
+                SD::verses(&reference, &translation, VerseFormat::PlainText, &conn)
+            })
+            .await??;
+            Ok::<_, JsonError>(Bytes::from(ndjson_lines(&book.name, &verses)))
+        }
+    });
+    HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(lines)
+}
+
+/// Looks up a JSON list of references at once (e.g. the citations of a study
+/// guide), up to [MAX_PASSAGES] of them.
+pub async fn bulk_passages<SD>(
+    data: web::Data<ServerData>,
+    raw_references: web::Json<Vec<String>>,
+    req: HttpRequest,
+) -> ApiResult
+where
+    SD: VerseStore,
+{
+    if raw_references.len() > MAX_PASSAGES {
+        let cause = format!(
+            "at most {} references can be looked up at once",
+            MAX_PASSAGES
+        );
+        return Err(Error::InvalidSearch(cause).into());
+    }
+    let references = raw_references
+        .iter()
+        .map(|r| {
+            r.parse::<Reference>()
+                .map_err(|_| Error::InvalidReference(r.to_string()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let version = translation_version(None, &req, &data);
+    let data_references = references.to_owned();
+    let results = web::block(move || {
+        passages::<SD>(
+            &references,
+            &version,
+            data.fallback_translation.as_deref(),
+            VerseFormat::PlainText,
+            &data.read_db().get().unwrap(),
+        )
+    })
+    .await??;
+
+    let passages: Vec<VersesData> = results
+        .into_iter()
+        .zip(data_references)
+        .map(|((translation, fallback, result), reference)| {
+            VersesData::new(result, reference, translation, &req)
+                .with_fallback(fallback)
+                .with_navigation(&req)
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(passages))
+}
+
+/// Picks a verse at random, for showing a different verse each time.
+pub async fn random<SD>(
+    data: web::Data<ServerData>,
+    query: web::Query<RandomParams>,
+    req: HttpRequest,
+) -> ApiResult
+where
+    SD: VerseStore,
+{
+    let version = translation_version(None, &req, &data);
+    let scope = query.scope()?;
+    let exclude_genealogies = query.exclude_genealogies.unwrap_or(false);
+    let picked = web::block(move || {
+        let conn = data.read_db().get().unwrap();
+        let translation = SD::translation(&version, &conn)?;
+        match SD::random_verse(&scope, exclude_genealogies, &translation, &conn)? {
+            Some(reference) => {
+                let result = SD::verses(&reference, &translation, VerseFormat::PlainText, &conn)?;
+                Ok::<_, DbError>(Some((reference, translation, result)))
+            }
+            None => Ok(None),
+        }
+    })
+    .await??;
+
+    let (reference, translation, result) = picked.ok_or_else(|| {
+        Error::InvalidSearch("there are no verses in those books to pick from".to_string())
+    })?;
+    let verses_data = VersesData::new(result, reference, translation, &req).with_navigation(&req);
+    Ok(HttpResponse::Ok()
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .json(verses_data))
+}
+
+/// Gets the verse of the day, which is the same for everyone on a day.
+pub async fn votd<SD>(
+    data: web::Data<ServerData>,
+    query: web::Query<DayParams>,
+    req: HttpRequest,
+) -> ApiResult
+where
+    SD: VerseStore,
+{
+    let version = translation_version(None, &req, &data);
+    let (day, date) = query.day()?;
+    let (reference, translation, result) = web::block(move || {
+        let conn = data.read_db().get().unwrap();
+        let translation = SD::translation(&version, &conn)?;
+        let reference = SD::verse_of_the_day(day, &conn)?;
+        let result = SD::verses(&reference, &translation, VerseFormat::PlainText, &conn)?;
+        Ok::<_, DbError>((reference, translation, result))
+    })
+    .await??;
+
+    let verses_data = VersesData::new(result, reference, translation, &req);
+    Ok(HttpResponse::Ok().json(VerseOfTheDayData::new(verses_data, date)))
+}
+
+/// Looks up a reference in every installed translation.
+pub async fn compare<SD>(
+    data: web::Data<ServerData>,
+    params: web::Path<ReferencePath>,
+    req: HttpRequest,
+) -> ApiResult
+where
+    SD: VerseStore,
+{
+    let ReferencePath {
+        reference: path_reference,
+        ..
+    } = params.into_inner();
+    let raw_reference = path_reference.replace("/", ".");
+
+    if let Ok(reference) = raw_reference.parse::<Reference>() {
+        let data_reference = reference.to_owned();
+        let translations = data.translations.to_owned();
         let result = web::block(move || {
-            SD::verses(&reference, VerseFormat::PlainText, &data.db.get().unwrap())
+            SD::parallel_verses(
+                &reference,
+                &data.translations,
+                VerseFormat::PlainText,
+                &data.read_db().get().unwrap(),
+            )
         })
         .await??;
 
-        let verses_data = VersesData::new(result, data_reference, &req);
-        Ok(HttpResponse::Ok().json(verses_data))
+        let compare_data = CompareData::new(result, data_reference, translations, &req);
+        Ok(HttpResponse::Ok().json(compare_data))
     } else {
         Err(Error::InvalidReference(raw_reference).into())
     }
 }
 
+/// Looks up the cross-references from the verses of a reference, for showing
+/// passages to see along with them.
+pub async fn cross_references<SD>(
+    data: web::Data<ServerData>,
+    params: web::Path<ReferencePath>,
+    req: HttpRequest,
+) -> ApiResult
+where
+    SD: VerseStore,
+{
+    let raw_reference = params.into_inner().reference.replace("/", ".");
+    let reference = raw_reference
+        .parse::<Reference>()
+        .map_err(|_| Error::InvalidReference(raw_reference))?;
+
+    let data_reference = reference.to_owned();
+    let result =
+        web::block(move || SD::cross_references(&reference, &data.read_db().get().unwrap()))
+            .await??;
+
+    Ok(HttpResponse::Ok().json(CrossReferencesData::new(result, &data_reference, &req)))
+}
+
+/// Looks up a passage along with verses of context before and after it
+/// (`before` and `after`, 2 of each by default), crossing into the chapters
+/// around it if needed.
+pub async fn context<SD>(
+    data: web::Data<ServerData>,
+    params: web::Path<ReferencePath>,
+    query: web::Query<ContextParams>,
+    req: HttpRequest,
+) -> ApiResult
+where
+    SD: VerseStore,
+{
+    let raw_reference = params.into_inner().reference.replace("/", ".");
+    let reference = raw_reference
+        .parse::<Reference>()
+        .map_err(|_| Error::InvalidReference(raw_reference))?;
+    let version = translation_version(None, &req, &data);
+    let (before, after) = query.context();
+
+    let data_reference = reference.to_owned();
+    let (translation, (context, result)) = web::block(move || {
+        let conn = data.read_db().get().unwrap();
+        let translation = SD::translation(&version, &conn)?;
+        let result = SD::verses_in_context(
+            &reference,
+            before,
+            after,
+            &translation,
+            VerseFormat::PlainText,
+            &conn,
+        )?;
+        Ok::<_, DbError>((translation, result))
+    })
+    .await??;
+
+    let verses_data = VersesData::new(result, context, translation, &req);
+    Ok(HttpResponse::Ok().json(ContextData::new(&data_reference, verses_data)))
+}
+
+/// Completes a partly typed book name or reference (e.g. `1 co` or `ps 11`)
+/// with books and chapters in the user's translation.
+pub async fn complete<SD>(
+    data: web::Data<ServerData>,
+    query: web::Query<SearchParams>,
+    req: HttpRequest,
+) -> ApiResult
+where
+    SD: BookStore,
+{
+    // A book name, optionally followed by part of a chapter number
+    let q = query.q.trim().to_string();
+    let book_end = q.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    let (prefix, chapter) = match q[..book_end].trim_end() {
+        // A lone number is the start of a book name (e.g. `1` for 1 Samuel)
+        "" => (q.to_owned(), None),
+        prefix if q.len() - book_end <= 3 => (
+            prefix.to_string(),
+            Some(q[book_end..].to_string()).filter(|c| !c.is_empty()),
+        ),
+        _ => (q.to_owned(), None),
+    };
+    if prefix.is_empty() {
+        return Ok(HttpResponse::Ok().json(CompletionData::new(vec![], None, &req)));
+    }
+
+    let version = translation_version(None, &req, &data);
+    let books = web::block(move || {
+        let conn = data.read_db().get().unwrap();
+        let translation = SD::translation(&version, &conn)?;
+        SD::complete_books(&prefix, &translation, &conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(CompletionData::new(books, chapter.as_deref(), &req)))
+}
+
+/// Suggests words and phrases of the user's translation that start with a
+/// partly typed search (e.g. `righteo`), for live suggestions in a search
+/// box.
+pub async fn suggest<SD>(
+    data: web::Data<ServerData>,
+    query: web::Query<SearchParams>,
+    req: HttpRequest,
+) -> ApiResult
+where
+    SD: BookStore + Searcher,
+{
+    let version = translation_version(None, &req, &data);
+    let (words, phrases) = web::block(move || {
+        let conn = data.read_db().get().unwrap();
+        let translation = SD::translation(&version, &conn)?;
+        SD::complete_search(&query.q, &translation, &conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(SearchCompletionData { words, phrases }))
+}
+
+/// Searches the requested translation by reference or words.
+///
+/// The translation is chosen with the `version` query parameter, falling
+/// back to the user's preferred translation. With `mode=regex`, the query
+/// is a regular expression matched against the text of each verse. Word
+/// searches also count the matches in each book and testament, and suggest
+/// a correction to the spelling of a query with no matches. With
+/// `versions=all` (or a list of translations), words are searched in
+/// several translations at once and the matches are grouped by verse. Word
+/// search results have a token for the verses they matched, which a search
+/// with `within=previous&results=<token>` narrows down.
+///
+/// Errors note the search they came from, except for books that don't exist,
+/// which have no matches.
 pub async fn search<SD>(
     data: web::Data<ServerData>,
     query: web::Query<SearchParams>,
+    fields: web::Query<FieldsParams>,
     req: HttpRequest,
 ) -> ApiResult
 where
-    SD: SwordDrillable,
+    SD: VerseStore + Searcher,
 {
-    if let Ok(reference) = query.q.parse::<Reference>() {
-        let results = web::block(move || {
-            SD::verses(&reference, VerseFormat::PlainText, &data.db.get().unwrap())
+    let q = query.q.to_owned();
+    match search_results::<SD>(data, query, &fields, req).await {
+        Err(JsonError {
+            error: Error::BookNotFound(_),
+            ..
+        }) => Ok(json_fields(&SearchResultData::empty(), &fields)),
+        result => result.map_err(|e| e.with_query(&q)),
+    }
+}
+
+/// Gets the results of [search].
+async fn search_results<SD>(
+    data: web::Data<ServerData>,
+    query: web::Query<SearchParams>,
+    fields: &FieldsParams,
+    req: HttpRequest,
+) -> ApiResult
+where
+    SD: VerseStore + Searcher,
+{
+    let version = translation_version(None, &req, &data);
+    let options = query.options()?;
+
+    if query.mode == Some(SearchMode::Regex) {
+        let (translation, results) = web::block({
+            let options = options.clone();
+            move || {
+                let conn = data.read_db().get().unwrap();
+                let translation = SD::translation(&version, &conn)?;
+                let results = SD::search_regex(&query.q, &options, &translation, &conn)?;
+                Ok::<_, DbError>((translation, results))
+            }
+        })
+        .await??;
+        let search_data =
+            SearchResultData::from_verses_fts(results, SearchMode::Regex, &options, &req)
+                .with_translation(translation);
+        Ok(json_fields(&search_data, fields))
+    } else if let Ok(reference) = query.q.parse::<Reference>() {
+        let (translation, results) = web::block(move || {
+            let conn = data.read_db().get().unwrap();
+            let translation = SD::translation(&version, &conn)?;
+            let results = SD::verses(&reference, &translation, VerseFormat::PlainText, &conn)?;
+            Ok::<_, DbError>((translation, results))
         })
         .await??;
-        Ok(HttpResponse::Ok().json(SearchResultData::from_verses(results, &req)))
+        let search_data =
+            SearchResultData::from_verses(results, &req).with_translation(translation);
+        Ok(json_fields(&search_data, fields))
+    } else if let Some(versions) = query.versions(&data) {
+        let mode = SearchMode::of(&query.q);
+        let (translations, results) = web::block({
+            let options = options.clone();
+            move || {
+                let conn = data.read_db().get().unwrap();
+                let translations = versions
+                    .iter()
+                    .map(|v| SD::translation(v, &conn))
+                    .collect::<Result<Vec<_>, DbError>>()?;
+                let results = SD::search_translations(&query.q, &options, &translations, &conn)?;
+                Ok::<_, DbError>((translations, results))
+            }
+        })
+        .await??;
+        let search_data =
+            TranslationsSearchResultData::new(results, mode, &options, translations, &req);
+        Ok(json_fields(&search_data, fields))
+    } else {
+        let mode = SearchMode::of(&query.q);
+        let (translation, results, result_set, facets, suggestion) = web::block({
+            let options = options.clone();
+            move || {
+                let conn = data.read_db().get().unwrap();
+                let translation = SD::translation(&version, &conn)?;
+                let results = SD::search(&query.q, &options, &translation, &conn)?;
+                let result_set = SD::search_result_set(&query.q, &options, &translation, &conn)?;
+                let facets = SD::search_facets(&query.q, &options, &translation, &conn)?;
+                let suggestion = match results.1 {
+                    0 => SD::suggest_spelling(&query.q, &translation, &conn)?,
+                    _ => None,
+                };
+                Ok::<_, DbError>((translation, results, result_set, facets, suggestion))
+            }
+        })
+        .await??;
+        let search_data = SearchResultData::from_verses_fts(results, mode, &options, &req)
+            .with_result_set(result_set)
+            .with_facets(facets, &req)
+            .with_suggestion(suggestion, &req)
+            .with_translation(translation);
+        Ok(json_fields(&search_data, fields))
+    }
+}
+
+/// Results of one of a batch of searches, before they're turned into the
+/// data of a search.
+enum BatchResults {
+    Passage((Book, Vec<Verse>)),
+    Regex((Vec<(VerseFTS, Book)>, i64)),
+    Words((Vec<(VerseFTS, Book)>, i64), ResultSet),
+}
+
+/// Runs a JSON list of searches at once (e.g. several word studies to
+/// compare), up to [MAX_SEARCHES] of them, in the user's translation.
+///
+/// Each search is either what to search for or an object with the same
+/// parameters as [search], except for `versions`. The results are keyed by
+/// query, without the links to their other pages or their facets, and the
+/// errors note the search they came from.
+pub async fn batch_search<SD>(
+    data: web::Data<ServerData>,
+    searches: web::Json<Vec<BatchSearch>>,
+    fields: web::Query<FieldsParams>,
+    req: HttpRequest,
+) -> ApiResult
+where
+    SD: VerseStore + Searcher,
+{
+    if searches.len() > MAX_SEARCHES {
+        let cause = format!("at most {} searches can be run at once", MAX_SEARCHES);
+        return Err(Error::InvalidSearch(cause).into());
+    }
+    let searches = searches
+        .into_inner()
+        .into_iter()
+        .map(|search| {
+            let params = SearchParams::from(search);
+            let options = match params.versions {
+                Some(_) => Err(Error::InvalidSearch(
+                    "a batch of searches can't search several translations".to_string(),
+                )),
+                None => params.options(),
+            };
+            match options {
+                Ok(options) => Ok((params, options)),
+                Err(e) => Err(JsonError::from(e).with_query(&params.q)),
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let version = translation_version(None, &req, &data);
+    let (translation, results) = web::block(move || {
+        let conn = data.read_db().get().unwrap();
+        let translation = SD::translation(&version, &conn).map_err(JsonError::from)?;
+        let results = searches
+            .into_iter()
+            .map(|(params, options)| {
+                match batch_results::<SD>(&params, &options, &translation, &conn) {
+                    Ok(results) => Ok((params.q, options, Some(results))),
+                    // Books that don't exist have no matches
+                    Err(DbError::BookNotFound { .. }) => Ok((params.q, options, None)),
+                    Err(e) => Err(JsonError::from(e).with_query(&params.q)),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok::<_, JsonError>((translation, results))
+    })
+    .await??;
+
+    let mut results_by_query = serde_json::Map::new();
+    for (q, options, results) in results {
+        let search_data = match results {
+            None => SearchResultData::empty(),
+            Some(BatchResults::Passage(passage)) => SearchResultData::from_verses(passage, &req),
+            Some(BatchResults::Regex(matches)) => {
+                SearchResultData::from_verses_fts(matches, SearchMode::Regex, &options, &req)
+            }
+            Some(BatchResults::Words(matches, result_set)) => {
+                SearchResultData::from_verses_fts(matches, SearchMode::of(&q), &options, &req)
+                    .with_result_set(result_set)
+            }
+        }
+        .with_translation(translation.to_owned())
+        .without_page_links();
+        results_by_query.insert(q, fields_value(&search_data, &fields));
+    }
+    Ok(HttpResponse::Ok().json(results_by_query))
+}
+
+/// Runs one of a batch of searches like [search] does.
+fn batch_results<SD>(
+    params: &SearchParams,
+    options: &SearchOptions,
+    translation: &Translation,
+    conn: &DbConnection,
+) -> Result<BatchResults, DbError>
+where
+    SD: VerseStore + Searcher,
+{
+    if params.mode == Some(SearchMode::Regex) {
+        let matches = SD::search_regex(&params.q, options, translation, conn)?;
+        Ok(BatchResults::Regex(matches))
+    } else if let Ok(reference) = params.q.parse::<Reference>() {
+        let passage = SD::verses(&reference, translation, VerseFormat::PlainText, conn)?;
+        Ok(BatchResults::Passage(passage))
     } else {
-        let results = web::block(move || SD::search(&query.q, &data.db.get().unwrap())).await??;
-        Ok(HttpResponse::Ok().json(SearchResultData::from_verses_fts(results, &req)))
+        let matches = SD::search(&params.q, options, translation, conn)?;
+        let result_set = SD::search_result_set(&params.q, options, translation, conn)?;
+        Ok(BatchResults::Words(matches, result_set))
     }
 }
 
+/// Exports the matches of a search, up to [EXPORT_LIMIT] of them, as a CSV
+/// or Markdown file.
+pub async fn export<SD>(
+    data: web::Data<ServerData>,
+    format: web::Path<String>,
+    query: web::Query<SearchParams>,
+    req: HttpRequest,
+) -> ApiResult
+where
+    SD: VerseStore + Searcher,
+{
+    let format = ExportFormat::from_extension(&format)
+        .ok_or_else(|| Error::InvalidSearch(format!("'{}' isn't a format to export in", format)))?;
+    let version = translation_version(None, &req, &data);
+    // Exports have all of the matches rather than a page of them
+    let mut options = query.options()?;
+    options.page = 1;
+    options.per_page = EXPORT_LIMIT;
+    let mode = match query.mode {
+        Some(SearchMode::Regex) => SearchMode::Regex,
+        _ => SearchMode::of(&query.q),
+    };
+
+    let (translation, results) = if mode == SearchMode::Regex {
+        let (translation, results) = web::block({
+            let options = options.clone();
+            move || {
+                let conn = data.read_db().get().unwrap();
+                let translation = SD::translation(&version, &conn)?;
+                let results = SD::search_regex(&query.q, &options, &translation, &conn)?;
+                Ok::<_, DbError>((translation, results))
+            }
+        })
+        .await??;
+        let results = SearchResultData::from_verses_fts(results, mode, &options, &req);
+        (translation, results)
+    } else if let Ok(reference) = query.q.parse::<Reference>() {
+        let (translation, results) = web::block(move || {
+            let conn = data.read_db().get().unwrap();
+            let translation = SD::translation(&version, &conn)?;
+            let results = SD::verses(&reference, &translation, VerseFormat::PlainText, &conn)?;
+            Ok::<_, DbError>((translation, results))
+        })
+        .await??;
+        (translation, SearchResultData::from_verses(results, &req))
+    } else {
+        let (translation, results) = web::block({
+            let options = options.clone();
+            move || {
+                let conn = data.read_db().get().unwrap();
+                let translation = SD::translation(&version, &conn)?;
+                let results = SD::search(&query.q, &options, &translation, &conn)?;
+                Ok::<_, DbError>((translation, results))
+            }
+        })
+        .await??;
+        let results = SearchResultData::from_verses_fts(results, mode, &options, &req);
+        (translation, results)
+    };
+
+    let body = format.export(&results.matches, Some(&translation.abbreviation));
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"search.{}\"", format.extension()),
+        ))
+        .body(body))
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::responder::{SearchResultData, VersesData};
-    use crate::test::json_response;
+    use std::collections::HashMap;
+
+    use actix_web::http::{header, Method};
+    use actix_web::test::TestRequest;
+    use db::models::SearchMode;
+
+    use crate::responder::{
+        BooksData, ChaptersData, CompareData, CompletionData, CrossReferencesData,
+        SearchCompletionData, SearchResultData, StatsData, TranslationsSearchResultData,
+        VerseOfTheDayData, VersesData,
+    };
+    use crate::test::{api_post, api_request, api_response, json_response};
+
+    #[test]
+    fn books() {
+        let result: BooksData = json_response("/api/v1/books.json");
+        assert_eq!(result.books.len(), 1);
+        assert_eq!(result.books[0].book.name, "Psalms");
+        assert_eq!(result.books[0].book.chapter_count, 150);
+        assert_eq!(result.books[0].abbreviations, vec!["ps"]);
+        assert_eq!(result.books[0].link.url, "/Psalms");
+    }
+
+    #[test]
+    fn chapters() {
+        let result: ChaptersData = json_response("/api/v1/psalms/chapters.json");
+        assert_eq!(result.book.name, "Psalms");
+        assert_eq!(result.chapters[0].chapter, 119);
+        assert_eq!(result.chapters[0].verse_count, 176);
+        assert_eq!(result.chapters[0].link.url, "/Psalms/119");
+    }
+
+    #[test]
+    fn stats() {
+        let result: StatsData = json_response("/api/v1/stats.json");
+        assert!(result.book.is_none());
+        assert_eq!(result.translation.abbreviation, "kjv");
+        assert_eq!(result.stats.verse_count, 1);
+
+        let result: serde_json::Value = json_response("/api/v1/psalms/stats.json");
+        assert_eq!(result["book"]["name"], "Psalms");
+        assert_eq!(result["word_count"], 15);
+        assert_eq!(result["longest_verse"]["verse"], 105);
+
+        let result: serde_json::Value = json_response("/api/v1/nope/stats.json");
+        assert_eq!(result["code"], "book_not_found");
+    }
+
+    #[test]
+    fn random() {
+        let (headers, body) = api_response("/api/v1/random.json?exclude_genealogies=true");
+        assert_eq!(headers[header::CACHE_CONTROL], "no-store");
+        let result: VersesData = serde_json::from_str(&body).unwrap();
+        assert_eq!(result.reference_string, "Psalms 119:105");
+
+        let result: serde_json::Value = json_response("/api/v1/random.json?testament=nt");
+        assert_eq!(
+            result["message"],
+            "The search couldn't be run because there are no verses in those books to pick from."
+        );
+    }
+
+    #[test]
+    fn votd() {
+        let result: VerseOfTheDayData = json_response("/api/v1/votd.json?date=2024-02-29");
+        assert_eq!(result.date, "2024-02-29");
+        assert_eq!(result.verses.reference_string, "Psalms 119:105");
+        assert_eq!(result.permalink, "https://bible.rs/Psalms/119/105");
+
+        let result: VerseOfTheDayData = json_response("/api/v1/votd.json");
+        assert_eq!(result.date.len(), 10);
+
+        let result: serde_json::Value = json_response("/api/v1/votd.json?date=2023-02-29");
+        assert_eq!(
+            result["message"],
+            "'2023-02-29' is not a valid date, which is written as YYYY-MM-DD."
+        );
+    }
+
+    #[test]
+    fn bulk_passages() {
+        let (_, body) = api_post(
+            "/api/v1/passages",
+            &["Psalms 119:105", "Psalms 119:105-106"],
+        );
+        let result: Vec<VersesData> = serde_json::from_str(&body).unwrap();
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].reference_string, "Psalms 119:105");
+        assert_eq!(result[1].reference_string, "Psalms 119:105-106");
+
+        let (_, body) = api_post("/api/v1/passages", &["Psalms 119:105", "nope"]);
+        let result: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["message"], "nope is not a valid Bible reference.");
+
+        let references = vec!["Psalms 119:105"; 101];
+        let (_, body) = api_post("/api/v1/passages", &references);
+        let result: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(
+            result["message"],
+            "The search couldn't be run because at most 100 references can be looked up at \
+             once."
+        );
+    }
+
+    #[test]
+    fn etags() {
+        let (headers, _) = api_response("/api/v1/psalms.119.105.json");
+        let etag = headers[header::ETAG].to_str().unwrap();
+
+        let req = TestRequest::with_uri("/api/v1/psalms.119.105.json")
+            .insert_header((header::IF_NONE_MATCH, etag));
+        let (headers, body) = api_request(req);
+        assert_eq!(headers[header::ETAG], etag);
+        assert!(body.is_empty());
+
+        let (headers, _) = api_response("/api/v1/psalms.119.105.json?fields=words");
+        assert_ne!(headers[header::ETAG], etag);
+
+        // Passages that aren't found don't match any tag
+        let req = TestRequest::with_uri("/api/v1/nope.1.json")
+            .insert_header((header::IF_NONE_MATCH, "*"));
+        let (headers, body) = api_request(req);
+        assert!(!headers.contains_key(header::ETAG));
+        let result: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["code"], "book_not_found");
+    }
+
+    #[test]
+    fn problem_details() {
+        let (headers, body) = api_response("/api/v1/nope.json");
+        assert_eq!(headers[header::CONTENT_TYPE], "application/problem+json");
+        let result: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["status"], 400);
+        assert_eq!(result["title"], "Bad Request");
+        assert_eq!(result["code"], "invalid_reference");
+        assert_eq!(result["detail"], "nope is not a valid Bible reference.");
+        assert_eq!(result["reference"], "nope");
+
+        let result: serde_json::Value = json_response("/api/v1/nope.1.json");
+        assert_eq!(result["status"], 404);
+        assert_eq!(result["code"], "book_not_found");
+        assert_eq!(result["book"], "nope");
+
+        let result: serde_json::Value = json_response("/api/v1/search?q=(&mode=regex");
+        assert_eq!(result["code"], "invalid_search");
+        assert_eq!(result["query"], "(");
+    }
+
+    #[test]
+    fn openapi() {
+        let result: serde_json::Value = json_response("/api/openapi.json");
+        assert_eq!(result["openapi"], "3.0.3");
+        assert!(result["paths"]["/api/v1/{reference}.json"].is_object());
+    }
+
+    #[test]
+    fn versions() {
+        let (headers, body) = api_response("/api/v1/psalms.119.105.json");
+        assert!(!headers.contains_key("Deprecation"));
+        let result: VersesData = serde_json::from_str(&body).unwrap();
+        assert_eq!(result.reference_string, "Psalms 119:105");
+
+        // Unversioned routes still work, but are deprecated
+        let (headers, _) = api_response("/api/psalms.119.105.json");
+        assert_eq!(headers["Deprecation"], "true");
+        assert_eq!(
+            headers[header::LINK],
+            "</api/v1>; rel=\"successor-version\""
+        );
+
+        let result: SearchResultData = json_response("/api/v1/search?q=word&page=2");
+        assert_eq!(
+            result.links.previous.unwrap().url,
+            "/api/v1/search?q=word&page=1"
+        );
+    }
 
     #[test]
     fn reference() {
@@ -71,15 +1166,236 @@ mod tests {
         );
     }
 
+    #[test]
+    fn cors() {
+        let req = TestRequest::with_uri("/api/v1/psalms.119.105.json")
+            .insert_header((header::ORIGIN, "https://example.com"));
+        let (headers, _) = api_request(req);
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://example.com"
+        );
+
+        let req = TestRequest::with_uri("/api/v1/psalms.119.105.json")
+            .insert_header((header::ORIGIN, "https://elsewhere.com"));
+        let (headers, _) = api_request(req);
+        assert!(headers.get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+
+        // Responses let apps read their tags, rate limits and links
+        let req = TestRequest::with_uri("/api/v1/psalms.119.105.json")
+            .insert_header((header::ORIGIN, "https://example.com"));
+        let (headers, _) = api_request(req);
+        let exposed = headers[header::ACCESS_CONTROL_EXPOSE_HEADERS]
+            .to_str()
+            .unwrap()
+            .to_lowercase();
+        for name in ["etag", "retry-after", "link"] {
+            assert!(exposed.contains(name), "{} isn't exposed", name);
+        }
+
+        // Preflights allow API keys and entity tags to be sent
+        let req = TestRequest::with_uri("/api/v1/psalms.119.105.json")
+            .method(Method::OPTIONS)
+            .insert_header((header::ORIGIN, "https://example.com"))
+            .insert_header((header::ACCESS_CONTROL_REQUEST_METHOD, "GET"))
+            .insert_header((
+                header::ACCESS_CONTROL_REQUEST_HEADERS,
+                "x-api-key, authorization, if-none-match",
+            ));
+        let (headers, _) = api_request(req);
+        let allowed = headers[header::ACCESS_CONTROL_ALLOW_HEADERS]
+            .to_str()
+            .unwrap()
+            .to_lowercase();
+        for name in ["x-api-key", "authorization", "if-none-match"] {
+            assert!(allowed.contains(name), "{} isn't allowed", name);
+        }
+    }
+
+    #[test]
+    fn sparse_fields() {
+        let result: serde_json::Value =
+            json_response("/api/v1/psalms.119.105.json?fields=verse,words");
+        assert_eq!(
+            result,
+            serde_json::json!({
+                "verses": [{
+                    "verse": 105,
+                    "words": "NUN. Thy word is a lamp unto my feet, and a light unto my path.",
+                }],
+            })
+        );
+
+        let result: serde_json::Value = json_response("/api/v1/search?q=word&fields=plain_text");
+        assert_eq!(
+            result["matches"][0],
+            serde_json::json!({
+                "plain_text": "NUN. Thy word is a lamp unto my feet, and a light unto my path.",
+            })
+        );
+        assert!(result.get("total").is_none());
+    }
+
+    #[test]
+    fn chapter_navigation() {
+        let result: VersesData = json_response("/api/v1/psalms.119.json");
+        let navigation = result.navigation.unwrap();
+        assert_eq!(navigation.previous.unwrap().url, "/api/v1/Psalms.118.json");
+        assert_eq!(navigation.next.unwrap().url, "/api/v1/Psalms.120.json");
+        assert!(!navigation.first_in_book);
+
+        let result: VersesData = json_response("/api/v1/kjv/psalms.150.json");
+        let navigation = result.navigation.unwrap();
+        let next = navigation.next.unwrap();
+        assert_eq!(next.book, "Proverbs");
+        assert_eq!(next.url, "/api/v1/kjv/Proverbs.1.json");
+        assert!(navigation.last_in_book);
+    }
+
+    #[test]
+    fn ndjson() {
+        let (headers, body) = api_response("/api/v1/psalms/full.ndjson");
+        assert_eq!(headers[header::CONTENT_TYPE], "application/x-ndjson");
+        let lines: Vec<serde_json::Value> = body
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        // A line for each chapter, which has the one test verse
+        assert_eq!(lines.len(), 150);
+        assert_eq!(lines[0]["book"], "Psalms");
+        assert_eq!(lines[0]["verse"], 105);
+
+        let (_, body) = api_response("/api/v1/full.ndjson");
+        assert_eq!(body.lines().count(), 150);
+
+        let result: serde_json::Value = json_response("/api/v1/nope/full.ndjson");
+        assert_eq!(result["code"], "book_not_found");
+    }
+
+    #[test]
+    fn verse_pages() {
+        let result: VersesData = json_response("/api/v1/psalms.119.json?limit=1");
+        assert_eq!(result.verses.len(), 1);
+        assert_eq!(result.total_verses, 1);
+
+        let result: VersesData = json_response("/api/v1/psalms.119.json?offset=1&limit=10");
+        assert!(result.verses.is_empty());
+        assert_eq!(result.total_verses, 1);
+
+        let result: Vec<VersesData> =
+            json_response("/api/v1/psalms.119.105;psalms.119.105.json?limit=0");
+        assert!(result
+            .iter()
+            .all(|p| p.verses.is_empty() && p.total_verses == 1));
+    }
+
+    #[test]
+    fn passage_links() {
+        let result: serde_json::Value = json_response("/api/v1/kjv/psalms.119.json");
+        assert_eq!(
+            result["_links"],
+            serde_json::json!({
+                "self": { "href": "/api/v1/kjv/Psalms.119.json" },
+                "book": { "href": "/api/v1/Psalms/chapters.json?version=kjv" },
+                "previous": { "href": "/api/v1/kjv/Psalms.118.json" },
+                "next": { "href": "/api/v1/kjv/Psalms.120.json" },
+                "html": { "href": "/kjv/Psalms/119" },
+            })
+        );
+
+        let result: VersesData = json_response("/api/v1/psalms.119.105.json");
+        let links = result.api_links.unwrap();
+        assert_eq!(links.current.href, "/api/v1/Psalms.119.105.json");
+        assert_eq!(links.book.href, "/api/v1/Psalms/chapters.json");
+    }
+
+    #[test]
+    fn multiple_references() {
+        let result: Vec<VersesData> = json_response("/api/psalms.119.105;psalms.119.105.json");
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[1].reference_string, "Psalms 119:105");
+    }
+
+    #[test]
+    fn reference_with_version() {
+        let result: VersesData = json_response("/api/kjv/psalms.119.105.json");
+        assert_eq!(result.translation.abbreviation, "kjv");
+        assert_eq!(result.links.current.url, "/kjv/Psalms/119/105");
+    }
+
+    #[test]
+    fn compare() {
+        let result: CompareData = json_response("/api/compare/psalms.119.105.json");
+        assert_eq!(result.reference_string, "Psalms 119:105");
+        assert_eq!(result.translations[0].translation.abbreviation, "kjv");
+        assert_eq!(
+            result.translations[0].verses[0].words,
+            "NUN. Thy word is a lamp unto my feet, and a light unto my path."
+        );
+    }
+
+    #[test]
+    fn context() {
+        let result: ContextData = json_response("/api/v1/context/psalms.119.105.json?before=3");
+        assert_eq!(result.reference_string, "Psalms 119:105");
+        assert_eq!(result.before, 0);
+        assert_eq!(result.after, 0);
+        assert_eq!(result.context.verses[0].verse, 105);
+
+        let result: serde_json::Value = json_response("/api/v1/context/nope.json");
+        assert_eq!(result["code"], "invalid_reference");
+    }
+
+    #[test]
+    fn cross_references() {
+        let result: CrossReferencesData = json_response("/api/v1/crossrefs/psalms.119.105.json");
+        assert_eq!(result.reference_string, "Psalms 119:105");
+        let cross_reference = &result.cross_references[0];
+        assert_eq!((cross_reference.chapter, cross_reference.verse), (119, 105));
+        assert_eq!(cross_reference.reference_string, "Psalms 19:8");
+        assert_eq!(cross_reference.link.url, "/Psalms/19/8");
+
+        let result: serde_json::Value = json_response("/api/v1/crossrefs/nope.json");
+        assert_eq!(result["code"], "invalid_reference");
+    }
+
+    #[test]
+    fn complete() {
+        let result: CompletionData = json_response("/api/complete?q=ps");
+        assert_eq!(result.completions[0].url, "/Psalms");
+
+        let result: CompletionData = json_response("/api/complete?q=ps%2011");
+        let urls: Vec<&str> = result.completions.iter().map(|l| l.url.as_str()).collect();
+        assert_eq!(urls[..3], ["/Psalms/11", "/Psalms/110", "/Psalms/111"]);
+        assert_eq!(urls.len(), 10);
+
+        let result: CompletionData = json_response("/api/complete?q=mat");
+        assert!(result.completions.is_empty());
+    }
+
     #[test]
     fn search() {
         // By words
         let result: SearchResultData = json_response("/api/search?q=word");
         assert_eq!(
             result.matches[0].text,
-            "NUN. Thy word is a lamp unto my feet, and a <em>light</em> unto my path."
+            "NUN. Thy word is a lamp unto my feet, and a <mark>light</mark> unto my path."
         );
         assert_eq!(result.matches[0].link.url, "/Psalms/119#v105");
+        assert_eq!(result.matches[0].highlights, vec![[44, 49]]);
+        assert_eq!(result.mode, SearchMode::Words);
+
+        // By phrase
+        let result: SearchResultData = json_response("/api/search?q=%22a%20lamp%22");
+        assert_eq!(result.mode, SearchMode::Phrase);
+
+        // With operators
+        let result: SearchResultData = json_response("/api/search?q=lamp%20OR%20light");
+        assert_eq!(result.mode, SearchMode::Boolean);
+
+        // By proximity
+        let result: SearchResultData = json_response("/api/search?q=NEAR(lamp%20light,%205)");
+        assert_eq!(result.mode, SearchMode::Proximity);
 
         // By reference
         let result: SearchResultData = json_response("/api/search?q=psalms%20119:105");
@@ -88,5 +1404,217 @@ mod tests {
             "NUN. Thy word is a lamp unto my feet, and a light unto my path."
         );
         assert_eq!(result.matches[0].link.url, "/Psalms/119#v105");
+        assert_eq!(result.mode, SearchMode::Reference);
+    }
+
+    #[test]
+    fn batch_search() {
+        let searches = serde_json::json!([
+            "word",
+            {"q": "light", "testament": "nt"},
+            {"q": "l.ght", "mode": "regex"},
+            "psalms 119:105",
+        ]);
+        let (_, body) = api_post("/api/v1/search/batch", &searches);
+        let result: HashMap<String, SearchResultData> = serde_json::from_str(&body).unwrap();
+        assert_eq!(result.len(), 4);
+        assert_eq!(result["word"].total, 1);
+        assert_eq!(result["word"].mode, SearchMode::Words);
+        assert!(result["word"].translation.is_some());
+        assert_eq!(result["light"].total, 0);
+        assert_eq!(result["l.ght"].mode, SearchMode::Regex);
+        assert_eq!(result["psalms 119:105"].mode, SearchMode::Reference);
+
+        let searches = serde_json::json!([{"q": "light", "versions": "all"}]);
+        let (_, body) = api_post("/api/v1/search/batch", &searches);
+        let result: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["code"], "invalid_search");
+        assert_eq!(result["query"], "light");
+
+        let searches = vec!["word"; 21];
+        let (_, body) = api_post("/api/v1/search/batch", &searches);
+        let result: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(result["code"], "invalid_search");
+    }
+
+    #[test]
+    fn search_regex() {
+        let result: SearchResultData = json_response("/api/search?q=l.ght&mode=regex");
+        assert_eq!(result.mode, SearchMode::Regex);
+        assert_eq!(result.matches.len(), 1);
+
+        let result: serde_json::Value = json_response("/api/search?q=(&mode=regex");
+        assert_eq!(
+            result["message"],
+            "The search couldn't be run because the pattern has an unclosed group."
+        );
+
+        // Regular expressions have to be asked for
+        let result: SearchResultData = json_response("/api/search?q=l.ght");
+        assert_eq!(result.mode, SearchMode::Words);
+    }
+
+    #[test]
+    fn search_scope() {
+        let result: SearchResultData = json_response("/api/search?q=word&books=Psalms,%20psalms");
+        assert_eq!(result.matches.len(), 1);
+
+        let result: SearchResultData =
+            json_response("/api/search?q=word&testament=ot&order=canonical");
+        assert_eq!(result.matches.len(), 1);
+
+        let result: SearchResultData = json_response("/api/search?q=word&testament=NT");
+        assert!(result.matches.is_empty());
+
+        let result: SearchResultData = json_response("/api/search?q=word&books=Nope");
+        assert!(result.matches.is_empty());
+
+        let result: serde_json::Value = json_response("/api/search?q=word&testament=apocrypha");
+        assert_eq!(
+            result["message"],
+            "The search couldn't be run because the testament has to be 'ot' or 'nt'."
+        );
+
+        let result: serde_json::Value = json_response("/api/search?q=word&range=Psalms-");
+        assert_eq!(
+            result["message"],
+            "The search couldn't be run because 'Psalms-' is not a range of books."
+        );
+    }
+
+    #[test]
+    fn search_facets() {
+        let result: SearchResultData = json_response("/api/search?q=word&page=2");
+        let facets = result.facets.unwrap();
+        assert_eq!(facets.books.len(), 1);
+        assert_eq!(facets.books[0].link.label, "Psalms");
+        assert_eq!(
+            facets.books[0].link.url,
+            "/api/search?q=word&books=Psalms&page=1"
+        );
+        assert_eq!(facets.books[0].count, 1);
+        assert_eq!(facets.testaments[0].link.label, "Old Testament");
+
+        let result: SearchResultData = json_response("/api/search?q=psalms%20119:105");
+        assert!(result.facets.is_none());
+    }
+
+    #[test]
+    fn search_suggestion() {
+        let result: SearchResultData = json_response("/api/search?q=lihgt&testament=nt");
+        assert_eq!(result.total, 0);
+        let suggestion = result.suggestion.unwrap();
+        assert_eq!(suggestion.label, "light");
+        assert_eq!(suggestion.url, "/api/search?testament=nt&q=light&page=1");
+
+        let result: SearchResultData = json_response("/api/search?q=light&testament=nt");
+        assert!(result.suggestion.is_none());
+
+        let result: SearchResultData = json_response("/api/search?q=lihgt");
+        assert!(result.suggestion.is_none());
+    }
+
+    #[test]
+    fn search_translations() {
+        let result: TranslationsSearchResultData = json_response("/api/search?q=word&versions=all");
+        assert_eq!(result.total, 1);
+        assert_eq!(result.translations[0].abbreviation, "kjv");
+        assert_eq!(result.groups[0].link.url, "/Psalms/119#v105");
+        let kjv = &result.groups[0].matches[0];
+        assert_eq!(kjv.translation, "kjv");
+        assert_eq!(kjv.result.link.url, "/kjv/Psalms/119#v105");
+        assert_eq!(kjv.result.highlights, vec![[44, 49]]);
+
+        let result: TranslationsSearchResultData =
+            json_response("/api/search?q=word&versions=kjv,%20KJV&page=2");
+        assert_eq!(result.translations.len(), 2);
+        assert!(result.groups.is_empty());
+    }
+
+    #[test]
+    fn search_within() {
+        let result: SearchResultData = json_response("/api/search?q=word");
+        let results = result.results.unwrap();
+
+        let uri = format!("/api/search?q=lamp&within=previous&results={}", results);
+        let result: SearchResultData = json_response(&uri);
+        assert_eq!(result.total, 1);
+        assert_eq!(result.results.unwrap(), results);
+        // Pages keep searching within the results
+        assert!(result.links.next.is_none());
+
+        // The test verse isn't in these results
+        let result: SearchResultData =
+            json_response("/api/search?q=lamp&within=previous&results=AgA");
+        assert_eq!(result.total, 0);
+        assert!(result.results.is_none());
+    }
+
+    #[test]
+    fn suggest() {
+        let result: SearchCompletionData = json_response("/api/search/suggest?q=LI");
+        assert_eq!(result.words, vec!["light"]);
+        assert_eq!(result.phrases, vec!["light unto"]);
+
+        let result: SearchCompletionData = json_response("/api/search/suggest?q=lamp");
+        assert!(result.words.is_empty());
+        assert!(result.phrases.is_empty());
+    }
+
+    #[test]
+    fn search_pages() {
+        let result: SearchResultData = json_response("/api/search?q=word&per_page=1000");
+        assert_eq!(result.total, 1);
+        assert_eq!(result.page, 1);
+        assert!(result.links.previous.is_none());
+        assert!(result.links.next.is_none());
+
+        let result: SearchResultData = json_response("/api/search?q=word&page=2&version=kjv");
+        assert!(result.matches.is_empty());
+        assert_eq!(result.total, 1);
+        assert_eq!(result.page, 2);
+        assert_eq!(
+            result.links.previous.unwrap().url,
+            "/api/search?q=word&version=kjv&page=1"
+        );
+        assert!(result.links.next.is_none());
+    }
+
+    #[test]
+    fn export() {
+        // Exports have all of the matches, whatever the page
+        let (headers, body) = api_response("/api/v1/search.csv?q=word&page=2");
+        assert_eq!(headers[header::CONTENT_TYPE], "text/csv; charset=utf-8");
+        assert_eq!(
+            headers[header::CONTENT_DISPOSITION],
+            "attachment; filename=\"search.csv\""
+        );
+        assert_eq!(
+            body,
+            "reference,translation,text\r\nPsalms 119:105,KJV,\"NUN. Thy word is a lamp unto my \
+             feet, and a light unto my path.\"\r\n"
+        );
+
+        let (headers, body) = api_response("/api/v1/search.md?q=psalms+119:105");
+        assert_eq!(
+            headers[header::CONTENT_TYPE],
+            "text/markdown; charset=utf-8"
+        );
+        assert_eq!(
+            body,
+            "- **Psalms 119:105** (KJV) NUN. Thy word is a lamp unto my feet, and a light unto \
+             my path.\n"
+        );
+    }
+
+    #[test]
+    fn search_with_version() {
+        let result: SearchResultData = json_response("/api/search?q=word&version=KJV");
+        assert_eq!(result.matches.len(), 1);
+        assert_eq!(result.translation.unwrap().direction, "ltr");
+
+        // Unknown translations fall back to the default
+        let result: SearchResultData = json_response("/api/search?q=word&version=nope");
+        assert_eq!(result.matches.len(), 1);
     }
 }
@@ -0,0 +1,532 @@
+use std::marker::PhantomData;
+
+use actix_web::{web, HttpResponse};
+use async_graphql::http::{playground_source, GraphQLPlaygroundConfig};
+use async_graphql::{Context, EmptyMutation, EmptySubscription, Object, Schema};
+use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse};
+
+use db::models::{Book, Reference, SearchOptions, Testament, Translation, Verse};
+use db::{BookStore, DbConnection, DbError, Searcher, VerseFormat, VerseStore};
+
+use crate::controllers::passage;
+use crate::ServerData;
+
+/// Max number of matches on a page of search results.
+const MAX_PER_PAGE: i32 = 100;
+
+/// Max depth of the fields of a query.
+const MAX_DEPTH: usize = 8;
+
+/// Schema of the GraphQL API, looking up verses with `SD`.
+pub type BibleSchema<SD> = Schema<Query<SD>, EmptyMutation, EmptySubscription>;
+
+/// Builds the schema of the GraphQL API.
+pub fn schema<SD>() -> BibleSchema<SD>
+where
+    SD: VerseStore + Searcher + 'static,
+{
+    Schema::build(Query(PhantomData), EmptyMutation, EmptySubscription)
+        .limit_depth(MAX_DEPTH)
+        .finish()
+}
+
+/// Runs a GraphQL query, so clients can fetch the books, chapters, verses
+/// and searches they need in the shape they need them.
+pub async fn graphql<SD>(
+    data: web::Data<ServerData>,
+    schema: web::Data<BibleSchema<SD>>,
+    req: GraphQLRequest,
+) -> GraphQLResponse
+where
+    SD: VerseStore + Searcher + 'static,
+{
+    schema.execute(req.into_inner().data(data)).await.into()
+}
+
+/// Serves a playground for trying out queries in the browser.
+pub async fn playground() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(playground_source(GraphQLPlaygroundConfig::new("/graphql")))
+}
+
+/// Runs a database lookup for a resolver on the thread pool for blocking
+/// work.
+async fn lookup<T, F>(ctx: &Context<'_>, f: F) -> async_graphql::Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&ServerData, &DbConnection) -> Result<T, DbError> + Send + 'static,
+{
+    let data = ctx.data::<web::Data<ServerData>>()?.clone();
+    let result = web::block(move || f(data.get_ref(), &*data.read_db().get().unwrap())).await?;
+    Ok(result?)
+}
+
+/// Looks up the translation a query asked for, or the default one.
+fn translation<SD>(
+    version: Option<&str>,
+    data: &ServerData,
+    conn: &DbConnection,
+) -> Result<Translation, DbError>
+where
+    SD: BookStore,
+{
+    SD::translation(version.unwrap_or(&data.default_translation), conn)
+}
+
+/// Root of the queries of the GraphQL API.
+pub struct Query<SD>(PhantomData<fn() -> SD>);
+
+#[Object]
+impl<SD> Query<SD>
+where
+    SD: VerseStore + Searcher + 'static,
+{
+    /// Translations installed on the site.
+    async fn translations(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<TranslationNode>> {
+        let data = ctx.data::<web::Data<ServerData>>()?;
+        Ok(data
+            .translations
+            .iter()
+            .cloned()
+            .map(TranslationNode)
+            .collect())
+    }
+
+    /// Books of a translation, in canonical order.
+    async fn books(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(desc = "Abbreviation of the translation, if not the default one.")]
+        version: Option<String>,
+    ) -> async_graphql::Result<Vec<BookNode<SD>>> {
+        lookup(ctx, move |data, conn| {
+            let translation = translation::<SD>(version.as_deref(), data, conn)?;
+            let books = SD::all_books(&translation, conn)?;
+            Ok(books
+                .into_iter()
+                .map(|book| BookNode::new(book, translation.to_owned()))
+                .collect())
+        })
+        .await
+    }
+
+    /// A book of a translation, by its name or an abbreviation.
+    async fn book(
+        &self,
+        ctx: &Context<'_>,
+        name: String,
+        #[graphql(desc = "Abbreviation of the translation, if not the default one.")]
+        version: Option<String>,
+    ) -> async_graphql::Result<BookNode<SD>> {
+        lookup(ctx, move |data, conn| {
+            let translation = translation::<SD>(version.as_deref(), data, conn)?;
+            let (book, _) = SD::book(&name, &translation, conn)?;
+            Ok(BookNode::new(book, translation))
+        })
+        .await
+    }
+
+    /// Verses of a reference (e.g. `Psalms 119:105-112`).
+    async fn passage(
+        &self,
+        ctx: &Context<'_>,
+        reference: String,
+        #[graphql(desc = "Abbreviation of the translation, if not the default one.")]
+        version: Option<String>,
+    ) -> async_graphql::Result<PassageNode<SD>> {
+        let mut reference: Reference = reference
+            .parse()
+            .map_err(|_| format!("{} is not a valid Bible reference.", reference))?;
+        lookup(ctx, move |data, conn| {
+            let version = version.unwrap_or_else(|| data.default_translation.to_owned());
+            let (translation, fallback, (book, verses)) = passage::<SD>(
+                &reference,
+                &version,
+                data.fallback_translation.as_deref(),
+                VerseFormat::PlainText,
+                conn,
+            )?;
+            reference.book = book.name.to_owned();
+            Ok(PassageNode {
+                reference: reference.to_string(),
+                book: BookNode::new(book, translation.to_owned()),
+                translation: TranslationNode(translation),
+                fallback: fallback.map(TranslationNode),
+                verses: verses.into_iter().map(VerseNode).collect(),
+            })
+        })
+        .await
+    }
+
+    /// Verses matching a full text search, in order of relevance.
+    async fn search(
+        &self,
+        ctx: &Context<'_>,
+        query: String,
+        #[graphql(desc = "Abbreviation of the translation, if not the default one.")]
+        version: Option<String>,
+        #[graphql(desc = "Page of matches to get, starting at 1.", default = 1)] page: i32,
+        #[graphql(desc = "Number of matches on a page, up to 100.", default = 15)] per_page: i32,
+    ) -> async_graphql::Result<SearchNode> {
+        let options = SearchOptions {
+            page: page.max(1).into(),
+            per_page: per_page.clamp(1, MAX_PER_PAGE).into(),
+            ..SearchOptions::default()
+        };
+        lookup(ctx, move |data, conn| {
+            let translation = translation::<SD>(version.as_deref(), data, conn)?;
+            let (matches, total) = SD::search(&query, &options, &translation, conn)?;
+            Ok(SearchNode {
+                total,
+                matches: matches
+                    .into_iter()
+                    .map(|(verse, book)| SearchMatchNode {
+                        reference: format!("{} {}:{}", book.name, verse.chapter, verse.verse),
+                        book: book.name,
+                        chapter: verse.chapter,
+                        verse: verse.verse,
+                        highlighted: verse.words,
+                    })
+                    .collect(),
+            })
+        })
+        .await
+    }
+}
+
+/// A translation installed on the site.
+pub struct TranslationNode(Translation);
+
+#[Object(name = "Translation")]
+impl TranslationNode {
+    /// Abbreviation of the translation (e.g. `kjv`).
+    async fn abbreviation(&self) -> &str {
+        &self.0.abbreviation
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    /// Language of the translation, as a BCP 47 tag.
+    async fn language(&self) -> &str {
+        &self.0.language
+    }
+
+    /// Direction the text is written in, `ltr` or `rtl`.
+    async fn direction(&self) -> &str {
+        &self.0.direction
+    }
+
+    async fn license(&self) -> Option<&str> {
+        self.0.license.as_deref()
+    }
+
+    async fn copyright(&self) -> Option<&str> {
+        self.0.copyright.as_deref()
+    }
+
+    async fn attribution(&self) -> Option<&str> {
+        self.0.attribution.as_deref()
+    }
+}
+
+/// A book of the Bible in a translation.
+pub struct BookNode<SD> {
+    book: Book,
+    translation: Translation,
+    drill: PhantomData<fn() -> SD>,
+}
+
+impl<SD> BookNode<SD> {
+    fn new(book: Book, translation: Translation) -> Self {
+        Self {
+            book,
+            translation,
+            drill: PhantomData,
+        }
+    }
+
+    fn chapter(&self, number: i32, verse_count: i64) -> ChapterNode<SD> {
+        ChapterNode {
+            book: self.book.name.to_owned(),
+            number,
+            verse_count,
+            translation: self.translation.to_owned(),
+            drill: PhantomData,
+        }
+    }
+}
+
+#[Object(name = "Book")]
+impl<SD> BookNode<SD>
+where
+    SD: VerseStore + 'static,
+{
+    async fn name(&self) -> &str {
+        &self.book.name
+    }
+
+    /// Testament of the book, `OLD` or `NEW`.
+    async fn testament(&self) -> &str {
+        match self.book.testament {
+            Testament::Old => "OLD",
+            Testament::New => "NEW",
+        }
+    }
+
+    async fn chapter_count(&self) -> i32 {
+        self.book.chapter_count
+    }
+
+    async fn deuterocanonical(&self) -> bool {
+        self.book.deuterocanonical
+    }
+
+    /// Chapters of the book in the translation.
+    async fn chapters(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<ChapterNode<SD>>> {
+        let name = self.book.name.to_owned();
+        let translation = self.translation.to_owned();
+        let (_, chapters) =
+            lookup(ctx, move |_, conn| SD::chapters(&name, &translation, conn)).await?;
+        Ok(chapters
+            .into_iter()
+            .map(|(number, verse_count)| self.chapter(number, verse_count))
+            .collect())
+    }
+
+    /// A chapter of the book, if it's in the translation.
+    async fn chapter(
+        &self,
+        ctx: &Context<'_>,
+        number: i32,
+    ) -> async_graphql::Result<Option<ChapterNode<SD>>> {
+        let name = self.book.name.to_owned();
+        let translation = self.translation.to_owned();
+        let (_, chapters) =
+            lookup(ctx, move |_, conn| SD::chapters(&name, &translation, conn)).await?;
+        Ok(chapters
+            .into_iter()
+            .find(|(n, _)| *n == number)
+            .map(|(number, verse_count)| self.chapter(number, verse_count)))
+    }
+}
+
+/// A chapter of a book in a translation.
+pub struct ChapterNode<SD> {
+    book: String,
+    number: i32,
+    verse_count: i64,
+    translation: Translation,
+    drill: PhantomData<fn() -> SD>,
+}
+
+#[Object(name = "Chapter")]
+impl<SD> ChapterNode<SD>
+where
+    SD: VerseStore + 'static,
+{
+    async fn number(&self) -> i32 {
+        self.number
+    }
+
+    /// Number of verses in the chapter.
+    async fn verse_count(&self) -> i64 {
+        self.verse_count
+    }
+
+    /// Verses of the chapter, in order.
+    async fn verses(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<VerseNode>> {
+        let reference: Reference = format!("{} {}", self.book, self.number).parse()?;
+        let translation = self.translation.to_owned();
+        let (_, verses) = lookup(ctx, move |_, conn| {
+            SD::verses(&reference, &translation, VerseFormat::PlainText, conn)
+        })
+        .await?;
+        Ok(verses.into_iter().map(VerseNode).collect())
+    }
+}
+
+/// The verses of a reference.
+pub struct PassageNode<SD> {
+    reference: String,
+    book: BookNode<SD>,
+    translation: TranslationNode,
+    fallback: Option<TranslationNode>,
+    verses: Vec<VerseNode>,
+}
+
+#[Object(name = "Passage")]
+impl<SD> PassageNode<SD>
+where
+    SD: VerseStore + 'static,
+{
+    /// Reference as it's written (e.g. `Psalms 119:105`).
+    async fn reference(&self) -> &str {
+        &self.reference
+    }
+
+    async fn book(&self) -> &BookNode<SD> {
+        &self.book
+    }
+
+    async fn translation(&self) -> &TranslationNode {
+        &self.translation
+    }
+
+    /// Translation that filled verses omitted by `translation`, if any were.
+    async fn fallback(&self) -> Option<&TranslationNode> {
+        self.fallback.as_ref()
+    }
+
+    /// Verses of the reference, in order.
+    async fn verses(&self) -> &[VerseNode] {
+        &self.verses
+    }
+}
+
+/// A verse of a passage.
+pub struct VerseNode(Verse);
+
+#[Object(name = "Verse")]
+impl VerseNode {
+    async fn chapter(&self) -> i32 {
+        self.0.chapter
+    }
+
+    async fn verse(&self) -> i32 {
+        self.0.verse
+    }
+
+    /// Plain text of the verse.
+    async fn text(&self) -> &str {
+        &self.0.words
+    }
+}
+
+/// A page of the verses matching a search.
+pub struct SearchNode {
+    total: i64,
+    matches: Vec<SearchMatchNode>,
+}
+
+#[Object(name = "SearchResults")]
+impl SearchNode {
+    /// Number of matches on all pages.
+    async fn total(&self) -> i64 {
+        self.total
+    }
+
+    async fn matches(&self) -> &[SearchMatchNode] {
+        &self.matches
+    }
+}
+
+/// A verse matching a search.
+pub struct SearchMatchNode {
+    reference: String,
+    book: String,
+    chapter: i32,
+    verse: i32,
+    highlighted: String,
+}
+
+#[Object(name = "SearchMatch")]
+impl SearchMatchNode {
+    /// Reference of the verse (e.g. `Psalms 119:105`).
+    async fn reference(&self) -> &str {
+        &self.reference
+    }
+
+    async fn book(&self) -> &str {
+        &self.book
+    }
+
+    async fn chapter(&self) -> i32 {
+        self.chapter
+    }
+
+    async fn verse(&self) -> i32 {
+        self.verse
+    }
+
+    /// Plain text of the verse.
+    async fn text(&self) -> String {
+        self.highlighted
+            .replace("<mark>", "")
+            .replace("</mark>", "")
+    }
+
+    /// Text of the verse with the matched words wrapped in `<mark>` tags.
+    async fn highlighted(&self) -> &str {
+        &self.highlighted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use crate::test::{api_post, api_response};
+
+    fn query(query: &str) -> Value {
+        let (_, body) = api_post("/graphql", &json!({ "query": query }));
+        serde_json::from_str(&body).unwrap()
+    }
+
+    #[test]
+    fn passage() {
+        let result = query(
+            r#"{ passage(reference: "Psalms 119:105") { reference verses { verse text } } }"#,
+        );
+        let passage = &result["data"]["passage"];
+        assert_eq!(passage["reference"], "Psalms 119:105");
+        assert_eq!(passage["verses"][0]["verse"], 105);
+        // Only the fields that were asked for are given
+        assert!(passage["verses"][0]["chapter"].is_null());
+
+        let result = query(r#"{ passage(reference: "nope") { reference } }"#);
+        assert_eq!(
+            result["errors"][0]["message"],
+            "nope is not a valid Bible reference."
+        );
+    }
+
+    #[test]
+    fn books() {
+        let result = query("{ books { name chapters { number verseCount } } }");
+        let book = &result["data"]["books"][0];
+        assert_eq!(book["name"], "Psalms");
+        assert_eq!(
+            book["chapters"],
+            json!([{ "number": 119, "verseCount": 176 }])
+        );
+
+        let result =
+            query(r#"{ book(name: "psalms") { chapter(number: 119) { verses { text } } } }"#);
+        let verses = &result["data"]["book"]["chapter"]["verses"];
+        assert_eq!(
+            verses[0]["text"],
+            "NUN. Thy word is a lamp unto my feet, and a light unto my path."
+        );
+    }
+
+    #[test]
+    fn search() {
+        let result = query(r#"{ search(query: "light") { total matches { reference text } } }"#);
+        let search = &result["data"]["search"];
+        assert_eq!(search["total"], 1);
+        assert_eq!(search["matches"][0]["reference"], "Psalms 119:105");
+        assert_eq!(
+            search["matches"][0]["text"],
+            "NUN. Thy word is a lamp unto my feet, and a light unto my path."
+        );
+    }
+
+    #[test]
+    fn playground() {
+        let (_, body) = api_response("/graphql");
+        assert!(body.contains("GraphQL Playground"));
+    }
+}
@@ -0,0 +1,286 @@
+use std::marker::PhantomData;
+
+use actix_web::web;
+use tonic::{Request, Response, Status};
+
+use db::models::{Book, Reference, SearchOptions, Testament, Translation};
+use db::{BookStore, DbConnection, DbError, Searcher, VerseFormat, VerseStore};
+
+use crate::controllers::passage;
+use crate::ServerData;
+
+use self::proto::bible_server::{Bible, BibleServer};
+
+/// Messages and service generated from `proto/bible.proto`.
+pub mod proto {
+    tonic::include_proto!("bible");
+}
+
+/// Max number of matches on a page of search results.
+const MAX_PER_PAGE: i64 = 100;
+
+/// Address the gRPC server listens on if `GRPC_ADDR` isn't set.
+pub const DEFAULT_ADDR: &str = "0.0.0.0:50051";
+
+/// Builds the gRPC service, looking up verses with `SD`.
+pub fn service<SD>(data: web::Data<ServerData>) -> BibleServer<BibleService<SD>>
+where
+    SD: VerseStore + Searcher + 'static,
+{
+    BibleServer::new(BibleService {
+        data,
+        drill: PhantomData,
+    })
+}
+
+/// gRPC service for backends that look up verses and search without
+/// going through the JSON API.
+pub struct BibleService<SD> {
+    data: web::Data<ServerData>,
+    drill: PhantomData<fn() -> SD>,
+}
+
+impl<SD> BibleService<SD>
+where
+    SD: VerseStore + Searcher,
+{
+    /// Runs a database lookup on the thread pool for blocking work.
+    async fn lookup<T, F>(&self, f: F) -> Result<T, Status>
+    where
+        T: Send + 'static,
+        F: FnOnce(&ServerData, &DbConnection) -> Result<T, DbError> + Send + 'static,
+    {
+        let data = self.data.clone();
+        web::block(move || f(data.get_ref(), &*data.read_db().get().unwrap()))
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?
+            .map_err(status)
+    }
+}
+
+/// Looks up the translation with the given abbreviation, or the default one
+/// if it's empty.
+fn translation<SD>(
+    version: &str,
+    data: &ServerData,
+    conn: &DbConnection,
+) -> Result<Translation, DbError>
+where
+    SD: BookStore,
+{
+    if version.is_empty() {
+        SD::translation(&data.default_translation, conn)
+    } else {
+        SD::translation(version, conn)
+    }
+}
+
+#[tonic::async_trait]
+impl<SD> Bible for BibleService<SD>
+where
+    SD: VerseStore + Searcher + 'static,
+{
+    async fn get_passage(
+        &self,
+        request: Request<proto::PassageRequest>,
+    ) -> Result<Response<proto::Passage>, Status> {
+        let proto::PassageRequest { reference, version } = request.into_inner();
+        let mut reference: Reference = reference.parse().map_err(status)?;
+        let passage = self
+            .lookup(move |data, conn| {
+                let version = if version.is_empty() {
+                    data.default_translation.to_owned()
+                } else {
+                    version
+                };
+                let (translation, fallback, (book, verses)) = passage::<SD>(
+                    &reference,
+                    &version,
+                    data.fallback_translation.as_deref(),
+                    VerseFormat::PlainText,
+                    conn,
+                )?;
+                reference.book = book.name.to_owned();
+                let translation_of = |id: i32| match &fallback {
+                    Some(fallback) if fallback.id == id => fallback.abbreviation.to_owned(),
+                    _ => translation.abbreviation.to_owned(),
+                };
+                Ok(proto::Passage {
+                    reference: reference.to_string(),
+                    verses: verses
+                        .into_iter()
+                        .map(|v| proto::Verse {
+                            chapter: v.chapter,
+                            verse: v.verse,
+                            translation: translation_of(v.translation_id),
+                            text: v.words,
+                        })
+                        .collect(),
+                    book: Some(book.into()),
+                    translation: Some(translation.into()),
+                })
+            })
+            .await?;
+        Ok(Response::new(passage))
+    }
+
+    async fn list_books(
+        &self,
+        request: Request<proto::BooksRequest>,
+    ) -> Result<Response<proto::BookList>, Status> {
+        let version = request.into_inner().version;
+        let books = self
+            .lookup(move |data, conn| {
+                let translation = translation::<SD>(&version, data, conn)?;
+                SD::all_books(&translation, conn)
+            })
+            .await?;
+        Ok(Response::new(proto::BookList {
+            books: books.into_iter().map(proto::Book::from).collect(),
+        }))
+    }
+
+    async fn search(
+        &self,
+        request: Request<proto::SearchRequest>,
+    ) -> Result<Response<proto::SearchResponse>, Status> {
+        let proto::SearchRequest {
+            query,
+            version,
+            page,
+            per_page,
+        } = request.into_inner();
+        let options = SearchOptions {
+            page: page.max(1),
+            per_page: match per_page {
+                0 => SearchOptions::PER_PAGE,
+                n => n.clamp(1, MAX_PER_PAGE),
+            },
+            ..SearchOptions::default()
+        };
+        let (matches, total) = self
+            .lookup(move |data, conn| {
+                let translation = translation::<SD>(&version, data, conn)?;
+                SD::search(&query, &options, &translation, conn)
+            })
+            .await?;
+        Ok(Response::new(proto::SearchResponse {
+            total,
+            matches: matches
+                .into_iter()
+                .map(|(verse, book)| proto::SearchMatch {
+                    reference: format!("{} {}:{}", book.name, verse.chapter, verse.verse),
+                    book: Some(book.into()),
+                    chapter: verse.chapter,
+                    verse: verse.verse,
+                    text: verse.words,
+                })
+                .collect(),
+        }))
+    }
+}
+
+impl From<Book> for proto::Book {
+    fn from(book: Book) -> Self {
+        let testament = match book.testament {
+            Testament::Old => proto::Testament::Old,
+            Testament::New => proto::Testament::New,
+        };
+        Self {
+            id: book.id,
+            name: book.name,
+            chapter_count: book.chapter_count,
+            testament: testament.into(),
+            deuterocanonical: book.deuterocanonical,
+        }
+    }
+}
+
+impl From<Translation> for proto::Translation {
+    fn from(translation: Translation) -> Self {
+        Self {
+            abbreviation: translation.abbreviation,
+            name: translation.name,
+            language: translation.language,
+        }
+    }
+}
+
+/// Gets the gRPC status for a database error, matching the HTTP status the
+/// JSON API responds with.
+fn status(e: DbError) -> Status {
+    match e {
+        DbError::BookNotFound { .. } | DbError::TranslationNotFound { .. } => {
+            Status::not_found(e.to_string())
+        }
+        DbError::InvalidReference { .. } | DbError::InvalidSearch { .. } => {
+            Status::invalid_argument(e.to_string())
+        }
+        DbError::ConnectionPool { .. }
+        | DbError::Other { .. }
+        | DbError::Migration { .. }
+        | DbError::SearchIndex { .. } => Status::internal(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::rt::System;
+    use tonic::Code;
+
+    use super::*;
+    use crate::test::{test_server_data, TestSwordDrill};
+
+    fn service() -> BibleService<TestSwordDrill> {
+        BibleService {
+            data: test_server_data(),
+            drill: PhantomData,
+        }
+    }
+
+    #[test]
+    fn get_passage() {
+        System::new().block_on(async {
+            let request = Request::new(proto::PassageRequest {
+                reference: "psalms 119:105".to_string(),
+                version: String::new(),
+            });
+            let passage = service().get_passage(request).await.unwrap().into_inner();
+            assert_eq!(passage.reference, "Psalms 119:105");
+            assert_eq!(passage.verses[0].verse, 105);
+            assert_eq!(passage.verses[0].translation, "kjv");
+
+            let request = Request::new(proto::PassageRequest {
+                reference: "nope".to_string(),
+                version: String::new(),
+            });
+            let error = service().get_passage(request).await.unwrap_err();
+            assert_eq!(error.code(), Code::InvalidArgument);
+        });
+    }
+
+    #[test]
+    fn list_books() {
+        System::new().block_on(async {
+            let request = Request::new(proto::BooksRequest {
+                version: "kjv".to_string(),
+            });
+            let books = service().list_books(request).await.unwrap().into_inner();
+            assert_eq!(books.books[0].name, "Psalms");
+            assert_eq!(books.books[0].testament(), proto::Testament::Old);
+        });
+    }
+
+    #[test]
+    fn search() {
+        System::new().block_on(async {
+            let request = Request::new(proto::SearchRequest {
+                query: "light".to_string(),
+                ..Default::default()
+            });
+            let results = service().search(request).await.unwrap().into_inner();
+            assert_eq!(results.total, 1);
+            assert_eq!(results.matches[0].reference, "Psalms 119:105");
+        });
+    }
+}
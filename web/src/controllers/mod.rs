@@ -1,9 +1,481 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use actix_web::cookie::Cookie;
+use actix_web::{web, HttpRequest};
 use serde_derive::Deserialize;
 
+use db::models::{
+    Book, Reference, ResultSet, SearchMode, SearchOptions, SearchOrder, SearchScope, Testament,
+    Translation, Verse,
+};
+use db::{DbConnection, DbError, VerseFormat, VerseStore};
+
+use crate::error::Error;
+use crate::ServerData;
+
+/// Name of the cookie that remembers the user's preferred translation.
+const TRANSLATION_COOKIE: &str = "translation";
+
+/// Max number of search matches on a page.
+const MAX_PER_PAGE: i64 = 100;
+
+/// Most verses of context that can be asked for on either side of a passage.
+const MAX_CONTEXT: i32 = 50;
+
+/// Number of seconds in a day, ignoring leap seconds like Unix time does.
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// Fields to keep in a JSON API response, so clients can leave out the
+/// ones they don't need.
 #[derive(Clone, Deserialize, Debug)]
+pub struct FieldsParams {
+    /// Comma-separated names of the fields (e.g. `verse,words`).
+    fields: Option<String>,
+}
+
+impl FieldsParams {
+    /// Gets the names of the fields, or `None` to keep all of them.
+    fn fields(&self) -> Option<Vec<&str>> {
+        self.fields.as_deref().map(|fields| {
+            fields
+                .split(',')
+                .map(str::trim)
+                .filter(|f| !f.is_empty())
+                .collect()
+        })
+    }
+}
+
+/// Query parameters for the verses of context around a passage.
+#[derive(Clone, Deserialize, Debug)]
+pub struct ContextParams {
+    /// Verses of context before the passage, 2 by default.
+    before: Option<i32>,
+    /// Verses of context after the passage, 2 by default.
+    after: Option<i32>,
+}
+
+impl ContextParams {
+    /// Gets the numbers of verses of context before and after the passage,
+    /// up to [MAX_CONTEXT] each.
+    fn context(&self) -> (i32, i32) {
+        let context = |n: Option<i32>| n.unwrap_or(2).clamp(0, MAX_CONTEXT);
+        (context(self.before), context(self.after))
+    }
+}
+
+/// Page of the verses of a passage, so long passages (e.g. Psalm 119 or a
+/// whole book) can be fetched a page at a time.
+#[derive(Clone, Deserialize, Debug)]
+pub struct VersePageParams {
+    /// Number of verses to skip.
+    offset: Option<usize>,
+    /// Most verses to respond with.
+    limit: Option<usize>,
+}
+
+#[derive(Clone, Default, Deserialize, Debug)]
 pub struct SearchParams {
     q: String,
+    /// Mode to search with instead of the one the query implies. Only the
+    /// JSON API supports `regex`.
+    mode: Option<SearchMode>,
+    /// Comma-separated names of books to search in (e.g. `Matthew,Mark`).
+    books: Option<String>,
+    /// Testament to search in, `ot` or `nt`.
+    testament: Option<String>,
+    /// Range of books to search in (e.g. `Genesis-Deuteronomy`).
+    range: Option<String>,
+    /// Order to list matches in, which defaults to relevance for queries
+    /// with more than one word and canonical order otherwise.
+    order: Option<SearchOrder>,
+    /// Page of matches to get, starting at 1.
+    page: Option<i64>,
+    /// Number of matches on a page, up to [MAX_PER_PAGE].
+    per_page: Option<i64>,
+    /// Whether to only match whole words (`true`), instead of also matching
+    /// a trailing word as the start of longer ones.
+    whole_word: Option<bool>,
+    /// Translations to search at once, `all` for every installed translation
+    /// or a comma-separated list of abbreviations (e.g. `kjv,web`). Only the
+    /// JSON API searches several translations.
+    versions: Option<String>,
+    /// `previous` to only search within the verses of an earlier search,
+    /// which are given by `results`.
+    within: Option<String>,
+    /// Token for the verses matched by an earlier search, from its results.
+    results: Option<String>,
+}
+
+/// One of the searches of a batch, either just what to search for or the
+/// same parameters as a single search (e.g. `{"q": "faith", "testament":
+/// "nt"}`).
+#[derive(Clone, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum BatchSearch {
+    Query(String),
+    Params(SearchParams),
+}
+
+impl From<BatchSearch> for SearchParams {
+    fn from(search: BatchSearch) -> Self {
+        match search {
+            BatchSearch::Query(q) => SearchParams {
+                q,
+                ..SearchParams::default()
+            },
+            BatchSearch::Params(params) => params,
+        }
+    }
+}
+
+impl SearchParams {
+    /// Gets the options to search with.
+    fn options(&self) -> Result<SearchOptions, Error> {
+        Ok(SearchOptions {
+            scope: self.scope()?,
+            order: self
+                .order
+                .unwrap_or_else(|| SearchOrder::default_for(&self.q)),
+            page: self.page.unwrap_or(1).max(1),
+            per_page: self
+                .per_page
+                .unwrap_or(SearchOptions::PER_PAGE)
+                .clamp(1, MAX_PER_PAGE),
+            whole_word: self.whole_word.unwrap_or(false),
+            within: self.within()?,
+        })
+    }
+
+    /// Gets the verses of an earlier search to search within, if a search
+    /// is narrowing down its results.
+    fn within(&self) -> Result<Option<ResultSet>, Error> {
+        match self.within.as_deref() {
+            None | Some("") => Ok(None),
+            Some("previous") => match self.results.as_deref() {
+                Some(results) => Ok(Some(results.parse()?)),
+                None => Err(Error::InvalidSearch(
+                    "there are no previous results to search within".to_string(),
+                )),
+            },
+            Some(_) => Err(Error::InvalidSearch(
+                "'within' has to be 'previous'".to_string(),
+            )),
+        }
+    }
+
+    /// Gets the abbreviations of the translations to search at once, if
+    /// several were requested.
+    fn versions(&self, data: &ServerData) -> Option<Vec<String>> {
+        match self.versions.as_deref().map(str::trim) {
+            None | Some("") => None,
+            Some("all") => Some(
+                data.translations
+                    .iter()
+                    .map(|t| t.abbreviation.to_owned())
+                    .collect(),
+            ),
+            Some(versions) => Some(
+                versions
+                    .split(',')
+                    .map(|v| v.trim().to_string())
+                    .filter(|v| !v.is_empty())
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Gets the books to search in from the `books`, `testament` and
+    /// `range` parameters.
+    fn scope(&self) -> Result<SearchScope, Error> {
+        search_scope(
+            self.books.as_deref(),
+            self.testament.as_deref(),
+            self.range.as_deref(),
+        )
+    }
+}
+
+/// Query parameters for a random verse.
+#[derive(Clone, Deserialize, Debug)]
+pub struct RandomParams {
+    /// Comma-separated names of books to pick from.
+    books: Option<String>,
+    /// Testament to pick from, `ot` or `nt`.
+    testament: Option<String>,
+    /// Range of books to pick from (e.g. `Genesis-Deuteronomy`).
+    range: Option<String>,
+    /// Whether to leave out genealogies and other lists of names (`true`).
+    exclude_genealogies: Option<bool>,
+}
+
+impl RandomParams {
+    /// Gets the books to pick from.
+    fn scope(&self) -> Result<SearchScope, Error> {
+        search_scope(
+            self.books.as_deref(),
+            self.testament.as_deref(),
+            self.range.as_deref(),
+        )
+    }
+}
+
+/// Gets the books to search in from comma-separated book names, a testament
+/// and a range of books.
+fn search_scope(
+    books: Option<&str>,
+    testament: Option<&str>,
+    range: Option<&str>,
+) -> Result<SearchScope, Error> {
+    let books = books
+        .iter()
+        .flat_map(|books| books.split(','))
+        .map(str::trim)
+        .filter(|book| !book.is_empty())
+        .map(str::to_string)
+        .collect();
+
+    let testament = match testament.map(str::to_lowercase).as_deref() {
+        None | Some("") => None,
+        Some("ot") | Some("old") => Some(Testament::Old),
+        Some("nt") | Some("new") => Some(Testament::New),
+        Some(_) => {
+            return Err(Error::InvalidSearch(
+                "the testament has to be 'ot' or 'nt'".to_string(),
+            ))
+        }
+    };
+
+    let range = match range.map(str::trim) {
+        None | Some("") => None,
+        Some(range) => match range.split_once('-') {
+            Some((first, last)) if !first.trim().is_empty() && !last.trim().is_empty() => {
+                Some((first.trim().to_string(), last.trim().to_string()))
+            }
+            _ => {
+                return Err(Error::InvalidSearch(format!(
+                    "'{}' is not a range of books",
+                    range
+                )))
+            }
+        },
+    };
+
+    Ok(SearchScope {
+        books,
+        testament,
+        range,
+    })
+}
+
+/// Query parameters for the verse of the day.
+#[derive(Clone, Deserialize, Debug)]
+pub struct DayParams {
+    /// Day to get the verse for, as `YYYY-MM-DD`, instead of today (UTC).
+    date: Option<String>,
+}
+
+impl DayParams {
+    /// Gets the day, counted from 1 January 1970, along with its date.
+    fn day(&self) -> Result<(i64, String), Error> {
+        let day = self.day_number()?;
+        let (year, month, day_of_month) = civil_from_days(day);
+        Ok((day, format!("{:04}-{:02}-{:02}", year, month, day_of_month)))
+    }
+
+    /// Gets the day, counted from 1 January 1970.
+    fn day_number(&self) -> Result<i64, Error> {
+        let date = match self.date.as_deref().map(str::trim) {
+            None | Some("") => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default();
+                return Ok((now.as_secs() / SECONDS_PER_DAY) as i64);
+            }
+            Some(date) => date,
+        };
+
+        let invalid = || Error::InvalidDate(date.to_string());
+        let parts: Vec<i64> = date
+            .split('-')
+            .map(|part| part.parse().map_err(|_| invalid()))
+            .collect::<Result<_, _>>()?;
+        let day = match parts[..] {
+            [year, month, day] if (1..=12).contains(&month) => days_from_civil(year, month, day),
+            _ => return Err(invalid()),
+        };
+        // Days past the end of a month (e.g. 30 February) don't round-trip
+        if civil_from_days(day) != (parts[0], parts[1], parts[2]) {
+            return Err(invalid());
+        }
+        Ok(day)
+    }
+}
+
+/// Counts the days from 1 January 1970 to a date of the Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    // Years start in March, so leap days come at the end of them
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Gets the date of the Gregorian calendar a number of days after 1 January
+/// 1970, as its year, month and day.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_shifted = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_shifted + 2) / 5 + 1;
+    let month = if month_shifted < 10 {
+        month_shifted + 3
+    } else {
+        month_shifted - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Query parameters for the parallel view.
+#[derive(Clone, Deserialize, Debug)]
+pub struct ParallelParams {
+    /// Comma-separated translation abbreviations (e.g. `kjv,web`).
+    versions: Option<String>,
+}
+
+/// Query or path parameters for choosing a translation.
+#[derive(Clone, Deserialize, Debug)]
+pub struct VersionParams {
+    version: Option<String>,
+}
+
+/// Path parameters for a book, optionally under a translation prefix.
+#[derive(Clone, Deserialize, Debug)]
+pub struct BookPath {
+    version: Option<String>,
+    book: String,
+}
+
+/// Path parameters for a reference, optionally under a translation prefix.
+#[derive(Clone, Deserialize, Debug)]
+pub struct ReferencePath {
+    version: Option<String>,
+    reference: String,
+}
+
+/// Returns the abbreviation if it belongs to an installed translation.
+fn installed_version(version: &str, data: &ServerData) -> Option<String> {
+    let version = version.to_lowercase();
+    data.translations
+        .iter()
+        .any(|t| t.abbreviation == version)
+        .then_some(version)
+}
+
+/// Gets the translation chosen with the `version` query parameter.
+fn chosen_version(req: &HttpRequest, data: &ServerData) -> Option<String> {
+    web::Query::<VersionParams>::from_query(req.query_string())
+        .ok()
+        .and_then(|params| params.into_inner().version)
+        .and_then(|version| installed_version(&version, data))
+}
+
+/// Gets the abbreviation of the translation to use for a request.
+///
+/// A translation prefix in the path takes precedence, followed by the
+/// `version` query parameter, the preference cookie, and lastly the
+/// server's default translation.
+pub(crate) fn translation_version(
+    requested: Option<String>,
+    req: &HttpRequest,
+    data: &ServerData,
+) -> String {
+    requested
+        .or_else(|| chosen_version(req, data))
+        .or_else(|| {
+            req.cookie(TRANSLATION_COOKIE)
+                .and_then(|c| installed_version(c.value(), data))
+        })
+        .unwrap_or_else(|| data.default_translation.to_owned())
+}
+
+/// Builds a cookie persisting the translation chosen with the `version`
+/// query parameter, if there was one.
+fn translation_cookie(req: &HttpRequest, data: &ServerData) -> Option<Cookie<'static>> {
+    chosen_version(req, data).map(|version| {
+        Cookie::build(TRANSLATION_COOKIE, version)
+            .path("/")
+            .permanent()
+            .finish()
+    })
+}
+
+/// A passage with the translation it was taken from and, if verses were
+/// filled from it, the fallback translation.
+type PassageResult = (Translation, Option<Translation>, (Book, Vec<Verse>));
+
+/// Looks up a passage in the translation with the given abbreviation.
+///
+/// When a fallback translation is given, verses the translation omits are
+/// filled from it, and the fallback is returned alongside the passage.
+fn passage<SD>(
+    reference: &Reference,
+    version: &str,
+    fallback: Option<&str>,
+    format: VerseFormat,
+    conn: &DbConnection,
+) -> Result<PassageResult, DbError>
+where
+    SD: VerseStore,
+{
+    // Chapters, which pages are read by, can be looked up all at once
+    if reference.is_chapter() && fallback.map_or(true, |f| f.eq_ignore_ascii_case(version)) {
+        let (translation, book, verses) = SD::chapter(reference, version, format, conn)?;
+        return Ok((translation, None, (book, verses)));
+    }
+
+    let translation = SD::translation(version, conn)?;
+    match fallback.filter(|f| !f.eq_ignore_ascii_case(&translation.abbreviation)) {
+        Some(fallback) => {
+            let fallback = SD::translation(fallback, conn)?;
+            let result =
+                SD::verses_with_fallback(reference, &translation, &fallback, format, conn)?;
+            Ok((translation, Some(fallback), result))
+        }
+        None => {
+            let result = SD::verses(reference, &translation, format, conn)?;
+            Ok((translation, None, result))
+        }
+    }
+}
+
+/// Looks up each of several passages with [passage].
+fn passages<SD>(
+    references: &[Reference],
+    version: &str,
+    fallback: Option<&str>,
+    format: VerseFormat,
+    conn: &DbConnection,
+) -> Result<Vec<PassageResult>, DbError>
+where
+    SD: VerseStore,
+{
+    references
+        .iter()
+        .map(|reference| passage::<SD>(reference, version, fallback, format, conn))
+        .collect()
 }
 
 pub mod api;
+pub mod graphql;
+#[cfg(feature = "grpc")]
+pub mod grpc;
 pub mod view;
+pub mod ws;
@@ -1,10 +1,16 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use actix_web::http::header::{self, HeaderValue, HttpDate};
 use actix_web::web;
 use actix_web::{HttpRequest, HttpResponse};
 
-use db::models::Reference;
-use db::{SwordDrillable, VerseFormat};
+use db::models::{Reference, SearchMode};
+use db::{BookStore, DbError, Searcher, VerseFormat, VerseStore};
 
-use crate::controllers::SearchParams;
+use crate::controllers::{
+    passage, passages, translation_cookie, translation_version, BookPath, ParallelParams,
+    ReferencePath, SearchParams, VersionParams,
+};
 use crate::error::{Error, HtmlError};
 use crate::responder::*;
 use crate::ServerData;
@@ -12,6 +18,86 @@ use crate::ServerData;
 /// Result for HTML response handlers
 type ViewResult = Result<HttpResponse, HtmlError>;
 
+/// `Cache-Control` of book and chapter pages, which only change when their
+/// translation is imported again.
+const PAGE_CACHE_CONTROL: &str = "public, max-age=86400";
+
+/// Builds an HTML response, remembering the translation if one was
+/// chosen with the `version` query parameter.
+fn html_ok(body: String, req: &HttpRequest, data: &ServerData) -> HttpResponse {
+    let mut resp = HttpResponse::Ok();
+    if let Some(cookie) = translation_cookie(req, data) {
+        resp.cookie(cookie);
+    }
+    resp.content_type("text/html").body(body)
+}
+
+/// Builds an HTML response for a book or chapter page, which browsers and
+/// shared caches can keep. It was last modified when its translation was
+/// imported, so requests with a later `If-Modified-Since` get a `304 Not
+/// Modified` response instead.
+///
+/// Pages that remember a translation in a cookie aren't cached, and pages
+/// without a translation in their URL vary by the cookie.
+fn page_ok(body: String, imported_at: i64, req: &HttpRequest, data: &ServerData) -> HttpResponse {
+    if translation_cookie(req, data).is_some() {
+        return html_ok(body, req, data);
+    }
+    let last_modified = UNIX_EPOCH + Duration::from_secs(imported_at.max(0) as u64);
+    let not_modified = req
+        .headers()
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|since| since.to_str().ok()?.parse::<HttpDate>().ok())
+        .map_or(false, |since| SystemTime::from(since) >= last_modified);
+
+    let mut resp = if not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    resp.insert_header((header::CACHE_CONTROL, PAGE_CACHE_CONTROL))
+        .insert_header((header::LAST_MODIFIED, HttpDate::from(last_modified)));
+    if req.match_info().get("version").is_none() {
+        resp.append_header((header::VARY, "Cookie"));
+    }
+    if not_modified {
+        resp.finish()
+    } else {
+        resp.content_type("text/html").body(body)
+    }
+}
+
+/// Permanently redirects to a URL, keeping the request's query string.
+fn redirect_permanently(url: &str, req: &HttpRequest) -> HttpResponse {
+    let location = match req.query_string() {
+        "" => url.to_string(),
+        query => format!("{}?{}", url, query),
+    };
+    HttpResponse::MovedPermanently()
+        .insert_header((header::LOCATION, location))
+        .finish()
+}
+
+/// Responds to a request for a book that wasn't found, suggesting the
+/// closest book name if there is one.
+fn book_not_found(
+    book_name: String,
+    reference: Option<&Reference>,
+    req: &HttpRequest,
+    data: &ServerData,
+) -> ViewResult {
+    match SuggestionData::new(&book_name, reference, req) {
+        Some(suggestion) => {
+            let body = TemplateData::new(suggestion, Meta::for_error())
+                .to_html("suggestion", &data.template)?;
+            Ok(HttpResponse::NotFound()
+                .content_type("text/html")
+                .body(body))
+        }
+        None => Err(Error::BookNotFound(book_name).into()),
+    }
+}
+
 /// Handles HTTP requests for the about page.
 pub async fn about(data: web::Data<ServerData>) -> ViewResult {
     let body = TemplateData::new(EmptyData, Meta::for_about()).to_html("about", &data.template)?;
@@ -19,15 +105,33 @@ pub async fn about(data: web::Data<ServerData>) -> ViewResult {
     Ok(HttpResponse::Ok().content_type("text/html").body(body))
 }
 
+/// Handles HTTP requests for the documentation of the JSON API.
+pub async fn api_docs(data: web::Data<ServerData>) -> ViewResult {
+    let docs = ApiDocs::new(&data.translations);
+    let body = TemplateData::new(docs, Meta::for_api_docs()).to_html("api-docs", &data.template)?;
+
+    Ok(HttpResponse::Ok().content_type("text/html").body(body))
+}
+
 /// Handles HTTP requests for a list of all books.
 ///
 /// Return an HTML page that lists all books in the Bible.
-pub async fn all_books<SD>(data: web::Data<ServerData>, req: HttpRequest) -> ViewResult
+pub async fn all_books<SD>(
+    data: web::Data<ServerData>,
+    params: web::Path<VersionParams>,
+    req: HttpRequest,
+) -> ViewResult
 where
-    SD: SwordDrillable,
+    SD: BookStore,
 {
-    let db = data.db.to_owned();
-    let books = web::block(move || SD::all_books(&db.get().unwrap())).await??;
+    let version = translation_version(params.into_inner().version, &req, &data);
+    let db = data.read_db().to_owned();
+    let books = web::block(move || {
+        let conn = db.get().unwrap();
+        let translation = SD::translation(&version, &conn)?;
+        SD::all_books(&translation, &conn)
+    })
+    .await??;
 
     let books_data = AllBooksData::new(books, &req);
     let body = TemplateData::new(
@@ -45,23 +149,38 @@ where
 /// that has book metadata and a list of chapters.
 pub async fn book<SD>(
     data: web::Data<ServerData>,
-    params: web::Path<(String,)>,
+    params: web::Path<BookPath>,
     req: HttpRequest,
 ) -> ViewResult
 where
-    SD: SwordDrillable,
+    SD: BookStore,
 {
-    let (book_name,) = params.into_inner();
-    let db = data.db.to_owned();
-    let result = web::block(move || SD::book(&book_name, &db.get().unwrap())).await??;
+    let BookPath {
+        version,
+        book: book_name,
+    } = params.into_inner();
+    let version = translation_version(version, &req, &data);
+    let picker = TranslationPicker::new(&data.translations, &version, &req);
+    let db = data.read_db().to_owned();
+    let (result, translation) = match web::block(move || {
+        let conn = db.get().unwrap();
+        let translation = SD::translation(&version, &conn)?;
+        SD::book(&book_name, &translation, &conn).map(|book| (book, translation))
+    })
+    .await?
+    {
+        Err(DbError::BookNotFound { book }) => return book_not_found(book, None, &req, &data),
+        result => result?,
+    };
     let book_data = BookData::new(result, &req);
     let body = TemplateData::new(
         &book_data,
         Meta::for_book(&book_data.book, &book_data.links),
     )
+    .with_picker(picker)
     .to_html("book", &data.template)?;
 
-    Ok(HttpResponse::Ok().content_type("text/html").body(body))
+    Ok(page_ok(body, translation.imported_at, &req, &data))
 }
 
 /// Handles HTTP requests for references (e.g. /John/1/1).
@@ -71,26 +190,58 @@ where
 /// layer and looked up, returning an HTTP response with the verse body.
 pub async fn reference<SD>(
     data: web::Data<ServerData>,
-    params: web::Path<(String,)>,
+    params: web::Path<ReferencePath>,
     req: HttpRequest,
 ) -> ViewResult
 where
-    SD: SwordDrillable,
+    SD: VerseStore,
 {
-    let (path_reference,) = params.into_inner();
-    let db = data.db.to_owned();
+    let ReferencePath {
+        version,
+        reference: path_reference,
+    } = params.into_inner();
+    let version = translation_version(version, &req, &data);
+    let picker = TranslationPicker::new(&data.translations, &version, &req);
+    let db = data.read_db().to_owned();
     let raw_reference = path_reference.replace("/", ".");
 
+    if let Some(format) = PassageFormat::from_request(&req) {
+        return formatted_references::<SD>(&raw_reference, version, format, &data, &req).await;
+    }
+    if raw_reference.contains(';') {
+        return multiple_references::<SD>(&raw_reference, version, picker, &data, &req).await;
+    }
     if let Ok(reference) = raw_reference.parse::<Reference>() {
         let data_reference = reference.to_owned();
-        let result =
-            web::block(move || SD::verses(&reference, VerseFormat::Html, &db.get().unwrap()))
-                .await??;
-        let verses_data = VersesData::new(result, data_reference, &req);
+        let fallback = data.fallback_translation.to_owned();
+        let (translation, fallback, result) = match web::block(move || {
+            passage::<SD>(
+                &reference,
+                &version,
+                fallback.as_deref(),
+                VerseFormat::Html,
+                &db.get().unwrap(),
+            )
+        })
+        .await?
+        {
+            Err(DbError::BookNotFound { book }) => {
+                return book_not_found(book, Some(&data_reference), &req, &data)
+            }
+            result => result?,
+        };
+        let verses_data =
+            VersesData::new(result, data_reference, translation, &req).with_fallback(fallback);
+        let mut attributions = vec![verses_data.translation.to_owned()];
+        attributions.extend(verses_data.fallback.to_owned());
 
         if verses_data.verses.is_empty() {
             return Err(Error::InvalidReference(raw_reference).into());
         }
+        // Every way of writing the reference redirects to one canonical URL
+        if req.path() != verses_data.links.current.url {
+            return Ok(redirect_permanently(&verses_data.links.current.url, &req));
+        }
 
         let body = TemplateData::new(
             &verses_data,
@@ -100,7 +251,180 @@ where
                 &verses_data.links,
             ),
         )
+        .with_attributions(&attributions)
+        .with_picker(picker)
         .to_html("chapter", &data.template)?;
+        let imported_at = attributions.iter().map(|t| t.imported_at).max();
+        let mut resp = page_ok(body, imported_at.unwrap_or_default(), &req, &data);
+        resp.headers_mut()
+            .append(header::VARY, HeaderValue::from_static("Accept"));
+        Ok(resp)
+    } else {
+        Err(Error::InvalidReference(raw_reference).into())
+    }
+}
+
+/// Responds with references in the format the `Accept` header asked for
+/// instead of HTML, with the same plain text verses as the JSON API.
+async fn formatted_references<SD>(
+    raw_reference: &str,
+    version: String,
+    format: PassageFormat,
+    data: &web::Data<ServerData>,
+    req: &HttpRequest,
+) -> ViewResult
+where
+    SD: VerseStore,
+{
+    let references = if raw_reference.contains(';') {
+        Reference::parse_list(raw_reference)?
+    } else {
+        let reference = raw_reference
+            .parse::<Reference>()
+            .map_err(|_| Error::InvalidReference(raw_reference.to_string()))?;
+        vec![reference]
+    };
+    let data_references = references.to_owned();
+    let db = data.read_db().to_owned();
+    let fallback = data.fallback_translation.to_owned();
+    let results = web::block(move || {
+        passages::<SD>(
+            &references,
+            &version,
+            fallback.as_deref(),
+            VerseFormat::PlainText,
+            &db.get().unwrap(),
+        )
+    })
+    .await??;
+
+    let passages: Vec<VersesData> = results
+        .into_iter()
+        .zip(data_references)
+        .map(|((translation, fallback, result), reference)| {
+            VersesData::new(result, reference, translation, req).with_fallback(fallback)
+        })
+        .collect();
+    if passages.iter().any(|p| p.verses.is_empty()) {
+        return Err(Error::InvalidReference(raw_reference.to_string()).into());
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header((header::VARY, "Accept"))
+        .body(format.format(&passages)))
+}
+
+/// Renders several references separated by semicolons (e.g.
+/// /John 3:16; Romans 8:28) as one page with a section for each passage.
+async fn multiple_references<SD>(
+    raw_reference: &str,
+    version: String,
+    picker: TranslationPicker,
+    data: &web::Data<ServerData>,
+    req: &HttpRequest,
+) -> ViewResult
+where
+    SD: VerseStore,
+{
+    let references = Reference::parse_list(raw_reference)?;
+    let data_references = references.to_owned();
+    let db = data.read_db().to_owned();
+    let fallback = data.fallback_translation.to_owned();
+    let results = web::block(move || {
+        passages::<SD>(
+            &references,
+            &version,
+            fallback.as_deref(),
+            VerseFormat::Html,
+            &db.get().unwrap(),
+        )
+    })
+    .await??;
+    let passages: Vec<VersesData> = results
+        .into_iter()
+        .zip(data_references)
+        .map(|((translation, fallback, result), reference)| {
+            VersesData::new(result, reference, translation, req).with_fallback(fallback)
+        })
+        .collect();
+
+    if passages.iter().any(|p| p.verses.is_empty()) {
+        return Err(Error::InvalidReference(raw_reference.to_string()).into());
+    }
+
+    let passages_data = PassagesData::new(passages);
+    let body = TemplateData::new(
+        &passages_data,
+        Meta::for_passages(&passages_data, req.path()),
+    )
+    .with_attributions(&passages_data.translations())
+    .with_picker(picker)
+    .to_html("passages", &data.template)?;
+    Ok(html_ok(body, req, data))
+}
+
+/// Handles HTTP requests for the parallel view (e.g. /parallel/John/3?versions=kjv,web).
+///
+/// Looks up the reference in each of the requested translations and
+/// renders the verses side by side. If no translations are given, every
+/// installed translation is shown.
+pub async fn parallel<SD>(
+    data: web::Data<ServerData>,
+    params: web::Path<ReferencePath>,
+    query: web::Query<ParallelParams>,
+    req: HttpRequest,
+) -> ViewResult
+where
+    SD: VerseStore,
+{
+    let ReferencePath {
+        reference: path_reference,
+        ..
+    } = params.into_inner();
+    let versions: Vec<String> = match query.into_inner().versions {
+        Some(versions) => versions
+            .split(',')
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+            .collect(),
+        None => data
+            .translations
+            .iter()
+            .map(|t| t.abbreviation.to_owned())
+            .collect(),
+    };
+    let db = data.read_db().to_owned();
+    let raw_reference = path_reference.replace("/", ".");
+
+    if let Ok(reference) = raw_reference.parse::<Reference>() {
+        let data_reference = reference.to_owned();
+        let (translations, result) = web::block(move || {
+            let conn = db.get().unwrap();
+            let translations = versions
+                .iter()
+                .map(|v| SD::translation(v, &conn))
+                .collect::<Result<Vec<_>, DbError>>()?;
+            let result = SD::parallel_verses(&reference, &translations, VerseFormat::Html, &conn)?;
+            Ok::<_, DbError>((translations, result))
+        })
+        .await??;
+        let parallel_data = ParallelData::new(result, data_reference, translations, &req);
+
+        if parallel_data.rows.is_empty() {
+            return Err(Error::InvalidReference(raw_reference).into());
+        }
+
+        let body = TemplateData::new(
+            &parallel_data,
+            Meta::for_parallel(
+                &parallel_data.reference,
+                &parallel_data.translations,
+                &parallel_data.links,
+            ),
+        )
+        .with_attributions(&parallel_data.translations)
+        .to_html("parallel", &data.template)?;
         Ok(HttpResponse::Ok().content_type("text/html").body(body))
     } else {
         Err(Error::InvalidReference(raw_reference).into())
@@ -110,29 +434,54 @@ where
 /// Handle HTTP requests for a search HTML page.
 ///
 /// Return an HTML page with search results based on the `q` query
-/// parameter.
+/// parameter, searching the translation chosen with the `version` query
+/// parameter or the user's preferred translation.
 pub async fn search<SD>(
     data: web::Data<ServerData>,
     query: web::Query<SearchParams>,
     req: HttpRequest,
 ) -> ViewResult
 where
-    SD: SwordDrillable,
+    SD: BookStore + Searcher,
 {
-    let db = data.db.to_owned();
+    let db = data.read_db().to_owned();
     let q = query.q.to_owned();
-    let result = web::block(move || SD::search(&query.q, &db.get().unwrap())).await??;
+    let version = translation_version(None, &req, &data);
+    let options = query.options()?;
+    let (translation, result, result_set, suggestion) = web::block({
+        let options = options.clone();
+        move || {
+            let conn = db.get().unwrap();
+            let translation = SD::translation(&version, &conn)?;
+            let result = SD::search(&query.q, &options, &translation, &conn)?;
+            let result_set = SD::search_result_set(&query.q, &options, &translation, &conn)?;
+            let suggestion = match result.1 {
+                0 => SD::suggest_spelling(&query.q, &translation, &conn)?,
+                _ => None,
+            };
+            Ok::<_, DbError>((translation, result, result_set, suggestion))
+        }
+    })
+    .await??;
     let body = TemplateData::new(
-        SearchResultData::from_verses_fts(result, &req),
+        SearchResultData::from_verses_fts(result, SearchMode::of(&q), &options, &req)
+            .with_result_set(result_set)
+            .with_suggestion(suggestion, &req)
+            .with_translation(translation),
         Meta::for_search(&q, &req.uri().to_string()),
     )
     .to_html("search-results", &data.template)?;
-    Ok(HttpResponse::Ok().content_type("text/html").body(body))
+    Ok(html_ok(body, &req, &data))
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::test::html_response;
+    use actix_web::http::header;
+    use actix_web::test;
+
+    use super::PAGE_CACHE_CONTROL;
+    use crate::responder::VersesData;
+    use crate::test::{accept_response, html_headers, html_redirect, html_response};
 
     #[test]
     fn about() {
@@ -140,6 +489,13 @@ mod tests {
         assert!(result.contains("Where Bible.rs Shines"));
     }
 
+    #[test]
+    fn api_docs() {
+        let result = html_response("/api/docs");
+        assert!(result.contains("<code>GET /api/v1/search</code>"));
+        assert!(result.contains("<code>kjv</code>"));
+    }
+
     #[test]
     fn all_books() {
         let result = html_response("/");
@@ -157,4 +513,140 @@ mod tests {
         let result = html_response("/Psalms/119");
         assert!(result.contains("NUN. Thy word is a lamp unto my feet, and a light unto my path."));
     }
+
+    #[test]
+    fn reference_formats() {
+        let (headers, body) = accept_response("/Psalms/119/105", "text/plain");
+        assert_eq!(headers[header::CONTENT_TYPE], "text/plain; charset=utf-8");
+        assert_eq!(headers[header::VARY], "Accept");
+        assert_eq!(
+            body,
+            "NUN. Thy word is a lamp unto my feet, and a light unto my path.\n"
+        );
+
+        let (_, body) = accept_response("/Psalms/119/105", "text/markdown");
+        assert!(body.starts_with("## Psalms 119:105 (KJV)\n\n**105** NUN. Thy word"));
+
+        let (_, body) = accept_response("/Psalms/119/105", "application/json");
+        let result: VersesData = serde_json::from_str(&body).unwrap();
+        assert_eq!(result.reference_string, "Psalms 119:105");
+
+        let (headers, _) = accept_response("/Psalms/119", "text/html");
+        assert_eq!(headers[header::CONTENT_TYPE], "text/html");
+    }
+
+    #[test]
+    fn page_caching() {
+        let (status, headers) = html_headers(test::TestRequest::with_uri("/Psalms/119"));
+        assert_eq!(status, 200);
+        assert_eq!(headers[header::CACHE_CONTROL], PAGE_CACHE_CONTROL);
+        assert_eq!(
+            headers[header::LAST_MODIFIED],
+            "Sat, 28 May 2022 00:00:00 GMT"
+        );
+        let vary: Vec<_> = headers.get_all(header::VARY).collect();
+        assert_eq!(vary, ["Cookie", "Accept"]);
+
+        let req = test::TestRequest::with_uri("/kjv/Psalms")
+            .insert_header((header::IF_MODIFIED_SINCE, "Sun, 29 May 2022 00:00:00 GMT"));
+        let (status, headers) = html_headers(req);
+        assert_eq!(status, 304);
+        assert!(!headers.contains_key(header::VARY));
+
+        // Pages remembering a translation aren't cached
+        let (_, headers) = html_headers(test::TestRequest::with_uri("/Psalms?version=kjv"));
+        assert!(headers.contains_key(header::SET_COOKIE));
+        assert!(!headers.contains_key(header::CACHE_CONTROL));
+    }
+
+    #[test]
+    fn reference_with_version() {
+        let result = html_response("/kjv/Psalms/119");
+        assert!(result.contains("NUN. Thy word is a lamp unto my feet, and a light unto my path."));
+        assert!(result.contains("/kjv/Psalms/120"));
+    }
+
+    #[test]
+    fn reference_redirect() {
+        let (status, location) = html_redirect("/psalms%20119v105?version=kjv");
+        assert_eq!(status, 301);
+        assert_eq!(location.unwrap(), "/Psalms/119/105?version=kjv");
+
+        let (status, location) = html_redirect("/Psalms/119/105");
+        assert_eq!(status, 200);
+        assert!(location.is_none());
+    }
+
+    #[test]
+    fn reference_part() {
+        let result = html_response("/Psalms/119/105a");
+        assert!(result.contains(r#"<p class="part-note">"#));
+        assert!(result.contains("NUN. Thy word is a lamp unto my feet, and a light unto my path."));
+    }
+
+    #[test]
+    fn reference_language() {
+        let result = html_response("/Psalms/119");
+        assert!(result
+            .contains(r#"<p id="v105" class="verse paragraph poetry-1" lang="en" dir="ltr">"#));
+    }
+
+    #[test]
+    fn multiple_references() {
+        let result = html_response("/Psalms%20119:105;%20Psalms%20119:105");
+        assert!(result.contains("<h1>Psalms 119:105; Psalms 119:105</h1>"));
+        assert!(result.contains(r#"<h2><a href="/Psalms/119/105">Psalms 119:105</a></h2>"#));
+        assert!(result.contains("NUN. Thy word is a lamp unto my feet, and a light unto my path."));
+    }
+
+    #[test]
+    fn reference_suggestion() {
+        let result = html_response("/Phillipians/4/13");
+        assert!(
+            result.contains(r#"Did you mean <a href="/Philippians/4/13">Philippians 4:13</a>?"#)
+        );
+    }
+
+    #[test]
+    fn reference_attribution() {
+        let result = html_response("/Psalms/119");
+        assert!(result.contains(r#"<p class="attribution">"#));
+        assert!(result.contains("Public Domain"));
+    }
+
+    #[test]
+    fn parallel() {
+        let result = html_response("/parallel/Psalms/119?versions=kjv");
+        assert!(result.contains("King James Version"));
+        assert!(result.contains("NUN. Thy word is a lamp unto my feet, and a light unto my path."));
+        assert!(result.contains("/parallel/Psalms/120?versions=kjv"));
+    }
+
+    #[test]
+    fn reference_translation_picker() {
+        let result = html_response("/kjv/Psalms/119?version=kjv");
+        assert!(result.contains(r#"<form id="translation-form" action="/Psalms/119">"#));
+        assert!(result.contains(r#"<option value="kjv" selected>"#));
+    }
+
+    #[test]
+    fn search() {
+        let result = html_response("/search?q=lamp");
+        assert!(result.contains("and a <mark>light</mark> unto my path."));
+        assert!(result.contains("Verses with all of the words"));
+
+        let result = html_response("/search?q=%22a%20lamp%22");
+        assert!(result.contains("Verses with the exact phrase"));
+
+        let result = html_response("/search?q=lamp%20-light");
+        assert!(result.contains("Verses matching the AND, OR and NOT operators"));
+        assert!(!result.contains("Did you mean"));
+
+        let result = html_response("/search?q=lamp");
+        assert!(result.contains(r#"<input type="hidden" name="within" value="previous">"#));
+
+        let result = html_response("/search?q=lihgt&testament=nt");
+        assert!(result.contains("Did you mean <a href="));
+        assert!(result.contains(">light</a>?"));
+    }
 }
@@ -0,0 +1,241 @@
+use std::marker::PhantomData;
+use std::time::{Duration, Instant};
+
+use actix::{
+    Actor, ActorContext, ActorFutureExt, AsyncContext, SpawnHandle, StreamHandler, WrapFuture,
+};
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use actix_web_actors::ws;
+
+use db::models::{Book, Reference, SearchMode, Verse, VerseFTS};
+use db::{DbError, Searcher, VerseFormat, VerseStore};
+
+use crate::controllers::{translation_version, SearchParams};
+use crate::error::{Error, JsonError};
+use crate::responder::{LiveSearchData, ProblemData, SearchResultData};
+use crate::ServerData;
+
+/// How long a query has to stay the same before it's searched for, so a
+/// search isn't run for every key that's pressed.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// How often the client is pinged to check it's still connected.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long to wait for the client to answer a ping before disconnecting.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Opens a WebSocket that searches the user's translation as they type.
+///
+/// Each text message is the query typed so far. Once it hasn't changed for
+/// [DEBOUNCE], the first page of its matches is sent back as
+/// [LiveSearchData], or the [ProblemData] of its error. Results of queries
+/// that have changed since are never sent.
+pub async fn search<SD>(
+    data: web::Data<ServerData>,
+    req: HttpRequest,
+    stream: web::Payload,
+) -> Result<HttpResponse, actix_web::Error>
+where
+    SD: VerseStore + Searcher + 'static,
+{
+    let session = LiveSearch::<SD> {
+        version: translation_version(None, &req, &data),
+        data,
+        req: req.clone(),
+        pending: None,
+        queries: 0,
+        heartbeat: Instant::now(),
+        drill: PhantomData,
+    };
+    ws::start(session, &req, stream)
+}
+
+/// Results of a live search, before they're turned into the data of a
+/// search.
+enum LiveResults {
+    Passage((Book, Vec<Verse>)),
+    Matches((Vec<(VerseFTS, Book)>, i64)),
+}
+
+/// Actor for a live search WebSocket, searching for the queries a client
+/// types.
+pub struct LiveSearch<SD> {
+    data: web::Data<ServerData>,
+    version: String,
+    /// Request the WebSocket was opened with, for linking to the matches.
+    req: HttpRequest,
+    /// Search waiting for its query to stop changing.
+    pending: Option<SpawnHandle>,
+    /// Number of queries typed, so a search can tell if its query is still
+    /// the latest.
+    queries: u64,
+    /// When the client last showed it was connected.
+    heartbeat: Instant,
+    drill: PhantomData<fn() -> SD>,
+}
+
+impl<SD> LiveSearch<SD>
+where
+    SD: VerseStore + Searcher + 'static,
+{
+    /// Searches for a query once it stops changing, instead of for the
+    /// query typed before it.
+    fn debounce(&mut self, q: &str, ctx: &mut ws::WebsocketContext<Self>) {
+        if let Some(pending) = self.pending.take() {
+            ctx.cancel_future(pending);
+        }
+        self.queries += 1;
+
+        let q = q.trim().to_string();
+        if !q.is_empty() {
+            self.pending = Some(ctx.run_later(DEBOUNCE, move |act, ctx| act.search(q, ctx)));
+        }
+    }
+
+    /// Searches for a query, sending its results if it's still the latest
+    /// query by the time they're found.
+    fn search(&mut self, q: String, ctx: &mut ws::WebsocketContext<Self>) {
+        self.pending = None;
+        let query = self.queries;
+        let params = SearchParams {
+            q: q.to_owned(),
+            ..SearchParams::default()
+        };
+        let options = match params.options() {
+            Ok(options) => options,
+            Err(e) => return ctx.text(problem(&JsonError::from(e), &q)),
+        };
+
+        let data = self.data.clone();
+        let version = self.version.to_owned();
+        let lookup = web::block({
+            let q = q.to_owned();
+            let options = options.clone();
+            move || {
+                let conn = data.read_db().get().unwrap();
+                let translation = SD::translation(&version, &conn)?;
+                let results = match q.parse::<Reference>() {
+                    Ok(reference) => LiveResults::Passage(SD::verses(
+                        &reference,
+                        &translation,
+                        VerseFormat::PlainText,
+                        &conn,
+                    )?),
+                    Err(_) => LiveResults::Matches(SD::search(&q, &options, &translation, &conn)?),
+                };
+                Ok::<_, DbError>((translation, results))
+            }
+        });
+
+        ctx.spawn(lookup.into_actor(self).map(move |result, act, ctx| {
+            // Another query has been typed since
+            if act.queries != query {
+                return;
+            }
+            let req = &act.req;
+            let results = match result
+                .map_err(JsonError::from)
+                .and_then(|r| r.map_err(JsonError::from))
+            {
+                Ok((translation, results)) => match results {
+                    LiveResults::Passage(passage) => SearchResultData::from_verses(passage, req),
+                    LiveResults::Matches(matches) => SearchResultData::from_verses_fts(
+                        matches,
+                        SearchMode::of(&q),
+                        &options,
+                        req,
+                    ),
+                }
+                .with_translation(translation)
+                .without_page_links(),
+                // Books that don't exist have no matches
+                Err(JsonError {
+                    error: Error::BookNotFound(_),
+                    ..
+                }) => SearchResultData::empty(),
+                Err(e) => return ctx.text(problem(&e, &q)),
+            };
+            let message = LiveSearchData { query: q, results };
+            ctx.text(serde_json::to_string(&message).unwrap());
+        }));
+    }
+}
+
+/// Gets the problem details of an error searching for a query, as a message.
+fn problem(e: &JsonError, q: &str) -> String {
+    serde_json::to_string(&ProblemData::new(&e.error, e.status_code(), Some(q))).unwrap()
+}
+
+impl<SD> Actor for LiveSearch<SD>
+where
+    SD: VerseStore + Searcher + 'static,
+{
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(HEARTBEAT_INTERVAL, |act, ctx| {
+            if act.heartbeat.elapsed() > CLIENT_TIMEOUT {
+                ctx.stop();
+            } else {
+                ctx.ping(b"");
+            }
+        });
+    }
+}
+
+impl<SD> StreamHandler<Result<ws::Message, ws::ProtocolError>> for LiveSearch<SD>
+where
+    SD: VerseStore + Searcher + 'static,
+{
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        match msg {
+            Ok(ws::Message::Text(q)) => self.debounce(&q, ctx),
+            Ok(ws::Message::Ping(msg)) => {
+                self.heartbeat = Instant::now();
+                ctx.pong(&msg);
+            }
+            Ok(ws::Message::Pong(_)) => self.heartbeat = Instant::now(),
+            Ok(ws::Message::Close(reason)) => {
+                ctx.close(reason);
+                ctx.stop();
+            }
+            Ok(_) => (),
+            Err(_) => ctx.stop(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::{header, StatusCode};
+    use actix_web::{rt::System, test, App};
+
+    use super::*;
+    use crate::test::{test_server_data, TestSwordDrill};
+
+    #[test]
+    fn handshake() {
+        System::new().block_on(async {
+            let srv = test::init_service(
+                App::new()
+                    .app_data(test_server_data())
+                    .route("/ws/search", web::get().to(search::<TestSwordDrill>)),
+            )
+            .await;
+
+            let req = test::TestRequest::with_uri("/ws/search").to_request();
+            let resp = test::call_service(&srv, req).await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+            let req = test::TestRequest::with_uri("/ws/search")
+                .insert_header((header::UPGRADE, "websocket"))
+                .insert_header((header::CONNECTION, "upgrade"))
+                .insert_header((header::SEC_WEBSOCKET_VERSION, "13"))
+                .insert_header((header::SEC_WEBSOCKET_KEY, "dGhlIHNhbXBsZSBub25jZQ=="))
+                .to_request();
+            let resp = test::call_service(&srv, req).await;
+            assert_eq!(resp.status(), StatusCode::SWITCHING_PROTOCOLS);
+        });
+    }
+}
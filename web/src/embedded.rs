@@ -0,0 +1,51 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+/// The seeded database, built into the binary from the file that
+/// `BIBLERS_EMBEDDED_DB` named when it was compiled.
+static DATABASE: &[u8] = include_bytes!(env!("BIBLERS_EMBEDDED_DB"));
+
+/// Writes the database built into the binary to a file in a directory, so
+/// the server can be deployed as one file, returning the file's path.
+///
+/// The file is named by a hash of the database, so a file extracted by an
+/// earlier start of the same binary is opened again, keeping anything the
+/// server wrote to it, while a binary with another database extracts its
+/// own. It's written under another name first, so a server stopped while
+/// extracting it never leaves part of a database behind.
+pub fn extract(dir: &Path) -> io::Result<PathBuf> {
+    let hash = format!("{:x}", Sha256::digest(DATABASE));
+    let path = dir.join(format!("biblers-{}.db", &hash[..16]));
+    if !path.is_file() {
+        let partial = path.with_extension("db.partial");
+        fs::write(&partial, DATABASE)?;
+        fs::rename(&partial, &path)?;
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn extracted() {
+        let dir = env::temp_dir().join("biblers-embedded-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let path = extract(&dir).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), DATABASE);
+        // The same file is opened again
+        fs::write(&path, b"written").unwrap();
+        assert_eq!(extract(&dir).unwrap(), path);
+        assert_eq!(fs::read(&path).unwrap(), b"written");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,6 +1,7 @@
 use std::convert::From;
 
 use actix_web::error::BlockingError;
+use actix_web::http::{header, StatusCode};
 use actix_web::HttpResponse;
 use actix_web::ResponseError;
 use handlebars::Handlebars;
@@ -10,7 +11,7 @@ use thiserror::Error;
 
 use db::DbError;
 
-use crate::responder::{ErrorData, Meta, SearchResultData, TemplateData};
+use crate::responder::{ErrorData, Meta, ProblemData, TemplateData};
 
 /// Error type for the Bible.rs application.
 #[derive(Clone, Error, Debug)]
@@ -21,11 +22,43 @@ pub enum Error {
     #[error("There was a database error. Root cause: {0}")]
     Db(String),
 
+    #[error("A valid API key is needed, given as an X-Api-Key header or a bearer token.")]
+    InvalidApiKey,
+
+    #[error("'{0}' is not a valid date, which is written as YYYY-MM-DD.")]
+    InvalidDate(String),
+
     #[error("{0} is not a valid Bible reference.")]
     InvalidReference(String),
 
+    #[error("The search couldn't be run because {0}.")]
+    InvalidSearch(String),
+
+    #[error("Too many requests were made, so try again in {0} seconds.")]
+    RateLimited(u64),
+
     #[error("There was an error rendering the HTML page.")]
     Template,
+
+    #[error("The '{0}' translation was not found.")]
+    TranslationNotFound(String),
+}
+
+impl Error {
+    /// Code of the kind of error, for API clients to tell errors apart.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::BookNotFound(_) => "book_not_found",
+            Error::Db(_) => "database_error",
+            Error::InvalidApiKey => "invalid_api_key",
+            Error::InvalidDate(_) => "invalid_date",
+            Error::InvalidReference(_) => "invalid_reference",
+            Error::InvalidSearch(_) => "invalid_search",
+            Error::RateLimited(_) => "rate_limited",
+            Error::Template => "template_error",
+            Error::TranslationNotFound(_) => "translation_not_found",
+        }
+    }
 }
 
 impl From<DbError> for Error {
@@ -33,40 +66,72 @@ impl From<DbError> for Error {
         match f {
             DbError::InvalidReference { reference } => Error::InvalidReference(reference),
             DbError::BookNotFound { book } => Error::BookNotFound(book),
+            DbError::TranslationNotFound { translation } => Error::TranslationNotFound(translation),
+            DbError::InvalidSearch { cause } => Error::InvalidSearch(cause),
             DbError::Migration { cause }
             | DbError::Other { cause }
+            | DbError::SearchIndex { cause }
+            | DbError::Import { cause }
             | DbError::ConnectionPool { cause } => Error::Db(cause),
         }
     }
 }
 
 #[derive(Error, Debug)]
-#[error("Error: {0}")]
-/// Error to display as JSON
-pub struct JsonError(#[from] pub Error);
+#[error("Error: {error}")]
+/// Error to display as JSON, with the problem details of [ProblemData].
+pub struct JsonError {
+    pub error: Error,
+    /// Search the error came from, if any.
+    pub query: Option<String>,
+}
+
+impl JsonError {
+    /// Notes the search the error came from.
+    pub fn with_query(mut self, query: &str) -> Self {
+        self.query = Some(query.to_string());
+        self
+    }
+}
+
+impl From<Error> for JsonError {
+    fn from(error: Error) -> Self {
+        JsonError { error, query: None }
+    }
+}
 
 impl From<DbError> for JsonError {
     fn from(f: DbError) -> Self {
-        JsonError(f.into())
+        Error::from(f).into()
     }
 }
 
 impl ResponseError for JsonError {
+    fn status_code(&self) -> StatusCode {
+        match self.error {
+            Error::Db { .. } | Error::Template => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::BookNotFound { .. } | Error::TranslationNotFound { .. } => StatusCode::NOT_FOUND,
+            Error::InvalidDate { .. }
+            | Error::InvalidReference { .. }
+            | Error::InvalidSearch { .. } => StatusCode::BAD_REQUEST,
+            Error::InvalidApiKey => StatusCode::UNAUTHORIZED,
+            Error::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+        }
+    }
+
     fn error_response(&self) -> HttpResponse {
-        match &self.0 {
-            Error::Template => {
-                error!("Unhandled: {}", &self.0);
-                HttpResponse::InternalServerError().json(ErrorData::from_error(&self.0))
-            }
-            Error::Db(cause) => {
-                error!("Database error: {}", cause);
-                HttpResponse::InternalServerError().json(ErrorData::new(cause))
-            }
-            Error::BookNotFound { .. } => HttpResponse::Ok().json(SearchResultData::empty()),
-            Error::InvalidReference { .. } => {
-                HttpResponse::BadRequest().json(ErrorData::from_error(&self.0))
-            }
+        let status = self.status_code();
+        if status.is_server_error() {
+            error!("Unhandled: {}", &self.error);
         }
+
+        let mut resp = HttpResponse::build(status);
+        if let Error::RateLimited(seconds) = self.error {
+            resp.insert_header((header::RETRY_AFTER, seconds.to_string()));
+        }
+        let problem = ProblemData::new(&self.error, status, self.query.as_deref());
+        resp.content_type("application/problem+json")
+            .body(serde_json::to_string(&problem).unwrap())
     }
 }
 
@@ -109,8 +174,18 @@ impl ResponseError for HtmlError {
                 error!("Unhandled: {}", &self.0);
                 HttpResponse::InternalServerError()
             }
-            Error::BookNotFound { .. } => HttpResponse::NotFound(),
-            Error::InvalidReference { .. } => HttpResponse::BadRequest(),
+            Error::BookNotFound { .. } | Error::TranslationNotFound { .. } => {
+                HttpResponse::NotFound()
+            }
+            Error::InvalidDate { .. }
+            | Error::InvalidReference { .. }
+            | Error::InvalidSearch { .. } => HttpResponse::BadRequest(),
+            Error::InvalidApiKey => HttpResponse::Unauthorized(),
+            Error::RateLimited(seconds) => {
+                let mut resp = HttpResponse::TooManyRequests();
+                resp.insert_header((header::RETRY_AFTER, seconds.to_string()));
+                resp
+            }
         }
         .content_type("text/html")
         .body(body)
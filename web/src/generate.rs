@@ -0,0 +1,190 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use actix_web::{test, web, App};
+
+use db::VerseStore;
+
+use crate::assets::{self, AssetManifest};
+use crate::controllers::view;
+use crate::minify::MinifyHtml;
+use crate::ServerData;
+
+/// Pre-renders the pages of the books of each installed translation, and of
+/// their books and chapters, to static HTML in a directory, so a read-only
+/// copy of the site can be hosted without running the server.
+///
+/// Each page is written to an `index.html` under its path, with the default
+/// translation's pages at the root and the others' under their abbreviation,
+/// and the static files are copied to `static`, by their names and by their
+/// hashed names. Returns the number of pages written.
+pub async fn generate<SD>(
+    data: web::Data<ServerData>,
+    version: &str,
+    manifest: &AssetManifest,
+    out: &Path,
+) -> io::Result<usize>
+where
+    SD: VerseStore + 'static,
+{
+    let srv = test::init_service(
+        App::new()
+            .wrap(MinifyHtml)
+            .app_data(data.clone())
+            .service(web::resource("parallel/{reference:.+\\d}").name("parallel"))
+            .service(
+                web::resource(version)
+                    .name("version_bible")
+                    .route(web::get().to(view::all_books::<SD>)),
+            )
+            .service(
+                web::resource(format!("{}/{{book}}", version))
+                    .name("version_book")
+                    .route(web::get().to(view::book::<SD>)),
+            )
+            .service(
+                web::resource(format!("{}/{{reference:.+\\d}}", version))
+                    .name("version_reference")
+                    .route(web::get().to(view::reference::<SD>)),
+            )
+            .service(
+                web::resource("/")
+                    .name("bible")
+                    .route(web::get().to(view::all_books::<SD>)),
+            )
+            .service(
+                web::resource("{book}")
+                    .name("book")
+                    .route(web::get().to(view::book::<SD>)),
+            )
+            .service(
+                web::resource("{reference:.+\\d}")
+                    .name("reference")
+                    .route(web::get().to(view::reference::<SD>)),
+            ),
+    )
+    .await;
+
+    let mut paths = vec![];
+    for translation in &data.translations {
+        let prefix = if translation.abbreviation == data.default_translation {
+            String::new()
+        } else {
+            format!("/{}", translation.abbreviation)
+        };
+        let books = web::block({
+            let data = data.clone();
+            let translation = translation.to_owned();
+            move || {
+                let conn = data.read_db().get().unwrap();
+                SD::all_books(&translation, &conn)?
+                    .iter()
+                    .map(|book| SD::book(&book.name, &translation, &conn))
+                    .collect::<Result<Vec<_>, _>>()
+            }
+        })
+        .await
+        .map_err(generate_error)?
+        .map_err(generate_error)?;
+
+        paths.push(if prefix.is_empty() {
+            "/".to_string()
+        } else {
+            prefix.to_owned()
+        });
+        for (book, chapters) in books {
+            paths.push(format!("{}/{}", prefix, book.name));
+            for chapter in chapters {
+                paths.push(format!("{}/{}/{}", prefix, book.name, chapter));
+            }
+        }
+    }
+
+    for path in &paths {
+        let req = test::TestRequest::with_uri(&path.replace(' ', "%20")).to_request();
+        let resp = test::call_service(&srv, req).await;
+        if !resp.status().is_success() {
+            return Err(generate_error(format!(
+                "{} responded with {}",
+                path,
+                resp.status()
+            )));
+        }
+        let dir = out.join(path.trim_start_matches('/'));
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join("index.html"), test::read_body(resp).await)?;
+    }
+
+    let static_dir = out.join(assets::STATIC_PATH.trim_start_matches('/'));
+    if manifest.dir().is_dir() {
+        copy_dir(manifest.dir(), &static_dir)?;
+    }
+    for (name, hashed) in manifest.files() {
+        fs::copy(static_dir.join(name), static_dir.join(hashed))?;
+    }
+    Ok(paths.len())
+}
+
+/// Copies a directory and everything in it.
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let to = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &to)?;
+        } else {
+            fs::copy(entry.path(), to)?;
+        }
+    }
+    Ok(())
+}
+
+/// Wraps an error generating the site as an I/O error.
+fn generate_error(e: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use actix_web::rt::System;
+    use db::{build_pool, PoolConfig, ReadReplicas};
+
+    use super::*;
+    use crate::test::{test_templates, test_translation, TestSwordDrill, VERSION};
+
+    #[test]
+    fn static_site() {
+        let manifest = AssetManifest::build(Path::new("./dist")).unwrap();
+        let template = test_templates();
+        let data = web::Data::new(ServerData {
+            db: build_pool(":memory:", &PoolConfig::default()),
+            replicas: ReadReplicas::default(),
+            default_translation: "kjv".to_string(),
+            fallback_translation: None,
+            template,
+            translations: vec![test_translation()],
+        });
+        let out = env::temp_dir().join("biblers-generate-test");
+        let _ = fs::remove_dir_all(&out);
+
+        let pages = System::new()
+            .block_on(generate::<TestSwordDrill>(data, VERSION, &manifest, &out))
+            .unwrap();
+        // The list of books, Psalms, and its 150 chapters
+        assert_eq!(pages, 152);
+        assert!(out.join("index.html").is_file());
+        assert!(out.join("Psalms/index.html").is_file());
+        let chapter = fs::read_to_string(out.join("Psalms/119/index.html")).unwrap();
+        assert!(chapter.contains("Thy word is a lamp unto my feet"));
+        assert!(out.join("static/manifest.json").is_file());
+        assert!(out
+            .join(manifest.url("img/book.svg").trim_start_matches('/'))
+            .is_file());
+
+        fs::remove_dir_all(&out).unwrap();
+    }
+}
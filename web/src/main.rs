@@ -3,32 +3,128 @@
 use std::env;
 use std::error::Error;
 use std::io;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
-use actix_web::{middleware, web, App, HttpResponse, HttpServer};
+use actix_web::middleware::{self, Condition};
+use actix_web::{web, App, HttpResponse, HttpServer};
+use clap::{Parser, Subcommand};
 use dotenv::dotenv;
 use handlebars::Handlebars;
 
-use db::{build_pool, establish_connection, run_migrations, SqliteConnectionPool, SwordDrill};
+use db::models::Translation;
+use db::{
+    build_pool, establish_tuned_connection, run_migrations, BookStore, PoolConfig, PoolMetrics,
+    ReadReplicas, SqliteConnectionPool, SqliteTuning, SwordDrill,
+};
 
-use crate::controllers::{api, view};
+use crate::assets::AssetManifest;
+use crate::auth::RequireApiKey;
+use crate::backup::AdminKey;
+use crate::cache::{CacheResponses, ResponseCache};
+use crate::compress::PreferBrotli;
+#[cfg(feature = "grpc")]
+use crate::controllers::grpc;
+use crate::controllers::{api, graphql, view, ws};
+use crate::minify::MinifyHtml;
+use crate::rate_limit::{RateLimit, RateLimiter};
+
+/// Implementation of [SwordDrillable](db::SwordDrillable) that the routes look up verses with.
+#[cfg(not(any(feature = "tantivy", feature = "memory-passages")))]
+type Drill = SwordDrill;
+#[cfg(all(feature = "tantivy", not(feature = "memory-passages")))]
+type Drill = db::TantivyDrill;
+#[cfg(feature = "memory-passages")]
+type Drill = db::MemoryDrill;
+
+/// Options the web app is run with, which serves the site unless given a
+/// command.
+#[derive(Parser, Debug)]
+#[clap(about = "Web server for Bible.rs")]
+struct Opts {
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Pre-render the pages to static HTML in a directory, instead of
+    /// serving them
+    Generate {
+        #[clap(long, value_name = "DIR", default_value = "./public")]
+        out: PathBuf,
+    },
+}
 
 /// Represents the [server data](actix_web.web.Data.html) for the application.
 pub struct ServerData {
+    /// Pool of connections to the primary database.
     pub db: SqliteConnectionPool,
+    /// Read replicas of the database, which pages and the API read from.
+    pub replicas: ReadReplicas,
+    /// Abbreviation of the translation served when a URL has no translation prefix.
+    pub default_translation: String,
+    /// Abbreviation of the translation that fills verses omitted by the requested one.
+    pub fallback_translation: Option<String>,
     pub template: Handlebars<'static>,
+    /// Translations installed in the database.
+    pub translations: Vec<Translation>,
 }
 
-/// Registers the [Handlebars](handlebars.handlebars.html) templates for the application.
-fn register_templates() -> Result<Handlebars<'static>, Box<dyn Error>> {
+impl ServerData {
+    /// Gets the pool to read from: the next read replica in turn, or the
+    /// primary database if there are none.
+    pub fn read_db(&self) -> &SqliteConnectionPool {
+        self.replicas.next(&self.db)
+    }
+}
+
+/// Registers the [Handlebars](handlebars.handlebars.html) templates for the application,
+/// along with the helper linking to the static files by their hashed names.
+fn register_templates(
+    manifest: &Arc<AssetManifest>,
+) -> Result<Handlebars<'static>, Box<dyn Error>> {
     let mut tpl = Handlebars::new();
     tpl.set_strict_mode(true);
+    assets::register_helper(&mut tpl, Arc::clone(manifest));
     tpl.register_templates_directory(".hbs", "./web/templates/")?;
 
     Ok(tpl)
 }
 
+/// Builds the route pattern matching the abbreviation of any installed translation.
+///
+/// The abbreviations are matched literally so a translation prefix is never
+/// mistaken for a book name (e.g. `/kjv/John` vs. `/John/3`).
+fn version_pattern(translations: &[Translation]) -> String {
+    let abbreviations: Vec<&str> = translations
+        .iter()
+        .map(|t| t.abbreviation.as_str())
+        .collect();
+    format!("{{version:{}}}", abbreviations.join("|"))
+}
+
+/// Gets the URL of the database used when `DATABASE_URL` isn't set.
+///
+/// With the `embedded-db` feature, that's the database built into the
+/// binary, extracted to the temp directory.
+fn default_database_url() -> String {
+    #[cfg(feature = "embedded-db")]
+    {
+        let path = embedded::extract(&env::temp_dir()).expect("Error extracting the database");
+        path.to_string_lossy().into_owned()
+    }
+    #[cfg(not(feature = "embedded-db"))]
+    {
+        "/tmp/biblers.db".to_string()
+    }
+}
+
 #[actix_web::main]
 async fn main() -> io::Result<()> {
+    let opts: Opts = Opts::parse();
     dotenv().ok();
 
     // Set up logging
@@ -36,60 +132,338 @@ async fn main() -> io::Result<()> {
     env_logger::init();
 
     // Get env configuration
-    let url = env::var("DATABASE_URL").unwrap_or_else(|_| "/tmp/biblers.db".to_string());
+    let url = env::var("DATABASE_URL").unwrap_or_else(|_| default_database_url());
+    // Read replicas of the database, separated by commas
+    let replica_urls: Vec<String> = env::var("DATABASE_REPLICA_URLS")
+        .map(|urls| {
+            urls.split(',')
+                .map(|u| u.trim().to_string())
+                .filter(|u| !u.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+    let default_translation = env::var("DEFAULT_TRANSLATION").unwrap_or_else(|_| "kjv".to_string());
+    let fallback_translation = env::var("FALLBACK_TRANSLATION").ok();
+    // Whether the API can only be called with an issued API key
+    let require_api_key = env::var("REQUIRE_API_KEY").map_or(false, |v| v == "true");
+    // Requests a minute each client can make to the API, or 0 for no limit
+    let rate_limit: u32 = env::var("RATE_LIMIT_PER_MINUTE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(120);
+    // Addresses of the proxies trusted to forward the addresses of clients
+    // for the rate limit, separated by commas
+    let trusted_proxies: Vec<IpAddr> = env::var("TRUSTED_PROXIES")
+        .map(|proxies| {
+            proxies
+                .split(',')
+                .filter_map(|p| p.trim().parse().ok())
+                .collect()
+        })
+        .unwrap_or_default();
+    // Passage responses to keep in memory, or 0 for none
+    let response_cache_size: usize = env::var("RESPONSE_CACHE_SIZE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(1000);
+    // Database connections to keep open at most and at least, and how long to
+    // wait for one
+    let pool_metrics = PoolMetrics::default();
+    let pool_config = PoolConfig {
+        max_size: env::var("DB_POOL_SIZE")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(15),
+        min_idle: env::var("DB_POOL_MIN_IDLE")
+            .ok()
+            .and_then(|v| v.parse().ok()),
+        connection_timeout: Duration::from_secs(
+            env::var("DB_POOL_TIMEOUT_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(30),
+        ),
+        metrics: Some(pool_metrics.clone()),
+        // Directory of translation files to read along with the database
+        translations_dir: env::var("TRANSLATIONS_DIR").ok().map(PathBuf::from),
+        // SQLite settings, so reads don't wait on imports writing
+        tuning: SqliteTuning {
+            wal: env::var("SQLITE_WAL").map_or(false, |v| v == "true"),
+            mmap_size: env::var("SQLITE_MMAP_SIZE")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            cache_size: env::var("SQLITE_CACHE_SIZE")
+                .ok()
+                .and_then(|v| v.parse().ok()),
+            busy_timeout: Duration::from_millis(
+                env::var("SQLITE_BUSY_TIMEOUT_MS")
+                    .ok()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(5000),
+            ),
+        },
+    };
+    // Origins of other sites allowed to call the API from browsers
+    let cors_origins: Vec<String> = env::var("CORS_ORIGINS")
+        .map(|origins| {
+            origins
+                .split(',')
+                .map(|o| o.trim().to_string())
+                .filter(|o| !o.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
 
     // Set up sentry
     let _sentry = sentry::init(sentry::ClientOptions::default());
 
     // Run DB migrations for a new SQLite database
-    run_migrations(&establish_connection(&url)).expect("Error running migrations");
+    let conn = establish_tuned_connection(&url, &pool_config.tuning);
+    run_migrations(&conn).expect("Error running migrations");
+    // The migrations load the King James Version, and databases whose
+    // tables were made some other way are seeded with the bundled texts
+    let seeded = db::seed_verses(&conn).expect("Error seeding the verses");
+    if seeded > 0 {
+        log::info!("Seeded the database with {} verses", seeded);
+    }
+    if db::verse_count(&conn).expect("Error counting verses") == 0 {
+        log::warn!("The database has no verses, so the Bible will be empty");
+    }
+    if let Some(dir) = &pool_config.translations_dir {
+        let files = db::attach_translations(dir, &conn).expect("Error attaching translations");
+        log::info!("Attached {} translation files", files);
+    }
+
+    // Match the installed translations as URL prefixes
+    let translations = SwordDrill::translations(&conn).expect("Error loading translations");
+    let version = version_pattern(&translations);
+
+    // Load the search index, building it the first time
+    #[cfg(feature = "tantivy")]
+    {
+        let dir = env::var("SEARCH_INDEX_DIR").unwrap_or_else(|_| "/tmp/biblers-index".to_string());
+        db::load_search_index(Path::new(&dir), &conn).expect("Error loading the search index");
+    }
+
+    // Load the verses into memory, so passages are read without querying
+    // SQLite
+    #[cfg(feature = "memory-passages")]
+    {
+        let verses = db::load_corpus(&conn).expect("Error loading the verses into memory");
+        log::info!("Loaded {} verses into memory", verses);
+    }
+
+    // Hash the static files, so clients can cache them until they change
+    let manifest = Arc::new(
+        AssetManifest::build(Path::new("./web/dist")).expect("Error hashing the static files"),
+    );
 
     let app_data = web::Data::new(ServerData {
         // Create database connection pool
-        db: build_pool(&url),
+        db: build_pool(&url, &pool_config),
+        // Spread reads over the read replicas, if there are any
+        replicas: ReadReplicas::new(
+            replica_urls
+                .iter()
+                .map(|url| build_pool(url, &pool_config))
+                .collect(),
+        ),
+        default_translation,
+        fallback_translation,
         // Create handlebars registry
-        template: register_templates().unwrap(),
+        template: register_templates(&manifest).unwrap(),
+        translations,
     });
 
-    HttpServer::new(move || {
+    if let Some(Command::Generate { out }) = opts.command {
+        let pages = generate::generate::<Drill>(app_data, &version, &manifest, &out).await?;
+        log::info!("Generated {} pages in {}", pages, out.display());
+        return Ok(());
+    }
+
+    let schema = web::Data::new(graphql::schema::<Drill>());
+
+    // Serve the gRPC service on its own port
+    #[cfg(feature = "grpc")]
+    {
+        let addr = env::var("GRPC_ADDR").unwrap_or_else(|_| grpc::DEFAULT_ADDR.to_string());
+        let addr = addr.parse().expect("Invalid GRPC_ADDR");
+        let service = grpc::service::<Drill>(app_data.clone());
+        actix_web::rt::spawn(async move {
+            if let Err(e) = tonic::transport::Server::builder()
+                .add_service(service)
+                .serve(addr)
+                .await
+            {
+                log::error!("Error serving gRPC: {}", e);
+            }
+        });
+    }
+
+    // Shared by the workers, so clients can't get around the limit
+    let rate_limiter =
+        Arc::new(RateLimiter::per_minute(rate_limit).with_trusted_proxies(trusted_proxies));
+    let response_cache = ResponseCache::new(response_cache_size);
+
+    // Share cached responses with other instances through Redis
+    #[cfg(feature = "shared-cache")]
+    let response_cache = match env::var("REDIS_URL") {
+        Ok(redis_url) => {
+            let data_version = db::data_version(&conn).expect("Error loading the data version");
+            let shared =
+                cache::SharedCache::connect(&redis_url, &data_version, &app_data.translations)
+                    .await
+                    .expect("Error connecting to Redis");
+            response_cache.with_shared(shared)
+        }
+        Err(_) => response_cache,
+    };
+    let response_cache = web::Data::new(response_cache);
+    let pool_metrics = web::Data::new(pool_metrics);
+    // Key that backups of the database can be downloaded with
+    let admin_key = web::Data::new(AdminKey(
+        env::var("ADMIN_API_KEY").ok().filter(|k| !k.is_empty()),
+    ));
+
+    let server = HttpServer::new(move || {
         // Wire up the application
         App::new()
+            .wrap(MinifyHtml)
             .wrap(middleware::Compress::default())
+            .wrap(PreferBrotli)
             .wrap(middleware::Logger::default())
             .app_data(app_data.clone())
-            .service(actix_files::Files::new("/static", "./web/dist").use_etag(true))
+            .app_data(schema.clone())
+            .app_data(response_cache.clone())
+            .app_data(pool_metrics.clone())
+            .app_data(admin_key.clone())
+            .configure(|cfg| assets::routes(cfg, &manifest))
+            .service(actix_files::Files::new(assets::STATIC_PATH, manifest.dir()).use_etag(true))
             .service(web::resource("about").to(view::about))
+            .service(web::resource("metrics").route(web::get().to(metrics::metrics)))
+            .service(web::resource("admin/backup").route(web::get().to(backup::backup)))
+            .service(
+                web::resource("graphql")
+                    .wrap(Condition::new(
+                        require_api_key,
+                        RequireApiKey::<Drill>::default(),
+                    ))
+                    .wrap(Condition::new(
+                        rate_limit > 0,
+                        RateLimit::<Drill>::new(rate_limiter.clone()),
+                    ))
+                    .wrap(api::cors(&cors_origins))
+                    .route(web::get().to(graphql::playground))
+                    .route(web::post().to(graphql::graphql::<Drill>)),
+            )
+            .service(
+                web::resource("parallel/{reference:.+\\d}")
+                    .name("parallel")
+                    .route(web::get().to(view::parallel::<Drill>)),
+            )
+            .service(
+                web::resource(version.as_str())
+                    .name("version_bible")
+                    .route(web::get().to(view::all_books::<Drill>)),
+            )
+            .service(
+                web::resource(format!("{}/{{book}}", version))
+                    .name("version_book")
+                    .route(web::get().to(view::book::<Drill>)),
+            )
+            .service(
+                web::resource(format!("{}/{{reference:.+\\d}}", version))
+                    .name("version_reference")
+                    .wrap(CacheResponses)
+                    .route(web::get().to(view::reference::<Drill>)),
+            )
             .service(
                 web::resource("/")
                     .name("bible")
-                    .route(web::get().to(view::all_books::<SwordDrill>)),
+                    .route(web::get().to(view::all_books::<Drill>)),
+            )
+            .service(
+                web::resource("search")
+                    .wrap(CacheResponses)
+                    .route(web::get().to(view::search::<Drill>)),
             )
-            .service(web::resource("search").route(web::get().to(view::search::<SwordDrill>)))
+            .service(web::resource("ws/search").route(web::get().to(ws::search::<Drill>)))
             .service(
                 web::resource("{book}")
                     .name("book")
-                    .route(web::get().to(view::book::<SwordDrill>)),
+                    .route(web::get().to(view::book::<Drill>)),
             )
             .service(
                 web::resource("{reference:.+\\d}")
                     .name("reference")
-                    .route(web::get().to(view::reference::<SwordDrill>)),
+                    .wrap(CacheResponses)
+                    .route(web::get().to(view::reference::<Drill>)),
             )
-            .service(web::resource("api/search").route(web::get().to(api::search::<SwordDrill>)))
+            .service(web::resource("api/openapi.json").route(web::get().to(api::openapi)))
+            .service(web::resource("api/docs").route(web::get().to(view::api_docs)))
             .service(
-                web::resource("api/{reference}.json")
-                    .route(web::get().to(api::reference::<SwordDrill>)),
+                web::scope(api::API_V1)
+                    .wrap(Condition::new(
+                        require_api_key,
+                        RequireApiKey::<Drill>::default(),
+                    ))
+                    .wrap(Condition::new(
+                        rate_limit > 0,
+                        RateLimit::<Drill>::new(rate_limiter.clone()),
+                    ))
+                    .wrap(api::cors(&cors_origins))
+                    .configure(|cfg| api::routes::<Drill>(cfg, &version)),
+            )
+            // Routes from before the API was versioned
+            .service(
+                web::scope("/api")
+                    .wrap(Condition::new(
+                        require_api_key,
+                        RequireApiKey::<Drill>::default(),
+                    ))
+                    .wrap(Condition::new(
+                        rate_limit > 0,
+                        RateLimit::<Drill>::new(rate_limiter.clone()),
+                    ))
+                    .wrap(api::cors(&cors_origins))
+                    .wrap(api::deprecated())
+                    .configure(|cfg| api::routes::<Drill>(cfg, &version)),
             )
             .default_service(web::route().to(HttpResponse::NotFound))
     })
-    .bind("0.0.0.0:8080")?
-    .run()
-    .await
+    .bind("0.0.0.0:8080")?;
+
+    // Serve HTTPS too, with HTTP/2 for clients that support it, when there's
+    // a certificate
+    #[cfg(feature = "tls")]
+    let server = match (env::var("TLS_CERT_PATH"), env::var("TLS_KEY_PATH")) {
+        (Ok(cert), Ok(key)) => {
+            let config = tls::server_config(Path::new(&cert), Path::new(&key))?;
+            let addr = env::var("TLS_ADDR").unwrap_or_else(|_| tls::DEFAULT_ADDR.to_string());
+            server.bind_rustls(addr, config)?
+        }
+        _ => server,
+    };
+
+    server.run().await
 }
 
+mod assets;
+mod auth;
+mod backup;
+mod cache;
+mod compress;
 mod controllers;
+#[cfg(feature = "embedded-db")]
+mod embedded;
 mod error;
+mod generate;
 mod macros;
+mod metrics;
+mod minify;
+mod rate_limit;
 mod responder;
 #[cfg(test)]
 mod test;
+#[cfg(feature = "tls")]
+mod tls;
@@ -0,0 +1,88 @@
+use std::fmt::Write;
+
+use actix_web::{web, HttpResponse};
+
+use db::PoolMetrics;
+
+use crate::ServerData;
+
+/// Responds with how busy the database connection pool is, in the
+/// Prometheus text format, so it can be told when the pool needs more
+/// connections.
+pub async fn metrics(data: web::Data<ServerData>, pool: web::Data<PoolMetrics>) -> HttpResponse {
+    let state = data.db.state();
+    let mut body = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, value: String| {
+        let _ = writeln!(body, "# HELP biblers_db_{} {}", name, help);
+        let _ = writeln!(body, "# TYPE biblers_db_{} {}", name, kind);
+        let _ = writeln!(body, "biblers_db_{} {}", name, value);
+    };
+    metric(
+        "connections",
+        "gauge",
+        "Connections open in the pool.",
+        state.connections.to_string(),
+    );
+    metric(
+        "idle_connections",
+        "gauge",
+        "Open connections that aren't checked out.",
+        state.idle_connections.to_string(),
+    );
+    metric(
+        "max_connections",
+        "gauge",
+        "Most connections the pool opens at once.",
+        data.db.max_size().to_string(),
+    );
+    metric(
+        "checkouts_total",
+        "counter",
+        "Connections checked out of the pool.",
+        pool.checkouts().to_string(),
+    );
+    metric(
+        "checkout_timeouts_total",
+        "counter",
+        "Times no connection was free before the connection timeout.",
+        pool.timeouts().to_string(),
+    );
+    metric(
+        "checkout_wait_seconds_total",
+        "counter",
+        "Time spent waiting for connections to be checked out.",
+        pool.wait_time().as_secs_f64().to_string(),
+    );
+
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{rt::System, test, App};
+
+    use super::*;
+    use crate::test::test_server_data;
+
+    #[test]
+    fn pool_metrics() {
+        System::new().block_on(async {
+            let srv = test::init_service(
+                App::new()
+                    .app_data(test_server_data())
+                    .app_data(web::Data::new(PoolMetrics::default()))
+                    .route("/metrics", web::get().to(metrics)),
+            )
+            .await;
+
+            let req = test::TestRequest::with_uri("/metrics").to_request();
+            let body =
+                String::from_utf8(test::call_and_read_body(&srv, req).await.to_vec()).unwrap();
+            assert!(body.contains("# TYPE biblers_db_connections gauge\n"));
+            assert!(body.contains("\nbiblers_db_max_connections 15\n"));
+            assert!(body.contains("\nbiblers_db_checkout_timeouts_total 0\n"));
+        });
+    }
+}
@@ -0,0 +1,204 @@
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+
+use actix_web::body::{self, BoxBody, MessageBody};
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header;
+
+/// Elements whose contents are kept as they are, since their whitespace
+/// (or what looks like comments in them) matters.
+const RAW_ELEMENTS: [&str; 4] = ["pre", "script", "style", "textarea"];
+
+/// Middleware minifying HTML responses, taking out the comments and the
+/// indentation of the templates, which make up a surprising share of the
+/// large chapter pages.
+///
+/// It has to be wrapped by [Compress](actix_web::middleware::Compress) to
+/// see the responses before they're compressed. Responses that aren't HTML,
+/// or that are already encoded, go through as they are.
+#[derive(Clone, Copy, Default)]
+pub struct MinifyHtml;
+
+impl<S, B> Transform<S, ServiceRequest> for MinifyHtml
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = actix_web::Error;
+    type Transform = MinifyHtmlMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(MinifyHtmlMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+/// Service minifying responses for [MinifyHtml].
+pub struct MinifyHtmlMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for MinifyHtmlMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        Box::pin(async move {
+            let (req, resp) = service.call(req).await?.into_parts();
+            let (resp, body) = resp.map_into_boxed_body().into_parts();
+            let html = resp
+                .headers()
+                .get(header::CONTENT_TYPE)
+                .and_then(|t| t.to_str().ok())
+                .map_or(false, |t| t.starts_with("text/html"));
+            if !html || resp.headers().contains_key(header::CONTENT_ENCODING) {
+                return Ok(ServiceResponse::new(req, resp.set_body(body)));
+            }
+
+            let body = body::to_bytes(body)
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+            let body = match std::str::from_utf8(&body) {
+                Ok(text) => minify(text).into_bytes().into(),
+                Err(_) => body,
+            };
+            Ok(ServiceResponse::new(
+                req,
+                resp.set_body(body).map_into_boxed_body(),
+            ))
+        })
+    }
+}
+
+/// Minifies HTML, taking out its comments and collapsing each run of
+/// whitespace to a space, or to a line break if it had one, so the text
+/// reads the same. The contents of [RAW_ELEMENTS] are kept as they are.
+fn minify(html: &str) -> String {
+    let mut minified = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(i) = rest.find(|c: char| c == '<' || c.is_ascii_whitespace()) {
+        minified.push_str(&rest[..i]);
+        rest = &rest[i..];
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            match comment.find("-->") {
+                Some(end) => rest = &comment[end + 3..],
+                None => rest = "",
+            }
+        } else if let Some(element) = raw_element(rest) {
+            let end = rest.find(&format!("</{}", element)).unwrap_or(rest.len());
+            minified.push_str(&rest[..end]);
+            rest = &rest[end..];
+            // Keep the closing tag's `<` from starting the element again
+            if !rest.is_empty() {
+                minified.push('<');
+                rest = &rest[1..];
+            }
+        } else if rest.starts_with('<') {
+            minified.push('<');
+            rest = &rest[1..];
+        } else {
+            let end = rest
+                .find(|c: char| !c.is_ascii_whitespace())
+                .unwrap_or(rest.len());
+            minified.push(if rest[..end].contains('\n') {
+                '\n'
+            } else {
+                ' '
+            });
+            rest = &rest[end..];
+        }
+    }
+    minified.push_str(rest);
+    minified
+}
+
+/// Gets the name of the raw element that the HTML starts with, if it starts
+/// with one.
+fn raw_element(html: &str) -> Option<&'static str> {
+    let tag = html.strip_prefix('<')?;
+    RAW_ELEMENTS.iter().copied().find(|element| {
+        tag.get(..element.len())
+            .map_or(false, |name| name.eq_ignore_ascii_case(element))
+            && tag[element.len()..].starts_with(|c: char| c == '>' || c.is_ascii_whitespace())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::middleware::Compress;
+    use actix_web::{rt::System, test, web, App, HttpResponse};
+
+    use super::*;
+
+    #[test]
+    fn minified() {
+        assert_eq!(
+            minify("<ul>\n    <li>A</li>\n    <li>B  C</li>\n</ul>"),
+            "<ul>\n<li>A</li>\n<li>B C</li>\n</ul>"
+        );
+        assert_eq!(
+            minify("<p>Thy word<!-- verse 105 --> is a lamp</p>"),
+            "<p>Thy word is a lamp</p>"
+        );
+        assert_eq!(
+            minify("<pre>  a\n  b</pre>\n  <script>\n  // <!-- x -->\n</script>"),
+            "<pre>  a\n  b</pre>\n<script>\n  // <!-- x -->\n</script>"
+        );
+        // Other elements that start with the same letters are minified
+        assert_eq!(
+            minify("<p>  <preview>  </preview>"),
+            "<p> <preview> </preview>"
+        );
+        assert_eq!(minify("<p>a<!-- open"), "<p>a");
+    }
+
+    #[test]
+    fn html_responses() {
+        System::new().block_on(async {
+            let srv = test::init_service(
+                App::new()
+                    .wrap(MinifyHtml)
+                    .wrap(Compress::default())
+                    .route(
+                        "/html",
+                        web::get().to(|| async {
+                            HttpResponse::Ok()
+                                .content_type("text/html; charset=utf-8")
+                                .body("<p>\n    Thy word\n</p>")
+                        }),
+                    )
+                    .route(
+                        "/text",
+                        web::get().to(|| async {
+                            HttpResponse::Ok()
+                                .content_type("text/plain")
+                                .body("Thy   word")
+                        }),
+                    ),
+            )
+            .await;
+
+            let req = test::TestRequest::with_uri("/html").to_request();
+            assert_eq!(
+                test::call_and_read_body(&srv, req).await,
+                "<p>\nThy word\n</p>"
+            );
+            let req = test::TestRequest::with_uri("/text").to_request();
+            assert_eq!(test::call_and_read_body(&srv, req).await, "Thy   word");
+        });
+    }
+}
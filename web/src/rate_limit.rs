@@ -0,0 +1,302 @@
+use std::collections::HashMap;
+use std::future::{ready, Future, Ready};
+use std::marker::PhantomData;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::web;
+
+use db::SwordDrillable;
+
+use crate::auth::api_key;
+use crate::error::{Error, JsonError};
+use crate::ServerData;
+
+/// Number of clients to keep buckets for before forgetting the idle ones.
+const MAX_CLIENTS: usize = 10_000;
+
+/// Tokens a client has left, as of when they were last counted.
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token buckets limiting how often each client can make requests. A
+/// bucket holds a minute's worth of requests, so clients can make them in
+/// bursts, and is refilled at the rate that's allowed.
+#[derive(Debug)]
+pub struct RateLimiter {
+    capacity: f64,
+    per_second: f64,
+    buckets: Mutex<HashMap<String, Bucket>>,
+    /// Addresses of the proxies trusted to give the addresses of the
+    /// clients they forward requests for.
+    trusted_proxies: Vec<IpAddr>,
+}
+
+impl RateLimiter {
+    /// Allows each client a number of requests a minute.
+    pub fn per_minute(requests: u32) -> Self {
+        Self {
+            capacity: requests.into(),
+            per_second: f64::from(requests) / 60.0,
+            buckets: Mutex::new(HashMap::new()),
+            trusted_proxies: vec![],
+        }
+    }
+
+    /// Trusts the `Forwarded` and `X-Forwarded-For` headers of requests
+    /// from the given proxies to give the addresses of clients. Those of
+    /// other requests are ignored, since clients could send any address.
+    pub fn with_trusted_proxies(mut self, proxies: Vec<IpAddr>) -> Self {
+        self.trusted_proxies = proxies;
+        self
+    }
+
+    /// Gets the address a request is counted by, which is the address it
+    /// came from unless it came through a trusted proxy.
+    fn client_address(&self, req: &ServiceRequest) -> String {
+        match req.peer_addr().map(|addr| addr.ip()) {
+            Some(ip) if self.trusted_proxies.contains(&ip) => req
+                .connection_info()
+                .realip_remote_addr()
+                .unwrap_or_default()
+                .to_string(),
+            Some(ip) => ip.to_string(),
+            None => String::new(),
+        }
+    }
+
+    /// Takes a token for a request from a client, or gets how long until
+    /// the client has one.
+    fn take(&self, client: &str, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_CLIENTS && !buckets.contains_key(client) {
+            // Full buckets are the same as new ones
+            buckets.retain(|_, b| self.refilled(b, now) < self.capacity);
+        }
+
+        let bucket = buckets.entry(client.to_string()).or_insert(Bucket {
+            tokens: self.capacity,
+            updated: now,
+        });
+        bucket.tokens = self.refilled(bucket, now);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / self.per_second,
+            ))
+        }
+    }
+
+    /// Counts the tokens a bucket has been refilled with by a time.
+    fn refilled(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        (bucket.tokens + elapsed * self.per_second).min(self.capacity)
+    }
+}
+
+/// Middleware limiting how often each client can call the API, to keep
+/// scrapers from overloading the database. Clients are told when to try
+/// again with a `429 Too Many Requests` response.
+///
+/// Clients with an API key that's been issued and not revoked are limited
+/// by their key, which is looked up with `SD`, and others by their IP
+/// address. Keys that are looked up are kept with the request, so
+/// [RequireApiKey](crate::auth::RequireApiKey) inside this doesn't look
+/// them up again.
+pub struct RateLimit<SD> {
+    limiter: Arc<RateLimiter>,
+    drill: PhantomData<fn() -> SD>,
+}
+
+impl<SD> RateLimit<SD> {
+    pub fn new(limiter: Arc<RateLimiter>) -> Self {
+        Self {
+            limiter,
+            drill: PhantomData,
+        }
+    }
+}
+
+impl<S, B, SD> Transform<S, ServiceRequest> for RateLimit<SD>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+    SD: SwordDrillable + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Transform = RateLimitMiddleware<S, SD>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware {
+            service: Rc::new(service),
+            limiter: Arc::clone(&self.limiter),
+            drill: PhantomData,
+        }))
+    }
+}
+
+/// Service limiting the requests of clients for [RateLimit].
+pub struct RateLimitMiddleware<S, SD> {
+    service: Rc<S>,
+    limiter: Arc<RateLimiter>,
+    drill: PhantomData<fn() -> SD>,
+}
+
+impl<S, B, SD> Service<ServiceRequest> for RateLimitMiddleware<S, SD>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+    SD: SwordDrillable + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let limiter = Arc::clone(&self.limiter);
+        Box::pin(async move {
+            // Keys are only counted once they're known to be issued, or else
+            // clients could send a new one with each request
+            let issued = match api_key(req.headers()) {
+                Some(key) => {
+                    let data = req
+                        .app_data::<web::Data<ServerData>>()
+                        .expect("Server data is missing")
+                        .clone();
+                    web::block(move || SD::api_key(&key, &data.read_db().get().unwrap()))
+                        .await?
+                        .map_err(JsonError::from)?
+                }
+                None => None,
+            };
+            let client = match issued {
+                Some(issued) => {
+                    let client = format!("key:{}", issued.id);
+                    req.extensions_mut().insert(issued);
+                    client
+                }
+                None => format!("ip:{}", limiter.client_address(&req)),
+            };
+
+            if let Err(wait) = limiter.take(&client, Instant::now()) {
+                // Round up, so clients don't try again too soon
+                let seconds = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+                return Err(JsonError::from(Error::RateLimited(seconds)).into());
+            }
+            service.call(req).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::{header, StatusCode};
+    use actix_web::rt::System;
+    use actix_web::{test, App, HttpResponse};
+
+    use super::*;
+    use crate::test::{test_server_data, TestSwordDrill, TEST_API_KEY};
+
+    #[test]
+    fn token_buckets() {
+        let limiter = RateLimiter::per_minute(2);
+        let start = Instant::now();
+        assert_eq!(limiter.take("a", start), Ok(()));
+        assert_eq!(limiter.take("a", start), Ok(()));
+        assert_eq!(limiter.take("a", start), Err(Duration::from_secs(30)));
+        // Other clients have their own buckets
+        assert_eq!(limiter.take("b", start), Ok(()));
+
+        // Tokens are refilled over time, up to the capacity
+        let later = start + Duration::from_secs(45);
+        assert_eq!(limiter.take("a", later), Ok(()));
+        assert_eq!(limiter.take("a", later), Err(Duration::from_secs(15)));
+        let much_later = start + Duration::from_secs(3600);
+        assert_eq!(limiter.take("a", much_later), Ok(()));
+        assert_eq!(limiter.take("a", much_later), Ok(()));
+        assert!(limiter.take("a", much_later).is_err());
+    }
+
+    #[test]
+    fn too_many_requests() {
+        System::new().block_on(async {
+            let limit = RateLimit::<TestSwordDrill>::new(Arc::new(RateLimiter::per_minute(1)));
+            let srv = test::init_service(
+                App::new().service(
+                    web::scope("/api")
+                        .wrap(limit)
+                        .route("/test", web::get().to(HttpResponse::Ok)),
+                ),
+            )
+            .await;
+            let call =
+                || test::call_service(&srv, test::TestRequest::with_uri("/api/test").to_request());
+
+            assert_eq!(call().await.status(), StatusCode::OK);
+            let resp = call().await;
+            assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+            assert_eq!(resp.headers()[header::RETRY_AFTER], "60");
+        });
+    }
+
+    #[test]
+    fn clients() {
+        System::new().block_on(async {
+            let proxy = "10.0.0.1".parse().unwrap();
+            let limiter = RateLimiter::per_minute(1).with_trusted_proxies(vec![proxy]);
+            let srv = test::init_service(
+                App::new().app_data(test_server_data()).service(
+                    web::scope("/api")
+                        .wrap(RateLimit::<TestSwordDrill>::new(Arc::new(limiter)))
+                        .route("/test", web::get().to(HttpResponse::Ok)),
+                ),
+            )
+            .await;
+            let call = |peer: &str, header: (&'static str, String)| {
+                let req = test::TestRequest::with_uri("/api/test")
+                    .peer_addr(format!("{}:4000", peer).parse().unwrap())
+                    .insert_header(header)
+                    .to_request();
+                test::call_service(&srv, req)
+            };
+
+            // Keys that weren't issued and addresses that weren't forwarded
+            // by a trusted proxy don't get clients new buckets
+            let key = |i: usize| ("x-api-key", format!("random-{}", i));
+            assert_eq!(call("192.0.2.1", key(1)).await.status(), StatusCode::OK);
+            for i in 2..4 {
+                let status = call("192.0.2.1", key(i)).await.status();
+                assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+            }
+            let forwarded = |i: usize| ("x-forwarded-for", format!("198.51.100.{}", i));
+            for i in 1..3 {
+                let status = call("192.0.2.1", forwarded(i)).await.status();
+                assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+            }
+
+            // Issued keys and the clients of trusted proxies have their own
+            let issued = ("x-api-key", TEST_API_KEY.to_string());
+            assert_eq!(call("192.0.2.1", issued).await.status(), StatusCode::OK);
+            for i in 1..3 {
+                let status = call("10.0.0.1", forwarded(i)).await.status();
+                assert_eq!(status, StatusCode::OK);
+            }
+        });
+    }
+}
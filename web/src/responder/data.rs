@@ -1,12 +1,21 @@
+use actix_web::http::StatusCode;
 use actix_web::HttpRequest;
 use handlebars::Handlebars;
 use log::error;
 use serde_derive::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
-use db::models::{Book, Reference, Verse, VerseFTS};
+use db::models::{
+    Book, BookAbbreviation, CrossReference, Reference, ResultSet, SearchMode, SearchOptions, Stats,
+    Testament, Translation, Verse, VerseFTS, VerseMatches,
+};
 
 use crate::error::Error;
-use crate::responder::link::{verse_url, AllBooksLinks, BookLinks, Link, VersesLinks};
+use crate::responder::link::{
+    book_url, chapter_url, reference_url, search_url, verse_url, version_verse_url, AllBooksLinks,
+    BookLinks, ChapterNavigation, Link, PassageLinks, SearchPageLinks, SuggestionLinks,
+    VersesLinks,
+};
 use crate::responder::meta::Meta;
 
 /// Represents empty data.
@@ -19,62 +28,430 @@ pub struct EmptyData;
 /// Error data for a view (HTML or JSON)
 #[derive(Clone, Deserialize, Serialize, Debug)]
 pub struct ErrorData {
+    /// What went wrong, to show to a user.
     message: String,
 }
 
 impl ErrorData {
-    /// Create new error data with a message
-    pub fn new(m: &str) -> Self {
+    /// Creates new error data from a (db.Error.html)
+    pub fn from_error(e: &Error) -> Self {
         Self {
-            message: m.to_string(),
+            message: e.to_string(),
         }
     }
+}
 
-    /// Creates new error data from a (db.Error.html)
-    pub fn from_error(e: &Error) -> Self {
-        Self {
+/// Problem details of a JSON API error (RFC 7807), telling clients what
+/// kind of error it was with its code and what it was about.
+///
+/// Responses have the `message` of [ErrorData] too, as API errors did before
+/// they had problem details.
+#[derive(Clone, Deserialize, Serialize, Debug, ToSchema)]
+pub struct ProblemData {
+    /// URI of the kind of problem, which is only described by its code.
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    /// Reason phrase of the HTTP status.
+    pub title: String,
+    pub status: u16,
+    /// What went wrong, to show to a user.
+    pub detail: String,
+    /// Kind of error (e.g. `book_not_found` or `invalid_reference`).
+    pub code: String,
+    pub message: String,
+    /// Reference that isn't valid.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reference: Option<String>,
+    /// Book that wasn't found.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub book: Option<String>,
+    /// Translation that wasn't found.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub translation: Option<String>,
+    /// Search the error came from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+}
+
+impl ProblemData {
+    /// Creates the problem details of an error responded with a status,
+    /// and the search it came from if any.
+    pub fn new(e: &Error, status: StatusCode, query: Option<&str>) -> Self {
+        let mut problem = Self {
+            problem_type: "about:blank".to_string(),
+            title: status.canonical_reason().unwrap_or_default().to_string(),
+            status: status.as_u16(),
+            detail: e.to_string(),
+            code: e.code().to_string(),
             message: e.to_string(),
+            reference: None,
+            book: None,
+            translation: None,
+            query: query.map(str::to_string),
+        };
+        match e {
+            Error::InvalidReference(reference) => problem.reference = Some(reference.to_owned()),
+            Error::BookNotFound(book) => problem.book = Some(book.to_owned()),
+            Error::TranslationNotFound(translation) => {
+                problem.translation = Some(translation.to_owned())
+            }
+            _ => (),
         }
+        problem
     }
 }
 
-/// Represents data of verses (HTML or JSON).
+/// Data for a page suggesting a book name when the requested one wasn't
+/// found (e.g. "Did you mean Philippians 4:13?").
 #[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct SuggestionData {
+    pub links: SuggestionLinks,
+    pub message: String,
+}
+
+impl SuggestionData {
+    /// Creates new suggestion data for a book name that wasn't found, or
+    /// nothing if no book name is close to it.
+    pub fn new(book_name: &str, reference: Option<&Reference>, req: &HttpRequest) -> Option<Self> {
+        SuggestionLinks::new(book_name, reference, req).map(|links| Self {
+            links,
+            message: Error::BookNotFound(book_name.to_string()).to_string(),
+        })
+    }
+}
+
+/// Represents data of verses (HTML or JSON).
+#[derive(Clone, Deserialize, Serialize, Debug, ToSchema)]
 pub struct VersesData {
+    /// Links to follow from the passage in the JSON API.
+    #[serde(rename = "_links")]
+    pub api_links: Option<PassageLinks>,
     pub book: Book,
+    /// Translation that filled verses omitted by `translation`, if any were.
+    pub fallback: Option<Translation>,
     pub links: VersesLinks,
+    /// Chapters before and after the passage in the JSON API.
+    pub navigation: Option<ChapterNavigation>,
+    /// Reference that was requested, with its book name resolved.
     pub reference: Reference,
+    /// Reference as it's written (e.g. `Psalms 119:105`).
     pub reference_string: String,
+    /// Number of verses in the passage, including any left off a page of
+    /// them.
+    pub total_verses: usize,
+    pub translation: Translation,
+    /// Verses of the reference, in order.
     pub verses: Vec<Verse>,
 }
 
+/// Resolves the book name and verse range of a reference against the
+/// verses that were actually found.
+fn resolve_reference(mut reference: Reference, book: &Book, verses: &[Verse]) -> Reference {
+    reference.book = book.name.to_owned();
+    // Listed verses are kept as requested
+    if !reference.additional_verses.is_empty() {
+        return reference;
+    }
+    let last = verses.last();
+    if let Some(vs) = reference.verses {
+        reference.verses = last.map(|last| *vs.start()..=last.verse);
+        // The part of the last verse no longer applies if it wasn't found
+        if last.map(|last| last.verse) != Some(*vs.end()) {
+            reference.end_part = None;
+        }
+    }
+    if reference.end_chapter.is_some() {
+        reference.end_chapter = last
+            .map(|last| last.chapter)
+            .filter(|c| *c != reference.chapter);
+    }
+    reference
+}
+
 impl VersesData {
     /// Creates new data for the verses page.
     pub fn new(
         (book, verses): (Book, Vec<Verse>),
-        mut reference: Reference,
+        reference: Reference,
+        translation: Translation,
         req: &HttpRequest,
     ) -> Self {
-        reference.book = book.name.to_owned();
-        reference.verses = if let Some(vs) = reference.verses {
-            match verses.len() {
-                0 => None,
-                n => Some(*vs.start()..=verses[n - 1].verse),
-            }
-        } else {
-            None
-        };
+        let reference = resolve_reference(reference, &book, &verses);
         let reference_string = reference.to_string();
         let links = VersesLinks::new(&book, &reference, req);
 
         Self {
+            api_links: None,
             book,
+            fallback: None,
             links,
+            navigation: None,
             reference,
             reference_string,
+            total_verses: verses.len(),
+            translation,
             verses,
         }
     }
+
+    /// Keeps a page of the verses, skipping `offset` of them and keeping up
+    /// to `limit` after those.
+    pub fn with_page(mut self, offset: usize, limit: Option<usize>) -> Self {
+        self.verses = self
+            .verses
+            .into_iter()
+            .skip(offset)
+            .take(limit.unwrap_or(usize::MAX))
+            .collect();
+        self
+    }
+
+    /// Adds the chapters before and after the passage, and the links to
+    /// follow from it, for a response of the JSON API.
+    pub fn with_navigation(mut self, req: &HttpRequest) -> Self {
+        let navigation = ChapterNavigation::new(&self.book, &self.reference, req);
+        self.api_links = Some(PassageLinks::new(
+            &self.book,
+            &self.reference,
+            &navigation,
+            &self.links.current,
+            req,
+        ));
+        self.navigation = Some(navigation);
+        self
+    }
+
+    /// Records the fallback translation if any of the verses came from it.
+    ///
+    /// Substituted verses are the ones whose `translation_id` differs from
+    /// the requested translation.
+    pub fn with_fallback(mut self, fallback: Option<Translation>) -> Self {
+        let translation_id = self.translation.id;
+        self.fallback = fallback.filter(|f| {
+            self.verses
+                .iter()
+                .any(|v| v.translation_id == f.id && v.translation_id != translation_id)
+        });
+        self
+    }
+}
+
+/// Data for the verse of the day endpoint (JSON).
+#[derive(Clone, Deserialize, Serialize, Debug, ToSchema)]
+pub struct VerseOfTheDayData {
+    /// Day the verse is for, as `YYYY-MM-DD`.
+    pub date: String,
+    /// Full URL of the verse's page, for sharing it.
+    pub permalink: String,
+    #[serde(flatten)]
+    pub verses: VersesData,
+}
+
+impl VerseOfTheDayData {
+    /// Creates the verse of the day for a date.
+    pub fn new(verses: VersesData, date: String) -> Self {
+        Self {
+            date,
+            permalink: format!(url_format!(), verses.links.current.url),
+            verses,
+        }
+    }
+}
+
+/// Represents data of several passages shown on one page (e.g. for
+/// John 3:16; Romans 8:28).
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct PassagesData {
+    pub books: Link,
+    pub passages: Vec<VersesData>,
+    pub reference_string: String,
+}
+
+impl PassagesData {
+    /// Creates new data for the passages page.
+    ///
+    /// There must be at least one passage.
+    pub fn new(passages: Vec<VersesData>) -> Self {
+        let reference_string = passages
+            .iter()
+            .map(|p| p.reference_string.as_str())
+            .collect::<Vec<_>>()
+            .join("; ");
+
+        Self {
+            books: passages[0].links.books.to_owned(),
+            passages,
+            reference_string,
+        }
+    }
+
+    /// Gets the translations the passages were taken from, without repeats.
+    pub fn translations(&self) -> Vec<Translation> {
+        let mut translations: Vec<Translation> = vec![];
+        for passage in &self.passages {
+            for t in std::iter::once(&passage.translation).chain(passage.fallback.iter()) {
+                if !translations.iter().any(|seen| seen.id == t.id) {
+                    translations.push(t.to_owned());
+                }
+            }
+        }
+        translations
+    }
+}
+
+/// A verse in the parallel view, with its text in each translation.
+///
+/// The text is empty for translations that don't contain the verse.
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct ParallelRow {
+    pub verse: i32,
+    pub words: Vec<String>,
+}
+
+/// Represents data of verses shown side by side in several translations.
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct ParallelData {
+    pub book: Book,
+    pub links: VersesLinks,
+    pub reference: Reference,
+    pub reference_string: String,
+    pub rows: Vec<ParallelRow>,
+    pub translations: Vec<Translation>,
+}
+
+impl ParallelData {
+    /// Creates new data for the parallel page.
+    ///
+    /// The verses are expected to be ordered by verse number, as returned
+    /// by [VerseStore::parallel_verses](db.VerseStore.html).
+    pub fn new(
+        (book, verses): (Book, Vec<Verse>),
+        reference: Reference,
+        translations: Vec<Translation>,
+        req: &HttpRequest,
+    ) -> Self {
+        let reference = resolve_reference(reference, &book, &verses);
+        let reference_string = reference.to_string();
+        let versions: Vec<&str> = translations
+            .iter()
+            .map(|t| t.abbreviation.as_str())
+            .collect();
+        let links =
+            VersesLinks::new(&book, &reference, req).into_parallel(&versions.join(","), req);
+
+        let mut rows: Vec<ParallelRow> = vec![];
+        for v in verses {
+            if rows.last().map_or(true, |r| r.verse != v.verse) {
+                rows.push(ParallelRow {
+                    verse: v.verse,
+                    words: vec![String::new(); translations.len()],
+                });
+            }
+            let column = translations.iter().position(|t| t.id == v.translation_id);
+            if let (Some(row), Some(column)) = (rows.last_mut(), column) {
+                row.words[column] = v.words;
+            }
+        }
+
+        Self {
+            book,
+            links,
+            reference,
+            reference_string,
+            rows,
+            translations,
+        }
+    }
+}
+
+/// Verses of a passage in a single translation.
+#[derive(Clone, Deserialize, Serialize, Debug, ToSchema)]
+pub struct TranslationVerses {
+    pub translation: Translation,
+    pub verses: Vec<Verse>,
+}
+
+/// Data for the context endpoint: the verses of a passage along with the
+/// verses around it, so it isn't read out of context.
+#[derive(Clone, Deserialize, Serialize, Debug, ToSchema)]
+pub struct ContextData {
+    /// Reference that was asked for, as it's written.
+    pub reference_string: String,
+    /// Number of verses of context before the ones that were asked for.
+    pub before: usize,
+    /// Number of verses of context after the ones that were asked for.
+    pub after: usize,
+    /// The verses that were asked for and their context.
+    pub context: VersesData,
+}
+
+impl ContextData {
+    /// Creates the data for a reference from its verses with context.
+    pub fn new(reference: &Reference, context: VersesData) -> Self {
+        let asked_for = |v: &Verse| reference.contains(v.chapter, v.verse);
+        let verses = &context.verses;
+        Self {
+            reference_string: Reference {
+                book: context.book.name.to_owned(),
+                ..reference.to_owned()
+            }
+            .to_string(),
+            before: verses.iter().position(asked_for).unwrap_or(verses.len()),
+            after: verses.iter().rev().position(asked_for).unwrap_or(0),
+            context,
+        }
+    }
+}
+
+/// Represents data of a passage compared across translations (JSON).
+#[derive(Clone, Deserialize, Serialize, Debug, ToSchema)]
+pub struct CompareData {
+    pub book: Book,
+    pub links: VersesLinks,
+    pub reference: Reference,
+    /// Reference as it's written (e.g. `Psalms 119:105`).
+    pub reference_string: String,
+    /// Verses of the reference in each translation, in the order requested.
+    pub translations: Vec<TranslationVerses>,
+}
+
+impl CompareData {
+    /// Creates new data for the compare endpoint, grouping the verses by
+    /// translation.
+    pub fn new(
+        (book, verses): (Book, Vec<Verse>),
+        reference: Reference,
+        translations: Vec<Translation>,
+        req: &HttpRequest,
+    ) -> Self {
+        let reference = resolve_reference(reference, &book, &verses);
+        let reference_string = reference.to_string();
+        let links = VersesLinks::new(&book, &reference, req);
+
+        let mut translations: Vec<TranslationVerses> = translations
+            .into_iter()
+            .map(|translation| TranslationVerses {
+                translation,
+                verses: vec![],
+            })
+            .collect();
+        for v in verses {
+            if let Some(t) = translations
+                .iter_mut()
+                .find(|t| t.translation.id == v.translation_id)
+            {
+                t.verses.push(v);
+            }
+        }
+
+        Self {
+            book,
+            links,
+            reference,
+            reference_string,
+            translations,
+        }
+    }
 }
 
 /// Represents data for the books endpoint (HTML or JSON).
@@ -97,6 +474,116 @@ impl BookData {
     }
 }
 
+/// A chapter in the chapters endpoint.
+#[derive(Clone, Deserialize, Serialize, Debug, ToSchema)]
+pub struct ChapterSummary {
+    pub chapter: i32,
+    /// Number of verses in the chapter in the translation.
+    pub verse_count: i64,
+    /// Link to the chapter's page.
+    pub link: Link,
+}
+
+/// Data for the chapters endpoint: the chapters of a book with their verse
+/// counts, for validating references without looking them up (JSON).
+#[derive(Clone, Deserialize, Serialize, Debug, ToSchema)]
+pub struct ChaptersData {
+    pub book: Book,
+    pub chapters: Vec<ChapterSummary>,
+    pub links: BookLinks,
+}
+
+impl ChaptersData {
+    /// Creates the list of a book's chapters.
+    pub fn new((book, chapters): (Book, Vec<(i32, i64)>), req: &HttpRequest) -> Self {
+        let numbers: Vec<i32> = chapters.iter().map(|(chapter, _)| *chapter).collect();
+        let links = BookLinks::new(&book, &numbers, req);
+        let chapters = chapters
+            .into_iter()
+            .map(|(chapter, verse_count)| ChapterSummary {
+                chapter,
+                verse_count,
+                link: chapter_url(&book.name, chapter, req),
+            })
+            .collect();
+        Self {
+            book,
+            chapters,
+            links,
+        }
+    }
+}
+
+/// A passage to see along with a verse of a cross-references response.
+#[derive(Clone, Deserialize, Serialize, Debug, ToSchema)]
+pub struct CrossReferenceData {
+    /// Chapter of the verse the cross-reference is from.
+    pub chapter: i32,
+    /// Verse the cross-reference is from.
+    pub verse: i32,
+    /// Passage the cross-reference points to, as it's written (e.g.
+    /// `Romans 5:8`).
+    pub reference_string: String,
+    /// How relevant the cross-reference was voted to be, or 0 if it wasn't.
+    pub votes: i32,
+    pub link: Link,
+}
+
+/// Data for the cross-references endpoint: passages to see along with the
+/// verses of a reference, so clients can show them as "see also" (JSON).
+#[derive(Clone, Deserialize, Serialize, Debug, ToSchema)]
+pub struct CrossReferencesData {
+    pub book: Book,
+    /// Reference that was asked for, as it's written.
+    pub reference_string: String,
+    /// Cross-references in the order of the verses they're from, with the
+    /// most voted first for each verse.
+    pub cross_references: Vec<CrossReferenceData>,
+}
+
+impl CrossReferencesData {
+    /// Creates the data for a reference from its cross-references.
+    pub fn new(
+        (book, cross_references): (Book, Vec<(CrossReference, Book)>),
+        reference: &Reference,
+        req: &HttpRequest,
+    ) -> Self {
+        let cross_references = cross_references
+            .into_iter()
+            .map(|(x, to_book)| {
+                let to = x.to_reference(&to_book);
+                CrossReferenceData {
+                    chapter: x.chapter,
+                    verse: x.verse,
+                    reference_string: to.to_string(),
+                    votes: x.votes,
+                    link: reference_url(&to_book.name, &to, req),
+                }
+            })
+            .collect();
+        Self {
+            reference_string: Reference {
+                book: book.name.to_owned(),
+                ..reference.to_owned()
+            }
+            .to_string(),
+            book,
+            cross_references,
+        }
+    }
+}
+
+/// Data for the stats endpoints: counts of the chapters, verses and words of
+/// a translation or one of its books (JSON).
+#[derive(Clone, Deserialize, Serialize, Debug, ToSchema)]
+pub struct StatsData {
+    pub translation: Translation,
+    /// Book the stats are for, if they aren't for the whole translation.
+    pub book: Option<Book>,
+    #[serde(flatten)]
+    pub stats: Stats,
+}
+
 /// Data for the "all books" endpoint (HTML or JSON).
 #[derive(Clone, Deserialize, Serialize, Debug)]
 pub struct AllBooksData {
@@ -111,61 +598,496 @@ impl AllBooksData {
     }
 }
 
+/// A book in the books endpoint, with the abbreviations references can use
+/// for it.
+#[derive(Clone, Deserialize, Serialize, Debug, ToSchema)]
+pub struct BookSummary {
+    /// The book, whose `id` is its position in canonical order.
+    #[serde(flatten)]
+    pub book: Book,
+    /// Abbreviations of the book in lower case (e.g. `ps` and `psa`).
+    pub abbreviations: Vec<String>,
+    /// Link to the book's page.
+    pub link: Link,
+}
+
+/// Data for the books endpoint: every book of a translation, in canonical
+/// order (JSON).
+#[derive(Clone, Deserialize, Serialize, Debug, ToSchema)]
+pub struct BooksData {
+    pub books: Vec<BookSummary>,
+}
+
+impl BooksData {
+    /// Creates the list of books, each with its abbreviations.
+    pub fn new(books: Vec<Book>, abbreviations: Vec<BookAbbreviation>, req: &HttpRequest) -> Self {
+        let books = books
+            .into_iter()
+            .map(|book| BookSummary {
+                abbreviations: abbreviations
+                    .iter()
+                    .filter(|a| a.book_id == book.id)
+                    .map(|a| a.abbreviation.to_owned())
+                    .collect(),
+                link: book_url(&book.name, req),
+                book,
+            })
+            .collect();
+        Self { books }
+    }
+}
+
+/// Most completions returned for what a user has typed.
+const MAX_COMPLETIONS: usize = 10;
+
+/// Data for the completion endpoint: books or chapters that complete what
+/// a user has typed, best first.
+#[derive(Clone, Deserialize, Serialize, Debug, ToSchema)]
+pub struct CompletionData {
+    /// Links to the completed books or chapters.
+    pub completions: Vec<Link>,
+}
+
+impl CompletionData {
+    /// Creates completions for the matching books, ordered as given.
+    ///
+    /// If part of a chapter number was typed (e.g. `1` in `Ps 1`), each
+    /// book's chapters starting with it are completed, in order.
+    pub fn new(books: Vec<Book>, chapter: Option<&str>, req: &HttpRequest) -> Self {
+        let completions = books
+            .iter()
+            .flat_map(|book| -> Vec<Link> {
+                match chapter {
+                    Some(chapter) => (1..=book.chapter_count)
+                        .filter(|c| c.to_string().starts_with(chapter))
+                        .map(|c| chapter_url(&book.name, c, req))
+                        .collect(),
+                    None => vec![book_url(&book.name, req)],
+                }
+            })
+            .take(MAX_COMPLETIONS)
+            .collect();
+
+        Self { completions }
+    }
+}
+
+/// Data for the search completion endpoint: words and phrases that start
+/// with what a user has typed, most used first.
+#[derive(Clone, Deserialize, Serialize, Debug, ToSchema)]
+pub struct SearchCompletionData {
+    /// Words completing the last word typed.
+    pub words: Vec<String>,
+    /// Phrases of two words completing what was typed.
+    pub phrases: Vec<String>,
+}
+
+/// Tags wrapping the matched words in search results.
+const MARK_START: &str = "<mark>";
+const MARK_END: &str = "</mark>";
+
 /// A search result.
-#[derive(Clone, Deserialize, Serialize, Debug)]
+#[derive(Clone, Deserialize, Serialize, Debug, ToSchema)]
 pub struct SearchResult {
     pub link: Link,
+    /// Verse text as HTML, with the matched words wrapped in `<mark>` tags.
     pub text: String,
+    /// Verse text without any tags.
+    pub plain_text: String,
+    /// Start and end character offsets of the matched words in the plain
+    /// text.
+    #[schema(value_type = Vec<Vec<usize>>)]
+    pub highlights: Vec<[usize; 2]>,
 }
 
-/// Data for the search endpoint (HTML or JSON).
+impl SearchResult {
+    /// Creates a search result from verse text with matches wrapped in
+    /// `<mark>` tags.
+    fn new(link: Link, text: String) -> Self {
+        let mut plain_text = String::with_capacity(text.len());
+        let mut highlights = vec![];
+        let mut rest = text.as_str();
+        while let Some(start) = rest.find(MARK_START) {
+            plain_text.push_str(&rest[..start]);
+            rest = &rest[start + MARK_START.len()..];
+            let end = rest.find(MARK_END).unwrap_or(rest.len());
+            let offset = plain_text.chars().count();
+            plain_text.push_str(&rest[..end]);
+            highlights.push([offset, plain_text.chars().count()]);
+            rest = rest.get(end + MARK_END.len()..).unwrap_or("");
+        }
+        plain_text.push_str(rest);
+
+        Self {
+            link,
+            text,
+            plain_text,
+            highlights,
+        }
+    }
+}
+
+/// Message of the live search WebSocket, with the matches of the latest
+/// query that was typed (JSON).
 #[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct LiveSearchData {
+    pub query: String,
+    #[serde(flatten)]
+    pub results: SearchResultData,
+}
+
+/// Data for the search endpoint (HTML or JSON).
+#[derive(Clone, Deserialize, Serialize, Debug, ToSchema)]
 pub struct SearchResultData {
     pub matches: Vec<SearchResult>,
+    /// How the matches were found.
+    pub mode: SearchMode,
+    /// Number of matches on all of the pages.
+    pub total: i64,
+    /// Page of matches, starting at 1.
+    pub page: i64,
+    pub links: SearchPageLinks,
+    /// Number of matches in each book and testament, for full text searches.
+    pub facets: Option<SearchFacets>,
+    /// Search with the misspelled words of the query corrected, when a full
+    /// text search has no matches.
+    pub suggestion: Option<Link>,
+    /// Token for the verses matched on all of the pages, so a search can be
+    /// run within them with `within=previous&results=<token>`.
+    pub results: Option<String>,
+    /// Translation that was searched, used to render the matches in its
+    /// language and direction.
+    pub translation: Option<Translation>,
 }
 
 impl SearchResultData {
     /// Creates an empty search result list.
     pub fn empty() -> Self {
-        Self { matches: vec![] }
+        Self {
+            matches: vec![],
+            mode: SearchMode::Words,
+            total: 0,
+            page: 1,
+            links: SearchPageLinks {
+                previous: None,
+                next: None,
+            },
+            facets: None,
+            suggestion: None,
+            results: None,
+            translation: None,
+        }
     }
 
-    /// Creates new search result data from full text search verses.
-    pub fn from_verses_fts(from_db: Vec<(VerseFTS, Book)>, req: &HttpRequest) -> Self {
-        let matches = from_db.into_iter().map(|(v, b)| SearchResult {
-            link: verse_url(&b.name, v.chapter, v.verse, req),
-            text: v.words,
-        });
+    /// Records the translation that was searched.
+    pub fn with_translation(self, translation: Translation) -> Self {
+        Self {
+            translation: Some(translation),
+            ..self
+        }
+    }
+
+    /// Adds a link to search for the query with its spelling corrected.
+    pub fn with_suggestion(self, suggestion: Option<String>, req: &HttpRequest) -> Self {
+        Self {
+            suggestion: suggestion.map(|q| Link {
+                url: search_url(&[("q", &q), ("page", "1")], req),
+                label: q,
+            }),
+            ..self
+        }
+    }
+
+    /// Adds the token for the verses matched, if any were.
+    pub fn with_result_set(self, results: ResultSet) -> Self {
+        Self {
+            results: (!results.is_empty()).then(|| results.to_string()),
+            ..self
+        }
+    }
+
+    /// Leaves out the links to the other pages of matches, for results that
+    /// aren't at a URL to page through (e.g. those of a batch of searches).
+    pub fn without_page_links(self) -> Self {
+        Self {
+            links: SearchPageLinks {
+                previous: None,
+                next: None,
+            },
+            ..self
+        }
+    }
+
+    /// Adds the number of matches in each book.
+    pub fn with_facets(self, counts: Vec<(Book, i64)>, req: &HttpRequest) -> Self {
+        Self {
+            facets: Some(SearchFacets::new(counts, req)),
+            ..self
+        }
+    }
+
+    /// Creates new search result data from a page of full text search
+    /// verses, and the total number of matches, found with the given mode
+    /// and options.
+    pub fn from_verses_fts(
+        from_db: (Vec<(VerseFTS, Book)>, i64),
+        mode: SearchMode,
+        options: &SearchOptions,
+        req: &HttpRequest,
+    ) -> Self {
+        let (verses, total) = from_db;
+        let matches = verses
+            .into_iter()
+            .map(|(v, b)| SearchResult::new(verse_url(&b.name, v.chapter, v.verse, req), v.words));
 
         Self {
             matches: matches.collect(),
+            mode,
+            total,
+            page: options.page,
+            links: SearchPageLinks::new(options.page, options.per_page, total, req),
+            facets: None,
+            suggestion: None,
+            results: None,
+            translation: None,
         }
     }
 
     /// Creates new search result data from standard verses.
     pub fn from_verses(from_db: (Book, Vec<Verse>), req: &HttpRequest) -> Self {
         let name = from_db.0.name;
-        let matches = from_db.1.into_iter().map(|v| SearchResult {
-            link: verse_url(&name, v.chapter, v.verse, req),
-            text: v.words,
-        });
+        let matches = from_db
+            .1
+            .into_iter()
+            .map(|v| SearchResult::new(verse_url(&name, v.chapter, v.verse, req), v.words));
 
+        let matches: Vec<SearchResult> = matches.collect();
         Self {
-            matches: matches.collect(),
+            total: matches.len() as i64,
+            matches,
+            mode: SearchMode::Reference,
+            page: 1,
+            links: SearchPageLinks {
+                previous: None,
+                next: None,
+            },
+            facets: None,
+            suggestion: None,
+            results: None,
+            translation: None,
+        }
+    }
+}
+
+/// A search result in one of several translations that were searched.
+#[derive(Clone, Deserialize, Serialize, Debug, ToSchema)]
+pub struct TranslationSearchResult {
+    /// Abbreviation of the translation.
+    pub translation: String,
+    /// The result, linking to the verse in the translation.
+    #[serde(flatten)]
+    pub result: SearchResult,
+}
+
+/// A verse matching a search of several translations, with its text in
+/// each translation it matched in.
+#[derive(Clone, Deserialize, Serialize, Debug, ToSchema)]
+pub struct SearchResultGroup {
+    pub link: Link,
+    pub matches: Vec<TranslationSearchResult>,
+}
+
+/// Data for the search endpoint when several translations are searched
+/// (JSON).
+#[derive(Clone, Deserialize, Serialize, Debug, ToSchema)]
+pub struct TranslationsSearchResultData {
+    /// Matches grouped by verse, in canonical order.
+    pub groups: Vec<SearchResultGroup>,
+    /// How the matches were found.
+    pub mode: SearchMode,
+    /// Number of verses matched on all of the pages.
+    pub total: i64,
+    /// Page of verses, starting at 1.
+    pub page: i64,
+    pub links: SearchPageLinks,
+    /// Translations that were searched.
+    pub translations: Vec<Translation>,
+}
+
+impl TranslationsSearchResultData {
+    /// Creates new search result data from a page of verses matched in
+    /// several translations, and the total number of verses matched.
+    pub fn new(
+        (verses, total): (Vec<VerseMatches>, i64),
+        mode: SearchMode,
+        options: &SearchOptions,
+        translations: Vec<Translation>,
+        req: &HttpRequest,
+    ) -> Self {
+        let groups = verses
+            .into_iter()
+            .map(|v| SearchResultGroup {
+                link: verse_url(&v.book.name, v.chapter, v.verse, req),
+                matches: v
+                    .matches
+                    .into_iter()
+                    .map(|(translation, words)| {
+                        let link = version_verse_url(
+                            &translation.abbreviation,
+                            &v.book.name,
+                            v.chapter,
+                            v.verse,
+                            req,
+                        );
+                        TranslationSearchResult {
+                            translation: translation.abbreviation,
+                            result: SearchResult::new(link, words),
+                        }
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        Self {
+            groups,
+            mode,
+            total,
+            page: options.page,
+            links: SearchPageLinks::new(options.page, options.per_page, total, req),
+            translations,
+        }
+    }
+}
+
+/// Number of search matches in a book or testament, with a link to only the
+/// matches in it.
+#[derive(Clone, Deserialize, Serialize, Debug, ToSchema)]
+pub struct SearchFacet {
+    pub link: Link,
+    pub count: i64,
+}
+
+/// Breakdown of the matches of a search by book and by testament.
+#[derive(Clone, Deserialize, Serialize, Debug, ToSchema)]
+pub struct SearchFacets {
+    /// Books with any matches, in canonical order.
+    pub books: Vec<SearchFacet>,
+    /// Testaments with any matches, the Old Testament first.
+    pub testaments: Vec<SearchFacet>,
+}
+
+impl SearchFacets {
+    /// Creates the breakdown from the number of matches in each book.
+    fn new(counts: Vec<(Book, i64)>, req: &HttpRequest) -> Self {
+        let testaments = [
+            (Testament::Old, "Old Testament", "ot"),
+            (Testament::New, "New Testament", "nt"),
+        ]
+        .into_iter()
+        .filter_map(|(testament, label, param)| {
+            let count: i64 = counts
+                .iter()
+                .filter(|(book, _)| book.testament == testament)
+                .map(|(_, count)| count)
+                .sum();
+            (count > 0).then(|| SearchFacet {
+                link: Link {
+                    label: label.to_string(),
+                    url: search_url(&[("testament", param), ("page", "1")], req),
+                },
+                count,
+            })
+        })
+        .collect();
+
+        let books = counts
+            .into_iter()
+            .map(|(book, count)| SearchFacet {
+                link: Link {
+                    url: search_url(&[("books", &book.name), ("page", "1")], req),
+                    label: book.name,
+                },
+                count,
+            })
+            .collect();
+
+        Self { books, testaments }
+    }
+}
+
+/// A translation listed in the translation picker.
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct TranslationChoice {
+    pub abbreviation: String,
+    pub name: String,
+    pub selected: bool,
+}
+
+/// Data for the translation picker shown on book and chapter pages.
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct TranslationPicker {
+    /// URL the picker submits to, without any translation prefix.
+    pub action: String,
+    pub translations: Vec<TranslationChoice>,
+}
+
+impl TranslationPicker {
+    /// Creates a picker for the installed translations with the given
+    /// translation selected.
+    pub fn new(translations: &[Translation], selected: &str, req: &HttpRequest) -> Self {
+        let path = req.path();
+        let action = match req.match_info().get("version") {
+            Some(version) => path
+                .strip_prefix(&format!("/{}", version))
+                .unwrap_or(path)
+                .to_string(),
+            None => path.to_string(),
+        };
+
+        Self {
+            action,
+            translations: translations
+                .iter()
+                .map(|t| TranslationChoice {
+                    abbreviation: t.abbreviation.to_owned(),
+                    name: t.name.to_owned(),
+                    selected: t.abbreviation == selected,
+                })
+                .collect(),
         }
     }
 }
 
 #[derive(Clone, Deserialize, Serialize, Debug)]
 pub struct TemplateData<T: serde::Serialize> {
+    /// Translations whose copyright and attribution belong in the footer.
+    attributions: Vec<Translation>,
     data: T,
     meta: Meta,
+    picker: Option<TranslationPicker>,
 }
 
 impl<T: serde::Serialize> TemplateData<T> {
     /// Create new HTML template Data.
     pub fn new(data: T, meta: Meta) -> Self {
-        Self { data, meta }
+        Self {
+            attributions: vec![],
+            data,
+            meta,
+            picker: None,
+        }
+    }
+
+    /// Adds the attribution of the translations shown on the page.
+    pub fn with_attributions(mut self, translations: &[Translation]) -> Self {
+        self.attributions = translations.to_vec();
+        self
+    }
+
+    /// Adds a translation picker to the template data.
+    pub fn with_picker(mut self, picker: TranslationPicker) -> Self {
+        self.picker = Some(picker);
+        self
     }
 
     /// Convert the template data to HTML
@@ -188,6 +1110,27 @@ mod tests {
     use crate::responder::meta::Meta;
     use crate::test::*;
 
+    #[test]
+    fn search_result_highlights() {
+        let link = Link {
+            label: "Psalms 119:105".to_string(),
+            url: "/Psalms/119#v105".to_string(),
+        };
+        let result = SearchResult::new(
+            link.clone(),
+            "Thy <mark>wörd</mark> is a lamp unto my feet, and a <mark>light</mark>".to_string(),
+        );
+        assert_eq!(
+            result.plain_text,
+            "Thy wörd is a lamp unto my feet, and a light"
+        );
+        assert_eq!(result.highlights, vec![[4, 8], [39, 44]]);
+
+        let result = SearchResult::new(link, "a lamp unto my feet".to_string());
+        assert_eq!(result.plain_text, "a lamp unto my feet");
+        assert!(result.highlights.is_empty());
+    }
+
     #[test]
     fn verses_data() {
         with_service(|req| {
@@ -196,6 +1139,7 @@ mod tests {
                 id: 20,
                 name: prov.0.to_string(),
                 testament: Testament::Old,
+                deuterocanonical: false,
                 chapter_count: prov.1,
             };
             let verses = vec![Verse {
@@ -204,10 +1148,13 @@ mod tests {
                 id: 555,
                 verse: 5,
                 words: "Trust in the LORD with all thine heart; and lean not unto thine own understanding.".to_string(),
-
+                translation_id: 1,
+                paragraph: false,
+                poetry_level: 0,
+                line_break: false,
             }];
             let reference: Reference = "Proverbs 3:5".parse().unwrap();
-            let data = VersesData::new((book, verses), reference, &req);
+            let data = VersesData::new((book, verses), reference, test_translation(), &req);
 
             assert_eq!(data.reference_string, "Proverbs 3:5");
             assert_eq!(data.reference.book, "Proverbs");
@@ -215,6 +1162,140 @@ mod tests {
         });
     }
 
+    #[test]
+    fn verses_data_with_fallback() {
+        with_service(|req| {
+            let book = Book {
+                id: 43,
+                name: BOOKS[43].0.to_string(),
+                testament: Testament::New,
+                deuterocanonical: false,
+                chapter_count: BOOKS[43].1,
+            };
+            let mut web = test_translation();
+            web.id = 2;
+            web.abbreviation = "web".to_string();
+            let verse = |verse, translation_id| Verse {
+                id: 0,
+                book: 43,
+                chapter: 5,
+                verse,
+                words: String::new(),
+                translation_id,
+                paragraph: false,
+                poetry_level: 0,
+                line_break: false,
+            };
+            let reference: Reference = "John 5:3-5".parse().unwrap();
+
+            let verses = vec![verse(3, 2), verse(5, 2)];
+            let data = VersesData::new(
+                (book.to_owned(), verses),
+                reference.to_owned(),
+                web.to_owned(),
+                &req,
+            )
+            .with_fallback(Some(test_translation()));
+            assert!(data.fallback.is_none());
+
+            let verses = vec![verse(3, 2), verse(4, 1), verse(5, 2)];
+            let data = VersesData::new((book, verses), reference, web, &req)
+                .with_fallback(Some(test_translation()));
+            assert_eq!(
+                data.fallback.map(|f| f.abbreviation),
+                Some("kjv".to_string())
+            );
+        });
+    }
+
+    #[test]
+    fn parallel_data() {
+        with_service(|req| {
+            let book = Book {
+                id: 43,
+                name: BOOKS[43].0.to_string(),
+                testament: Testament::New,
+                deuterocanonical: false,
+                chapter_count: BOOKS[43].1,
+            };
+            let mut web = test_translation();
+            web.id = 2;
+            web.abbreviation = "web".to_string();
+            let verse = |verse, words: &str, translation_id| Verse {
+                id: 0,
+                book: 43,
+                chapter: 11,
+                verse,
+                words: words.to_string(),
+                translation_id,
+                paragraph: false,
+                poetry_level: 0,
+                line_break: false,
+            };
+            let verses = vec![
+                verse(35, "Jesus wept.", 1),
+                verse(35, "Jesus wept.", 2),
+                verse(36, "Then said the Jews, Behold how he loved him!", 1),
+            ];
+            let reference: Reference = "John 11:35-36".parse().unwrap();
+            let data = ParallelData::new(
+                (book, verses),
+                reference,
+                vec![test_translation(), web],
+                &req,
+            );
+
+            assert_eq!(data.reference_string, "John 11:35-36");
+            assert_eq!(data.rows.len(), 2);
+            assert_eq!(data.rows[0].words, vec!["Jesus wept.", "Jesus wept."]);
+            assert_eq!(data.rows[1].words[1], "");
+            assert_eq!(
+                data.links.current.url,
+                "/parallel/John/11/35-36?versions=kjv,web"
+            );
+        });
+    }
+
+    #[test]
+    fn compare_data() {
+        with_service(|req| {
+            let book = Book {
+                id: 43,
+                name: BOOKS[43].0.to_string(),
+                testament: Testament::New,
+                deuterocanonical: false,
+                chapter_count: BOOKS[43].1,
+            };
+            let mut web = test_translation();
+            web.id = 2;
+            web.abbreviation = "web".to_string();
+            let verse = |verse, translation_id| Verse {
+                id: 0,
+                book: 43,
+                chapter: 11,
+                verse,
+                words: "Jesus wept.".to_string(),
+                translation_id,
+                paragraph: false,
+                poetry_level: 0,
+                line_break: false,
+            };
+            let verses = vec![verse(35, 1), verse(35, 2)];
+            let reference: Reference = "John 11:35".parse().unwrap();
+            let data = CompareData::new(
+                (book, verses),
+                reference,
+                vec![test_translation(), web],
+                &req,
+            );
+
+            assert_eq!(data.reference_string, "John 11:35");
+            assert_eq!(data.translations.len(), 2);
+            assert_eq!(data.translations[1].translation.abbreviation, "web");
+            assert_eq!(data.translations[1].verses.len(), 1);
+        });
+    }
+
     #[test]
     fn book_data() {
         with_service(|req| {
@@ -223,6 +1304,7 @@ mod tests {
                 id: 20,
                 name: prov.0.to_string(),
                 testament: Testament::Old,
+                deuterocanonical: false,
                 chapter_count: prov.1,
             };
             let chapters = (1..=book.chapter_count).collect::<Vec<i32>>();
@@ -241,6 +1323,7 @@ mod tests {
                 id: 20,
                 name: prov.0.to_string(),
                 testament: Testament::Old,
+                deuterocanonical: false,
                 chapter_count: prov.1,
             };
             let book_2 = book.clone();
@@ -250,7 +1333,10 @@ mod tests {
                 id: 555,
                 verse: 5,
                 words: "Trust in the LORD with all thine heart; and lean not unto thine own understanding.".to_string(),
-
+                translation_id: 1,
+                paragraph: false,
+                poetry_level: 0,
+                line_break: false,
             }];
             let data = SearchResultData::from_verses((book, verses), &req);
             assert_eq!(data.matches.len(), 1);
@@ -262,8 +1348,61 @@ mod tests {
                 verse: 5,
                 words: "Trust in the LORD with all thine heart; and lean not unto thine own understanding.".to_string(),
             }, book_2)];
-            let data = SearchResultData::from_verses_fts(results, &req);
+            let data = SearchResultData::from_verses_fts(
+                (results, 1),
+                SearchMode::Words,
+                &SearchOptions::default(),
+                &req,
+            );
             assert_eq!(data.matches.len(), 1);
+            assert_eq!(data.total, 1);
+        });
+    }
+
+    #[test]
+    fn search_facets() {
+        with_service(|req| {
+            let book = |id, name: &str, testament| Book {
+                id,
+                name: name.to_string(),
+                chapter_count: 1,
+                testament,
+                deuterocanonical: false,
+            };
+            let facets = SearchFacets::new(
+                vec![
+                    (book(19, "Psalms", Testament::Old), 3),
+                    (book(20, "Proverbs", Testament::Old), 2),
+                    (book(45, "Romans", Testament::New), 14),
+                ],
+                &req,
+            );
+            assert_eq!(facets.books.len(), 3);
+            assert_eq!(facets.books[2].link.label, "Romans");
+            assert_eq!(facets.books[2].link.url, "/test?books=Romans&page=1");
+            assert_eq!(facets.books[2].count, 14);
+            assert_eq!(facets.testaments[0].link.label, "Old Testament");
+            assert_eq!(facets.testaments[0].link.url, "/test?testament=ot&page=1");
+            assert_eq!(facets.testaments[0].count, 5);
+            assert_eq!(facets.testaments[1].count, 14);
+
+            let facets = SearchFacets::new(vec![(book(45, "Romans", Testament::New), 1)], &req);
+            assert_eq!(facets.testaments.len(), 1);
+            assert_eq!(facets.testaments[0].link.label, "New Testament");
+        });
+    }
+
+    #[test]
+    fn translation_picker() {
+        with_service(|req| {
+            let mut web = test_translation();
+            web.abbreviation = "web".to_string();
+            web.name = "World English Bible".to_string();
+
+            let picker = TranslationPicker::new(&[test_translation(), web], "web", &req);
+            assert_eq!(picker.action, "/test");
+            assert!(!picker.translations[0].selected);
+            assert!(picker.translations[1].selected);
         });
     }
 
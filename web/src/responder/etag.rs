@@ -0,0 +1,86 @@
+use actix_web::http::header::{EntityTag, Header, IfNoneMatch};
+use actix_web::HttpRequest;
+use db::models::Translation;
+use sha2::{Digest, Sha256};
+
+/// Gets a strong entity tag for a passage in some translations: the one
+/// asked for and any that filled verses it omits. Verses only change when a
+/// translation is imported again, so the tag is a hash of what's asked for,
+/// the reference and the query string (e.g. the fields or page of verses to
+/// respond with), with the translations and when they were imported, along
+/// with the version of Bible.rs in case a release changes its responses.
+pub fn passage_etag<'a, T>(translations: T, reference: &str, query: &str) -> EntityTag
+where
+    T: IntoIterator<Item = &'a Translation>,
+{
+    let mut hasher = Sha256::new();
+    let translations = translations
+        .into_iter()
+        .map(|t| format!("{}@{}", t.abbreviation, t.imported_at));
+    let parts = [env!("CARGO_PKG_VERSION"), reference, query]
+        .map(str::to_string)
+        .into_iter()
+        .chain(translations);
+    for part in parts {
+        hasher.update(part.as_bytes());
+        // Keeps the parts apart, so they can't run into each other
+        hasher.update([0]);
+    }
+    let hash = format!("{:x}", hasher.finalize());
+    EntityTag::new_strong(hash[..32].to_string())
+}
+
+/// Whether a client already has the response with an entity tag, going by
+/// the request's `If-None-Match` header. A `*` matches any tag, so this is
+/// only asked once the passage has been found.
+pub fn has_etag(req: &HttpRequest, etag: &EntityTag) -> bool {
+    match IfNoneMatch::parse(req) {
+        Ok(IfNoneMatch::Any) => true,
+        Ok(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(etag)),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::header;
+    use actix_web::test::TestRequest;
+
+    use super::*;
+    use crate::test::test_translation;
+
+    #[test]
+    fn etags() {
+        let kjv = test_translation();
+        let etag = passage_etag([&kjv], "psalms.119.105", "");
+        assert!(!etag.weak);
+        assert_eq!(etag, passage_etag([&kjv], "psalms.119.105", ""));
+        assert_ne!(etag, passage_etag([&kjv], "psalms.119.105", "fields=words"));
+        let asv = Translation {
+            abbreviation: "asv".to_string(),
+            ..test_translation()
+        };
+        assert_ne!(etag, passage_etag([&asv], "psalms.119.105", ""));
+        assert_ne!(etag, passage_etag([&kjv, &asv], "psalms.119.105", ""));
+        // Importing a translation again changes its passages' tags
+        let reimported = Translation {
+            imported_at: kjv.imported_at + 1,
+            ..test_translation()
+        };
+        assert_ne!(etag, passage_etag([&reimported], "psalms.119.105", ""));
+
+        let req = TestRequest::default()
+            .insert_header((header::IF_NONE_MATCH, format!("\"other\", {}", etag)))
+            .to_http_request();
+        assert!(has_etag(&req, &etag));
+        let req = TestRequest::default()
+            .insert_header((header::IF_NONE_MATCH, "\"other\""))
+            .to_http_request();
+        assert!(!has_etag(&req, &etag));
+        assert!(!has_etag(&TestRequest::default().to_http_request(), &etag));
+        let req = TestRequest::default()
+            .insert_header((header::IF_NONE_MATCH, "*"))
+            .to_http_request();
+        assert!(has_etag(&req, &etag));
+    }
+}
@@ -0,0 +1,294 @@
+use actix_web::http::header::{Accept, Header};
+use actix_web::HttpRequest;
+use serde_derive::Serialize;
+
+use db::models::Verse;
+
+use crate::responder::{SearchResult, VersesData};
+
+/// Formats that search results can be exported in, for pasting them into
+/// study documents.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    /// Comma-separated values, with a header row.
+    Csv,
+    /// A Markdown list with the references in bold.
+    Markdown,
+}
+
+impl ExportFormat {
+    /// Gets the format for a file extension (`csv` or `md`).
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "csv" => Some(Self::Csv),
+            "md" => Some(Self::Markdown),
+            _ => None,
+        }
+    }
+
+    /// Gets the file extension of the format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Markdown => "md",
+        }
+    }
+
+    /// Gets the MIME type of the format.
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Markdown => "text/markdown; charset=utf-8",
+        }
+    }
+
+    /// Exports the plain text of search results with their references, and
+    /// the abbreviation of the translation they're from if it's known.
+    pub fn export(self, matches: &[SearchResult], translation: Option<&str>) -> String {
+        let translation = translation.unwrap_or_default().to_uppercase();
+        let mut exported = String::new();
+        match self {
+            Self::Csv => {
+                exported.push_str("reference,translation,text\r\n");
+                for m in matches {
+                    let fields = [&m.link.label, &translation, &m.plain_text];
+                    let row: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+                    exported.push_str(&row.join(","));
+                    exported.push_str("\r\n");
+                }
+            }
+            Self::Markdown => {
+                for m in matches {
+                    exported.push_str(&format!("- **{}**", m.link.label));
+                    if !translation.is_empty() {
+                        exported.push_str(&format!(" ({})", translation));
+                    }
+                    exported.push_str(&format!(" {}\n", markdown_text(&m.plain_text)));
+                }
+            }
+        }
+        exported
+    }
+}
+
+/// Formats that passages can be served in besides HTML, for clients that ask
+/// for them with the `Accept` header (e.g. `curl` and scripts).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PassageFormat {
+    /// The same JSON as the API responds with.
+    Json,
+    /// The bare text of the verses, one on a line.
+    PlainText,
+    /// A heading for each passage, then its numbered verses.
+    Markdown,
+}
+
+impl PassageFormat {
+    /// Picks the format a request prefers, if it prefers one of these to
+    /// HTML.
+    pub fn from_request(req: &HttpRequest) -> Option<Self> {
+        let accepted = Accept::parse(req).map(|a| a.ranked()).unwrap_or_default();
+        for mime in accepted {
+            match mime.essence_str() {
+                "application/json" => return Some(Self::Json),
+                "text/plain" => return Some(Self::PlainText),
+                "text/markdown" => return Some(Self::Markdown),
+                "text/html" | "text/*" | "*/*" => return None,
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// Gets the MIME type of the format.
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::PlainText => "text/plain; charset=utf-8",
+            Self::Markdown => "text/markdown; charset=utf-8",
+        }
+    }
+
+    /// Formats passages with plain text verses. A single passage is a JSON
+    /// object, as the API gives for one reference.
+    pub fn format(self, passages: &[VersesData]) -> String {
+        let mut formatted = String::new();
+        match self {
+            Self::Json => {
+                let json = match passages {
+                    [passage] => serde_json::to_string(passage),
+                    _ => serde_json::to_string(passages),
+                };
+                formatted = json.unwrap();
+            }
+            Self::PlainText => {
+                let texts: Vec<String> = passages
+                    .iter()
+                    .map(|p| {
+                        let words: Vec<&str> = p.verses.iter().map(|v| v.words.as_str()).collect();
+                        words.join("\n")
+                    })
+                    .collect();
+                formatted.push_str(&texts.join("\n\n"));
+                formatted.push('\n');
+            }
+            Self::Markdown => {
+                for (i, p) in passages.iter().enumerate() {
+                    if i > 0 {
+                        formatted.push('\n');
+                    }
+                    formatted.push_str(&format!(
+                        "## {} ({})\n\n",
+                        p.reference_string,
+                        p.translation.abbreviation.to_uppercase()
+                    ));
+                    for v in &p.verses {
+                        formatted.push_str(&format!(
+                            "**{}** {}\n",
+                            v.verse,
+                            markdown_text(&v.words)
+                        ));
+                    }
+                }
+            }
+        }
+        formatted
+    }
+}
+
+/// A verse on a line of a streamed book, with the name of its book so each
+/// line stands on its own.
+#[derive(Serialize)]
+struct VerseLine<'a> {
+    book: &'a str,
+    chapter: i32,
+    verse: i32,
+    words: &'a str,
+}
+
+/// Formats verses of a book as newline-delimited JSON, a verse on each line.
+pub fn ndjson_lines(book: &str, verses: &[Verse]) -> String {
+    let mut lines = String::new();
+    for v in verses {
+        let line = VerseLine {
+            book,
+            chapter: v.chapter,
+            verse: v.verse,
+            words: &v.words,
+        };
+        lines.push_str(&serde_json::to_string(&line).unwrap());
+        lines.push('\n');
+    }
+    lines
+}
+
+/// Quotes a CSV field if it has a comma, quote or line break in it.
+fn csv_field(field: &str) -> String {
+    if field.contains(|c| matches!(c, ',' | '"' | '\r' | '\n')) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Escapes the characters of text that Markdown would read as formatting.
+fn markdown_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']' | '<' | '>') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::header;
+    use actix_web::test::TestRequest;
+
+    use super::*;
+    use crate::responder::link::Link;
+
+    fn matches() -> Vec<SearchResult> {
+        let result = |label: &str, plain_text: &str| SearchResult {
+            link: Link {
+                label: label.to_string(),
+                url: String::new(),
+            },
+            text: String::new(),
+            plain_text: plain_text.to_string(),
+            highlights: vec![],
+        };
+        vec![
+            result(
+                "Psalms 119:105",
+                "Thy word is a lamp unto my feet, and a light unto my path.",
+            ),
+            result(
+                "John 1:1",
+                "In the beginning was the Word, and the Word was with God.",
+            ),
+        ]
+    }
+
+    #[test]
+    fn csv() {
+        assert_eq!(
+            ExportFormat::Csv.export(&matches(), Some("kjv")),
+            "reference,translation,text\r\n\
+             Psalms 119:105,KJV,\"Thy word is a lamp unto my feet, and a light unto my path.\"\r\n\
+             John 1:1,KJV,\"In the beginning was the Word, and the Word was with God.\"\r\n"
+        );
+        assert_eq!(csv_field("say \"Amen\""), "\"say \"\"Amen\"\"\"");
+    }
+
+    #[test]
+    fn markdown() {
+        assert_eq!(
+            ExportFormat::Markdown.export(&matches()[1..], None),
+            "- **John 1:1** In the beginning was the Word, and the Word was with God.\n"
+        );
+        assert_eq!(
+            ExportFormat::Markdown.export(&matches()[..1], Some("kjv")),
+            "- **Psalms 119:105** (KJV) Thy word is a lamp unto my feet, and a light unto my \
+             path.\n"
+        );
+        assert_eq!(markdown_text("*LORD* [God]"), "\\*LORD\\* \\[God\\]");
+    }
+
+    #[test]
+    fn passage_formats() {
+        let accept = |value: &str| {
+            let req = TestRequest::default()
+                .insert_header((header::ACCEPT, value))
+                .to_http_request();
+            PassageFormat::from_request(&req)
+        };
+        assert_eq!(accept("text/plain"), Some(PassageFormat::PlainText));
+        assert_eq!(
+            accept("text/html;q=0.5, text/markdown"),
+            Some(PassageFormat::Markdown)
+        );
+        assert_eq!(accept("text/html,application/xhtml+xml,*/*;q=0.8"), None);
+        assert_eq!(
+            accept("image/png, application/json;q=0.1"),
+            Some(PassageFormat::Json)
+        );
+        assert_eq!(
+            PassageFormat::from_request(&TestRequest::default().to_http_request()),
+            None
+        );
+    }
+
+    #[test]
+    fn extensions() {
+        assert_eq!(
+            ExportFormat::from_extension("md"),
+            Some(ExportFormat::Markdown)
+        );
+        assert_eq!(ExportFormat::Csv.extension(), "csv");
+        assert_eq!(ExportFormat::from_extension("json"), None);
+    }
+}
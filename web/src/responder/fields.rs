@@ -0,0 +1,70 @@
+use serde_json::{Map, Value};
+
+/// Keeps only the named fields of a JSON response, at any depth, along with
+/// the objects and lists leading to them. For example, `verse` and `words`
+/// keep the list of verses with only those fields of each verse, dropping
+/// the book, links and the rest.
+pub fn select_fields(value: Value, fields: &[&str]) -> Value {
+    select(value, fields).unwrap_or_else(|| Value::Object(Map::new()))
+}
+
+/// Selects the fields in a value, if any of them are in it.
+fn select(value: Value, fields: &[&str]) -> Option<Value> {
+    match value {
+        Value::Object(object) => {
+            let selected: Map<String, Value> = object
+                .into_iter()
+                .filter_map(|(key, value)| {
+                    if fields.contains(&key.as_str()) {
+                        Some((key, value))
+                    } else {
+                        select(value, fields).map(|value| (key, value))
+                    }
+                })
+                .collect();
+            (!selected.is_empty()).then(|| Value::Object(selected))
+        }
+        Value::Array(items) => {
+            let selected: Vec<Value> = items
+                .into_iter()
+                .filter_map(|item| select(item, fields))
+                .collect();
+            (!selected.is_empty()).then(|| Value::Array(selected))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn selects_fields() {
+        let passage = json!({
+            "book": { "name": "Psalms", "chapter_count": 150 },
+            "links": { "current": { "label": "Psalms 119:105", "url": "/Psalms/119/105" } },
+            "verses": [
+                { "chapter": 119, "verse": 105, "words": "Thy word is a lamp" },
+                { "chapter": 119, "verse": 106, "words": "I have sworn" },
+            ],
+        });
+        assert_eq!(
+            select_fields(passage.clone(), &["verse", "words"]),
+            json!({
+                "verses": [
+                    { "verse": 105, "words": "Thy word is a lamp" },
+                    { "verse": 106, "words": "I have sworn" },
+                ],
+            })
+        );
+        // Named objects are kept whole
+        assert_eq!(
+            select_fields(passage.clone(), &["book"]),
+            json!({ "book": { "name": "Psalms", "chapter_count": 150 } })
+        );
+        assert_eq!(select_fields(passage, &["nope"]), json!({}));
+    }
+}
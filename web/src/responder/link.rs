@@ -1,11 +1,11 @@
-use std::ops::RangeInclusive;
-
 use actix_web::error::UrlGenerationError;
 use actix_web::HttpRequest;
 use log::error;
 use serde_derive::{Deserialize, Serialize};
-use url::Url;
+use url::{form_urlencoded, Url};
+use utoipa::ToSchema;
 
+use db::edit_distance;
 use db::models::{Book, Reference};
 
 /// Name used in the HTML title generator
@@ -84,25 +84,82 @@ pub const BOOKS: [(&str, i32); 68] = [
     ("_", 0), // Dummy to avoid having to do a range check for the "next" book of Revelation
 ];
 
+/// Deuterocanonical books in reading order, including the total number of
+/// chapters in that book. Their ids follow on from Revelation (the first is
+/// book 67), and they're read as a section between Malachi and Matthew.
+pub const DEUTEROCANON: [(&str, i32); 10] = [
+    ("Tobit", 14),
+    ("Judith", 16),
+    ("Wisdom of Solomon", 19),
+    ("Sirach", 51),
+    ("Baruch", 6),
+    ("1 Maccabees", 16),
+    ("2 Maccabees", 15),
+    ("1 Esdras", 9),
+    ("2 Esdras", 16),
+    ("Prayer of Manasseh", 1),
+];
+
+/// Id of the first deuterocanonical book.
+const DEUTEROCANON_START: usize = 67;
+
+/// Gets the previous, current, and next books around the book with the
+/// given id, in reading order.
+///
+/// Leaving the deuterocanonical section goes back to the Protestant canon,
+/// so translations without those books never link to them.
+fn book_neighbors(id: i32) -> [(&'static str, i32); 3] {
+    let id = id as usize;
+    match id.checked_sub(DEUTEROCANON_START) {
+        Some(i) if i < DEUTEROCANON.len() => [
+            if i == 0 {
+                BOOKS[39]
+            } else {
+                DEUTEROCANON[i - 1]
+            },
+            DEUTEROCANON[i],
+            DEUTEROCANON.get(i + 1).copied().unwrap_or(BOOKS[40]),
+        ],
+        _ => [BOOKS[id - 1], BOOKS[id], BOOKS[id + 1]],
+    }
+}
+
 fn invalid_url(e: UrlGenerationError) -> Url {
     error!("{:?}", e);
     Url::parse("https://bible.rs").unwrap()
 }
 
+/// Generates a URL for the named resource.
+///
+/// If the current request was made under a translation prefix
+/// (e.g. `/kjv/John/3`), the generated URL keeps that prefix.
+fn url_for(name: &str, element: &str, req: &HttpRequest) -> Url {
+    match req.match_info().get("version") {
+        Some(version) => req.url_for(&format!("version_{}", name), &[version, element]),
+        None => req.url_for(name, &[element]),
+    }
+    .unwrap_or_else(invalid_url)
+}
+
+/// Generates the URL for the list of all books.
+fn bible_url(req: &HttpRequest) -> Url {
+    match req.match_info().get("version") {
+        Some(version) => req.url_for("version_bible", &[version]),
+        None => req.url_for_static("bible"),
+    }
+    .unwrap_or_else(invalid_url)
+}
+
 /// Generates a book URL for the given book.
-fn book_url(b: &str, req: &HttpRequest) -> Link {
-    Link::new(
-        &req.url_for("book", &[b]).unwrap_or_else(invalid_url),
-        b.to_string(),
-    )
+pub(super) fn book_url(b: &str, req: &HttpRequest) -> Link {
+    Link::new(&url_for("book", b, req), b.to_string())
 }
 
 /// Generates a chapter URL for the given book and chapter.
-fn chapter_url(b: &str, c: i32, req: &HttpRequest) -> Link {
+pub(super) fn chapter_url(b: &str, c: i32, req: &HttpRequest) -> Link {
     let chapter_string = c.to_string();
     Link::new(
-        &req.url_for("reference", &[format!("{}/{}", b, chapter_string)])
-            .unwrap_or_else(invalid_url),
+        &url_for("reference", &format!("{}/{}", b, chapter_string), req),
         format!("{} {}", b, chapter_string),
     )
 }
@@ -111,33 +168,52 @@ fn chapter_url(b: &str, c: i32, req: &HttpRequest) -> Link {
 pub(super) fn verse_url(b: &str, c: i32, v: i32, req: &HttpRequest) -> Link {
     let chapter_string = c.to_string();
     let verse_string = v.to_string();
-    let mut url = req
-        .url_for("reference", &[format!("{}/{}", b, chapter_string)])
-        .unwrap_or_else(invalid_url);
+    let mut url = url_for("reference", &format!("{}/{}", b, chapter_string), req);
     url.set_fragment(Some(&format!("v{}", verse_string)));
     Link::new(&url, format!("{} {}:{}", b, chapter_string, verse_string))
 }
 
-/// Generates a URL for verses from the given book, chapter, and verse range.
-fn verse_range_url(b: &str, c: i32, verses: &RangeInclusive<i32>, req: &HttpRequest) -> Link {
-    let chapter_string = c.to_string();
-    let verses_string = if verses.start() == verses.end() {
-        verses.start().to_string()
-    } else {
-        format!("{}-{}", verses.start(), verses.end())
-    };
-    Link::new(
-        &req.url_for(
-            "reference",
-            &[format!("{}/{}/{}", b, chapter_string, verses_string)],
-        )
-        .unwrap_or_else(invalid_url),
-        format!("{} {}:{}", b, chapter_string, verses_string),
-    )
+/// Generates a URL for a verse in the given translation, whatever the
+/// translation of the current request.
+pub(super) fn version_verse_url(version: &str, b: &str, c: i32, v: i32, req: &HttpRequest) -> Link {
+    let mut url = req
+        .url_for("version_reference", &[version, &format!("{}/{}", b, c)])
+        .unwrap_or_else(invalid_url);
+    url.set_fragment(Some(&format!("v{}", v)));
+    Link::new(&url, format!("{} {}:{}", b, c, v))
+}
+
+/// Generates a URL for a reference in the given book.
+///
+/// The path is the reference as it's written with slashes between the book,
+/// chapter, and verses (e.g. `/John/3/16,18` or `/Genesis/1/31-2/3`).
+pub(super) fn reference_url(b: &str, reference: &Reference, req: &HttpRequest) -> Link {
+    let label = Reference {
+        book: b.to_string(),
+        ..reference.to_owned()
+    }
+    .to_string();
+    let path = format!("{}/{}", b, label[b.len() + 1..].replace(':', "/"));
+    Link::new(&url_for("reference", &path, req), label)
+}
+
+/// Finds the book whose name is closest to a misspelled one, if any is
+/// close enough to be what was meant.
+fn closest_book(name: &str) -> Option<&'static str> {
+    let name = name.to_lowercase();
+    // Allow roughly one mistake for every three letters
+    let max_distance = (name.chars().count() / 3).max(1);
+    BOOKS[1..]
+        .iter()
+        .chain(DEUTEROCANON.iter())
+        .map(|(book, _)| (edit_distance(&name, &book.to_lowercase()), *book))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, book)| book)
 }
 
 /// Link representing a URL and label
-#[derive(Clone, Deserialize, Serialize, Debug)]
+#[derive(Clone, Deserialize, Serialize, Debug, ToSchema)]
 pub struct Link {
     pub label: String,
     pub url: String,
@@ -158,8 +234,153 @@ impl Link {
     }
 }
 
+/// A chapter to page to from a passage, with its URL in the JSON API.
+#[derive(Clone, Deserialize, Serialize, Debug, ToSchema)]
+pub struct ChapterLink {
+    pub book: String,
+    pub chapter: i32,
+    /// URL of the chapter in the version of the API that was requested.
+    pub url: String,
+}
+
+impl ChapterLink {
+    fn new(b: &str, c: i32, req: &HttpRequest) -> Self {
+        Self {
+            book: b.to_string(),
+            chapter: c,
+            url: api_url(&format!("{}/{}.{}.json", passages_path(req), b, c)),
+        }
+    }
+}
+
+/// Gets the path that passages are under in the version of the API (and
+/// translation) of the request, e.g. `/api/v1/kjv`.
+fn passages_path(req: &HttpRequest) -> &str {
+    req.path().rsplit_once('/').map_or("", |(base, _)| base)
+}
+
+/// Encodes a path of the JSON API as a URL path.
+fn api_url(path: &str) -> String {
+    let mut url = Url::parse("https://bible.rs").unwrap();
+    url.set_path(path);
+    url.path().to_string()
+}
+
+/// A link to follow from a response of the JSON API.
+#[derive(Clone, Deserialize, Serialize, Debug, ToSchema)]
+pub struct Href {
+    pub href: String,
+}
+
+/// Links to follow from a passage in the JSON API, so clients don't need to
+/// build its URLs themselves.
+#[derive(Clone, Deserialize, Serialize, Debug, ToSchema)]
+pub struct PassageLinks {
+    /// The passage itself.
+    #[serde(rename = "self")]
+    pub current: Href,
+    /// Chapters of the passage's book.
+    pub book: Href,
+    pub previous: Option<Href>,
+    pub next: Option<Href>,
+    /// Page of the passage on the site.
+    pub html: Href,
+}
+
+impl PassageLinks {
+    pub(super) fn new(
+        book: &Book,
+        reference: &Reference,
+        navigation: &ChapterNavigation,
+        html: &Link,
+        req: &HttpRequest,
+    ) -> Self {
+        let base = passages_path(req);
+        let label = reference.to_string();
+        let path = format!(
+            "{}.{}",
+            book.name,
+            label[book.name.len() + 1..].replace(':', ".")
+        );
+
+        // Chapters of books are listed at the root of the API, with the
+        // translation as a query parameter
+        let version = req.match_info().get("version");
+        let root = version
+            .and_then(|v| base.strip_suffix(&format!("/{}", v)))
+            .unwrap_or(base);
+        let mut book_href = api_url(&format!("{}/{}/chapters.json", root, book.name));
+        if let Some(version) = version {
+            book_href.push_str(&format!("?version={}", version));
+        }
+
+        let href = |link: &ChapterLink| Href {
+            href: link.url.to_owned(),
+        };
+        Self {
+            current: Href {
+                href: api_url(&format!("{}/{}.json", base, path)),
+            },
+            book: Href { href: book_href },
+            previous: navigation.previous.as_ref().map(href),
+            next: navigation.next.as_ref().map(href),
+            html: Href {
+                href: html.url.to_owned(),
+            },
+        }
+    }
+}
+
+/// The chapters before and after a passage in canonical order, so readers
+/// can page through the Bible with the JSON API.
+#[derive(Clone, Deserialize, Serialize, Debug, ToSchema)]
+pub struct ChapterNavigation {
+    pub previous: Option<ChapterLink>,
+    pub next: Option<ChapterLink>,
+    /// Whether the passage starts at the first chapter of its book, so the
+    /// previous chapter is in the book before.
+    pub first_in_book: bool,
+    /// Whether the passage ends at the last chapter of its book, so the
+    /// next chapter is in the book after.
+    pub last_in_book: bool,
+}
+
+impl ChapterNavigation {
+    pub(super) fn new(book: &Book, reference: &Reference, req: &HttpRequest) -> Self {
+        let [prev, curr, next] = book_neighbors(book.id);
+        let first = reference.chapter;
+        let last = reference.last_chapter();
+        let first_in_book = first == 1;
+        let last_in_book = last >= curr.1;
+
+        // The same chapters as the previous and next links of the verses
+        // pages, from Genesis 1 to Revelation 22
+        let previous = if book.id == 1 && first_in_book {
+            None
+        } else if first_in_book {
+            Some(ChapterLink::new(prev.0, prev.1, req))
+        } else {
+            Some(ChapterLink::new(&book.name, first - 1, req))
+        };
+        let next = if book.id == 66 && last == 22 {
+            None
+        } else if last_in_book {
+            Some(ChapterLink::new(next.0, 1, req))
+        } else {
+            Some(ChapterLink::new(&book.name, last + 1, req))
+        };
+
+        Self {
+            previous,
+            next,
+            first_in_book,
+            last_in_book,
+        }
+    }
+}
+
 /// Links for the verses endpoint.
-#[derive(Clone, Deserialize, Serialize, Debug)]
+#[derive(Clone, Deserialize, Serialize, Debug, ToSchema)]
 pub struct VersesLinks {
     pub books: Link,
     pub book: Link,
@@ -172,68 +393,35 @@ pub struct VersesLinks {
 impl VersesLinks {
     /// Creates a new structure of verses links.
     pub(super) fn new(book: &Book, reference: &Reference, req: &HttpRequest) -> Self {
-        let bible_root = Link::new(&req.url_for_static("bible").unwrap(), NAME.to_string());
-        let book_link = Link::new(
-            &req.url_for("book", &[&book.name]).unwrap(),
-            book.name.to_string(),
-        );
+        let bible_root = Link::new(&bible_url(req), NAME.to_string());
+        let book_link = book_url(&book.name, req);
         let chapter_link = Some(chapter_url(&book.name, reference.chapter, req));
-        let curr_link = match reference.verses {
-            Some(ref vs) => verse_range_url(&book.name, reference.chapter, vs, req),
-            None => chapter_url(&book.name, reference.chapter, req),
+        let curr_link = reference_url(&book.name, reference, req);
+
+        let [prev, curr, next] = book_neighbors(book.id);
+        let first = reference.chapter;
+        let last = reference.last_chapter();
+
+        // Previous: the chapter before the first one in the passage, which is
+        // the last chapter of the previous book for a book's first chapter.
+        // Nothing comes before Genesis 1 (first chapter in the Bible).
+        let prev_link = if book.id == 1 && first == 1 {
+            None
+        } else if first == 1 {
+            Some(chapter_url(prev.0, prev.1, req))
+        } else {
+            Some(chapter_url(&book.name, first - 1, req))
         };
 
-        let (prev_link, next_link) = {
-            let c = reference.chapter;
-            let prev = BOOKS[book.id as usize - 1];
-            let curr = BOOKS[book.id as usize];
-            let next = BOOKS[book.id as usize + 1];
-
-            // Genesis 1 (first chapter in the Bible)
-            // Previous: nothing
-            // Next: Genesis 2
-            if book.id == 1 && c == 1 {
-                (None, Some(chapter_url("Genesis", 2, req)))
-
-            // Revelation 22 (last chapter in the Bible)
-            // Previous: Revelation 21
-            // Next: nothing
-            } else if book.id == 66 && c == 22 {
-                (Some(chapter_url("Revelation", 21, req)), None)
-
-            // Books with one chapter
-            // Previous: last chapter of the previous book
-            // Next: first chapter of the last book
-            } else if curr.1 == 1 {
-                (
-                    Some(chapter_url(prev.0, prev.1, req)),
-                    Some(chapter_url(next.0, 1, req)),
-                )
-            // First chapter in a book
-            // Previous: last chapter of the previous book
-            // Next: next chapter in same book
-            } else if c == 1 {
-                (
-                    Some(chapter_url(prev.0, prev.1, req)),
-                    Some(chapter_url(&book.name, c + 1, req)),
-                )
-            // Last chapter in a book
-            // Previous: last chapter in same book
-            // Next: first chapter in next book
-            } else if c == curr.1 {
-                (
-                    Some(chapter_url(&book.name, c - 1, req)),
-                    Some(chapter_url(next.0, 1, req)),
-                )
-            // Everything else
-            // Previous: last chapter in same book
-            // Next: next chapter in same book
-            } else {
-                (
-                    Some(chapter_url(&book.name, c - 1, req)),
-                    Some(chapter_url(&book.name, c + 1, req)),
-                )
-            }
+        // Next: the chapter after the last one in the passage, which is the
+        // first chapter of the next book for a book's last chapter.
+        // Nothing comes after Revelation 22 (last chapter in the Bible).
+        let next_link = if book.id == 66 && last == 22 {
+            None
+        } else if last >= curr.1 {
+            Some(chapter_url(next.0, 1, req))
+        } else {
+            Some(chapter_url(&book.name, last + 1, req))
         };
 
         Self {
@@ -245,10 +433,34 @@ impl VersesLinks {
             current: curr_link,
         }
     }
+
+    /// Points the chapter links at the parallel view of the given
+    /// comma-separated translations.
+    ///
+    /// The links to the list of books and the book itself are left as-is.
+    pub(super) fn into_parallel(self, versions: &str, req: &HttpRequest) -> Self {
+        let parallel = |link: Link| {
+            let url = req
+                .url_for("parallel", &[link.url.trim_start_matches('/')])
+                .unwrap_or_else(invalid_url);
+            Link {
+                label: link.label,
+                url: format!("{}?versions={}", url.path(), versions),
+            }
+        };
+
+        Self {
+            chapter: self.chapter.map(parallel),
+            previous: self.previous.map(parallel),
+            next: self.next.map(parallel),
+            current: parallel(self.current),
+            ..self
+        }
+    }
 }
 
 /// Links for the books endpoint.
-#[derive(Clone, Deserialize, Serialize, Debug)]
+#[derive(Clone, Deserialize, Serialize, Debug, ToSchema)]
 pub struct BookLinks {
     pub books: Link,
     pub chapters: Vec<String>,
@@ -260,19 +472,21 @@ pub struct BookLinks {
 impl BookLinks {
     /// Creates a new structure of book links.
     pub(super) fn new(book: &Book, chapters: &[i32], req: &HttpRequest) -> Self {
+        let [prev, _, next] = book_neighbors(book.id);
+
         Self {
-            books: Link::new(&req.url_for_static("bible").unwrap(), NAME.to_string()),
+            books: Link::new(&bible_url(req), NAME.to_string()),
             chapters: chapters
                 .iter()
                 .map(|c| chapter_url(&book.name, *c, req).url)
                 .collect(),
             previous: if book.id != 1 {
-                Some(book_url(BOOKS[book.id as usize - 1].0, req))
+                Some(book_url(prev.0, req))
             } else {
                 None
             },
             next: if book.id != 66 {
-                Some(book_url(BOOKS[book.id as usize + 1].0, req))
+                Some(book_url(next.0, req))
             } else {
                 None
             },
@@ -284,16 +498,92 @@ impl BookLinks {
 #[derive(Clone, Deserialize, Serialize, Debug)]
 pub struct AllBooksLinks {
     pub books: Vec<Link>,
+    /// Links to the deuterocanonical books, listed separately.
+    pub deuterocanon: Vec<Link>,
 }
 
 impl AllBooksLinks {
     pub(super) fn new(books: Vec<Book>, req: &HttpRequest) -> Self {
+        let (deuterocanon, books): (Vec<Book>, Vec<Book>) =
+            books.into_iter().partition(|b| b.deuterocanonical);
+
         Self {
             books: books.iter().map(|b| book_url(&b.name, req)).collect(),
+            deuterocanon: deuterocanon
+                .iter()
+                .map(|b| book_url(&b.name, req))
+                .collect(),
+        }
+    }
+}
+
+/// Links for the pages before and after a page of search results.
+#[derive(Clone, Deserialize, Serialize, Debug, ToSchema)]
+pub struct SearchPageLinks {
+    pub previous: Option<Link>,
+    pub next: Option<Link>,
+}
+
+impl SearchPageLinks {
+    pub fn new(page: i64, per_page: i64, total: i64, req: &HttpRequest) -> Self {
+        Self {
+            previous: (page > 1).then(|| Link {
+                label: "Previous".to_string(),
+                url: page_url(page - 1, req),
+            }),
+            next: (page * per_page < total).then(|| Link {
+                label: "Next".to_string(),
+                url: page_url(page + 1, req),
+            }),
         }
     }
 }
 
+/// Generates the URL for another page of the requested search results,
+/// keeping the rest of the query string.
+fn page_url(page: i64, req: &HttpRequest) -> String {
+    search_url(&[("page", &page.to_string())], req)
+}
+
+/// Generates the URL for the requested search with the given query
+/// parameters set, keeping the rest of the query string.
+pub fn search_url(params: &[(&str, &str)], req: &HttpRequest) -> String {
+    let pairs = form_urlencoded::parse(req.query_string().as_bytes())
+        .filter(|(k, _)| !params.iter().any(|(key, _)| k == key));
+    let query = form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(pairs)
+        .extend_pairs(params)
+        .finish();
+    format!("{}?{}", req.path(), query)
+}
+
+/// Links for a page suggesting what was meant by an unknown book.
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct SuggestionLinks {
+    pub books: Link,
+    pub suggestion: Link,
+}
+
+impl SuggestionLinks {
+    /// Creates links to the closest match for a book name, keeping the rest
+    /// of the reference if there is one. There are none if no book is close.
+    pub(super) fn new(
+        book_name: &str,
+        reference: Option<&Reference>,
+        req: &HttpRequest,
+    ) -> Option<Self> {
+        let book = closest_book(book_name)?;
+
+        Some(Self {
+            books: Link::new(&bible_url(req), NAME.to_string()),
+            suggestion: match reference {
+                Some(reference) => reference_url(book, reference, req),
+                None => book_url(book, req),
+            },
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -309,6 +599,7 @@ mod tests {
                 id: 1,
                 name: gen.0.to_string(),
                 testament: Testament::Old,
+                deuterocanonical: false,
                 chapter_count: gen.1,
             };
             let links = BookLinks::new(
@@ -326,6 +617,7 @@ mod tests {
                 id: 66,
                 name: rev.0.to_string(),
                 testament: Testament::New,
+                deuterocanonical: false,
                 chapter_count: rev.1,
             };
             let links = BookLinks::new(
@@ -343,6 +635,7 @@ mod tests {
                 id: 19,
                 name: psa.0.to_string(),
                 testament: Testament::Old,
+                deuterocanonical: false,
                 chapter_count: psa.1,
             };
             let links = BookLinks::new(
@@ -353,6 +646,43 @@ mod tests {
             assert_eq!(links.current.url, "/Psalms");
             assert_eq!(links.previous.unwrap().url, "/Job");
             assert_eq!(links.next.unwrap().url, "/Proverbs");
+
+            // Deuterocanonical book
+            let bar = DEUTEROCANON[4];
+            let book = Book {
+                id: 71,
+                name: bar.0.to_string(),
+                testament: Testament::Old,
+                deuterocanonical: true,
+                chapter_count: bar.1,
+            };
+            let links = BookLinks::new(
+                &book,
+                (1..=book.chapter_count).collect::<Vec<i32>>().as_slice(),
+                &req,
+            );
+            assert_eq!(links.current.url, "/Baruch");
+            assert_eq!(links.previous.unwrap().url, "/Sirach");
+            assert_eq!(links.next.unwrap().url, "/1%20Maccabees");
+        });
+    }
+
+    #[test]
+    fn closest_books() {
+        assert_eq!(closest_book("Phillipians"), Some("Philippians"));
+        assert_eq!(closest_book("genisis"), Some("Genesis"));
+        assert_eq!(closest_book("Revelations"), Some("Revelation"));
+        assert_eq!(closest_book("Barukh"), Some("Baruch"));
+        assert_eq!(closest_book("Hezekiah"), None);
+
+        with_service(|req| {
+            let reference: Reference = "Phillipians 4:13".parse().unwrap();
+            let links = SuggestionLinks::new(&reference.book, Some(&reference), &req).unwrap();
+            assert_eq!(links.suggestion.url, "/Philippians/4/13");
+            assert_eq!(links.suggestion.label, "Philippians 4:13");
+
+            let links = SuggestionLinks::new("Mathew", None, &req).unwrap();
+            assert_eq!(links.suggestion.url, "/Matthew");
         });
     }
 
@@ -365,6 +695,7 @@ mod tests {
                 id: 1,
                 name: gen.0.to_string(),
                 testament: Testament::Old,
+                deuterocanonical: false,
                 chapter_count: gen.1,
             };
             let reference: Reference = "Genesis 1".parse().unwrap();
@@ -381,6 +712,7 @@ mod tests {
                 id: 66,
                 name: rev.0.to_string(),
                 testament: Testament::New,
+                deuterocanonical: false,
                 chapter_count: rev.1,
             };
             let reference: Reference = "Revelation 22".parse().unwrap();
@@ -395,6 +727,7 @@ mod tests {
                 id: 19,
                 name: psa.0.to_string(),
                 testament: Testament::Old,
+                deuterocanonical: false,
                 chapter_count: psa.1,
             };
             let reference: Reference = "Psalms 1".parse().unwrap();
@@ -408,6 +741,31 @@ mod tests {
             assert_eq!(links.current.url, "/Psalms/150");
             assert_eq!(links.next.unwrap().url, "/Proverbs/1");
 
+            // Verses spanning chapters
+            let reference: Reference = "Psalms 119:176-120:2".parse().unwrap();
+            let links = VersesLinks::new(&book, &reference, &req);
+            assert_eq!(links.current.url, "/Psalms/119/176-120/2");
+            assert_eq!(links.previous.unwrap().url, "/Psalms/118");
+            assert_eq!(links.next.unwrap().url, "/Psalms/121");
+
+            // Chapters
+            let reference: Reference = "Psalms 120-134".parse().unwrap();
+            let links = VersesLinks::new(&book, &reference, &req);
+            assert_eq!(links.current.url, "/Psalms/120-134");
+            assert_eq!(links.previous.unwrap().url, "/Psalms/119");
+            assert_eq!(links.next.unwrap().url, "/Psalms/135");
+
+            // Listed verses
+            let reference: Reference = "Psalms 119:105,107-108".parse().unwrap();
+            let links = VersesLinks::new(&book, &reference, &req);
+            assert_eq!(links.current.url, "/Psalms/119/105,107-108");
+            assert_eq!(links.current.label, "Psalms 119:105,107-108");
+
+            // Part of a verse
+            let reference: Reference = "Psalms 119:105a".parse().unwrap();
+            let links = VersesLinks::new(&book, &reference, &req);
+            assert_eq!(links.current.url, "/Psalms/119/105a");
+
             // Typical verses
             let reference: Reference = "Psalms 119".parse().unwrap();
             let links = VersesLinks::new(&book, &reference, &req);
@@ -416,4 +774,17 @@ mod tests {
             assert_eq!(links.next.unwrap().url, "/Psalms/120");
         });
     }
+
+    #[test]
+    fn search_pages() {
+        with_service(|req| {
+            let links = SearchPageLinks::new(2, 10, 25, &req);
+            assert_eq!(links.previous.unwrap().url, "/test?page=1");
+            assert_eq!(links.next.unwrap().url, "/test?page=3");
+
+            let links = SearchPageLinks::new(1, 10, 10, &req);
+            assert!(links.previous.is_none());
+            assert!(links.next.is_none());
+        });
+    }
 }
@@ -1,7 +1,8 @@
 use serde_derive::{Deserialize, Serialize};
 
-use db::models::{Book, Reference, Verse};
+use db::models::{Book, Reference, Translation, Verse};
 
+use crate::responder::data::PassagesData;
 use crate::responder::json_ld::*;
 use crate::responder::link::{AllBooksLinks, BookLinks, VersesLinks};
 
@@ -47,6 +48,15 @@ impl Meta {
         }
     }
 
+    pub fn for_api_docs() -> Self {
+        Self {
+            description: "Documentation of the Bible.rs JSON API".to_string(),
+            json_ld: vec![],
+            title: format!(title_format!(), "API"),
+            url: format!(url_format!(), "/api/docs"),
+        }
+    }
+
     pub fn for_error() -> Self {
         Self {
             description: "Error page".to_string(),
@@ -76,6 +86,41 @@ impl Meta {
         }
     }
 
+    pub fn for_passages(data: &PassagesData, url: &str) -> Self {
+        Self {
+            description: match data.passages.iter().find_map(|p| p.verses.first()) {
+                None => data.reference_string.to_owned(),
+                Some(v) => format!("{}...", v.words),
+            },
+            json_ld: data
+                .passages
+                .iter()
+                .map(|p| JsonLd::Reference(ReferenceJsonLd::new(&p.reference, &p.links)))
+                .collect(),
+            title: format!(title_format!(), data.reference_string),
+            url: format!(url_format!(), url),
+        }
+    }
+
+    pub fn for_parallel(
+        reference: &Reference,
+        translations: &[Translation],
+        links: &VersesLinks,
+    ) -> Self {
+        let names: Vec<&str> = translations.iter().map(|t| t.name.as_str()).collect();
+        let ref_string = format!("{} ({})", reference, names.join(", "));
+        Self {
+            description: format!("{} side by side", ref_string),
+            json_ld: vec![JsonLd::BreadcrumbList(BreadcrumbListJsonLd::new(vec![
+                ListItemJsonLd::new(&links.books, 1),
+                ListItemJsonLd::new(&links.book, 2),
+                ListItemJsonLd::new(&links.current, 3),
+            ]))],
+            title: format!(title_format!(), ref_string),
+            url: format!(url_format!(), links.current.url),
+        }
+    }
+
     pub fn for_search(query: &str, url: &str) -> Self {
         let results_string = format!("Results for '{}'", query);
         Self {
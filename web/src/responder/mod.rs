@@ -1,7 +1,15 @@
 mod data;
+mod etag;
+mod export;
+mod fields;
 mod json_ld;
 mod link;
 mod meta;
+mod openapi;
 
 pub use crate::responder::data::*;
+pub use crate::responder::etag::{has_etag, passage_etag};
+pub use crate::responder::export::{ndjson_lines, ExportFormat, PassageFormat};
+pub use crate::responder::fields::select_fields;
 pub use crate::responder::meta::Meta;
+pub use crate::responder::openapi::ApiDocs;
@@ -0,0 +1,696 @@
+use serde_derive::Serialize;
+use utoipa::openapi::path::{
+    OperationBuilder, ParameterBuilder, ParameterIn, PathItem, PathItemType,
+};
+use utoipa::openapi::request_body::RequestBodyBuilder;
+use utoipa::openapi::{
+    Array, Content, Object, ObjectBuilder, OneOfBuilder, OpenApi, Ref, RefOr, Required, Response,
+    ResponseBuilder, Schema, SchemaType,
+};
+
+use db::models::{Book, Reference, SearchMode, Stats, Testament, Translation, Verse, VerseLength};
+
+use crate::controllers::api::API_V1;
+use crate::responder::link::{
+    BookLinks, ChapterLink, ChapterNavigation, Href, Link, PassageLinks, SearchPageLinks,
+    VersesLinks,
+};
+use crate::responder::{
+    BookSummary, BooksData, ChapterSummary, ChaptersData, CompareData, CompletionData, ContextData,
+    CrossReferenceData, CrossReferencesData, ProblemData, SearchCompletionData, SearchFacet,
+    SearchFacets, SearchResult, SearchResultData, SearchResultGroup, StatsData,
+    TranslationSearchResult, TranslationVerses, TranslationsSearchResultData, VerseOfTheDayData,
+    VersesData,
+};
+
+/// A path or query parameter of an API endpoint.
+#[derive(Clone, Serialize, Debug)]
+pub struct ApiParameter {
+    pub name: &'static str,
+    /// Where the parameter goes, `path` or `query`.
+    pub location: &'static str,
+    pub required: bool,
+    pub description: &'static str,
+    /// Values the parameter can have, if only some are allowed.
+    pub values: Vec<String>,
+}
+
+impl ApiParameter {
+    fn path(name: &'static str, description: &'static str) -> Self {
+        Self {
+            name,
+            location: "path",
+            required: true,
+            description,
+            values: vec![],
+        }
+    }
+
+    fn query(name: &'static str, description: &'static str) -> Self {
+        Self {
+            name,
+            location: "query",
+            required: false,
+            description,
+            values: vec![],
+        }
+    }
+
+    fn required(mut self) -> Self {
+        self.required = true;
+        self
+    }
+
+    fn values(mut self, values: &[&str]) -> Self {
+        self.values = values.iter().map(|v| v.to_string()).collect();
+        self
+    }
+}
+
+/// An endpoint of the JSON API.
+#[derive(Clone, Serialize, Debug)]
+pub struct ApiEndpoint {
+    /// HTTP method of the endpoint, `GET` or `POST`.
+    pub method: &'static str,
+    /// Path of the endpoint, with its path parameters in braces.
+    pub path: String,
+    pub summary: &'static str,
+    pub parameters: Vec<ApiParameter>,
+    /// JSON the endpoint takes in the body of the request, if any.
+    pub request_body: Option<&'static str>,
+    /// JSON types of the items in the list of the request body, `string` or
+    /// `object`.
+    pub request_items: Vec<&'static str>,
+    /// What the endpoint responds with, usually one of the structs in
+    /// [crate::responder].
+    pub response: &'static str,
+    /// Schema of the response, by the names of the structs.
+    pub schema: ResponseSchema,
+    /// MIME types of the responses.
+    pub content_types: Vec<&'static str>,
+}
+
+/// Documentation of the endpoints of the current version of the JSON API,
+/// shown on the API docs page and as an OpenAPI document.
+///
+/// New endpoints of [crate::controllers::api::routes] need to be added
+/// here too.
+#[derive(Clone, Serialize, Debug)]
+pub struct ApiDocs {
+    pub endpoints: Vec<ApiEndpoint>,
+}
+
+impl ApiDocs {
+    /// Version of the API that's documented.
+    pub const VERSION: &'static str = "1.0.0";
+
+    /// Documents the endpoints for the installed translations.
+    pub fn new(translations: &[Translation]) -> Self {
+        let abbreviations: Vec<&str> = translations
+            .iter()
+            .map(|t| t.abbreviation.as_str())
+            .collect();
+        let version = || {
+            ApiParameter::query(
+                "version",
+                "Translation to use instead of the user's preferred one.",
+            )
+            .values(&abbreviations)
+        };
+        let reference = || {
+            ApiParameter::path(
+                "reference",
+                "Bible reference with dots for spaces and colons (e.g. `psalms.119.105`). \
+                 Several references can be separated by semicolons.",
+            )
+        };
+        let q = ApiParameter::query("q", "What was typed so far.").required();
+        let fields = || {
+            ApiParameter::query(
+                "fields",
+                "Comma-separated names of the only fields to respond with, at any depth \
+                 (e.g. `verse,words`).",
+            )
+        };
+        let offset = || ApiParameter::query("offset", "Number of verses to skip.");
+        let limit = || ApiParameter::query("limit", "Most verses to respond with.");
+
+        let json = vec!["application/json"];
+        let endpoints = vec![
+            ApiEndpoint {
+                method: "GET",
+                path: format!("{}/{{reference}}.json", API_V1),
+                summary: "Verses of a reference in the user's translation.",
+                parameters: vec![reference(), fields(), offset(), limit()],
+                request_body: None,
+                request_items: vec![],
+                response: "VersesData, or a list of them for several references",
+                schema: ResponseSchema::OneOrList("VersesData"),
+                content_types: json.clone(),
+            },
+            ApiEndpoint {
+                method: "GET",
+                path: format!("{}/{{version}}/{{reference}}.json", API_V1),
+                summary: "Verses of a reference in a translation.",
+                parameters: vec![
+                    ApiParameter::path("version", "Translation of the verses.")
+                        .values(&abbreviations),
+                    reference(),
+                    fields(),
+                    offset(),
+                    limit(),
+                ],
+                request_body: None,
+                request_items: vec![],
+                response: "VersesData, or a list of them for several references",
+                schema: ResponseSchema::OneOrList("VersesData"),
+                content_types: json.clone(),
+            },
+            ApiEndpoint {
+                method: "GET",
+                path: format!("{}/books.json", API_V1),
+                summary: "Books of the user's translation, in canonical order.",
+                parameters: vec![version()],
+                request_body: None,
+                request_items: vec![],
+                response: "BooksData",
+                schema: ResponseSchema::One("BooksData"),
+                content_types: json.clone(),
+            },
+            ApiEndpoint {
+                method: "GET",
+                path: format!("{}/{{book}}/chapters.json", API_V1),
+                summary: "Chapters of a book in the user's translation, with their verse counts.",
+                parameters: vec![
+                    ApiParameter::path("book", "Name or abbreviation of the book."),
+                    version(),
+                ],
+                request_body: None,
+                request_items: vec![],
+                response: "ChaptersData",
+                schema: ResponseSchema::One("ChaptersData"),
+                content_types: json.clone(),
+            },
+            ApiEndpoint {
+                method: "GET",
+                path: format!("{}/stats.json", API_V1),
+                summary: "Counts of the books, chapters, verses and words of the user's \
+                          translation, with its longest and shortest verses.",
+                parameters: vec![version()],
+                request_body: None,
+                request_items: vec![],
+                response: "StatsData",
+                schema: ResponseSchema::One("StatsData"),
+                content_types: json.clone(),
+            },
+            ApiEndpoint {
+                method: "GET",
+                path: format!("{}/{{book}}/stats.json", API_V1),
+                summary: "Counts of the chapters, verses and words of a book in the user's \
+                          translation, with its longest and shortest verses.",
+                parameters: vec![
+                    ApiParameter::path("book", "Name or abbreviation of the book."),
+                    version(),
+                ],
+                request_body: None,
+                request_items: vec![],
+                response: "StatsData",
+                schema: ResponseSchema::One("StatsData"),
+                content_types: json.clone(),
+            },
+            ApiEndpoint {
+                method: "GET",
+                path: format!("{}/{{book}}/full.ndjson", API_V1),
+                summary: "Every verse of a book in the user's translation, streamed.",
+                parameters: vec![
+                    ApiParameter::path("book", "Name or abbreviation of the book."),
+                    version(),
+                ],
+                request_body: None,
+                request_items: vec![],
+                response: "A JSON object on each line with the book, chapter, verse and words of \
+                           a verse",
+                schema: ResponseSchema::Text,
+                content_types: vec!["application/x-ndjson"],
+            },
+            ApiEndpoint {
+                method: "GET",
+                path: format!("{}/full.ndjson", API_V1),
+                summary: "Every verse of the Bible in the user's translation, streamed.",
+                parameters: vec![version()],
+                request_body: None,
+                request_items: vec![],
+                response: "A JSON object on each line with the book, chapter, verse and words of \
+                           a verse",
+                schema: ResponseSchema::Text,
+                content_types: vec!["application/x-ndjson"],
+            },
+            ApiEndpoint {
+                method: "GET",
+                path: format!("{}/random.json", API_V1),
+                summary: "A verse picked at random from the user's translation.",
+                parameters: vec![
+                    ApiParameter::query("books", "Comma-separated names of books to pick from."),
+                    ApiParameter::query("testament", "Testament to pick from.")
+                        .values(&["ot", "nt"]),
+                    ApiParameter::query("range", "Range of books to pick from."),
+                    ApiParameter::query(
+                        "exclude_genealogies",
+                        "Whether to leave out genealogies and other lists of names.",
+                    )
+                    .values(&["true", "false"]),
+                    version(),
+                ],
+                request_body: None,
+                request_items: vec![],
+                response: "VersesData",
+                schema: ResponseSchema::One("VersesData"),
+                content_types: json.clone(),
+            },
+            ApiEndpoint {
+                method: "GET",
+                path: format!("{}/votd.json", API_V1),
+                summary: "The verse of the day, which is the same for everyone on a day.",
+                parameters: vec![
+                    ApiParameter::query("date", "Day to get the verse for, as `YYYY-MM-DD`."),
+                    version(),
+                ],
+                request_body: None,
+                request_items: vec![],
+                response: "VerseOfTheDayData",
+                schema: ResponseSchema::One("VerseOfTheDayData"),
+                content_types: json.clone(),
+            },
+            ApiEndpoint {
+                method: "POST",
+                path: format!("{}/search/batch", API_V1),
+                summary: "Several searches at once, in the user's translation.",
+                parameters: vec![fields()],
+                request_body: Some(
+                    "a list of up to 20 searches, each a query or an object of the parameters \
+                     of a search (e.g. `[\"faith\", {\"q\": \"hope\", \"testament\": \"nt\"}]`)",
+                ),
+                request_items: vec!["string", "object"],
+                response: "an object of SearchResultData keyed by query",
+                schema: ResponseSchema::Map("SearchResultData"),
+                content_types: json.clone(),
+            },
+            ApiEndpoint {
+                method: "POST",
+                path: format!("{}/passages", API_V1),
+                summary: "Verses of several references at once, in the user's translation.",
+                parameters: vec![],
+                request_body: Some("a list of up to 100 references (e.g. `[\"John 3:16\"]`)"),
+                request_items: vec!["string"],
+                response: "a list of VersesData, in the order of the references",
+                schema: ResponseSchema::List("VersesData"),
+                content_types: json.clone(),
+            },
+            ApiEndpoint {
+                method: "GET",
+                path: format!("{}/compare/{{reference}}.json", API_V1),
+                summary: "Verses of a reference in every installed translation.",
+                parameters: vec![reference()],
+                request_body: None,
+                request_items: vec![],
+                response: "CompareData",
+                schema: ResponseSchema::One("CompareData"),
+                content_types: json.clone(),
+            },
+            ApiEndpoint {
+                method: "GET",
+                path: format!("{}/context/{{reference}}.json", API_V1),
+                summary: "Verses of a reference with verses of context around them.",
+                parameters: vec![
+                    reference(),
+                    ApiParameter::query(
+                        "before",
+                        "Verses of context before, up to 50 (2 by default).",
+                    ),
+                    ApiParameter::query(
+                        "after",
+                        "Verses of context after, up to 50 (2 by default).",
+                    ),
+                    version(),
+                ],
+                request_body: None,
+                request_items: vec![],
+                response: "ContextData",
+                schema: ResponseSchema::One("ContextData"),
+                content_types: json.clone(),
+            },
+            ApiEndpoint {
+                method: "GET",
+                path: format!("{}/crossrefs/{{reference}}.json", API_V1),
+                summary: "Passages to see along with the verses of a reference.",
+                parameters: vec![reference()],
+                request_body: None,
+                request_items: vec![],
+                response: "CrossReferencesData",
+                schema: ResponseSchema::One("CrossReferencesData"),
+                content_types: json.clone(),
+            },
+            ApiEndpoint {
+                method: "GET",
+                path: format!("{}/search", API_V1),
+                summary: "Verses matching a search, or the verses of a reference.",
+                parameters: search_parameters(version())
+                    .into_iter()
+                    .chain([fields()])
+                    .collect(),
+                request_body: None,
+                request_items: vec![],
+                response: "SearchResultData, or TranslationsSearchResultData when `versions` \
+                           are given",
+                schema: ResponseSchema::OneOf("SearchResultData", "TranslationsSearchResultData"),
+                content_types: json.clone(),
+            },
+            ApiEndpoint {
+                method: "GET",
+                path: format!("{}/search.{{format}}", API_V1),
+                summary: "All of the matches of a search, exported as a file.",
+                parameters: [
+                    ApiParameter::path("format", "Format of the file.").values(&["csv", "md"])
+                ]
+                .into_iter()
+                .chain(search_parameters(version()))
+                .filter(|p| !matches!(p.name, "page" | "per_page" | "versions"))
+                .collect(),
+                request_body: None,
+                request_items: vec![],
+                response: "The reference, translation and text of each match",
+                schema: ResponseSchema::Text,
+                content_types: vec!["text/csv", "text/markdown"],
+            },
+            ApiEndpoint {
+                method: "GET",
+                path: format!("{}/search/suggest", API_V1),
+                summary: "Words and phrases completing a partly typed search.",
+                parameters: vec![q.clone(), version()],
+                request_body: None,
+                request_items: vec![],
+                response: "SearchCompletionData",
+                schema: ResponseSchema::One("SearchCompletionData"),
+                content_types: json.clone(),
+            },
+            ApiEndpoint {
+                method: "GET",
+                path: format!("{}/complete", API_V1),
+                summary: "Books and chapters completing a partly typed reference.",
+                parameters: vec![q, version()],
+                request_body: None,
+                request_items: vec![],
+                response: "CompletionData",
+                schema: ResponseSchema::One("CompletionData"),
+                content_types: json,
+            },
+        ];
+        Self { endpoints }
+    }
+
+    /// Gets the documentation as an OpenAPI 3 document, with the schemas of
+    /// the responses derived from their structs.
+    pub fn to_openapi(&self) -> OpenApi {
+        let mut openapi = <ApiSchemas as utoipa::OpenApi>::openapi();
+        openapi.info.title = "Bible.rs".to_string();
+        openapi.info.description =
+            Some("Verses and searches of the translations installed on Bible.rs.".to_string());
+        openapi.info.version = Self::VERSION.to_string();
+
+        for endpoint in &self.endpoints {
+            let mut operation = OperationBuilder::new()
+                .summary(Some(endpoint.summary))
+                .response("200", endpoint.to_response())
+                .response(
+                    "400",
+                    error_response("The reference or search isn't valid."),
+                )
+                .response(
+                    "404",
+                    error_response("The book or translation wasn't found."),
+                )
+                .response("429", error_response("Too many requests were made."));
+            for p in &endpoint.parameters {
+                let mut schema = ObjectBuilder::new().schema_type(SchemaType::String);
+                if !p.values.is_empty() {
+                    schema = schema.enum_values(Some(p.values.clone()));
+                }
+                let location = match p.location {
+                    "path" => ParameterIn::Path,
+                    _ => ParameterIn::Query,
+                };
+                let required = if p.required {
+                    Required::True
+                } else {
+                    Required::False
+                };
+                operation = operation.parameter(
+                    ParameterBuilder::new()
+                        .name(p.name)
+                        .parameter_in(location)
+                        .required(required)
+                        .description(Some(p.description))
+                        .schema(Some(RefOr::T(Schema::Object(schema.build()))))
+                        .build(),
+                );
+            }
+            if let Some(request_body) = endpoint.request_body {
+                let request_body = RequestBodyBuilder::new()
+                    .description(Some(request_body))
+                    .required(Some(Required::True))
+                    .content(
+                        "application/json",
+                        Content::new(list(endpoint.request_item_schema())),
+                    )
+                    .build();
+                operation = operation.request_body(Some(request_body));
+            }
+            let method = match endpoint.method {
+                "POST" => PathItemType::Post,
+                _ => PathItemType::Get,
+            };
+            openapi.paths.paths.insert(
+                endpoint.path.clone(),
+                PathItem::new(method, operation.build()),
+            );
+        }
+        openapi
+    }
+}
+
+impl ApiEndpoint {
+    /// Documents the successful response of the endpoint.
+    fn to_response(&self) -> Response {
+        let schema = match self.schema {
+            ResponseSchema::One(name) => component(name),
+            ResponseSchema::List(name) => list(component(name)),
+            ResponseSchema::OneOrList(name) => one_of(vec![component(name), list(component(name))]),
+            ResponseSchema::OneOf(first, second) => {
+                one_of(vec![component(first), component(second)])
+            }
+            ResponseSchema::Map(name) => RefOr::T(Schema::Object(
+                ObjectBuilder::new()
+                    .additional_properties(Some(component(name)))
+                    .build(),
+            )),
+            ResponseSchema::Text => of_type(SchemaType::String),
+        };
+        let mut response = ResponseBuilder::new().description(self.response);
+        for content_type in &self.content_types {
+            response = response.content(*content_type, Content::new(schema.clone()));
+        }
+        response.build()
+    }
+
+    /// Gets the schema of the items in the list of the request body, which
+    /// can be one of several types.
+    fn request_item_schema(&self) -> RefOr<Schema> {
+        let items = self
+            .request_items
+            .iter()
+            .map(|item| match *item {
+                "object" => of_type(SchemaType::Object),
+                _ => of_type(SchemaType::String),
+            })
+            .collect::<Vec<_>>();
+        match items.len() {
+            1 => items.into_iter().next().unwrap(),
+            _ => one_of(items),
+        }
+    }
+}
+
+/// Schema of what an endpoint responds with, by the names of the structs
+/// in the components of the OpenAPI document.
+#[derive(Clone, Copy, Serialize, Debug)]
+pub enum ResponseSchema {
+    /// Just the one.
+    One(&'static str),
+    /// A list, in the order it was asked for.
+    List(&'static str),
+    /// One, or a list of them when several were asked for.
+    OneOrList(&'static str),
+    /// Either of two, depending on the parameters.
+    OneOf(&'static str, &'static str),
+    /// An object of them, keyed by what was asked for.
+    Map(&'static str),
+    /// Plain text, such as a file or a stream of JSON lines.
+    Text,
+}
+
+/// Schemas of the structs the JSON API responds with, derived from their
+/// definitions, which the paths of [ApiDocs::to_openapi] refer to.
+#[derive(utoipa::OpenApi)]
+#[openapi(components(schemas(
+    Book,
+    BookLinks,
+    BookSummary,
+    BooksData,
+    ChapterLink,
+    ChapterNavigation,
+    ChapterSummary,
+    ChaptersData,
+    CompareData,
+    CompletionData,
+    ContextData,
+    CrossReferenceData,
+    CrossReferencesData,
+    Href,
+    Link,
+    PassageLinks,
+    ProblemData,
+    Reference,
+    SearchCompletionData,
+    SearchFacet,
+    SearchFacets,
+    SearchMode,
+    SearchPageLinks,
+    SearchResult,
+    SearchResultData,
+    SearchResultGroup,
+    Stats,
+    StatsData,
+    Testament,
+    Translation,
+    TranslationSearchResult,
+    TranslationVerses,
+    TranslationsSearchResultData,
+    Verse,
+    VerseLength,
+    VerseOfTheDayData,
+    VersesData,
+    VersesLinks,
+)))]
+struct ApiSchemas;
+
+/// Parameters of the search endpoints.
+fn search_parameters(version: ApiParameter) -> Vec<ApiParameter> {
+    vec![
+        ApiParameter::query("q", "Words or a reference to search for.").required(),
+        ApiParameter::query("mode", "`regex` to search with a regular expression.")
+            .values(&["regex"]),
+        ApiParameter::query("books", "Comma-separated names of books to search in."),
+        ApiParameter::query("testament", "Testament to search in.").values(&["ot", "nt"]),
+        ApiParameter::query(
+            "range",
+            "Range of books to search in (e.g. `Genesis-Deuteronomy`).",
+        ),
+        ApiParameter::query("order", "Order to list the matches in.")
+            .values(&["canonical", "relevance"]),
+        ApiParameter::query("page", "Page of matches to get, starting at 1."),
+        ApiParameter::query("per_page", "Number of matches on a page, up to 100."),
+        ApiParameter::query("whole_word", "Whether to only match whole words.")
+            .values(&["true", "false"]),
+        version,
+        ApiParameter::query(
+            "versions",
+            "Translations to search at once, `all` or a comma-separated list of them.",
+        ),
+        ApiParameter::query(
+            "within",
+            "`previous` to only search within the verses of an earlier search.",
+        )
+        .values(&["previous"]),
+        ApiParameter::query(
+            "results",
+            "Token for the verses matched by an earlier search.",
+        ),
+    ]
+}
+
+/// Documents an error response, with problem details.
+fn error_response(description: &str) -> Response {
+    ResponseBuilder::new()
+        .description(description)
+        .content(
+            "application/problem+json",
+            Content::new(component("ProblemData")),
+        )
+        .build()
+}
+
+/// Refers to the schema of a struct in the components of the document.
+fn component(name: &str) -> RefOr<Schema> {
+    RefOr::Ref(Ref::from_schema_name(name))
+}
+
+/// Gets the schema of a list of values matching a schema.
+fn list(items: RefOr<Schema>) -> RefOr<Schema> {
+    RefOr::T(Schema::Array(Array::new(items)))
+}
+
+/// Gets the schema of values of a JSON type.
+fn of_type(schema_type: SchemaType) -> RefOr<Schema> {
+    RefOr::T(Schema::Object(Object::with_type(schema_type)))
+}
+
+/// Combines schemas that a value can match any one of.
+fn one_of(schemas: Vec<RefOr<Schema>>) -> RefOr<Schema> {
+    let one_of = schemas
+        .into_iter()
+        .fold(OneOfBuilder::new(), |one_of, schema| one_of.item(schema));
+    RefOr::T(Schema::OneOf(one_of.build()))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::test::test_translation;
+
+    #[test]
+    fn openapi() {
+        let openapi =
+            serde_json::to_value(ApiDocs::new(&[test_translation()]).to_openapi()).unwrap();
+        let search = &openapi["paths"]["/api/v1/search"]["get"];
+        assert_eq!(search["parameters"][0]["name"], "q");
+        assert_eq!(search["parameters"][0]["required"], true);
+
+        let reference = &openapi["paths"]["/api/v1/{version}/{reference}.json"]["get"];
+        assert_eq!(reference["parameters"][0]["schema"]["enum"], json!(["kjv"]));
+
+        let export = &openapi["paths"]["/api/v1/search.{format}"]["get"];
+        let names: Vec<&Value> = export["parameters"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| &p["name"])
+            .collect();
+        assert!(names.contains(&&json!("format")));
+        assert!(!names.contains(&&json!("page")));
+
+        let errors = &search["responses"]["400"]["content"]["application/problem+json"];
+        assert_eq!(errors["schema"]["$ref"], "#/components/schemas/ProblemData");
+
+        let passages = &openapi["paths"]["/api/v1/passages"]["post"];
+        assert_eq!(passages["requestBody"]["required"], true);
+
+        let books = &openapi["paths"]["/api/v1/books.json"]["get"]["responses"]["200"];
+        let schema = &books["content"]["application/json"]["schema"];
+        assert_eq!(schema["$ref"], "#/components/schemas/BooksData");
+        let schemas = &openapi["components"]["schemas"];
+        assert!(schemas["BooksData"]["properties"]["books"].is_object());
+        assert!(schemas["Book"]["properties"]["chapter_count"].is_object());
+    }
+}
@@ -1,14 +1,27 @@
 use std::str;
+use std::sync::Arc;
 
+use actix_web::http::header::{self, HeaderMap};
 use actix_web::{rt::System, test, web, App, HttpRequest, HttpResponse};
 use handlebars::Handlebars;
 use serde::de::DeserializeOwned;
+use serde::Serialize;
 
 use db::models::*;
 use db::*;
 
+use crate::assets::{register_helper, AssetManifest};
 use crate::ServerData;
-use crate::{api, view};
+use crate::{api, graphql, view};
+
+/// Route pattern for the translations available in tests.
+pub const VERSION: &str = "{version:kjv}";
+
+/// ID of the verse that test searches match.
+const TEST_VERSE_ID: i32 = 1;
+
+/// API key that's been issued in tests.
+pub const TEST_API_KEY: &str = "test-key";
 
 pub fn with_service<F>(f: F)
 where
@@ -20,6 +33,12 @@ where
                 f(req);
                 HttpResponse::Ok()
             }))
+            .service(web::resource("parallel/{reference:.+\\d}").name("parallel"))
+            .service(web::resource(VERSION).name("version_bible"))
+            .service(web::resource(format!("{}/{{book}}", VERSION)).name("version_book"))
+            .service(
+                web::resource(format!("{}/{{reference:.+\\d}}", VERSION)).name("version_reference"),
+            )
             .service(web::resource("/").name("bible"))
             .service(web::resource("{book}").name("book"))
             .service(web::resource("{reference:.+\\d}").name("reference")),
@@ -34,24 +53,105 @@ where
     });
 }
 
+pub fn test_translation() -> Translation {
+    Translation {
+        id: 1,
+        abbreviation: "kjv".to_string(),
+        name: "King James Version".to_string(),
+        language: "en".to_string(),
+        license: Some("Public Domain".to_string()),
+        copyright: None,
+        attribution: None,
+        direction: "ltr".to_string(),
+        versification: "kjv".to_string(),
+        imported_at: 1653696000,
+    }
+}
+
 fn test_book() -> Book {
     Book {
         id: 19,
         name: "Psalms".to_string(),
         chapter_count: 150,
         testament: Testament::Old,
+        deuterocanonical: false,
+    }
+}
+
+/// Looks up the test book by name, which is the only book there is.
+fn find_test_book(name: &str) -> Result<Book, DbError> {
+    let book = test_book();
+    if book.name.eq_ignore_ascii_case(name) {
+        Ok(book)
+    } else {
+        Err(DbError::BookNotFound {
+            book: name.to_string(),
+        })
     }
 }
 
 pub struct TestSwordDrill;
 
-impl SwordDrillable for TestSwordDrill {
+impl BookStore for TestSwordDrill {
+    fn book(name: &str, _: &Translation, _: &DbConnection) -> Result<(Book, Vec<i32>), DbError> {
+        Ok((find_test_book(name)?, (1..=150).collect()))
+    }
+
+    fn chapters(
+        name: &str,
+        _: &Translation,
+        _: &DbConnection,
+    ) -> Result<(Book, Vec<(i32, i64)>), DbError> {
+        Ok((find_test_book(name)?, vec![(119, 176)]))
+    }
+
+    fn all_books(_: &Translation, _: &DbConnection) -> Result<Vec<Book>, DbError> {
+        Ok(vec![test_book()])
+    }
+
+    fn complete_books(
+        prefix: &str,
+        _: &Translation,
+        _: &DbConnection,
+    ) -> Result<Vec<Book>, DbError> {
+        let book = test_book();
+        if book.name.to_lowercase().starts_with(&prefix.to_lowercase()) {
+            Ok(vec![book])
+        } else {
+            Ok(vec![])
+        }
+    }
+
+    fn book_abbreviations(_: &DbConnection) -> Result<Vec<BookAbbreviation>, DbError> {
+        Ok(vec![BookAbbreviation {
+            id: 1,
+            book_id: 19,
+            abbreviation: "ps".to_string(),
+        }])
+    }
+
+    fn translations(_: &DbConnection) -> Result<Vec<Translation>, DbError> {
+        Ok(vec![test_translation()])
+    }
+
+    fn translation(abbreviation: &str, _: &DbConnection) -> Result<Translation, DbError> {
+        match abbreviation {
+            "kjv" => Ok(test_translation()),
+            _ => Err(DbError::TranslationNotFound {
+                translation: abbreviation.to_string(),
+            }),
+        }
+    }
+}
+
+impl VerseStore for TestSwordDrill {
     fn verses(
-        _: &Reference,
+        reference: &Reference,
+        _: &Translation,
         _: VerseFormat,
         _: &DbConnection,
     ) -> Result<(Book, Vec<Verse>), DbError> {
-        let book = test_book();
+        let book = find_test_book(&reference.book)?;
 
         let verse = Verse {
             id: 555,
@@ -59,31 +159,222 @@ impl SwordDrillable for TestSwordDrill {
             chapter: 119,
             verse: 105,
             words: "NUN. Thy word is a lamp unto my feet, and a light unto my path.".to_string(),
+            translation_id: 1,
+            paragraph: true,
+            poetry_level: 1,
+            line_break: false,
         };
 
         Ok((book, vec![verse]))
     }
 
-    fn book(_: &str, _: &DbConnection) -> Result<(Book, Vec<i32>), DbError> {
-        Ok((test_book(), (1..=150).collect()))
+    fn parallel_verses(
+        reference: &Reference,
+        translations: &[Translation],
+        format: VerseFormat,
+        conn: &DbConnection,
+    ) -> Result<(Book, Vec<Verse>), DbError> {
+        Self::verses(reference, &translations[0], format, conn)
     }
 
-    fn all_books(_: &DbConnection) -> Result<Vec<Book>, DbError> {
-        Ok(vec![test_book()])
+    fn random_verse(
+        scope: &SearchScope,
+        _: bool,
+        _: &Translation,
+        _: &DbConnection,
+    ) -> Result<Option<Reference>, DbError> {
+        for name in &scope.books {
+            find_test_book(name)?;
+        }
+        if matches!(scope.testament, Some(t) if t != test_book().testament) {
+            return Ok(None);
+        }
+        Ok(Some("Psalms 119:105".parse()?))
+    }
+
+    fn verse_of_the_day(_: i64, _: &DbConnection) -> Result<Reference, DbError> {
+        "Psalms 119:105".parse()
+    }
+
+    fn stats(_: Option<&Book>, _: &Translation, _: &DbConnection) -> Result<Stats, DbError> {
+        let verse = VerseLength {
+            book: "Psalms".to_string(),
+            chapter: 119,
+            verse: 105,
+            words: "NUN. Thy word is a lamp unto my feet, and a light unto my path.".to_string(),
+            word_count: 15,
+        };
+        Ok(Stats {
+            book_count: 1,
+            chapter_count: 1,
+            verse_count: 1,
+            word_count: 15,
+            longest_verse: Some(verse.clone()),
+            shortest_verse: Some(verse),
+        })
+    }
+
+    fn cross_references(
+        reference: &Reference,
+        _: &DbConnection,
+    ) -> Result<(Book, Vec<(CrossReference, Book)>), DbError> {
+        let book = find_test_book(&reference.book)?;
+        let cross_reference = CrossReference {
+            id: 1,
+            book: book.id,
+            chapter: 119,
+            verse: 105,
+            to_book: book.id,
+            to_chapter: 19,
+            to_verse: 8,
+            to_end_chapter: 19,
+            to_end_verse: 8,
+            votes: 12,
+        };
+        Ok((book.to_owned(), vec![(cross_reference, book)]))
     }
+}
 
-    fn search(_: &str, _: &DbConnection) -> Result<Vec<(VerseFTS, Book)>, DbError> {
+impl Searcher for TestSwordDrill {
+    fn search(
+        _: &str,
+        options: &SearchOptions,
+        _: &Translation,
+        _: &DbConnection,
+    ) -> Result<(Vec<(VerseFTS, Book)>, i64), DbError> {
+        for name in &options.scope.books {
+            find_test_book(name)?;
+        }
         let book = test_book();
+        if matches!(options.scope.testament, Some(t) if t != book.testament) {
+            return Ok((vec![], 0));
+        }
+        if matches!(options.within, Some(ref within) if !within.contains(TEST_VERSE_ID)) {
+            return Ok((vec![], 0));
+        }
+        // The only match is on the first page
+        if options.page > 1 {
+            return Ok((vec![], 1));
+        }
+
         let verse = VerseFTS {
             book: 19,
             chapter: 119,
             verse: 105,
-            words: "NUN. Thy word is a lamp unto my feet, and a <em>light</em> unto my path."
+            words: "NUN. Thy word is a lamp unto my feet, and a <mark>light</mark> unto my path."
                 .to_string(),
             rank: 1.1,
         };
 
-        Ok(vec![(verse, book)])
+        Ok((vec![(verse, book)], 1))
+    }
+
+    fn search_translations(
+        query: &str,
+        options: &SearchOptions,
+        translations: &[Translation],
+        conn: &DbConnection,
+    ) -> Result<(Vec<VerseMatches>, i64), DbError> {
+        let mut groups: Vec<VerseMatches> = vec![];
+        let mut total = 0;
+        for translation in translations {
+            let (matches, count) = Self::search(query, options, translation, conn)?;
+            total = total.max(count);
+            for (verse, book) in matches {
+                match groups
+                    .iter_mut()
+                    .find(|g| (g.chapter, g.verse) == (verse.chapter, verse.verse))
+                {
+                    Some(group) => group.matches.push((translation.to_owned(), verse.words)),
+                    None => groups.push(VerseMatches {
+                        book,
+                        chapter: verse.chapter,
+                        verse: verse.verse,
+                        matches: vec![(translation.to_owned(), verse.words)],
+                    }),
+                }
+            }
+        }
+        Ok((groups, total))
+    }
+
+    fn search_regex(
+        pattern: &str,
+        options: &SearchOptions,
+        translation: &Translation,
+        conn: &DbConnection,
+    ) -> Result<(Vec<(VerseFTS, Book)>, i64), DbError> {
+        if pattern.contains('(') && !pattern.contains(')') {
+            return Err(DbError::InvalidSearch {
+                cause: "the pattern has an unclosed group".to_string(),
+            });
+        }
+        Self::search(pattern, options, translation, conn)
+    }
+
+    fn search_result_set(
+        query: &str,
+        options: &SearchOptions,
+        translation: &Translation,
+        conn: &DbConnection,
+    ) -> Result<ResultSet, DbError> {
+        let options = SearchOptions {
+            page: 1,
+            ..options.clone()
+        };
+        let (matches, _) = Self::search(query, &options, translation, conn)?;
+        Ok(match matches.is_empty() {
+            true => ResultSet::default(),
+            false => ResultSet::new(vec![TEST_VERSE_ID]),
+        })
+    }
+
+    fn search_facets(
+        query: &str,
+        options: &SearchOptions,
+        translation: &Translation,
+        conn: &DbConnection,
+    ) -> Result<Vec<(Book, i64)>, DbError> {
+        let options = SearchOptions {
+            page: 1,
+            ..options.clone()
+        };
+        let (matches, total) = Self::search(query, &options, translation, conn)?;
+        Ok(matches.into_iter().map(|(_, book)| (book, total)).collect())
+    }
+
+    fn suggest_spelling(
+        query: &str,
+        _: &Translation,
+        _: &DbConnection,
+    ) -> Result<Option<String>, DbError> {
+        // "light" is the only word that can be misspelled
+        Ok(query
+            .contains("lihgt")
+            .then(|| query.replace("lihgt", "light")))
+    }
+
+    fn complete_search(
+        prefix: &str,
+        _: &Translation,
+        _: &DbConnection,
+    ) -> Result<(Vec<String>, Vec<String>), DbError> {
+        if "light".starts_with(&prefix.to_lowercase()) {
+            Ok((vec!["light".to_string()], vec!["light unto".to_string()]))
+        } else {
+            Ok((vec![], vec![]))
+        }
+    }
+}
+
+impl SwordDrillable for TestSwordDrill {
+    fn api_key(key: &str, _: &DbConnection) -> Result<Option<ApiKey>, DbError> {
+        Ok((key == TEST_API_KEY).then(|| ApiKey {
+            id: 1,
+            name: "Test".to_string(),
+            created_at: "2022-05-14 00:00:00".to_string(),
+            revoked_at: None,
+        }))
     }
 }
 
@@ -91,38 +382,157 @@ pub fn json_response<T>(uri: &str) -> T
 where
     T: DeserializeOwned,
 {
+    let (_, body) = api_response(uri);
+    serde_json::from_str(&body).expect("Could not parse JSON response")
+}
+
+/// Gets the headers and body of an API response.
+pub fn api_response(uri: &str) -> (HeaderMap, String) {
+    api_request(test::TestRequest::with_uri(uri))
+}
+
+/// Posts JSON to the API, getting the headers and body of the response.
+pub fn api_post<T>(uri: &str, json: &T) -> (HeaderMap, String)
+where
+    T: Serialize,
+{
+    api_request(test::TestRequest::post().uri(uri).set_json(json))
+}
+
+/// Server data for the API, with the test translation installed.
+pub fn test_server_data() -> web::Data<ServerData> {
+    web::Data::new(ServerData {
+        db: build_pool(":memory:", &PoolConfig::default()),
+        replicas: ReadReplicas::default(),
+        default_translation: "kjv".to_string(),
+        fallback_translation: None,
+        template: Handlebars::default(),
+        translations: vec![test_translation()],
+    })
+}
+
+/// Origin allowed to call the API in tests.
+const CORS_ORIGIN: &str = "https://example.com";
+
+/// Gets the headers and body of the API's response to a request.
+pub fn api_request(req: test::TestRequest) -> (HeaderMap, String) {
     let srv = test::init_service(
         App::new()
-            .app_data(web::Data::new(ServerData {
-                db: build_pool(":memory:"),
-                template: Handlebars::default(),
-            }))
+            .app_data(test_server_data())
+            .app_data(web::Data::new(graphql::schema::<TestSwordDrill>()))
+            .service(
+                web::resource("/graphql")
+                    .route(web::get().to(graphql::playground))
+                    .route(web::post().to(graphql::graphql::<TestSwordDrill>)),
+            )
+            .service(web::resource(VERSION).name("version_bible"))
+            .service(web::resource(format!("{}/{{book}}", VERSION)).name("version_book"))
+            .service(
+                web::resource(format!("{}/{{reference:.+\\d}}", VERSION)).name("version_reference"),
+            )
             .service(web::resource("/").name("bible"))
             .service(web::resource("{book}").name("book"))
             .service(web::resource("{reference:.+\\d}").name("reference"))
-            .service(web::resource("api/search").to(api::search::<TestSwordDrill>))
-            .service(web::resource("api/{reference}.json").to(api::reference::<TestSwordDrill>)),
+            .service(web::resource("api/openapi.json").to(api::openapi))
+            .service(
+                web::scope(api::API_V1)
+                    .wrap(api::cors(&[CORS_ORIGIN.to_string()]))
+                    .configure(|cfg| api::routes::<TestSwordDrill>(cfg, VERSION)),
+            )
+            .service(
+                web::scope("/api")
+                    .wrap(api::cors(&[CORS_ORIGIN.to_string()]))
+                    .wrap(api::deprecated())
+                    .configure(|cfg| api::routes::<TestSwordDrill>(cfg, VERSION)),
+            ),
     );
 
-    let req = test::TestRequest::with_uri(uri).to_request();
+    let req = req.to_request();
 
-    System::new().block_on(async move { test::call_and_read_body_json(&srv.await, req).await })
+    System::new().block_on(async move {
+        let resp = test::call_service(&srv.await, req).await;
+        let headers = resp.headers().clone();
+        let body = str::from_utf8(&test::read_body(resp).await)
+            .expect("Could not convert response to UTF8")
+            .to_string();
+        (headers, body)
+    })
 }
 
 pub fn html_response(uri: &str) -> String {
+    call_html(test::TestRequest::with_uri(uri)).2
+}
+
+/// Gets the status code and `Location` header of an HTML response.
+pub fn html_redirect(uri: &str) -> (u16, Option<String>) {
+    let (status, headers, _) = call_html(test::TestRequest::with_uri(uri));
+    let location = headers
+        .get(header::LOCATION)
+        .map(|l| l.to_str().unwrap().to_string());
+    (status, location)
+}
+
+/// Gets the status code and headers of the response to a page request.
+pub fn html_headers(req: test::TestRequest) -> (u16, HeaderMap) {
+    let (status, headers, _) = call_html(req);
+    (status, headers)
+}
+
+/// Gets the headers and body of a page requested with an `Accept` header.
+pub fn accept_response(uri: &str, accept: &str) -> (HeaderMap, String) {
+    let req = test::TestRequest::with_uri(uri).insert_header((header::ACCEPT, accept));
+    let (_, headers, body) = call_html(req);
+    (headers, body)
+}
+
+/// Gets the registry of the site's templates, linking to the static files
+/// by their names.
+pub fn test_templates() -> Handlebars<'static> {
     let mut template = Handlebars::new();
     template.set_strict_mode(true);
+    register_helper(&mut template, Arc::new(AssetManifest::default()));
     template
         .register_templates_directory(".hbs", "./templates/")
         .expect("Could not register template files");
+    template
+}
+
+fn call_html(req: test::TestRequest) -> (u16, HeaderMap, String) {
+    let template = test_templates();
 
     let srv = test::init_service(
         App::new()
             .app_data(web::Data::new(ServerData {
-                db: build_pool(":memory:"),
+                db: build_pool(":memory:", &PoolConfig::default()),
+                replicas: ReadReplicas::default(),
+                default_translation: "kjv".to_string(),
+                fallback_translation: None,
                 template,
+                translations: vec![test_translation()],
             }))
             .service(web::resource("about").to(view::about))
+            .service(web::resource("api/docs").to(view::api_docs))
+            .service(web::resource("search").to(view::search::<TestSwordDrill>))
+            .service(
+                web::resource("parallel/{reference:.+\\d}")
+                    .name("parallel")
+                    .to(view::parallel::<TestSwordDrill>),
+            )
+            .service(
+                web::resource(VERSION)
+                    .name("version_bible")
+                    .to(view::all_books::<TestSwordDrill>),
+            )
+            .service(
+                web::resource(format!("{}/{{book}}", VERSION))
+                    .name("version_book")
+                    .to(view::book::<TestSwordDrill>),
+            )
+            .service(
+                web::resource(format!("{}/{{reference:.+\\d}}", VERSION))
+                    .name("version_reference")
+                    .to(view::reference::<TestSwordDrill>),
+            )
             .service(
                 web::resource("/")
                     .name("bible")
@@ -142,11 +552,15 @@ pub fn html_response(uri: &str) -> String {
             .service(web::resource("api/{reference}.json").to(api::reference::<TestSwordDrill>)),
     );
 
-    let req = test::TestRequest::with_uri(uri).to_request();
+    let req = req.to_request();
 
     System::new().block_on(async move {
-        str::from_utf8(&test::call_and_read_body(&srv.await, req).await)
+        let resp = test::call_service(&srv.await, req).await;
+        let status = resp.status().as_u16();
+        let headers = resp.headers().clone();
+        let body = str::from_utf8(&test::read_body(resp).await)
             .expect("Could not convert response to UTF8")
-            .to_string()
+            .to_string();
+        (status, headers, body)
     })
 }
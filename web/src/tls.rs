@@ -0,0 +1,71 @@
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+use rustls::{Certificate, PrivateKey, ServerConfig};
+use rustls_pemfile::Item;
+
+/// Address HTTPS is served on when it isn't configured.
+pub const DEFAULT_ADDR: &str = "0.0.0.0:8443";
+
+/// Builds the TLS configuration of the server from a PEM file of its
+/// certificate chain and one of its private key, which can be a PKCS #8,
+/// RSA or SEC1 EC key.
+///
+/// Clients that support it are served HTTP/2, which the server negotiates
+/// when it binds.
+pub fn server_config(cert_path: &Path, key_path: &Path) -> io::Result<ServerConfig> {
+    let certs: Vec<Certificate> =
+        rustls_pemfile::certs(&mut BufReader::new(File::open(cert_path)?))?
+            .into_iter()
+            .map(Certificate)
+            .collect();
+    if certs.is_empty() {
+        return Err(tls_error(format!(
+            "no certificates in {}",
+            cert_path.display()
+        )));
+    }
+    let key = rustls_pemfile::read_all(&mut BufReader::new(File::open(key_path)?))?
+        .into_iter()
+        .find_map(|item| match item {
+            Item::PKCS8Key(key) | Item::RSAKey(key) | Item::ECKey(key) => Some(PrivateKey(key)),
+            _ => None,
+        })
+        .ok_or_else(|| tls_error(format!("no private key in {}", key_path.display())))?;
+
+    ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(tls_error)
+}
+
+/// Wraps an error loading the TLS configuration as an I/O error.
+fn tls_error(e: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn invalid_files() {
+        let dir = env::temp_dir().join("biblers-tls-test");
+        fs::create_dir_all(&dir).unwrap();
+        let empty = dir.join("empty.pem");
+        fs::write(&empty, "").unwrap();
+
+        let e = server_config(&dir.join("missing.pem"), &empty).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::NotFound);
+        let e = server_config(&empty, &empty).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert!(e.to_string().starts_with("no certificates"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}